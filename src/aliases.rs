@@ -0,0 +1,92 @@
+use crate::args::RustflixArgs;
+use crate::t;
+use clap::CommandFactory;
+use log::warn;
+use std::collections::HashMap;
+
+/// The names of the `view` command, which accepts `+N` as a short form of `add N`
+const VIEW_COMMAND_NAMES: [&str; 2] = ["view", "views"];
+
+/// Expands aliases and short forms in the command line before it is parsed
+///
+/// The first argument that isn't a flag is replaced by the arguments it expands to if it is one of
+/// the given aliases. Aliases can't replace built-in commands. After that, a `+N` directly following the `view` command becomes `add N`.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, starting with the program name
+/// * `aliases` - Alias names mapped to the whitespace-separated arguments they expand to
+///
+/// # Returns
+///
+/// The command line with every alias and short form expanded
+pub fn expand(args: Vec<String>, aliases: &HashMap<String, String>) -> Vec<String> {
+    let mut args = args;
+
+    if let Some(position) = args.iter().skip(1).position(|arg| !arg.starts_with('-')) {
+        let position = position + 1;
+        if let Some(expansion) = aliases.get(&args[position]) {
+            if RustflixArgs::command()
+                .find_subcommand(&args[position])
+                .is_some()
+            {
+                warn!("{}", t!("config.alias_builtin", name = args[position]));
+            } else {
+                let expansion: Vec<String> =
+                    expansion.split_whitespace().map(String::from).collect();
+                args.splice(position..=position, expansion);
+            }
+        }
+    }
+
+    if let Some(position) = args
+        .windows(2)
+        .position(|pair| VIEW_COMMAND_NAMES.contains(&pair[0].as_str()) && is_view_count(&pair[1]))
+    {
+        let count = args[position + 1][1..].to_string();
+        args.splice(position + 1..=position + 1, ["add".to_string(), count]);
+    }
+
+    args
+}
+
+/// Determines if an argument is a `+N` view count
+fn is_view_count(arg: &str) -> bool {
+    arg.strip_prefix('+')
+        .is_some_and(|count| count.parse::<u32>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = HashMap::from([("top".to_string(), "video list --all".to_string())]);
+        assert_eq!(
+            expand(args("rustflix -v top --limit 3"), &aliases),
+            args("rustflix -v video list --all --limit 3")
+        );
+        assert_eq!(
+            expand(args("rustflix user list --name top"), &aliases),
+            args("rustflix user list --name top")
+        );
+    }
+
+    #[test]
+    fn test_expand_view_count() {
+        let aliases = HashMap::new();
+        assert_eq!(
+            expand(args("rustflix views +10 --name foo"), &aliases),
+            args("rustflix views add 10 --name foo")
+        );
+        assert_eq!(
+            expand(args("rustflix view add --name +10"), &aliases),
+            args("rustflix view add --name +10")
+        );
+    }
+}
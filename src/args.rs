@@ -1,6 +1,27 @@
 pub mod command_types;
 
-use clap::{Parser, Subcommand};
+use crate::audit::{self, AuditCommand, AuditSubcommand};
+use crate::batch::Batch;
+use crate::bench::Bench;
+use crate::channel::{self, ChannelCommand, ChannelSubcommand};
+use crate::config::{self, ConfigCommand, ConfigSubcommand, Flags};
+use crate::daemon::{self, DaemonCommand, DaemonSubcommand};
+use crate::feed::Feed;
+use crate::fsck::Fsck;
+use crate::init::Init;
+use crate::metadata::SetIdStrategy;
+use crate::notifications;
+use crate::output::ColorChoice;
+use crate::profile::{self, ProfileCommand, ProfileSubcommand};
+use crate::recommend;
+use crate::scheduler;
+use crate::self_test::SelfTest;
+use crate::server::Serve;
+use crate::sync::Synchronize;
+use crate::undo::Undo;
+use crate::webhooks::{self, WebhookCommand, WebhookSubcommand};
+use crate::{jellyfin, transfer};
+use clap::{ArgAction, Parser, Subcommand};
 use command_types::*;
 
 #[derive(Parser, Debug)]
@@ -8,28 +29,168 @@ use command_types::*;
 pub struct RustflixArgs {
     #[clap(subcommand)]
     pub command_type: CommandType,
+    /// Answer yes to every confirmation prompt instead of asking
+    #[arg(
+        short,
+        long,
+        visible_alias = "force",
+        global = true,
+        default_value_t = false
+    )]
+    pub yes: bool,
+    /// Give up waiting for an answer to a confirmation prompt after this many seconds, answering no
+    #[arg(long, global = true, default_value = None)]
+    pub prompt_timeout: Option<u64>,
+    /// Salvage the records that can still be read from corrupt store files instead of stopping
+    #[arg(long, global = true, default_value_t = false)]
+    pub recover: bool,
+    /// Run the command against a copy of the records kept in memory, printing how they would
+    /// change instead of writing anything
+    #[arg(long, global = true, default_value_t = false)]
+    pub dry_run: bool,
+    /// Exit with a failure whenever an error or warning is printed, e.g. when no query is given or
+    /// nothing matches it, instead of only printing it
+    #[arg(long, global = true, default_value_t = false)]
+    pub strict: bool,
+    /// Only print errors and warnings
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "verbose"
+    )]
+    pub quiet: bool,
+    /// Print details such as file paths and timings. Repeat for even more detail
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// When to color output
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Use the stores of the rustflix server at this URL, e.g. `http://host:8080`, instead of local files
+    #[arg(long, global = true, default_value = None)]
+    pub remote: Option<String>,
+    /// The API key token to send to the server given by `--remote`
+    #[arg(long, global = true, default_value = None)]
+    pub token: Option<String>,
+    /// The profile whose settings to use, from a `[profiles.<name>]` section of the config file
+    #[arg(long, global = true, default_value = None)]
+    pub profile: Option<String>,
+    /// The number of threads to search and filter records with. 0 uses one per core
+    #[arg(long, global = true, default_value_t = 1)]
+    pub threads: usize,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum CommandType {
     /// Create, update, delete, or show users
+    #[command(visible_alias = "u")]
     User(UserCommand),
     /// Create, update, delete, or show videos
+    #[command(visible_alias = "v")]
     Video(VideoCommand),
-    /// Add or show views on a video
+    /// Add or show views on a video. `view +N` is short for `view add N`
+    #[command(visible_alias = "views")]
     View(ViewCommand),
+    /// Browse and edit users, videos, and views in a full-screen interface
+    Tui,
+    /// Serve the stores over HTTP for other rustflix commands to use with `--remote`, along with a web dashboard
+    Serve(Serve),
+    /// Define genres, also called tags, and assign them to videos
+    #[command(visible_aliases = ["genres", "tag", "tags"])]
+    Genre(GenreCommand),
+    /// Create, delete, or list series of videos
+    Series(SeriesCommand),
+    /// Put videos together in playlists, and share and follow them
+    #[command(visible_alias = "playlists")]
+    Playlist(PlaylistCommand),
+    /// Create, revoke, or list the API keys clients of `serve` need
+    #[command(name = "apikey")]
+    ApiKey(ApiKeyCommand),
+    /// Keep the stores loaded in memory for other rustflix commands, which use it automatically
+    Daemon(DaemonCommand),
+    /// Log in as a user, so commands taking `--user` act as them when it isn't given
+    Login(user_subcommands::UserQuery),
+    /// Log out of the current session
+    Logout,
+    /// Import users, videos, and views from other services
+    Import(ImportCommand),
+    /// Back up every store to a single archive, or restore one
+    Backup(BackupCommand),
+    /// Sync the stores with another data directory, locally or at `ssh://host/path`, so both hold the same records
+    Sync(Synchronize),
+    /// Export the records of every store as JSON Lines, optionally only those changed since a given time
+    Export(ExportCommand),
+    /// Encrypt the stores at rest, or decrypt them again
+    Encrypt(EncryptCommand),
+    /// Check that every store matches its checksum and only refers to records that exist
+    Fsck(Fsck),
+    /// Apply many create, update, and view operations from a JSON Lines file, writing each store once
+    Batch(Batch),
+    /// Show or change how the IDs of new users, videos, and series are picked
+    IdStrategy(SetIdStrategy),
+    /// Measure how fast the active backend creates, lists, finds, and updates records, on synthetic videos kept apart from the real stores
+    Bench(Bench),
+    /// Recommend videos to a user from what others who watched the same videos watched, the genres they watch, and their subscriptions
+    Recommend(RecommendCommand),
+    /// Show the home feed of a user, mixing their subscriptions, recent uploads, trending videos, and the genres they watch as weighted in the `[feed]` section of the config file
+    Feed(Feed),
+    /// Review the changes made to records, recorded with who made them and with which command
+    Audit(AuditCommand),
+    /// Undo the latest command that changed any record, or list those that can be undone
+    Undo(Undo),
+    /// Publish the uploads of a channel as a feed
+    Channel(ChannelCommand),
+    /// Send test events to the webhooks in the config file, which are sent every change to users and videos
+    Webhook(WebhookCommand),
+    /// Show, get, and set the settings in effect and where each came from
+    Config(ConfigCommand),
+    /// Set rustflix up for the first time: create the data directory, write a starter config file with the backend chosen, and optionally add demo data
+    Init(Init),
+    /// Manage profiles, named sets of settings such as the data directory or remote chosen with `--profile`
+    Profile(ProfileCommand),
+    /// Fuzz random sequences of create, update, delete, and view operations in memory, checking
+    /// that records stay consistent. Meant for development
+    #[command(hide = true)]
+    SelfTest(SelfTest),
 }
 
 pub fn handle_user_command(command: UserCommand) {
     match command.subcommand {
         UserSubcommand::Create(create_user) => user_subcommands::handle_create_user(create_user),
         UserSubcommand::Update(update_user) => user_subcommands::handle_update_user(update_user),
-        UserSubcommand::Delete(user_query) => user_subcommands::handle_delete_user(user_query),
+        UserSubcommand::Delete(delete_user) => user_subcommands::handle_delete_user(delete_user),
+        UserSubcommand::DeleteMany(delete_many) => {
+            user_subcommands::handle_delete_many_users(delete_many)
+        }
         UserSubcommand::List(show_user) => user_subcommands::handle_list_users(show_user),
+        UserSubcommand::Search(search) => user_subcommands::handle_search_users(search),
+        UserSubcommand::Export(export) => user_subcommands::handle_export_users(export),
+        UserSubcommand::Import(import) => user_subcommands::handle_import_users(import),
+        UserSubcommand::SetPassword(user_query) => {
+            user_subcommands::handle_set_password(user_query)
+        }
+        UserSubcommand::SetRole(set_role) => user_subcommands::handle_set_role(set_role),
+        UserSubcommand::Show(user_query) => user_subcommands::handle_show_user(user_query),
+        UserSubcommand::Suspend(user_query) => {
+            user_subcommands::handle_set_suspended(user_query, true)
+        }
+        UserSubcommand::Reactivate(user_query) => {
+            user_subcommands::handle_set_suspended(user_query, false)
+        }
+        UserSubcommand::Merge(merge_users) => user_subcommands::handle_merge_users(merge_users),
+        UserSubcommand::ExportData(export) => user_subcommands::handle_export_user_data(export),
+        UserSubcommand::Erase(erase_user) => user_subcommands::handle_erase_user(erase_user),
+        UserSubcommand::Onboard(onboard_user) => {
+            user_subcommands::handle_onboard_user(onboard_user)
+        }
+        UserSubcommand::Notifications(show_notifications) => {
+            notifications::handle_show_notifications(show_notifications)
+        }
     }
 }
 
-pub fn handle_video_command(command: VideoCommand) {
+pub fn handle_video_command(command: VideoCommand, player: Option<String>) {
     match command.subcommand {
         VideoSubcommand::Create(create_video) => {
             video_subcommands::handle_create_video(create_video)
@@ -39,14 +200,207 @@ pub fn handle_video_command(command: VideoCommand) {
             video_subcommands::handle_update_video(update_video)
         }
 
-        VideoSubcommand::Delete(video_query) => video_subcommands::handle_delete_video(video_query),
+        VideoSubcommand::UpdateMany(update_many) => {
+            video_subcommands::handle_update_many_videos(update_many)
+        }
+
+        VideoSubcommand::Delete(delete_video) => {
+            video_subcommands::handle_delete_video(delete_video)
+        }
         VideoSubcommand::List(show_video) => video_subcommands::handle_list_videos(show_video),
+        VideoSubcommand::Search(search) => video_subcommands::handle_search_videos(search),
+        VideoSubcommand::Export(export) => video_subcommands::handle_export_videos(export),
+        VideoSubcommand::Import(import) => video_subcommands::handle_import_videos(import),
+        VideoSubcommand::Verify => video_subcommands::handle_verify_videos(),
+        VideoSubcommand::Probe(video_query) => video_subcommands::handle_probe_video(video_query),
+        VideoSubcommand::Thumbnail(thumbnail_command) => {
+            handle_thumbnail_command(thumbnail_command)
+        }
+        VideoSubcommand::Play(play_video) => {
+            view_subcommands::handle_play_video(play_video, player)
+        }
+        VideoSubcommand::Similar(similar_videos) => {
+            recommend::handle_similar_videos(similar_videos)
+        }
+    }
+}
+
+pub fn handle_thumbnail_command(command: ThumbnailCommand) {
+    match command.subcommand {
+        ThumbnailSubcommand::Set(set_thumbnail) => {
+            thumbnail_subcommands::handle_set_thumbnail(set_thumbnail)
+        }
+        ThumbnailSubcommand::Get(video_query) => {
+            thumbnail_subcommands::handle_get_thumbnail(video_query)
+        }
+        ThumbnailSubcommand::Generate(generate_thumbnail) => {
+            thumbnail_subcommands::handle_generate_thumbnail(generate_thumbnail)
+        }
+    }
+}
+
+pub fn handle_daemon_command(command: DaemonCommand) {
+    match command.subcommand {
+        None => daemon::handle_daemon(),
+        Some(DaemonSubcommand::Stats) => daemon::handle_stats(),
+        Some(DaemonSubcommand::Schedule) => scheduler::handle_show_schedule(),
+    }
+}
+
+pub fn handle_audit_command(command: AuditCommand) {
+    match command.subcommand {
+        AuditSubcommand::List(list_audit) => audit::handle_list_audit(list_audit),
+    }
+}
+
+pub fn handle_channel_command(command: ChannelCommand) {
+    match command.subcommand {
+        ChannelSubcommand::Feed(channel_feed) => channel::handle_channel_feed(channel_feed),
+    }
+}
+
+pub fn handle_config_command(command: ConfigCommand, flags: &Flags) {
+    match command.subcommand {
+        ConfigSubcommand::Show(show_config) => config::handle_show_config(show_config, flags),
+        ConfigSubcommand::Get(get_config) => config::handle_get_config(get_config, flags),
+        ConfigSubcommand::Set(set_config) => config::handle_set_config(set_config),
+        ConfigSubcommand::Unset(unset_config) => config::handle_unset_config(unset_config),
+    }
+}
+
+pub fn handle_profile_command(command: ProfileCommand, flags: &Flags) {
+    match command.subcommand {
+        ProfileSubcommand::List => profile::handle_list_profiles(flags),
+        ProfileSubcommand::Create(create_profile) => profile::handle_create_profile(create_profile),
+        ProfileSubcommand::Delete(delete_profile) => profile::handle_delete_profile(delete_profile),
+    }
+}
+
+pub fn handle_webhook_command(command: WebhookCommand) {
+    match command.subcommand {
+        WebhookSubcommand::Test(test_webhook) => webhooks::handle_test_webhook(test_webhook),
     }
 }
 
 pub fn handle_view_command(command: ViewCommand) {
     match command.subcommand {
         ViewSubcommand::Add(add_views) => view_subcommands::handle_add_views(add_views),
-        ViewSubcommand::Show(video_query) => view_subcommands::handle_show_views(video_query),
+        ViewSubcommand::Show(show_views) => view_subcommands::handle_show_views(show_views),
+    }
+}
+
+pub fn handle_apikey_command(command: ApiKeyCommand) {
+    match command.subcommand {
+        ApiKeySubcommand::Create(create_key) => apikey_subcommands::handle_create_key(create_key),
+        ApiKeySubcommand::Revoke(revoke_key) => apikey_subcommands::handle_revoke_key(revoke_key),
+        ApiKeySubcommand::List => apikey_subcommands::handle_list_keys(),
+    }
+}
+
+pub fn handle_genre_command(command: GenreCommand) {
+    match command.subcommand {
+        GenreSubcommand::Create(create_genre) => {
+            genre_subcommands::handle_create_genre(create_genre)
+        }
+        GenreSubcommand::Delete(delete_genre) => {
+            genre_subcommands::handle_delete_genre(delete_genre)
+        }
+        GenreSubcommand::List => genre_subcommands::handle_list_genres(),
+        GenreSubcommand::Assign(assign_genre) => {
+            genre_subcommands::handle_assign_genre(assign_genre)
+        }
+        GenreSubcommand::Unassign(unassign_genre) => {
+            genre_subcommands::handle_unassign_genre(unassign_genre)
+        }
+        GenreSubcommand::Rename(rename_genre) => {
+            genre_subcommands::handle_rename_genre(rename_genre)
+        }
+        GenreSubcommand::Merge(merge_genres) => {
+            genre_subcommands::handle_merge_genres(merge_genres)
+        }
+        GenreSubcommand::Suggest(suggest_genres) => {
+            genre_subcommands::handle_suggest_genres(suggest_genres)
+        }
+    }
+}
+
+pub fn handle_import_command(command: ImportCommand) {
+    match command.subcommand {
+        ImportSubcommand::YoutubeTakeout(takeout) => {
+            import_subcommands::handle_import_youtube_takeout(takeout)
+        }
+        ImportSubcommand::Jellyfin(server) => jellyfin::handle_import_jellyfin(server),
+    }
+}
+
+pub fn handle_export_command(command: ExportCommand) {
+    match command.subcommand {
+        None => transfer::handle_export_changes(command.changes),
+        Some(ExportSubcommand::Jellyfin(server)) => jellyfin::handle_export_jellyfin(server),
+    }
+}
+
+pub fn handle_recommend_command(command: RecommendCommand) {
+    match command.subcommand {
+        None => recommend::handle_recommend(command.recommend),
+        Some(RecommendSubcommand::Dismiss(dismiss_video)) => {
+            recommend::handle_dismiss_video(dismiss_video)
+        }
+    }
+}
+
+pub fn handle_backup_command(command: BackupCommand) {
+    match command.subcommand {
+        BackupSubcommand::Create(create_backup) => {
+            backup_subcommands::handle_create_backup(create_backup)
+        }
+        BackupSubcommand::Restore(restore_backup) => {
+            backup_subcommands::handle_restore_backup(restore_backup)
+        }
+    }
+}
+
+pub fn handle_encrypt_command(command: EncryptCommand) {
+    match command.subcommand {
+        EncryptSubcommand::Enable => encrypt_subcommands::handle_enable_encryption(),
+        EncryptSubcommand::Disable => encrypt_subcommands::handle_disable_encryption(),
+    }
+}
+
+pub fn handle_series_command(command: SeriesCommand) {
+    match command.subcommand {
+        SeriesSubcommand::Create(create_series) => {
+            series_subcommands::handle_create_series(create_series)
+        }
+        SeriesSubcommand::Delete(delete_series) => {
+            series_subcommands::handle_delete_series(delete_series)
+        }
+        SeriesSubcommand::List(list_series) => series_subcommands::handle_list_series(list_series),
+    }
+}
+
+pub fn handle_playlist_command(command: PlaylistCommand) {
+    match command.subcommand {
+        PlaylistSubcommand::Create(create_playlist) => {
+            playlist_subcommands::handle_create_playlist(create_playlist)
+        }
+        PlaylistSubcommand::Push(playlist_video) => {
+            playlist_subcommands::handle_add_to_playlist(playlist_video)
+        }
+        PlaylistSubcommand::Pull(playlist_video) => {
+            playlist_subcommands::handle_remove_from_playlist(playlist_video)
+        }
+        PlaylistSubcommand::Share(share_playlist) => {
+            playlist_subcommands::handle_share_playlist(share_playlist)
+        }
+        PlaylistSubcommand::Follow(follow_playlist) => {
+            playlist_subcommands::handle_follow_playlist(follow_playlist)
+        }
+        PlaylistSubcommand::List(list_playlists) => {
+            playlist_subcommands::handle_list_playlists(list_playlists)
+        }
+        PlaylistSubcommand::Delete(delete_playlist) => {
+            playlist_subcommands::handle_delete_playlist(delete_playlist)
+        }
     }
 }
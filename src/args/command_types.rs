@@ -1,8 +1,28 @@
+pub mod apikey_subcommands;
+pub mod backup_subcommands;
+pub mod encrypt_subcommands;
+pub mod genre_subcommands;
+pub mod import_subcommands;
+pub mod playlist_subcommands;
+pub mod series_subcommands;
+pub mod thumbnail_subcommands;
 pub mod user_subcommands;
 pub mod video_subcommands;
 pub mod view_subcommands;
 
+use crate::jellyfin::JellyfinServer;
+use crate::listing::Search;
+use crate::notifications::ShowNotifications;
+use crate::recommend::{DismissVideo, Recommend, SimilarVideos};
+use crate::transfer::{Export, ExportChanges, Import};
+use apikey_subcommands::*;
+use backup_subcommands::*;
 use clap::{Args, Subcommand};
+use genre_subcommands::*;
+use import_subcommands::*;
+use playlist_subcommands::*;
+use series_subcommands::*;
+use thumbnail_subcommands::*;
 use user_subcommands::*;
 use video_subcommands::*;
 use view_subcommands::*;
@@ -16,13 +36,45 @@ pub struct UserCommand {
 #[derive(Debug, Subcommand)]
 pub enum UserSubcommand {
     /// Create a new user
+    #[command(visible_aliases = ["add", "new"])]
     Create(CreateUser),
     /// Update an existing user by either ID, name, or email.
+    #[command(visible_alias = "edit")]
     Update(UpdateUser),
-    /// Delete an existing user by
-    Delete(UserQuery),
+    /// Delete an existing user by either ID, name, or email
+    #[command(visible_alias = "rm")]
+    Delete(DeleteUser),
+    /// Delete every user matching a query, e.g. `--email-contains @example.com`, after typing how many
+    DeleteMany(DeleteManyUsers),
     /// List one or more users
+    #[command(visible_alias = "ls")]
     List(ShowUser),
+    /// Show every detail of a single user
+    Show(UserQuery),
+    /// Search for users by approximate name or email
+    Search(Search),
+    /// Export every user to a file
+    Export(Export),
+    /// Import users from a file
+    Import(Import),
+    /// Set the password a user logs in with
+    SetPassword(UserQuery),
+    /// Give a user a role, deciding what they may do while logged in
+    SetRole(SetRole),
+    /// Suspend a user, keeping their account but stopping them from logging in or adding views
+    Suspend(UserQuery),
+    /// Lift the suspension of a user
+    Reactivate(UserQuery),
+    /// Merge a duplicate user into another, moving their watch history and videos
+    Merge(MergeUsers),
+    /// Export everything referencing a user as a JSON bundle
+    ExportData(ExportUserData),
+    /// Erase a user from every store, anonymizing their watch history and videos
+    Erase(EraseUser),
+    /// Ask which genres interest a new user, so they can be recommended videos before watching any
+    Onboard(OnboardUser),
+    /// Show the new notifications of a user, such as their videos reaching a milestone, marking them read
+    Notifications(ShowNotifications),
 }
 
 #[derive(Debug, Args)]
@@ -34,13 +86,51 @@ pub struct VideoCommand {
 #[derive(Debug, Subcommand)]
 pub enum VideoSubcommand {
     /// Create a new video
+    #[command(visible_aliases = ["add", "new"])]
     Create(CreateVideo),
     /// Update an existing video by either ID or name
+    #[command(visible_alias = "edit")]
     Update(UpdateVideo),
+    /// Update every video matching a filter, e.g. `--where 'views > 1000' --set visibility=private`
+    UpdateMany(UpdateManyVideos),
     /// Delete an existing video by either ID or name
-    Delete(VideoQuery),
+    #[command(visible_alias = "rm")]
+    Delete(DeleteVideo),
     /// List one or more videos
+    #[command(visible_alias = "ls")]
     List(ListVideo),
+    /// Search for videos by approximate name
+    Search(Search),
+    /// Export every video to a file
+    Export(Export),
+    /// Import videos from a file
+    Import(Import),
+    /// Report videos whose media files have gone missing
+    Verify,
+    /// Read the duration, resolution and codec of a video from its media file using ffprobe
+    Probe(VideoQuery),
+    /// Manage the thumbnail of a video
+    Thumbnail(ThumbnailCommand),
+    /// Open the media file of a video in the configured player, counting a view once it finishes
+    Play(PlayVideo),
+    /// List the videos most like a video, by viewers, genres, series, and channel, for an "up next" list
+    Similar(SimilarVideos),
+}
+
+#[derive(Debug, Args)]
+pub struct ThumbnailCommand {
+    #[clap(subcommand)]
+    pub subcommand: ThumbnailSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ThumbnailSubcommand {
+    /// Use an existing image as the thumbnail of a video
+    Set(SetThumbnail),
+    /// Print the path of the thumbnail of a video
+    Get(VideoQuery),
+    /// Take the thumbnail of a video from a frame of its media file using ffmpeg
+    Generate(GenerateThumbnail),
 }
 
 #[derive(Debug, Args)]
@@ -54,5 +144,174 @@ pub enum ViewSubcommand {
     /// Add one or more views to a video
     Add(AddViews),
     /// Show the views on a video
-    Show(VideoQuery),
+    #[command(visible_alias = "get")]
+    Show(ShowViews),
+}
+
+#[derive(Debug, Args)]
+pub struct ApiKeyCommand {
+    #[clap(subcommand)]
+    pub subcommand: ApiKeySubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ApiKeySubcommand {
+    /// Create a new API key and print its token
+    #[command(visible_aliases = ["add", "new"])]
+    Create(CreateApiKey),
+    /// Revoke an API key by ID
+    #[command(visible_alias = "rm")]
+    Revoke(RevokeApiKey),
+    /// List every API key
+    #[command(visible_alias = "ls")]
+    List,
+}
+
+#[derive(Debug, Args)]
+pub struct GenreCommand {
+    #[clap(subcommand)]
+    pub subcommand: GenreSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GenreSubcommand {
+    /// Define one or more genres videos may have
+    #[command(visible_aliases = ["add", "new"])]
+    Create(CreateGenre),
+    /// Delete a genre, removing it from every video
+    #[command(visible_alias = "rm")]
+    Delete(DeleteGenre),
+    /// List every genre and how many videos have it
+    #[command(visible_alias = "ls")]
+    List,
+    /// Assign a genre to a video by either ID or name
+    Assign(AssignGenre),
+    /// Remove a genre from a video by either ID or name
+    Unassign(UnassignGenre),
+    /// Rename a genre, on every video it is assigned to
+    #[command(visible_alias = "mv")]
+    Rename(RenameGenre),
+    /// Merge genres into one, replacing them on every video
+    Merge(MergeGenres),
+    /// Print the genres starting with a prefix, most used first, one per line for shell completion
+    Suggest(SuggestGenres),
+}
+
+#[derive(Debug, Args)]
+pub struct SeriesCommand {
+    #[clap(subcommand)]
+    pub subcommand: SeriesSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SeriesSubcommand {
+    /// Create a new series. Mark videos as its episodes with `video create --series`
+    #[command(visible_aliases = ["add", "new"])]
+    Create(CreateSeries),
+    /// Delete a series by ID. Refused while it has episodes, unless `--cascade` deletes them too or `--orphan` keeps them as standalone videos
+    #[command(visible_alias = "rm")]
+    Delete(DeleteSeries),
+    /// List every series
+    #[command(visible_alias = "ls")]
+    List(ListSeries),
+}
+
+#[derive(Debug, Args)]
+pub struct PlaylistCommand {
+    #[clap(subcommand)]
+    pub subcommand: PlaylistSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PlaylistSubcommand {
+    /// Create a new, empty playlist
+    #[command(visible_aliases = ["add", "new"])]
+    Create(CreatePlaylist),
+    /// Add a video to the end of a playlist, notifying its followers
+    Push(PlaylistVideo),
+    /// Remove a video from a playlist
+    Pull(PlaylistVideo),
+    /// Share a playlist, so other users can see and follow it
+    Share(SharePlaylist),
+    /// Follow a shared playlist, to be notified when videos are added to it
+    Follow(FollowPlaylist),
+    /// List your playlists and those shared, with how many users follow each
+    #[command(visible_alias = "ls")]
+    List(ListPlaylists),
+    /// Delete a playlist by ID, keeping the videos in it
+    #[command(visible_alias = "rm")]
+    Delete(DeletePlaylist),
+}
+
+#[derive(Debug, Args)]
+pub struct ImportCommand {
+    #[clap(subcommand)]
+    pub subcommand: ImportSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportSubcommand {
+    /// Import the watch history and subscriptions of an extracted YouTube Google Takeout archive
+    YoutubeTakeout(YoutubeTakeout),
+    /// Import the users and videos of a Jellyfin or Emby server, mirroring how often each video was played there
+    Jellyfin(JellyfinServer),
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ExportCommand {
+    #[command(flatten)]
+    pub changes: ExportChanges,
+    #[clap(subcommand)]
+    pub subcommand: Option<ExportSubcommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExportSubcommand {
+    /// Mark every video in the watch history as played on a Jellyfin or Emby server, for the user of the same name there
+    Jellyfin(JellyfinServer),
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct RecommendCommand {
+    #[command(flatten)]
+    pub recommend: Recommend,
+    #[clap(subcommand)]
+    pub subcommand: Option<RecommendSubcommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RecommendSubcommand {
+    /// Mark a video as not interesting to a user, so it is never recommended to them again
+    #[command(visible_alias = "not-interested")]
+    Dismiss(DismissVideo),
+}
+
+#[derive(Debug, Args)]
+pub struct BackupCommand {
+    #[clap(subcommand)]
+    pub subcommand: BackupSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupSubcommand {
+    /// Bundle every store into a zstd-compressed tar archive, along with a manifest describing them
+    Create(CreateBackup),
+    /// Replace all local data with a backup, once it has been checked against its manifest
+    Restore(RestoreBackup),
+}
+
+#[derive(Debug, Args)]
+pub struct EncryptCommand {
+    #[clap(subcommand)]
+    pub subcommand: EncryptSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum EncryptSubcommand {
+    /// Encrypt every store with the passphrase or keyfile set in the `[encryption]` section of the config file
+    Enable,
+    /// Decrypt every store, so the `[encryption]` section can be removed from the config file
+    Disable,
 }
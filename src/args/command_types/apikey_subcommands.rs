@@ -0,0 +1,159 @@
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities;
+use clap::{Args, ValueEnum};
+use log::{error, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A key letting clients use the server. Only a hash of the token is kept
+///
+/// # Fields
+///
+/// * `id` - The ID of the key
+/// * `name` - What the key is for
+/// * `scope` - What the key allows
+/// * `hash` - The SHA-256 hash of the token, in hex
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ApiKey {
+    pub id: u32,
+    pub name: String,
+    pub scope: Scope,
+    pub hash: String,
+}
+
+/// What an API key allows
+///
+/// # Variants
+///
+/// * `Read` - Fetching records and listening for events
+/// * `Admin` - Everything, including changing records
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+pub enum Scope {
+    Read,
+    Admin,
+}
+
+impl Scope {
+    /// Whether a key with this scope may do something needing the given scope
+    pub fn allows(&self, needed: Scope) -> bool {
+        *self == Scope::Admin || needed == Scope::Read
+    }
+}
+
+/// Hashes a token the way it is kept in the store
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Finds the key a token belongs to
+///
+/// # Arguments
+///
+/// * `keys` - The list of keys to search
+/// * `token` - The token given by a client
+pub fn find_key<'a>(keys: &'a [ApiKey], token: &str) -> Option<&'a ApiKey> {
+    let hash = hash_token(token);
+    keys.iter().find(|key| key.hash == hash)
+}
+
+#[derive(Debug, Args)]
+pub struct CreateApiKey {
+    /// What the key is for
+    #[arg(long)]
+    pub name: String,
+    /// What the key allows
+    #[arg(long, value_enum, default_value_t = Scope::Read)]
+    pub scope: Scope,
+}
+
+/// Handles the creation of a new API key, printing its token once
+///
+/// API keys are always kept in the local data directory, as they belong to the server.
+///
+/// # Arguments
+///
+/// * `create_key` - The arguments for the key creation
+pub fn handle_create_key(create_key: CreateApiKey) {
+    let mut keys: Vec<ApiKey> = storage::load_file(Store::ApiKeys);
+
+    let mut rng = rand::thread_rng();
+    let mut id = rng.gen_range(0..=u32::MAX);
+    while keys.iter().any(|key| key.id == id) {
+        id = rng.gen_range(0..=u32::MAX);
+    }
+    let token: String = (0..32)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect();
+
+    keys.push(ApiKey {
+        id,
+        name: create_key.name,
+        scope: create_key.scope,
+        hash: hash_token(&token),
+    });
+
+    storage::save_file(Store::ApiKeys, &keys);
+
+    info!("{}", t!("apikey.created"));
+    println!("ID: {}", id);
+    println!("{}: {}", t!("apikey.token"), token);
+}
+
+#[derive(Debug, Args)]
+pub struct RevokeApiKey {
+    /// The ID of the key to revoke
+    #[arg(long)]
+    pub id: u32,
+}
+
+/// Handles revoking an API key so its token stops working
+///
+/// # Arguments
+///
+/// * `revoke_key` - The arguments for the revocation
+pub fn handle_revoke_key(revoke_key: RevokeApiKey) {
+    let mut keys: Vec<ApiKey> = storage::load_file(Store::ApiKeys);
+
+    let key_index = match keys.iter().position(|key| key.id == revoke_key.id) {
+        Some(key_index) => key_index,
+        None => {
+            error!("{}", t!("apikey.not_found", id = revoke_key.id));
+            return;
+        }
+    };
+
+    if !utilities::confirm(
+        &t!("apikey.confirm_revoke"),
+        Some(format!("{} ({:?})", keys[key_index].name, keys[key_index].scope).as_str()),
+        Some(&t!("apikey.revoke_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return;
+    }
+
+    keys.remove(key_index);
+
+    storage::save_file(Store::ApiKeys, &keys);
+
+    info!("{}", t!("apikey.revoked"));
+}
+
+/// Lists every API key without its token
+pub fn handle_list_keys() {
+    let keys: Vec<ApiKey> = storage::load_file(Store::ApiKeys);
+
+    if keys.is_empty() {
+        warn!("{}", t!("apikey.none"));
+        return;
+    }
+
+    for key in keys {
+        println!("{}\t{}\t{:?}", key.id, key.name, key.scope);
+    }
+}
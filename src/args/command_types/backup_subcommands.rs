@@ -0,0 +1,138 @@
+use super::user_subcommands::Role;
+use super::video_subcommands::format_timestamp;
+use crate::backup;
+use crate::daemon;
+use crate::dry_run;
+use crate::remote;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities::{self, ConfirmCount};
+use clap::Args;
+use log::{error, info};
+use serde_json::Value;
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+pub struct CreateBackup {
+    /// The archive to write, e.g. `rustflix.tar.zst`
+    pub file: PathBuf,
+}
+
+/// Handles bundling every store into a single archive
+///
+/// # Arguments
+///
+/// * `create_backup` - The arguments for the backup
+pub fn handle_create_backup(create_backup: CreateBackup) {
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("backup.remote_create", remote = remote));
+        return;
+    }
+    if dry_run::skip(&format!(
+        "back up the stores to {}",
+        create_backup.file.display()
+    )) {
+        return;
+    }
+
+    match backup::create(&create_backup.file) {
+        Ok(manifest) => info!(
+            "{}",
+            t!(
+                "backup.created",
+                count = manifest.stores.len(),
+                path = create_backup.file.display()
+            )
+        ),
+        Err(e) => error!("{}", t!("backup.failed", error = e)),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreBackup {
+    /// The archive made with `backup create` to restore
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
+
+/// Handles replacing every store with those of a backup, once the backup has been checked
+/// against its manifest
+///
+/// # Arguments
+///
+/// * `restore_backup` - The arguments for the restore
+pub fn handle_restore_backup(restore_backup: RestoreBackup) {
+    if !session::require_role(Role::Admin, &t!("role.restore_backups")) {
+        return;
+    }
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("backup.remote_restore", remote = remote));
+        return;
+    }
+    if daemon::connect().is_some() {
+        error!("{}", t!("backup.daemon_running"));
+        return;
+    }
+
+    let (manifest, stores) = match backup::read(&restore_backup.file) {
+        Ok(backup) => backup,
+        Err(e) => {
+            error!(
+                "{}",
+                t!(
+                    "backup.cannot_restore",
+                    path = restore_backup.file.display(),
+                    error = e
+                )
+            );
+            return;
+        }
+    };
+
+    let mut preview = t!(
+        "backup.made_by",
+        version = manifest.version,
+        created_at = format_timestamp(manifest.created_at)
+    );
+    for entry in &manifest.stores {
+        preview.push_str(&format!(
+            "\n  {}",
+            t!(
+                "backup.store_size",
+                store = format!("{:?}", entry.store),
+                size = entry.size
+            )
+        ));
+    }
+
+    // Every local record is replaced, whether or not the backup has a store for it
+    let replaced: usize = Store::ALL
+        .into_iter()
+        .map(|store| storage::load::<Value>(store).len())
+        .sum();
+    preview.push_str(&format!(
+        "\n{}",
+        t!("backup.records_replaced", count = replaced)
+    ));
+
+    if !utilities::confirm_bulk(
+        &t!("backup.confirm_restore"),
+        Some(preview.as_str()),
+        Some(&t!("backup.restore_cancelled")),
+        Some(false),
+        replaced,
+        &restore_backup.confirm_count,
+    ) {
+        return;
+    }
+
+    match backup::restore(&stores) {
+        Ok(()) => info!(
+            "{}",
+            t!("backup.restored", path = restore_backup.file.display())
+        ),
+        Err(e) => error!("{}", t!("backup.restore_failed", error = e)),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::backup;
+use crate::daemon;
+use crate::encryption;
+use crate::remote;
+use crate::storage::{self, Store};
+use crate::t;
+use log::{error, info, warn};
+use serde_json::Value;
+
+/// Checks that the stores can be rewritten from this process
+///
+/// # Returns
+///
+/// Whether the stores can be rewritten
+fn can_rewrite() -> bool {
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("encrypt.remote", remote = remote));
+        return false;
+    }
+    if daemon::connect().is_some() {
+        error!("{}", t!("encrypt.daemon_running"));
+        return false;
+    }
+    true
+}
+
+/// Rewrites every store that has records, so it is written the way encryption is set up now
+///
+/// # Returns
+///
+/// How many stores were rewritten
+fn rewrite_stores() -> usize {
+    let mut rewritten = 0;
+    for store in Store::ALL {
+        let records: Vec<Value> = storage::load(store);
+        if records.is_empty() {
+            continue;
+        }
+        storage::save(store, &records);
+        rewritten += 1;
+    }
+    rewritten
+}
+
+/// Handles encrypting every store with the key set in the `[encryption]` section of the config
+/// file. Stores written afterwards are encrypted as well
+pub fn handle_enable_encryption() {
+    if !encryption::enabled() {
+        error!("{}", t!("encrypt.no_key"));
+        return;
+    }
+    if !can_rewrite() {
+        return;
+    }
+
+    let rewritten = rewrite_stores();
+    info!("{}", t!("encrypt.encrypted", count = rewritten));
+    if backup::backups_dir().exists() {
+        warn!(
+            "{}",
+            t!(
+                "encrypt.old_backups",
+                path = backup::backups_dir().display()
+            )
+        );
+    }
+}
+
+/// Handles decrypting every store, after which the `[encryption]` section can be removed from the
+/// config file
+pub fn handle_disable_encryption() {
+    if !encryption::enabled() {
+        error!("{}", t!("encrypt.not_encrypted"));
+        return;
+    }
+    if !can_rewrite() {
+        return;
+    }
+
+    encryption::disable();
+    let rewritten = rewrite_stores();
+    info!("{}", t!("encrypt.decrypted", count = rewritten));
+}
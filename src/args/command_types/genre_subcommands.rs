@@ -0,0 +1,623 @@
+use super::user_subcommands::{Role, User};
+use super::video_subcommands::{select_video, Video, VideoQuery};
+use crate::repository::Repository;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities;
+use crate::{formatting, query};
+use clap::Args;
+use log::{debug, error, info, warn};
+
+/// Finds the name a genre was defined with, ignoring case
+///
+/// # Arguments
+///
+/// * `genres` - The defined genres
+/// * `name` - The name to look for
+pub fn find_genre<'a>(genres: &'a [String], name: &str) -> Option<&'a String> {
+    genres
+        .iter()
+        .find(|genre| query::text_equals(genre, name.trim(), true))
+}
+
+/// Checks that a genre name can be stored
+///
+/// # Returns
+///
+/// An error message if the name is empty or contains a comma, which separates genres
+fn validate_genre(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err(t!("genre.empty"));
+    }
+    if name.contains(',') {
+        return Err(t!("genre.comma", name = name));
+    }
+    Ok(())
+}
+
+/// Looks up the genres given for a video, so that only defined genres are assigned
+///
+/// # Arguments
+///
+/// * `names` - The genres given on the command line
+/// * `allow_new` - Define unknown genres instead of rejecting them
+///
+/// # Returns
+///
+/// The genres as they were defined, or `None` after reporting an error if any is unknown
+pub fn resolve_genres(names: &[String], allow_new: bool) -> Option<Vec<String>> {
+    if names.is_empty() {
+        return Some(vec![]);
+    }
+
+    let mut genres: Vec<String> = storage::load(Store::Genres);
+    let mut resolved = vec![];
+    let mut defined_new = false;
+    for name in names {
+        if let Some(genre) = find_genre(&genres, name) {
+            if !resolved.contains(genre) {
+                resolved.push(genre.clone());
+            }
+            continue;
+        }
+
+        if !allow_new {
+            error!("{}", t!("genre.unknown", name = name));
+            return None;
+        }
+        if let Err(e) = validate_genre(name) {
+            error!("{}", e);
+            return None;
+        }
+
+        info!("{}", t!("genre.defining", name = name.trim()));
+        genres.push(name.trim().to_string());
+        resolved.push(name.trim().to_string());
+        defined_new = true;
+    }
+
+    if defined_new {
+        storage::save(Store::Genres, &genres);
+    }
+    Some(resolved)
+}
+
+#[derive(Debug, Args)]
+pub struct CreateGenre {
+    /// The names of the genres to define
+    #[arg(required = true)]
+    pub names: Vec<String>,
+}
+
+/// Handles defining new genres
+///
+/// # Arguments
+///
+/// * `create_genre` - The arguments for the genre creation
+pub fn handle_create_genre(create_genre: CreateGenre) {
+    if !session::require_role(Role::Moderator, &t!("role.define_genres")) {
+        return;
+    }
+
+    let mut genres: Vec<String> = storage::load(Store::Genres);
+
+    let mut added = 0;
+    for name in create_genre.names {
+        if let Err(e) = validate_genre(&name) {
+            error!("{}", e);
+            return;
+        }
+        if let Some(genre) = find_genre(&genres, &name) {
+            warn!("{}", t!("genre.duplicate", genre = genre));
+            continue;
+        }
+        genres.push(name.trim().to_string());
+        added += 1;
+    }
+
+    storage::save(Store::Genres, &genres);
+
+    info!("{}", t!("genre.defined", count = added));
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteGenre {
+    /// The name of the genre to delete
+    pub name: String,
+}
+
+/// Handles deleting a genre, removing it from every video it was assigned to
+///
+/// # Arguments
+///
+/// * `delete_genre` - The arguments for the genre deletion
+pub fn handle_delete_genre(delete_genre: DeleteGenre) {
+    if !session::require_role(Role::Moderator, &t!("role.delete_genres")) {
+        return;
+    }
+
+    let mut genres: Vec<String> = storage::load(Store::Genres);
+
+    let genre = match find_genre(&genres, &delete_genre.name) {
+        Some(genre) => genre.clone(),
+        None => {
+            error!("{}", t!("genre.not_found", name = delete_genre.name));
+            return;
+        }
+    };
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let used_by = videos
+        .iter()
+        .filter(|video| video.genres.contains(&genre))
+        .count();
+
+    if !utilities::confirm(
+        &t!("genre.confirm_delete", genre = genre),
+        Some(&t!("genre.removed_from", count = used_by)),
+        Some(&t!("genre.delete_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return;
+    }
+
+    genres.retain(|other| *other != genre);
+    for video in &mut videos {
+        video.genres.retain(|other| *other != genre);
+    }
+
+    storage::save(Store::Genres, &genres);
+    if used_by > 0 {
+        storage::save(Store::Videos, &videos);
+    }
+    replace_interests(&[genre], None);
+
+    info!("{}", t!("genre.deleted"));
+}
+
+/// Lists every genre with the number of videos it is assigned to
+pub fn handle_list_genres() {
+    let genres: Vec<String> = storage::load(Store::Genres);
+
+    if genres.is_empty() {
+        warn!("{}", t!("genre.none"));
+        return;
+    }
+
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    for genre in genres {
+        let count = videos
+            .iter()
+            .filter(|video| video.genres.contains(&genre))
+            .count();
+        println!("{}\t{}", genre, formatting::count(count as u64));
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AssignGenre {
+    /// The genre to assign
+    pub genre: String,
+    #[command(flatten)]
+    pub query: VideoQuery,
+    /// Define the genre if it doesn't exist yet
+    #[arg(long, default_value_t = false)]
+    pub allow_new_genre: bool,
+}
+
+/// Handles assigning a genre to a video
+///
+/// # Arguments
+///
+/// * `assign_genre` - The arguments for the assignment
+pub fn handle_assign_genre(assign_genre: AssignGenre) {
+    if !session::require_role(Role::Moderator, &t!("role.assign_genres")) {
+        return;
+    }
+
+    if assign_genre.query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
+    }
+
+    let genre = match resolve_genres(&[assign_genre.genre], assign_genre.allow_new_genre) {
+        Some(mut genres) => genres.remove(0),
+        None => return,
+    };
+
+    let mut videos = Repository::<Video>::load();
+
+    let mut video = match select_video(&videos, &assign_genre.query, &t!("action.update")) {
+        Some(video) => video.clone(),
+        None => return,
+    };
+
+    if video.genres.contains(&genre) {
+        warn!(
+            "{}",
+            t!("genre.already_assigned", video = video.name, genre = genre)
+        );
+        return;
+    }
+    video.genres.push(genre.clone());
+    let name = video.name.clone();
+
+    videos.replace(video);
+    videos.save();
+
+    info!("{}", t!("genre.assigned", genre = genre, video = name));
+}
+
+#[derive(Debug, Args)]
+pub struct UnassignGenre {
+    /// The genre to remove
+    pub genre: String,
+    #[command(flatten)]
+    pub query: VideoQuery,
+}
+
+/// Handles removing a genre from a video
+///
+/// # Arguments
+///
+/// * `unassign_genre` - The arguments for the removal
+pub fn handle_unassign_genre(unassign_genre: UnassignGenre) {
+    if !session::require_role(Role::Moderator, &t!("role.unassign_genres")) {
+        return;
+    }
+
+    if unassign_genre.query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
+    }
+
+    let mut videos = Repository::<Video>::load();
+
+    let mut video = match select_video(&videos, &unassign_genre.query, &t!("action.update")) {
+        Some(video) => video.clone(),
+        None => return,
+    };
+
+    let before = video.genres.len();
+    video
+        .genres
+        .retain(|genre| !query::text_equals(genre, &unassign_genre.genre, true));
+    if video.genres.len() == before {
+        warn!(
+            "{}",
+            t!(
+                "genre.not_assigned",
+                video = video.name,
+                genre = unassign_genre.genre
+            )
+        );
+        return;
+    }
+    let name = video.name.clone();
+
+    videos.replace(video);
+    videos.save();
+
+    info!(
+        "{}",
+        t!(
+            "genre.unassigned",
+            genre = unassign_genre.genre,
+            video = name
+        )
+    );
+}
+
+/// Replaces genres in a list of them, keeping them unique
+///
+/// # Arguments
+///
+/// * `genres` - The genres to rewrite
+/// * `from` - The genres to replace
+/// * `into` - The genre to replace them with, or `None` to remove them
+///
+/// # Returns
+///
+/// Whether any genre was replaced
+fn replace_in(genres: &mut Vec<String>, from: &[String], into: Option<&str>) -> bool {
+    if !genres.iter().any(|genre| from.contains(genre)) {
+        return false;
+    }
+    let mut replaced: Vec<String> = vec![];
+    for genre in genres.drain(..) {
+        let genre = match (from.contains(&genre), into) {
+            (false, _) => genre,
+            (true, Some(into)) => into.to_string(),
+            (true, None) => continue,
+        };
+        if !replaced.contains(&genre) {
+            replaced.push(genre);
+        }
+    }
+    *genres = replaced;
+    true
+}
+
+/// Replaces genres on every video that has any of them, keeping each video's genres unique
+///
+/// # Arguments
+///
+/// * `videos` - The videos to rewrite
+/// * `from` - The genres to replace
+/// * `into` - The genre to replace them with
+///
+/// # Returns
+///
+/// How many videos were changed
+fn replace_genres(videos: &mut [Video], from: &[String], into: &str) -> usize {
+    videos
+        .iter_mut()
+        .map(|video| replace_in(&mut video.genres, from, Some(into)))
+        .filter(|changed| *changed)
+        .count()
+}
+
+/// Replaces genres among the interests of every user, saving the users if any changed
+///
+/// # Arguments
+///
+/// * `from` - The genres to replace
+/// * `into` - The genre to replace them with, or `None` to remove them
+fn replace_interests(from: &[String], into: Option<&str>) {
+    let mut users: Vec<User> = storage::load(Store::Users);
+    let changed = users
+        .iter_mut()
+        .map(|user| replace_in(&mut user.interests, from, into))
+        .filter(|changed| *changed)
+        .count();
+    if changed > 0 {
+        storage::save(Store::Users, &users);
+        debug!("Changed the interests of {} users", changed);
+    }
+}
+
+/// Finds the genres starting with a prefix, ignoring case, ordered by how many videos have them
+/// and then by name
+///
+/// # Arguments
+///
+/// * `genres` - The defined genres
+/// * `videos` - Every video, to count how often each genre is used
+/// * `prefix` - The start of the genre names to find. Every genre is found if it's empty
+pub fn genres_with_prefix(genres: &[String], videos: &[Video], prefix: &str) -> Vec<String> {
+    let prefix = formatting::fold_case(prefix.trim_start());
+    let mut found: Vec<(usize, &String)> = genres
+        .iter()
+        .filter(|genre| formatting::fold_case(genre).starts_with(&prefix))
+        .map(|genre| {
+            let used = videos
+                .iter()
+                .filter(|video| video.genres.contains(genre))
+                .count();
+            (used, genre)
+        })
+        .collect();
+    found.sort_by(|(a_used, a), (b_used, b)| {
+        b_used
+            .cmp(a_used)
+            .then_with(|| formatting::fold_case(a).cmp(&formatting::fold_case(b)))
+    });
+    found.into_iter().map(|(_, genre)| genre.clone()).collect()
+}
+
+#[derive(Debug, Args)]
+pub struct RenameGenre {
+    /// The name of the genre to rename
+    pub old: String,
+    /// The new name of the genre
+    pub new: String,
+}
+
+/// Handles renaming a genre, on every video it is assigned to as well
+///
+/// Renaming a genre to the name of another is refused, since `genre merge` does that.
+///
+/// # Arguments
+///
+/// * `rename_genre` - The arguments for the rename
+pub fn handle_rename_genre(rename_genre: RenameGenre) {
+    if !session::require_role(Role::Moderator, &t!("role.rename_genres")) {
+        return;
+    }
+
+    let mut genres: Vec<String> = storage::load(Store::Genres);
+
+    let old = match find_genre(&genres, &rename_genre.old) {
+        Some(genre) => genre.clone(),
+        None => {
+            error!("{}", t!("genre.not_found", name = rename_genre.old));
+            return;
+        }
+    };
+    if let Err(e) = validate_genre(&rename_genre.new) {
+        error!("{}", e);
+        return;
+    }
+    let new = formatting::normalize(rename_genre.new.trim());
+    if new == old {
+        warn!("{}", t!("genre.same_name", genre = old));
+        return;
+    }
+    if let Some(other) = find_genre(&genres, &new).filter(|other| **other != old) {
+        error!("{}", t!("genre.exists", other = other, genre = old));
+        return;
+    }
+
+    for genre in &mut genres {
+        if *genre == old {
+            *genre = new.clone();
+        }
+    }
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let changed = replace_genres(&mut videos, std::slice::from_ref(&old), &new);
+
+    storage::save(Store::Genres, &genres);
+    if changed > 0 {
+        storage::save(Store::Videos, &videos);
+    }
+    replace_interests(std::slice::from_ref(&old), Some(&new));
+
+    info!(
+        "{}",
+        t!("genre.renamed", old = old, new = new, count = changed)
+    );
+}
+
+#[derive(Debug, Args)]
+pub struct MergeGenres {
+    /// The names of the genres to merge
+    #[arg(required = true)]
+    pub names: Vec<String>,
+    /// The genre to merge them into, which is defined if it doesn't exist yet
+    #[arg(long)]
+    pub into: String,
+}
+
+/// Handles merging genres into one, replacing them on every video and deleting them
+///
+/// # Arguments
+///
+/// * `merge_genres` - The arguments for the merge
+pub fn handle_merge_genres(merge_genres: MergeGenres) {
+    if !session::require_role(Role::Moderator, &t!("role.merge_genres")) {
+        return;
+    }
+
+    let mut genres: Vec<String> = storage::load(Store::Genres);
+
+    let into = match find_genre(&genres, &merge_genres.into) {
+        Some(genre) => genre.clone(),
+        None => {
+            if let Err(e) = validate_genre(&merge_genres.into) {
+                error!("{}", e);
+                return;
+            }
+            formatting::normalize(merge_genres.into.trim())
+        }
+    };
+
+    let mut merged = vec![];
+    for name in &merge_genres.names {
+        let genre = match find_genre(&genres, name) {
+            Some(genre) => genre.clone(),
+            None => {
+                error!("{}", t!("genre.not_found", name = name));
+                return;
+            }
+        };
+        if genre != into && !merged.contains(&genre) {
+            merged.push(genre);
+        }
+    }
+    if merged.is_empty() {
+        warn!("{}", t!("genre.nothing_to_merge", genre = into));
+        return;
+    }
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let used_by = videos
+        .iter()
+        .filter(|video| video.genres.iter().any(|genre| merged.contains(genre)))
+        .count();
+
+    if !utilities::confirm(
+        &t!(
+            "genre.confirm_merge",
+            genres = merged.join(", "),
+            genre = into
+        ),
+        Some(&t!("genre.videos_changed", count = used_by)),
+        Some(&t!("genre.merge_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return;
+    }
+
+    genres.retain(|genre| !merged.contains(genre));
+    if !genres.contains(&into) {
+        info!("{}", t!("genre.defining", name = into));
+        genres.push(into.clone());
+    }
+    let changed = replace_genres(&mut videos, &merged, &into);
+
+    storage::save(Store::Genres, &genres);
+    if changed > 0 {
+        storage::save(Store::Videos, &videos);
+    }
+    replace_interests(&merged, Some(&into));
+
+    info!(
+        "{}",
+        t!(
+            "genre.merged",
+            count = merged.len(),
+            genre = into,
+            videos = changed
+        )
+    );
+}
+
+#[derive(Debug, Args)]
+pub struct SuggestGenres {
+    /// The start of the genre names to suggest. Every genre is suggested if not given
+    #[arg(default_value = "")]
+    pub prefix: String,
+}
+
+/// Handles printing the genres starting with a prefix, one per line and most used first, for shell
+/// completion scripts to read
+///
+/// # Arguments
+///
+/// * `suggest_genres` - The arguments for the suggestions
+pub fn handle_suggest_genres(suggest_genres: SuggestGenres) {
+    let genres: Vec<String> = storage::load(Store::Genres);
+    let videos: Vec<Video> = storage::load(Store::Videos);
+
+    for genre in genres_with_prefix(&genres, &videos, &suggest_genres.prefix) {
+        println!("{}", genre);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(genres: &[&str]) -> Video {
+        Video {
+            genres: genres.iter().map(|genre| genre.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replace_genres() {
+        let mut videos = vec![
+            video(&["Sci-Fi", "Drama"]),
+            video(&["SF", "Science Fiction"]),
+            video(&["Comedy"]),
+        ];
+        let from = ["SF".to_string(), "Science Fiction".to_string()];
+        assert_eq!(replace_genres(&mut videos, &from, "Sci-Fi"), 1);
+        assert_eq!(videos[1].genres, ["Sci-Fi"]);
+        assert_eq!(videos[0].genres, ["Sci-Fi", "Drama"]);
+
+        let genres: Vec<String> = ["Drama", "documentary", "Sci-Fi", "Dance"]
+            .iter()
+            .map(|genre| genre.to_string())
+            .collect();
+        assert_eq!(
+            genres_with_prefix(&genres, &videos, "d"),
+            ["Drama", "Dance", "documentary"]
+        );
+        assert_eq!(genres_with_prefix(&genres, &videos, "S").len(), 1);
+    }
+}
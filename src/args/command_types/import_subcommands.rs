@@ -0,0 +1,350 @@
+use super::user_subcommands::{self, Role, Subscription, User};
+use super::video_subcommands::{self, Video};
+use super::view_subcommands::WatchEntry;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use clap::Args;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// What Takeout puts before the title of every watched video
+const WATCHED_PREFIX: &str = "Watched ";
+
+#[derive(Debug, Args)]
+pub struct YoutubeTakeout {
+    /// The directory the Takeout archive was extracted to
+    pub dir: PathBuf,
+    /// The ID of the user whose Takeout it is, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Only show what would be imported, without changing anything
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+}
+
+/// An entry of `watch-history.json`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TakeoutEntry {
+    title: String,
+    /// Missing for videos that have since been removed
+    #[serde(default)]
+    title_url: Option<String>,
+    /// The channel of the video, if it still exists
+    #[serde(default)]
+    subtitles: Vec<TakeoutChannel>,
+    time: String,
+    /// Only given for ads, e.g. `From Google Ads`
+    #[serde(default)]
+    details: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TakeoutChannel {
+    name: String,
+}
+
+/// A row of `subscriptions.csv`
+#[derive(Debug, Deserialize)]
+struct TakeoutSubscription {
+    #[serde(rename = "Channel Title")]
+    title: String,
+}
+
+/// A video watched according to a Takeout archive
+///
+/// # Fields
+///
+/// * `title` - The title of the video
+/// * `channel` - The name of the channel of the video, if it still exists
+/// * `at` - When the video was watched, in seconds since the Unix epoch
+#[derive(Debug, PartialEq)]
+struct Watch {
+    title: String,
+    channel: Option<String>,
+    at: u64,
+}
+
+/// Reads the watch history of a Takeout archive, leaving out ads and videos that have since been
+/// removed
+///
+/// # Arguments
+///
+/// * `json` - The contents of `watch-history.json`
+fn parse_watch_history(json: &[u8]) -> Result<Vec<Watch>, String> {
+    let entries: Vec<TakeoutEntry> = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    let mut watches = vec![];
+    for entry in entries {
+        if entry.title_url.is_none() || !entry.details.is_empty() {
+            debug!("Skipping {}", entry.title);
+            continue;
+        }
+        let at = humantime::parse_rfc3339_weak(&entry.time)
+            .map_err(|e| t!("import.invalid_time", time = entry.time, error = e))?;
+        watches.push(Watch {
+            title: entry
+                .title
+                .strip_prefix(WATCHED_PREFIX)
+                .unwrap_or(&entry.title)
+                .to_string(),
+            channel: entry
+                .subtitles
+                .into_iter()
+                .next()
+                .map(|channel| channel.name),
+            at: at.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        });
+    }
+    Ok(watches)
+}
+
+/// Finds a file anywhere within a directory, as Takeout nests its files in localized directories
+///
+/// # Arguments
+///
+/// * `dir` - The directory to search
+/// * `name` - The name of the file
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    if let Some(file) = entries
+        .iter()
+        .find(|path| path.is_file() && path.file_name().is_some_and(|file| file == name))
+    {
+        return Some(file.clone());
+    }
+    entries
+        .iter()
+        .filter(|path| path.is_dir())
+        .find_map(|path| find_file(path, name))
+}
+
+/// Finds the user standing for a channel by name, creating one if there is none. Channels become
+/// users without an email address, which can be set later with `user update`
+///
+/// # Arguments
+///
+/// * `users` - Every user, which the new user is added to
+/// * `name` - The name of the channel
+/// * `created` - Counts the users created
+///
+/// # Returns
+///
+/// The ID of the user
+fn channel_user(users: &mut Vec<User>, name: &str, created: &mut usize) -> u32 {
+    if let Some(user) = users.iter().find(|user| user.name == name) {
+        return user.id;
+    }
+    let user = User {
+        id: user_subcommands::generate_valid_id(users),
+        name: name.to_string(),
+        handle: Some(user_subcommands::suggest_handle(users, name)),
+        ..Default::default()
+    };
+    let id = user.id;
+    users.push(user);
+    *created += 1;
+    id
+}
+
+/// Handles importing the watch history and subscriptions of a YouTube Takeout archive
+///
+/// Channels become users, watched videos become videos owned by them, and every watch becomes a
+/// view and a watch history entry of the given user. Watches already imported are skipped, so the
+/// same archive can be imported again after a newer one.
+///
+/// # Arguments
+///
+/// * `takeout` - The arguments for the import
+pub fn handle_import_youtube_takeout(takeout: YoutubeTakeout) {
+    if !session::require_role(Role::Admin, &t!("role.import_watch_history")) {
+        return;
+    }
+
+    let Some(user_id) = takeout.user.or_else(session::current_user) else {
+        error!("{}", t!("import.no_user"));
+        return;
+    };
+
+    let mut users: Vec<User> = storage::load(Store::Users);
+    if !users.iter().any(|user| user.id == user_id) {
+        error!("{}", t!("user.not_found_id", id = user_id));
+        return;
+    }
+
+    let history_file = find_file(&takeout.dir, "watch-history.json");
+    let subscriptions_file = find_file(&takeout.dir, "subscriptions.csv");
+    if history_file.is_none() && find_file(&takeout.dir, "watch-history.html").is_some() {
+        warn!("{}", t!("import.html_history"));
+    }
+    if history_file.is_none() && subscriptions_file.is_none() {
+        error!(
+            "{}",
+            t!("import.nothing_found", path = takeout.dir.display())
+        );
+        return;
+    }
+
+    let watches = match &history_file {
+        Some(file) => match fs::read(file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| parse_watch_history(&json))
+        {
+            Ok(watches) => watches,
+            Err(e) => {
+                error!(
+                    "{}",
+                    t!("import.could_not_read", path = file.display(), error = e)
+                );
+                return;
+            }
+        },
+        None => vec![],
+    };
+    let channels: Vec<TakeoutSubscription> = match &subscriptions_file {
+        Some(file) => match csv::Reader::from_path(file).and_then(|mut reader| {
+            reader
+                .deserialize()
+                .collect::<Result<Vec<TakeoutSubscription>, csv::Error>>()
+        }) {
+            Ok(channels) => channels,
+            Err(e) => {
+                error!(
+                    "{}",
+                    t!("import.could_not_read", path = file.display(), error = e)
+                );
+                return;
+            }
+        },
+        None => vec![],
+    };
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let mut history: Vec<WatchEntry> = storage::load(Store::History);
+    let mut subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+    let mut created_users = 0;
+    let mut created_videos = 0;
+    let mut views = 0;
+    let mut subscribed = 0;
+
+    for watch in watches {
+        let owner = watch
+            .channel
+            .as_deref()
+            .map(|channel| channel_user(&mut users, channel, &mut created_users));
+        let index = match videos
+            .iter()
+            .position(|video| video.name == watch.title && video.owner == owner)
+        {
+            Some(index) => index,
+            None => {
+                videos.push(Video {
+                    id: video_subcommands::generate_valid_id(&videos),
+                    name: watch.title.clone(),
+                    owner,
+                    ..Default::default()
+                });
+                created_videos += 1;
+                videos.len() - 1
+            }
+        };
+
+        let entry = WatchEntry {
+            video: videos[index].id,
+            user: Some(user_id),
+            started_at: watch.at,
+            seconds: 0,
+            finished: true,
+        };
+        if history.contains(&entry) {
+            debug!(
+                "Skipping {} watched at {}, already imported",
+                watch.title, watch.at
+            );
+            continue;
+        }
+        history.push(entry);
+        videos[index].add_views(1);
+        views += 1;
+    }
+
+    for channel in channels {
+        let subscription = Subscription {
+            user: user_id,
+            channel: channel_user(&mut users, &channel.title, &mut created_users),
+            created_at: None,
+        };
+        if !subscriptions.contains(&subscription) {
+            subscriptions.push(subscription);
+            subscribed += 1;
+        }
+    }
+
+    let summary = t!(
+        "import.summary",
+        channels = created_users,
+        videos = created_videos,
+        views = views,
+        subscriptions = subscribed
+    );
+    if takeout.dry_run {
+        println!("{}", t!("import.would_import", summary = summary));
+        return;
+    }
+
+    storage::save(Store::Users, &users);
+    storage::save(Store::Videos, &videos);
+    storage::save(Store::History, &history);
+    storage::save(Store::Subscriptions, &subscriptions);
+
+    info!("{}", t!("import.imported", summary = summary));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watch_history() {
+        let json = br#"[
+            {
+                "header": "YouTube",
+                "title": "Watched Rust in 100 Seconds",
+                "titleUrl": "https://www.youtube.com/watch?v=5C_HPTJg5ek",
+                "subtitles": [{ "name": "Fireship", "url": "https://www.youtube.com/channel/x" }],
+                "time": "2024-03-01T12:00:00.512Z",
+                "products": ["YouTube"]
+            },
+            {
+                "header": "YouTube",
+                "title": "Watched a video that has been removed",
+                "time": "2024-03-01T11:00:00Z"
+            },
+            {
+                "header": "YouTube",
+                "title": "Watched Some ad",
+                "titleUrl": "https://www.youtube.com/watch?v=ad",
+                "time": "2024-03-01T10:00:00Z",
+                "details": [{ "name": "From Google Ads" }]
+            }
+        ]"#;
+        assert_eq!(
+            parse_watch_history(json),
+            Ok(vec![Watch {
+                title: "Rust in 100 Seconds".to_string(),
+                channel: Some("Fireship".to_string()),
+                at: 1709294400,
+            }])
+        );
+        assert!(
+            parse_watch_history(br#"[{ "title": "x", "titleUrl": "y", "time": "z" }]"#).is_err()
+        );
+    }
+}
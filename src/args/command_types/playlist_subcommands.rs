@@ -0,0 +1,460 @@
+use super::user_subcommands::{Role, User};
+use super::video_subcommands::Video;
+use crate::formatting::{self, Table};
+use crate::metadata;
+use crate::notifications::{self, NotificationKind};
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities;
+use crate::validation::Violations;
+use clap::Args;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// A list of videos put together by a user, which other users can follow once it is shared
+///
+/// # Fields
+///
+/// * `id` - The ID of the playlist
+/// * `name` - The name of the playlist
+/// * `owner` - The ID of the user who made the playlist, the only one who may change it
+/// * `videos` - The IDs of the videos in the playlist, in order
+/// * `shared` - Whether other users may see and follow the playlist
+/// * `followers` - The IDs of the users following the playlist, who are notified when videos are
+///   added to it
+/// * `created_at` - When the playlist was created, in seconds since the Unix epoch
+/// * `updated_at` - When the playlist last changed, in seconds since the Unix epoch
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct Playlist {
+    pub id: u32,
+    pub name: String,
+    pub owner: u32,
+    #[serde(default)]
+    pub videos: Vec<u32>,
+    #[serde(default)]
+    pub shared: bool,
+    #[serde(default)]
+    pub followers: Vec<u32>,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+}
+
+/// Finds the playlist with the given ID, reporting an error if there is none
+fn find(playlists: &[Playlist], id: u32) -> Option<usize> {
+    let index = playlists.iter().position(|playlist| playlist.id == id);
+    if index.is_none() {
+        error!("{}", t!("playlist.not_found", id = id));
+    }
+    index
+}
+
+/// Checks that the current user may change a playlist: its owner, or a moderator
+fn may_change(playlist: &Playlist) -> bool {
+    session::require_self_or_role(
+        playlist.owner,
+        Role::Moderator,
+        &t!("role.change_playlists_of_other_users"),
+    )
+}
+
+#[derive(Debug, Args)]
+pub struct CreatePlaylist {
+    /// The name of the playlist
+    pub name: String,
+    /// The ID of the user the playlist belongs to, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Let other users see and follow the playlist right away
+    #[arg(long, default_value_t = false)]
+    pub shared: bool,
+}
+
+/// Handles the creation of a new, empty playlist
+///
+/// # Arguments
+///
+/// * `create_playlist` - The arguments for the playlist creation
+pub fn handle_create_playlist(create_playlist: CreatePlaylist) {
+    let Some(owner) = create_playlist.user.or_else(session::current_user) else {
+        error!("{}", t!("user.none_given"));
+        return;
+    };
+    if !session::require_self_or_role(
+        owner,
+        Role::Moderator,
+        &t!("role.create_playlists_for_other_users"),
+    ) {
+        return;
+    }
+    let users: Vec<User> = storage::load(Store::Users);
+    if !users.iter().any(|user| user.id == owner) {
+        error!("{}", t!("user.not_found_id", id = owner));
+        return;
+    }
+    if !Violations::new()
+        .name(&t!("field.name"), &create_playlist.name)
+        .report(&t!("playlist.not_created"))
+    {
+        return;
+    }
+
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let id = metadata::generate_id(Store::Playlists, |id| {
+        playlists.iter().any(|playlist| playlist.id == id)
+    });
+    playlists.push(Playlist {
+        id,
+        name: formatting::normalize(&create_playlist.name),
+        owner,
+        shared: create_playlist.shared,
+        ..Default::default()
+    });
+    storage::save(Store::Playlists, &playlists);
+
+    info!("{}", t!("playlist.created"));
+    println!("ID: {}", id);
+}
+
+#[derive(Debug, Args)]
+pub struct PlaylistVideo {
+    /// The ID of the playlist
+    #[arg(long)]
+    pub id: u32,
+    /// The ID of the video
+    #[arg(long)]
+    pub video: u32,
+}
+
+/// Handles adding a video to the end of a playlist, notifying its followers
+///
+/// # Arguments
+///
+/// * `playlist_video` - The playlist and the video to add
+pub fn handle_add_to_playlist(playlist_video: PlaylistVideo) {
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let Some(index) = find(&playlists, playlist_video.id) else {
+        return;
+    };
+    if !may_change(&playlists[index]) {
+        return;
+    }
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    let Some(video) = videos.iter().find(|video| video.id == playlist_video.video) else {
+        error!("{}", t!("video.not_found_id", id = playlist_video.video));
+        return;
+    };
+
+    let playlist = &mut playlists[index];
+    if playlist.videos.contains(&video.id) {
+        warn!(
+            "{}",
+            t!(
+                "playlist.already_in",
+                video = video.name,
+                playlist = playlist.name
+            )
+        );
+        return;
+    }
+    playlist.videos.push(video.id);
+    let playlist = playlist.clone();
+    storage::save(Store::Playlists, &playlists);
+
+    if playlist.shared && !playlist.followers.is_empty() {
+        notifications::notify(
+            NotificationKind::Playlist,
+            &playlist.followers,
+            &format!("{} was added to {}", video.name, playlist.name),
+            Some(video.id),
+        );
+    }
+    info!(
+        "{}",
+        t!(
+            "playlist.added",
+            video = video.name,
+            playlist = playlist.name
+        )
+    );
+}
+
+/// Handles removing a video from a playlist
+///
+/// # Arguments
+///
+/// * `playlist_video` - The playlist and the video to remove
+pub fn handle_remove_from_playlist(playlist_video: PlaylistVideo) {
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let Some(index) = find(&playlists, playlist_video.id) else {
+        return;
+    };
+    if !may_change(&playlists[index]) {
+        return;
+    }
+
+    let playlist = &mut playlists[index];
+    let before = playlist.videos.len();
+    playlist
+        .videos
+        .retain(|video| *video != playlist_video.video);
+    if playlist.videos.len() == before {
+        warn!(
+            "{}",
+            t!(
+                "playlist.not_in",
+                id = playlist_video.video,
+                playlist = playlist.name
+            )
+        );
+        return;
+    }
+    let name = playlist.name.clone();
+    storage::save(Store::Playlists, &playlists);
+    info!(
+        "{}",
+        t!(
+            "playlist.removed",
+            id = playlist_video.video,
+            playlist = name
+        )
+    );
+}
+
+#[derive(Debug, Args)]
+pub struct SharePlaylist {
+    /// The ID of the playlist
+    #[arg(long)]
+    pub id: u32,
+    /// Stop sharing the playlist instead. Its followers keep following it, but are no longer
+    /// notified until it is shared again
+    #[arg(long, default_value_t = false)]
+    pub undo: bool,
+}
+
+/// Handles marking a playlist shared, so other users can see and follow it, or no longer shared
+///
+/// # Arguments
+///
+/// * `share_playlist` - The arguments for sharing the playlist
+pub fn handle_share_playlist(share_playlist: SharePlaylist) {
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let Some(index) = find(&playlists, share_playlist.id) else {
+        return;
+    };
+    if !may_change(&playlists[index]) {
+        return;
+    }
+
+    let playlist = &mut playlists[index];
+    let shared = !share_playlist.undo;
+    if playlist.shared == shared {
+        match shared {
+            true => warn!("{}", t!("playlist.already_shared", name = playlist.name)),
+            false => warn!("{}", t!("playlist.already_unshared", name = playlist.name)),
+        }
+        return;
+    }
+    playlist.shared = shared;
+    let name = playlist.name.clone();
+    storage::save(Store::Playlists, &playlists);
+    match shared {
+        true => info!("{}", t!("playlist.shared", name = name)),
+        false => info!("{}", t!("playlist.unshared", name = name)),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct FollowPlaylist {
+    /// The ID of the playlist
+    #[arg(long)]
+    pub id: u32,
+    /// The ID of the user following the playlist, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Stop following the playlist instead
+    #[arg(long, default_value_t = false)]
+    pub undo: bool,
+}
+
+/// Handles following a shared playlist, so the user is notified when videos are added to it, or
+/// no longer following it
+///
+/// # Arguments
+///
+/// * `follow_playlist` - The arguments for following the playlist
+pub fn handle_follow_playlist(follow_playlist: FollowPlaylist) {
+    let Some(user) = follow_playlist.user.or_else(session::current_user) else {
+        error!("{}", t!("user.none_given"));
+        return;
+    };
+    if !session::require_self_or_role(
+        user,
+        Role::Moderator,
+        &t!("role.follow_playlists_for_other_users"),
+    ) {
+        return;
+    }
+    let users: Vec<User> = storage::load(Store::Users);
+    if !users.iter().any(|other| other.id == user) {
+        error!("{}", t!("user.not_found_id", id = user));
+        return;
+    }
+
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let Some(index) = find(&playlists, follow_playlist.id) else {
+        return;
+    };
+    let playlist = &mut playlists[index];
+    let following = playlist.followers.contains(&user);
+    match (follow_playlist.undo, following) {
+        (false, _) if playlist.owner == user => {
+            warn!("{}", t!("playlist.own", name = playlist.name));
+            return;
+        }
+        (false, _) if !playlist.shared => {
+            error!("{}", t!("playlist.not_shared", name = playlist.name));
+            return;
+        }
+        (false, true) => {
+            warn!("{}", t!("playlist.already_following", name = playlist.name));
+            return;
+        }
+        (true, false) => {
+            warn!("{}", t!("playlist.not_following", name = playlist.name));
+            return;
+        }
+        (false, false) => playlist.followers.push(user),
+        (true, true) => playlist.followers.retain(|follower| *follower != user),
+    }
+    let name = playlist.name.clone();
+    storage::save(Store::Playlists, &playlists);
+    match follow_playlist.undo {
+        false => info!("{}", t!("playlist.following", name = name)),
+        true => info!("{}", t!("playlist.unfollowed", name = name)),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ListPlaylists {
+    /// Only list the playlists of the user with this ID, and those they follow. Every playlist
+    /// shown to the logged in user is listed if not given
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Print the videos in each playlist
+    #[arg(long, default_value_t = false)]
+    pub with_videos: bool,
+}
+
+/// Lists the playlists a user may see: their own and those shared, with how many users follow
+/// each
+///
+/// # Arguments
+///
+/// * `list_playlists` - The arguments for the listing
+pub fn handle_list_playlists(list_playlists: ListPlaylists) {
+    let playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let viewer = session::current_user_id();
+    let listed: Vec<&Playlist> = playlists
+        .iter()
+        .filter(|playlist| playlist.shared || Some(playlist.owner) == viewer)
+        .filter(|playlist| {
+            list_playlists
+                .user
+                .is_none_or(|user| playlist.owner == user || playlist.followers.contains(&user))
+        })
+        .collect();
+    if listed.is_empty() {
+        warn!("{}", t!("playlist.none"));
+        return;
+    }
+
+    let users: Vec<User> = storage::load(Store::Users);
+    let owner = |id: u32| {
+        users
+            .iter()
+            .find(|user| user.id == id)
+            .map_or(id.to_string(), |user| user.name.clone())
+    };
+    if !list_playlists.with_videos {
+        let mut table = Table::new(
+            ["id", "name", "owner", "videos", "followers", "shared"]
+                .map(str::to_string)
+                .to_vec(),
+        );
+        for playlist in listed {
+            table.row(vec![
+                playlist.id.to_string(),
+                playlist.name.clone(),
+                owner(playlist.owner),
+                playlist.videos.len().to_string(),
+                playlist.followers.len().to_string(),
+                playlist.shared.to_string(),
+            ]);
+        }
+        table.print();
+        return;
+    }
+
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    for playlist in listed {
+        println!(
+            "{}",
+            t!(
+                "playlist.heading",
+                name = playlist.name,
+                id = playlist.id,
+                owner = owner(playlist.owner),
+                followers = playlist.followers.len()
+            )
+        );
+        for (position, id) in playlist.videos.iter().enumerate() {
+            let name = videos
+                .iter()
+                .find(|video| video.id == *id)
+                .map_or(t!("playlist.deleted_video"), |video| video.name.clone());
+            println!("  {}. {} ({})", position + 1, name, id);
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DeletePlaylist {
+    /// The ID of the playlist to delete
+    #[arg(long)]
+    pub id: u32,
+}
+
+/// Handles deleting a playlist. The videos in it are kept
+///
+/// # Arguments
+///
+/// * `delete_playlist` - The arguments for the playlist deletion
+pub fn handle_delete_playlist(delete_playlist: DeletePlaylist) {
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+    let Some(index) = find(&playlists, delete_playlist.id) else {
+        return;
+    };
+    if !may_change(&playlists[index]) {
+        return;
+    }
+
+    let followers = playlists[index].followers.len();
+    if !utilities::confirm(
+        &t!("playlist.confirm_delete", name = playlists[index].name),
+        (followers > 0)
+            .then(|| t!("playlist.followed_by", count = followers))
+            .as_deref(),
+        Some(&t!("playlist.delete_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return;
+    }
+
+    let playlist = playlists.remove(index);
+    storage::save(Store::Playlists, &playlists);
+    info!("{}", t!("playlist.deleted", name = playlist.name));
+}
@@ -0,0 +1,236 @@
+use super::user_subcommands::Role;
+use super::video_subcommands::Video;
+use crate::formatting;
+use crate::integrity::{OnDelete, Policy, References, Target};
+use crate::metadata;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities::{self, ConfirmCount};
+use crate::validation::Violations;
+use clap::Args;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// A series whose episodes are videos
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct Series {
+    pub id: u32,
+    pub name: String,
+    /// When the series was created, in seconds since the Unix epoch
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    /// When the series last changed, in seconds since the Unix epoch
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+}
+
+/// Checks that a video can be marked as the given episode
+///
+/// # Arguments
+///
+/// * `videos` - Every video
+/// * `video_id` - The ID of the video being marked, which may already be the episode
+/// * `series_id` - The ID of the series
+/// * `season` - The season number
+/// * `episode` - The episode number within the season
+///
+/// # Returns
+///
+/// `false` after reporting an error if the series doesn't exist or another video is already the
+/// episode
+pub fn validate_episode(
+    videos: &[Video],
+    video_id: u32,
+    series_id: u32,
+    season: u32,
+    episode: u32,
+) -> bool {
+    let series: Vec<Series> = storage::load(Store::Series);
+    if !series.iter().any(|series| series.id == series_id) {
+        error!("{}", t!("series.not_found_id", id = series_id));
+        return false;
+    }
+
+    if let Some(other) = videos.iter().find(|video| {
+        video.id != video_id
+            && video.series == Some(series_id)
+            && video.season == Some(season)
+            && video.episode == Some(episode)
+    }) {
+        error!(
+            "{}",
+            t!(
+                "series.episode_taken",
+                name = other.name,
+                episode = episode,
+                season = season
+            )
+        );
+        return false;
+    }
+
+    true
+}
+
+#[derive(Debug, Args)]
+pub struct CreateSeries {
+    /// The name of the series
+    pub name: String,
+}
+
+/// Handles the creation of a new series
+///
+/// # Arguments
+///
+/// * `create_series` - The arguments for the series creation
+pub fn handle_create_series(create_series: CreateSeries) {
+    if !session::require_role(Role::Moderator, &t!("role.create_series")) {
+        return;
+    }
+
+    let mut series: Vec<Series> = storage::load(Store::Series);
+
+    if !Violations::new()
+        .name(&t!("field.name"), &create_series.name)
+        .report(&t!("series.not_created"))
+    {
+        return;
+    }
+
+    let id = metadata::generate_id(Store::Series, |id| {
+        series.iter().any(|series| series.id == id)
+    });
+
+    series.push(Series {
+        id,
+        name: formatting::normalize(&create_series.name),
+        ..Default::default()
+    });
+
+    storage::save(Store::Series, &series);
+
+    info!("{}", t!("series.created"));
+    println!("ID: {}", id);
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteSeries {
+    /// The ID of the series to delete
+    #[arg(long)]
+    pub id: u32,
+    #[command(flatten)]
+    pub on_delete: OnDelete,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
+
+/// Handles deleting a series. It is only deleted while it has no episodes, unless `--cascade`
+/// deletes them too or `--orphan` keeps them as standalone videos
+///
+/// # Arguments
+///
+/// * `delete_series` - The arguments for the series deletion
+pub fn handle_delete_series(delete_series: DeleteSeries) {
+    if !session::require_role(Role::Admin, &t!("role.delete_series")) {
+        return;
+    }
+
+    let mut series: Vec<Series> = storage::load(Store::Series);
+
+    let series_index = match series
+        .iter()
+        .position(|series| series.id == delete_series.id)
+    {
+        Some(series_index) => series_index,
+        None => {
+            error!("{}", t!("series.not_found_id", id = delete_series.id));
+            return;
+        }
+    };
+
+    let policy = delete_series.on_delete.policy();
+    let references = References::find(Target::Series(delete_series.id));
+    if policy == Policy::Refuse && !references.is_empty() {
+        error!(
+            "{}",
+            t!(
+                "series.referred_to",
+                name = series[series_index].name,
+                references = references.describe()
+            )
+        );
+        return;
+    }
+
+    if !utilities::confirm_bulk(
+        &t!("series.confirm_delete", name = series[series_index].name),
+        Some(references.summary(policy).as_str()),
+        Some(&t!("series.delete_cancelled")),
+        Some(true),
+        references.affected(policy),
+        &delete_series.confirm_count,
+    ) {
+        return;
+    }
+
+    series.remove(series_index);
+
+    storage::save(Store::Series, &series);
+    references.resolve(policy);
+
+    info!("{}", t!("series.deleted"));
+}
+
+#[derive(Debug, Args)]
+pub struct ListSeries {
+    /// Print the episodes of each series by season
+    #[arg(long, default_value_t = false)]
+    pub with_episodes: bool,
+}
+
+/// Lists every series, optionally as a tree of seasons and episodes
+///
+/// # Arguments
+///
+/// * `list_series` - The arguments for the listing
+pub fn handle_list_series(list_series: ListSeries) {
+    let series: Vec<Series> = storage::load(Store::Series);
+
+    if series.is_empty() {
+        warn!("{}", t!("series.none"));
+        return;
+    }
+
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    for series in series {
+        let mut episodes: Vec<&Video> = videos
+            .iter()
+            .filter(|video| video.series == Some(series.id))
+            .collect();
+
+        if !list_series.with_episodes {
+            println!("{}\t{}\t{}", series.id, series.name, episodes.len());
+            continue;
+        }
+
+        println!("{} ({})", series.name, series.id);
+        episodes.sort_by_key(|video| (video.season, video.episode));
+        let mut season = None;
+        for video in episodes {
+            if video.season != season {
+                season = video.season;
+                println!(
+                    "  {}",
+                    t!("series.season", season = season.unwrap_or_default())
+                );
+            }
+            println!(
+                "    {}. {} ({})",
+                video.episode.unwrap_or_default(),
+                video.name,
+                video.id
+            );
+        }
+    }
+}
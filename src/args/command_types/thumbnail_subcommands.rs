@@ -0,0 +1,148 @@
+use super::user_subcommands::Role;
+use super::video_subcommands::{self, parse_duration, select_video, Video, VideoQuery};
+use crate::dry_run;
+use crate::media;
+use crate::repository::Repository;
+use crate::session;
+use crate::storage;
+use crate::t;
+use clap::Args;
+use log::{error, info};
+use std::fs;
+use std::path::PathBuf;
+
+/// The directory generated thumbnails are kept in
+pub fn thumbnails_dir() -> PathBuf {
+    storage::data_dir().join("thumbnails")
+}
+
+/// Finds the single video matching the given query
+///
+/// # Returns
+///
+/// A copy of the video, or `None` after reporting an error if no single video was chosen
+fn find_single_video(videos: &Repository<Video>, query: &VideoQuery) -> Option<Video> {
+    if query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return None;
+    }
+
+    select_video(videos, query, &t!("action.update")).cloned()
+}
+
+#[derive(Debug, Args)]
+pub struct SetThumbnail {
+    /// The image to use as the thumbnail
+    pub image: PathBuf,
+    #[command(flatten)]
+    pub query: VideoQuery,
+}
+
+/// Handles using an existing image as the thumbnail of a video
+///
+/// # Arguments
+///
+/// * `set_thumbnail` - The arguments for setting the thumbnail
+pub fn handle_set_thumbnail(set_thumbnail: SetThumbnail) {
+    if !session::require_role(Role::Moderator, &t!("role.update_videos")) {
+        return;
+    }
+
+    let image = match video_subcommands::resolve_file(&set_thumbnail.image) {
+        Some(image) => image,
+        None => return,
+    };
+
+    let mut videos = Repository::<Video>::load();
+
+    let mut video = match find_single_video(&videos, &set_thumbnail.query) {
+        Some(video) => video,
+        None => return,
+    };
+
+    video.thumbnail = Some(image);
+
+    videos.replace(video);
+    videos.save();
+
+    info!("{}", t!("thumbnail.set"));
+}
+
+/// Handles printing the path of the thumbnail of a video
+///
+/// # Arguments
+///
+/// * `video_query` - The query for the video
+pub fn handle_get_thumbnail(video_query: VideoQuery) {
+    let videos = Repository::<Video>::load();
+
+    let video = match find_single_video(&videos, &video_query) {
+        Some(video) => video,
+        None => return,
+    };
+
+    match &video.thumbnail {
+        Some(thumbnail) => println!("{}", thumbnail.display()),
+        None => error!("{}", t!("thumbnail.none")),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateThumbnail {
+    #[command(flatten)]
+    pub query: VideoQuery,
+    /// How far into the video to take the frame from, as `1:30` or `90s`. Defaults to a tenth of
+    /// the duration of the video
+    #[arg(long, default_value = None, value_parser = parse_duration)]
+    pub at: Option<u32>,
+}
+
+/// Handles taking the thumbnail of a video from a frame of its media file
+///
+/// # Arguments
+///
+/// * `generate_thumbnail` - The arguments for generating the thumbnail
+pub fn handle_generate_thumbnail(generate_thumbnail: GenerateThumbnail) {
+    if !session::require_role(Role::Moderator, &t!("role.update_videos")) {
+        return;
+    }
+
+    let mut videos = Repository::<Video>::load();
+
+    let mut video = match find_single_video(&videos, &generate_thumbnail.query) {
+        Some(video) => video,
+        None => return,
+    };
+
+    let Some(file) = &video.file else {
+        error!("{}", t!("video.no_file"));
+        return;
+    };
+    let at = generate_thumbnail
+        .at
+        .unwrap_or(video.duration.unwrap_or(0) / 10);
+
+    let output = thumbnails_dir().join(format!("{}.jpg", video.id));
+    if !dry_run::skip(&format!("write {}", output.display())) {
+        fs::create_dir_all(thumbnails_dir()).unwrap();
+        if let Err(e) = media::extract_frame(file, at, &output) {
+            error!(
+                "{}",
+                t!(
+                    "thumbnail.could_not_generate",
+                    path = file.display(),
+                    error = e
+                )
+            );
+            return;
+        }
+    }
+
+    video.thumbnail = Some(output.clone());
+
+    videos.replace(video);
+    videos.save();
+
+    info!("{}", t!("thumbnail.generated"));
+    println!("{}", output.display());
+}
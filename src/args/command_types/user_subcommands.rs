@@ -1,15 +1,316 @@
-use crate::utilities;
-use clap::Args;
-use rand::Rng;
+use super::genre_subcommands;
+use super::playlist_subcommands::Playlist;
+use super::video_subcommands::{format_timestamp, Video};
+use super::view_subcommands::WatchEntry;
+use crate::batch;
+use crate::dns;
+use crate::email_filter;
+use crate::formatting;
+use crate::formatting::Table;
+use crate::integrity::{self, OnDelete, Policy, References, Target};
+use crate::listing::{self, Pagination, Search};
+use crate::metadata;
+use crate::notifications::Notification;
+use crate::query::{self, MatchMode};
+use crate::repository::{self, Record, Repository};
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::transfer::{self, Export, Import};
+use crate::utilities::{self, ConfirmCount};
+use crate::validation::Violations;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use clap::{Args, ValueEnum};
+use log::{error, info, warn};
+use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::File;
-use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
-struct User {
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct User {
     pub id: u32,
     pub name: String,
     pub email: String,
+    /// The unique, URL-safe name of the user, e.g. `deevin`. Unknown for users created before it
+    /// was recorded
+    #[serde(default)]
+    pub handle: Option<String>,
+    /// The salted argon2 hash of the password of the user, if one was set
+    #[serde(default)]
+    pub password: Option<String>,
+    /// The hash of the token of the session the user is logged in with, if any
+    #[serde(default)]
+    pub session: Option<String>,
+    /// What the user may do while logged in
+    #[serde(default)]
+    pub role: Role,
+    /// The path or URL of the picture of the user
+    #[serde(default)]
+    pub avatar: Option<String>,
+    /// What the user says about themselves
+    #[serde(default)]
+    pub bio: Option<String>,
+    /// When the user was born, as `YYYY-MM-DD`
+    #[serde(default)]
+    pub birthdate: Option<String>,
+    /// The ISO 3166 code of the country the user lives in, e.g. `NL`
+    #[serde(default)]
+    pub country: Option<String>,
+    /// Whether the account was suspended, keeping it from logging in or adding views
+    #[serde(default)]
+    pub suspended: bool,
+    /// Whether the user may watch age-restricted videos
+    #[serde(default)]
+    pub maturity: Maturity,
+    /// When the user was created, in seconds since the Unix epoch. Set when the user is first
+    /// saved
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    /// When the user last changed, in seconds since the Unix epoch. Set whenever the user is saved
+    /// changed
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+    /// How many times the user was changed, so concurrent changes don't overwrite each other
+    #[serde(default)]
+    pub revision: u32,
+    /// The IDs of the videos the user isn't interested in, which are never recommended to them
+    #[serde(default, with = "crate::transfer::comma_separated")]
+    pub dismissed: Vec<u32>,
+    /// The genres the user picked when onboarded, recommended to them as if they had watched
+    /// videos with them
+    #[serde(default, with = "crate::transfer::comma_separated")]
+    pub interests: Vec<String>,
+}
+
+/// The fields of a user never sent outside of rustflix: the hashes of their password and of the
+/// token of their login session
+const PRIVATE_FIELDS: [&str; 2] = ["password", "session"];
+
+impl User {
+    /// The user as it is sent over the API and to webhooks, leaving out its private fields
+    pub fn public(&self) -> serde_json::Value {
+        let mut user = serde_json::to_value(self).unwrap();
+        if let Some(fields) = user.as_object_mut() {
+            for field in PRIVATE_FIELDS {
+                fields.remove(field);
+            }
+        }
+        user
+    }
+
+    /// Keeps the private fields of a user as they were, for a user given from outside of
+    /// rustflix, which has no say over them
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - The user as it was, or `None` if it is new
+    pub fn keep_private_fields(&mut self, old: Option<&User>) {
+        self.password = old.and_then(|old| old.password.clone());
+        self.session = old.and_then(|old| old.session.clone());
+    }
+}
+
+/// Serializes a user leaving out its private fields, for `#[serde(serialize_with)]`
+pub fn serialize_public<S: serde::Serializer>(
+    user: &User,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    user.public().serialize(serializer)
+}
+
+impl Record for User {
+    const STORE: Store = Store::Users;
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn email(&self) -> Option<&str> {
+        Some(&self.email)
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(&mut self, revision: u32) {
+        self.revision = revision;
+    }
+}
+
+/// Whether a user may watch age-restricted videos
+///
+/// # Variants
+///
+/// * `Auto` - Only once they are known to be adults from their birthdate
+/// * `Adult` - Always, whatever their birthdate
+/// * `Minor` - Never, whatever their birthdate
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum Maturity {
+    #[default]
+    Auto,
+    Adult,
+    Minor,
+}
+
+/// A user following the videos of a channel, which is itself a user owning videos
+///
+/// # Fields
+///
+/// * `user` - The ID of the subscribed user
+/// * `channel` - The ID of the user subscribed to
+/// * `created_at` - When the subscription was made, in seconds since the Unix epoch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub user: u32,
+    pub channel: u32,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+/// Subscriptions are the same if the same user is subscribed to the same channel, whenever
+impl PartialEq for Subscription {
+    fn eq(&self, other: &Self) -> bool {
+        self.user == other.user && self.channel == other.channel
+    }
+}
+
+/// The age from which users are adults
+pub const AGE_OF_MAJORITY: u32 = 18;
+
+/// Works out how old someone is on a given day
+///
+/// # Arguments
+///
+/// * `birthdate` - When they were born, as `YYYY-MM-DD`
+/// * `today` - The day to work out their age on, as `YYYY-MM-DD`
+///
+/// # Returns
+///
+/// Their age in whole years, or `None` if either date can't be read
+pub fn age(birthdate: &str, today: &str) -> Option<u32> {
+    let parse = |date: &str| -> Option<(u32, u32, u32)> {
+        let mut parts = date.get(..10)?.split('-').map(|part| part.parse::<u32>());
+        Some((
+            parts.next()?.ok()?,
+            parts.next()?.ok()?,
+            parts.next()?.ok()?,
+        ))
+    };
+    let (birth_year, birth_month, birth_day) = parse(birthdate)?;
+    let (year, month, day) = parse(today)?;
+    let had_birthday = (month, day) >= (birth_month, birth_day);
+    year.checked_sub(birth_year)?
+        .checked_sub(if had_birthday { 0 } else { 1 })
+}
+
+impl User {
+    /// Determines if the user may not watch age-restricted videos, following their maturity
+    /// setting or else their birthdate. Users without a birthdate count as adults
+    pub fn is_minor(&self) -> bool {
+        match self.maturity {
+            Maturity::Adult => false,
+            Maturity::Minor => true,
+            Maturity::Auto => {
+                let today = humantime::format_rfc3339(SystemTime::now()).to_string();
+                self.birthdate
+                    .as_ref()
+                    .and_then(|birthdate| age(birthdate, &today))
+                    .is_some_and(|age| age < AGE_OF_MAJORITY)
+            }
+        }
+    }
+}
+
+/// What a logged in user may do. Each role may do everything the roles before it may
+///
+/// Nothing is restricted while nobody is logged in and no user has a role above viewer, as whoever
+/// runs rustflix then owns the data directory anyway. Once anyone has, commands needing a role
+/// fail while logged out.
+///
+/// # Variants
+///
+/// * `Viewer` - May only add views and change their own account
+/// * `Moderator` - May also create and change users, videos, series, and genres, and suspend
+///   users
+/// * `Admin` - May also delete, import, merge, and erase users and videos, delete series, set
+///   view counts and passwords, and give users roles
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize, ValueEnum,
+)]
+pub enum Role {
+    #[default]
+    Viewer,
+    Moderator,
+    Admin,
+}
+
+// Written by hand so password and session hashes are never printed and profile details that were
+// never given are left out
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("User");
+        debug.field("id", &self.id).field("name", &self.name);
+        if let Some(handle) = &self.handle {
+            debug.field("handle", handle);
+        }
+        debug.field("email", &self.email).field("role", &self.role);
+        if self.suspended {
+            debug.field("suspended", &self.suspended);
+        }
+        if self.maturity != Maturity::Auto {
+            debug.field("maturity", &self.maturity);
+        }
+        if let Some(country) = &self.country {
+            debug.field("country", country);
+        }
+        debug.finish()
+    }
+}
+
+/// The fields of a user that can be printed on their own
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum UserField {
+    Id,
+    Name,
+    Handle,
+    Email,
+    Role,
+    Avatar,
+    Bio,
+    Birthdate,
+    Country,
+    Suspended,
+    Maturity,
+}
+
+impl User {
+    /// Formats the given field of the user for printing
+    fn field(&self, field: UserField) -> String {
+        match field {
+            UserField::Id => self.id.to_string(),
+            UserField::Name => self.name.clone(),
+            UserField::Handle => self.handle.clone().unwrap_or_default(),
+            UserField::Email => self.email.clone(),
+            UserField::Role => listing::field_names(&[self.role]).concat(),
+            UserField::Avatar => self.avatar.clone().unwrap_or_default(),
+            UserField::Bio => self.bio.clone().unwrap_or_default(),
+            UserField::Birthdate => self.birthdate.clone().unwrap_or_default(),
+            UserField::Country => self.country.clone().unwrap_or_default(),
+            UserField::Suspended => self.suspended.to_string(),
+            UserField::Maturity => listing::field_names(&[self.maturity]).concat(),
+        }
+    }
 }
 
 #[derive(Debug, Args)]
@@ -20,17 +321,263 @@ pub struct UserQuery {
     /// The name of the user to query
     #[arg(long, default_value = None)]
     pub name: Option<String>,
+    /// The handle of the user to query
+    #[arg(long, default_value = None)]
+    pub handle: Option<String>,
     /// The email address of the user to query
     #[arg(long, default_value = None)]
     pub email: Option<String>,
+    /// Text contained in the name of the user to query
+    #[arg(long, default_value = None)]
+    pub name_contains: Option<String>,
+    /// Text contained in the email address of the user to query
+    #[arg(long, default_value = None)]
+    pub email_contains: Option<String>,
+    /// Ignore case when matching names and email addresses
+    #[arg(long, default_value_t = false)]
+    pub ignore_case: bool,
+    /// Whether a user must match any or all of the given fields
+    #[arg(long = "match", value_enum, default_value_t = MatchMode::All)]
+    pub match_mode: MatchMode,
+}
+
+impl UserQuery {
+    /// Determines if no field to query by was given
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none()
+            && self.name.is_none()
+            && self.handle.is_none()
+            && self.email.is_none()
+            && self.name_contains.is_none()
+            && self.email_contains.is_none()
+    }
+
+    /// Determines which fields of the query match the given user
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to test against the query
+    ///
+    /// # Returns
+    ///
+    /// Every field of the query matching the user
+    fn matching_fields(&self, user: &User) -> Vec<QueryField> {
+        let mut fields = vec![];
+
+        if let Some(id) = self.id {
+            if user.id == id {
+                fields.push(QueryField::Id);
+            }
+        }
+
+        if let Some(name) = &self.name {
+            if query::text_equals(&user.name, name, self.ignore_case) {
+                fields.push(QueryField::Name);
+            }
+        }
+
+        if let Some(handle) = &self.handle {
+            if user.handle.as_ref() == Some(&normalize_handle(handle)) {
+                fields.push(QueryField::Handle);
+            }
+        }
+
+        if let Some(email) = &self.email {
+            if query::text_equals(&user.email, email, self.ignore_case) {
+                fields.push(QueryField::Email);
+            }
+        }
+
+        if let Some(name) = &self.name_contains {
+            if query::text_contains(&user.name, name, self.ignore_case) {
+                fields.push(QueryField::NameContains);
+            }
+        }
+
+        if let Some(email) = &self.email_contains {
+            if query::text_contains(&user.email, email, self.ignore_case) {
+                fields.push(QueryField::EmailContains);
+            }
+        }
+
+        fields
+    }
+
+    /// The number of fields given in the query
+    fn given_fields(&self) -> usize {
+        [
+            self.id.is_some(),
+            self.name.is_some(),
+            self.handle.is_some(),
+            self.email.is_some(),
+            self.name_contains.is_some(),
+            self.email_contains.is_some(),
+        ]
+        .iter()
+        .filter(|given| **given)
+        .count()
+    }
+
+    /// Determines if the given user matches the query as a whole, following its match mode
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to test against the query
+    fn matches(&self, user: &User) -> bool {
+        self.match_mode
+            .is_match(self.matching_fields(user).len(), self.given_fields())
+    }
+}
+
+/// The fields a user can be queried by
+enum QueryField {
+    Id,
+    Name,
+    Handle,
+    Email,
+    NameContains,
+    EmailContains,
 }
 
 #[derive(Debug, Args)]
 pub struct CreateUser {
     /// The name of the user
-    pub name: String,
+    #[arg(required_unless_present_any = ["interactive", "from_stdin"])]
+    pub name: Option<String>,
     /// The email address of the user
-    pub email: String,
+    #[arg(required_unless_present_any = ["interactive", "from_stdin"])]
+    pub email: Option<String>,
+    /// The unique, URL-safe handle of the user. Suggested from the name if not given
+    #[arg(long, default_value = None)]
+    pub handle: Option<String>,
+    /// Ask for each field in turn instead of taking them as arguments
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["name", "email"]
+    )]
+    pub interactive: bool,
+    /// Check that the domain of the email address can receive mail by looking up its MX records
+    #[arg(long, default_value_t = false)]
+    pub verify_dns: bool,
+    /// Create a user per line of stdin instead, given as JSON Lines, e.g.
+    /// `{"name": "Ada", "email": "ada@example.com"}`, or as CSV with a `name,email,handle` header
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["name", "email", "handle", "interactive"]
+    )]
+    pub from_stdin: bool,
+}
+
+/// A user to create, as read from a line of stdin
+///
+/// # Fields
+///
+/// * `name` - The name of the user
+/// * `email` - The email address of the user
+/// * `handle` - The handle of the user, suggested from the name if not given
+#[derive(Debug, Deserialize)]
+struct NewUser {
+    name: String,
+    email: String,
+    #[serde(default)]
+    handle: Option<String>,
+}
+
+/// Normalizes and validates an email address given for a user
+///
+/// # Arguments
+///
+/// * `email` - The email address as given
+/// * `verify_dns` - Whether to also check that the domain has a mail exchanger
+///
+/// # Returns
+///
+/// The normalized email address, or `None` after reporting an error if it is invalid
+fn check_email(email: &str, verify_dns: bool) -> Option<String> {
+    let email = utilities::normalize_email(email);
+    if let Err(e) = utilities::validate_email(&email) {
+        error!("{}", e);
+        return None;
+    }
+
+    if verify_dns {
+        let domain = email.rsplit_once('@').unwrap().1;
+        match dns::has_mx(domain) {
+            Ok(true) => {}
+            Ok(false) => {
+                error!("{}", t!("user.no_mx", domain = domain));
+                return None;
+            }
+            Err(e) => {
+                error!("{}", t!("user.dns_failed", domain = domain, error = e));
+                return None;
+            }
+        }
+    }
+
+    Some(email)
+}
+
+/// Turns text into a handle, lower case and with every run of characters that aren't URL-safe
+/// replaced by a single hyphen
+///
+/// # Arguments
+///
+/// * `text` - The text to turn into a handle, e.g. a name
+pub fn normalize_handle(text: &str) -> String {
+    let mut handle = String::new();
+    for c in text.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            handle.push(c);
+        } else if !handle.ends_with('-') {
+            handle.push('-');
+        }
+    }
+    handle.trim_matches('-').to_string()
+}
+
+/// Checks that a handle is URL-safe, made only of lower case letters, digits, hyphens and
+/// underscores
+pub fn validate_handle(handle: &str) -> Result<(), String> {
+    if handle.is_empty()
+        || handle.len() > 32
+        || handle.starts_with('-')
+        || !handle
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(t!("user.invalid_handle", handle = handle));
+    }
+    Ok(())
+}
+
+/// Suggests an unused handle based on the given one, adding a number to it if it is taken, e.g.
+/// `deevin2`
+///
+/// # Arguments
+///
+/// * `users` - The existing users, whose handles are taken
+/// * `base` - The handle wanted
+pub fn suggest_handle(users: &[User], base: &str) -> String {
+    let base = match normalize_handle(base) {
+        base if base.is_empty() => "user".to_string(),
+        base => base,
+    };
+    let taken = |handle: &str| {
+        users
+            .iter()
+            .any(|user| user.handle.as_deref() == Some(handle))
+    };
+    if !taken(&base) {
+        return base;
+    }
+    (2..)
+        .map(|n| format!("{}{}", base, n))
+        .find(|handle| !taken(handle))
+        .unwrap()
 }
 
 /// Determines if the list of users contains a user with the given ID
@@ -64,16 +611,16 @@ fn has_id(users: &Vec<User>, id: u32) -> bool {
 ///
 /// * `true` if a user with the given email address is found
 /// * `false` if a user with the given email address is not found
-fn has_email(users: &Vec<User>, email: &String) -> bool {
+pub fn has_email(users: &Vec<User>, email: &str) -> bool {
     for user in users {
-        if user.email == *email {
+        if user.email.eq_ignore_ascii_case(email) {
             return true;
         }
     }
     false
 }
 
-/// Generates an unused ID for a new user
+/// Generates an unused ID for a new user, following the ID strategy of the stores
 ///
 /// # Arguments
 ///
@@ -81,13 +628,8 @@ fn has_email(users: &Vec<User>, email: &String) -> bool {
 ///
 /// # Returns
 /// A valid ID that is not already in use by a user
-fn generate_valid_id(users: &Vec<User>) -> u32 {
-    let mut rng = rand::thread_rng();
-    let mut id = rng.gen_range(0..=std::u32::MAX);
-    while has_id(users, id) {
-        id = rng.gen_range(0..=std::u32::MAX);
-    }
-    id
+pub fn generate_valid_id(users: &Vec<User>) -> u32 {
+    metadata::generate_id(Store::Users, |id| has_id(users, id))
 }
 
 /// Handles the creation of a new user
@@ -96,34 +638,140 @@ fn generate_valid_id(users: &Vec<User>) -> u32 {
 ///
 /// * `create_user` - The arguments for the user creation
 pub fn handle_create_user(create_user: CreateUser) {
-    let path = concat!(env!("HOME"), "/.rustflix/users.bc");
-    let path = Path::new(path);
-    let mut users: Vec<User> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
+    if !session::require_role(Role::Moderator, &t!("role.create_users")) {
+        return;
+    }
+
+    if create_user.from_stdin {
+        let records = match transfer::read_stdin::<NewUser>() {
+            Ok(records) => records,
+            Err(e) => {
+                error!("{}", t!("transfer.stdin_failed", error = e));
+                return;
+            }
+        };
+        batch::create_each(records, &t!("transfer.users"), |user| {
+            handle_create_user(CreateUser {
+                name: Some(user.name),
+                email: Some(user.email),
+                handle: user.handle,
+                interactive: false,
+                verify_dns: create_user.verify_dns,
+                from_stdin: false,
+            })
+        });
+        return;
+    }
+
+    let mut users: Vec<User> = storage::load(Store::Users);
+
+    let (name, email) = if create_user.interactive {
+        match prompt_new_user(&users) {
+            Some(fields) => fields,
+            None => return,
+        }
     } else {
-        vec![]
+        (create_user.name.unwrap(), create_user.email.unwrap())
+    };
+    let name = formatting::normalize(&name);
+
+    let mut violations = Violations::new();
+    violations.name(&t!("field.name"), &name).email(&email);
+    if let Some(ref handle) = create_user.handle {
+        violations.check(validate_handle(handle));
+    }
+    if !violations.report(&t!("user.not_created")) {
+        return;
+    }
+
+    let Some(email) = check_email(&email, create_user.verify_dns) else {
+        return;
+    };
+
+    let handle = match create_user.handle {
+        Some(handle) => {
+            if users
+                .iter()
+                .any(|user| user.handle.as_ref() == Some(&handle))
+            {
+                error!(
+                    "{}. {}",
+                    t!("user.not_created"),
+                    t!(
+                        "user.handle_taken",
+                        handle = handle,
+                        suggestion = suggest_handle(&users, &handle)
+                    )
+                );
+                return;
+            }
+            handle
+        }
+        None => suggest_handle(&users, &name),
     };
 
-    if has_email(&users, &create_user.email) {
-        eprintln!("User not generated. Given email already exists");
+    // Only searching the users when the email filter can't rule the address out
+    if email_filter::may_exist(&email) && has_email(&users, &email) {
+        error!("{}. {}", t!("user.not_created"), t!("user.email_taken"));
         return;
     }
 
     let user = User {
         id: generate_valid_id(&users),
-        name: create_user.name,
-        email: create_user.email,
+        name,
+        email,
+        handle: Some(handle),
+        ..Default::default()
     };
 
     users.push(user.clone());
 
-    let file = File::create(path).unwrap();
-
-    bincode::serialize_into(file, &users).unwrap();
+    storage::save(Store::Users, &users);
 
-    println!("User created successfully");
+    info!("{}", t!("user.created"));
     println!("ID: {}", user.id);
+    println!("{}: {}", t!("user.handle"), user.handle.unwrap());
+}
+
+/// Asks for each field of a new user, validating them as they are entered
+///
+/// # Arguments
+///
+/// * `users` - The existing users, used to reject email addresses already in use
+///
+/// # Returns
+///
+/// The name and email address of the new user, or `None` if creation was cancelled
+fn prompt_new_user(users: &Vec<User>) -> Option<(String, String)> {
+    let name = utilities::prompt(&t!("field.name"), utilities::validate_name)?;
+    let email = utilities::prompt(&t!("field.email"), |email| {
+        utilities::validate_email(email)?;
+        if email_filter::may_exist(email) && has_email(users, email) {
+            return Err(t!("user.email_taken"));
+        }
+        Ok(())
+    })?;
+
+    if !utilities::confirm(
+        &t!("user.confirm_create"),
+        Some(
+            format!(
+                "{}: {}\n{}: {}",
+                t!("field.name"),
+                name,
+                t!("field.email"),
+                email
+            )
+            .as_str(),
+        ),
+        Some(&t!("user.create_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return None;
+    }
+
+    Some((name, email))
 }
 
 #[derive(Debug, Args)]
@@ -134,9 +782,24 @@ pub struct UpdateUser {
     /// The name of the user to update
     #[arg(long, default_value = None)]
     pub query_name: Option<String>,
+    /// The handle of the user to update
+    #[arg(long, default_value = None)]
+    pub query_handle: Option<String>,
     /// The email address of the user to update
     #[arg(long, default_value = None)]
     pub query_email: Option<String>,
+    /// Text contained in the name of the user to update
+    #[arg(long, default_value = None)]
+    pub query_name_contains: Option<String>,
+    /// Text contained in the email address of the user to update
+    #[arg(long, default_value = None)]
+    pub query_email_contains: Option<String>,
+    /// Ignore case when matching names and email addresses
+    #[arg(long, default_value_t = false)]
+    pub ignore_case: bool,
+    /// Whether a user must match any or all of the given fields
+    #[arg(long = "match", value_enum, default_value_t = MatchMode::All)]
+    pub match_mode: MatchMode,
 
     /// The new name of the user
     #[arg(long, default_value = None)]
@@ -145,6 +808,71 @@ pub struct UpdateUser {
     /// The new email address of the user
     #[arg(long, default_value = None)]
     pub new_email: Option<String>,
+
+    /// The new handle of the user
+    #[arg(long, visible_alias = "handle", default_value = None)]
+    pub new_handle: Option<String>,
+
+    /// Check that the domain of the new email address can receive mail by looking up its MX
+    /// records
+    #[arg(long, default_value_t = false, requires = "new_email")]
+    pub verify_dns: bool,
+
+    /// The path or URL of the new picture of the user
+    #[arg(long, visible_alias = "avatar", default_value = None)]
+    pub new_avatar: Option<String>,
+
+    /// What the user says about themselves from now on
+    #[arg(long, visible_alias = "bio", default_value = None)]
+    pub new_bio: Option<String>,
+
+    /// When the user was born, as `YYYY-MM-DD`
+    #[arg(long, visible_alias = "birthdate", default_value = None, value_parser = parse_birthdate)]
+    pub new_birthdate: Option<String>,
+
+    /// The ISO 3166 code of the country the user lives in from now on, e.g. `NL`
+    #[arg(long, visible_alias = "country", default_value = None, value_parser = parse_country)]
+    pub new_country: Option<String>,
+
+    /// Whether the user may watch age-restricted videos from now on
+    #[arg(long, value_enum, visible_alias = "maturity", default_value = None)]
+    pub new_maturity: Option<Maturity>,
+}
+
+/// Parses a birthdate written as `YYYY-MM-DD`
+///
+/// # Returns
+///
+/// The birthdate as given, or a message explaining why it is invalid
+pub fn parse_birthdate(birthdate: &str) -> Result<String, String> {
+    let invalid = || format!("{} is not a date written as YYYY-MM-DD", birthdate);
+    let parts: Vec<&str> = birthdate.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return Err(invalid());
+    }
+    let (Ok(_), Ok(month), Ok(day)) = (year.parse::<u16>(), month.parse::<u8>(), day.parse::<u8>())
+    else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    Ok(birthdate.to_string())
+}
+
+/// Parses a two letter ISO 3166 country code, ignoring case
+///
+/// # Returns
+///
+/// The code in upper case, or a message explaining why it is invalid
+pub fn parse_country(country: &str) -> Result<String, String> {
+    if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(t!("user.invalid_country", country = country));
+    }
+    Ok(country.to_ascii_uppercase())
 }
 
 /// Error returned from `find_user`
@@ -165,52 +893,122 @@ enum FindError {
 ///
 /// * `id` - The number of matches for the ID query
 /// * `name` - The number of matches for the name query
+/// * `handle` - The number of matches for the handle query
 /// * `email` - The number of matches for the email query
-#[derive(Debug)]
+/// * `name_contains` - The number of matches for the name substring query
+/// * `email_contains` - The number of matches for the email substring query
+/// * `overlapping` - The number of users matched by more than one field, which are counted once
+///   for each of them above
+#[derive(Debug, Default, PartialEq)]
 struct MatchedQueries {
     id: u32,
     name: u32,
+    handle: u32,
     email: u32,
+    name_contains: u32,
+    email_contains: u32,
+    overlapping: u32,
+}
+
+impl MatchedQueries {
+    /// Prints the number of matches for each field given in the query
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query the matches were counted for
+    fn print(&self, query: &UserQuery) {
+        if query.id.is_some() {
+            warn!("{}", t!("query.id_matches", count = self.id));
+        }
+        if query.name.is_some() {
+            warn!("{}", t!("query.name_matches", count = self.name));
+        }
+        if query.handle.is_some() {
+            warn!("{}", t!("query.handle_matches", count = self.handle));
+        }
+        if query.email.is_some() {
+            warn!("{}", t!("query.email_matches", count = self.email));
+        }
+        if query.name_contains.is_some() {
+            warn!(
+                "{}",
+                t!("query.name_contains_matches", count = self.name_contains)
+            );
+        }
+        if query.email_contains.is_some() {
+            warn!(
+                "{}",
+                t!("query.email_contains_matches", count = self.email_contains)
+            );
+        }
+        if self.overlapping > 0 {
+            warn!("{}", t!("user.overlapping", count = self.overlapping));
+        }
+    }
+}
+
+/// Narrows down the users that may match a query using the indexes of the repository, so only
+/// those have to be tested against it
+///
+/// An ID, email, or name in the query is looked up directly when every field must match, or when
+/// it is the only field given. Otherwise, every user may match.
+///
+/// # Arguments
+///
+/// * `users` - The users to search
+/// * `query` - The query to search for
+///
+/// # Returns
+///
+/// The users that may match the query, in the order they are kept
+fn candidates<'a>(users: &'a Repository<User>, query: &UserQuery) -> Vec<&'a User> {
+    if query.match_mode == MatchMode::All || query.given_fields() == 1 {
+        if let Some(id) = query.id {
+            return users.with_id(id);
+        }
+        if let Some(email) = &query.email {
+            return users.with_email(email);
+        }
+        if let Some(name) = &query.name {
+            return users.with_name(name);
+        }
+    }
+    users.iter().collect()
 }
 
 /// Finds a user in the given list of users matching the given query
 ///
 /// # Arguments
 ///
-/// * `users` - The list of users to search
+/// * `users` - The users to search
 /// * `query` - The query to search for
 ///
 /// # Returns
 ///
 /// The user matching the given query. If multiple or none are found, returns a `FindError` variant matching the error case.
-fn find_user<'a>(users: &'a Vec<User>, query: &UserQuery) -> Result<&'a User, FindError> {
+fn find_user<'a>(users: &'a Repository<User>, query: &UserQuery) -> Result<&'a User, FindError> {
     let mut found_users: Vec<&User> = vec![];
-    let mut id_matches = 0;
-    let mut name_matches = 0;
-    let mut email_matches = 0;
+    let mut matches = MatchedQueries::default();
 
-    for user in users {
-        if let Some(id) = query.id {
-            if user.id == id {
-                found_users.push(user);
-                id_matches += 1;
-                continue;
-            }
+    // Each user is matched once, however many fields they match
+    for user in candidates(users, query) {
+        if !query.matches(user) {
+            continue;
         }
 
-        if let Some(name) = &query.name {
-            if user.name == *name {
-                found_users.push(user);
-                name_matches += 1;
-                continue;
-            }
+        found_users.push(user);
+        let fields = query.matching_fields(user);
+        if fields.len() > 1 {
+            matches.overlapping += 1;
         }
-
-        if let Some(email) = &query.email {
-            if user.email == *email {
-                found_users.push(user);
-                email_matches += 1;
-                continue;
+        for field in fields {
+            match field {
+                QueryField::Id => matches.id += 1,
+                QueryField::Name => matches.name += 1,
+                QueryField::Handle => matches.handle += 1,
+                QueryField::Email => matches.email += 1,
+                QueryField::NameContains => matches.name_contains += 1,
+                QueryField::EmailContains => matches.email_contains += 1,
             }
         }
     }
@@ -220,255 +1018,1407 @@ fn find_user<'a>(users: &'a Vec<User>, query: &UserQuery) -> Result<&'a User, Fi
     }
 
     if found_users.len() > 1 {
-        return Err(FindError::MultipleUsersFound(MatchedQueries {
-            id: id_matches,
-            name: name_matches,
-            email: email_matches,
-        }));
+        return Err(FindError::MultipleUsersFound(matches));
     }
 
     Ok(found_users[0])
 }
 
-/// Handles the updating of an existing user
+/// Finds the single user matching the given query, letting the user pick one if several match
 ///
 /// # Arguments
 ///
-/// * `update_user` - The arguments for the user update
-pub fn handle_update_user(update_user: UpdateUser) {
-    if update_user.query_id.is_none()
-        && update_user.query_name.is_none()
-        && update_user.query_email.is_none()
-    {
-        eprintln!("No query given. Please provide an ID, name, or email");
-        return;
+/// * `users` - The list of users to search
+/// * `query` - The query to search for
+/// * `action` - The name of the action being performed, used in messages
+///
+/// # Returns
+///
+/// The user matching the query, or `None` if none was found or the user cancelled
+fn select_user<'a>(
+    users: &'a Repository<User>,
+    query: &UserQuery,
+    action: &str,
+) -> Option<&'a User> {
+    match find_user(users, query) {
+        Ok(user) => Some(user),
+        Err(FindError::NoUserFound) => {
+            error!("{}", t!("user.query_failed", action = action));
+            None
+        }
+        Err(FindError::MultipleUsersFound(counts)) => {
+            warn!("{}", t!("user.multiple_found"));
+            counts.print(query);
+
+            let candidates: Vec<&User> = candidates(users, query)
+                .into_iter()
+                .filter(|user| query.matches(user))
+                .collect();
+            let cancel_message = t!("query.cancelled", action = action);
+            utilities::choose(
+                &t!("user.which"),
+                &candidates,
+                Some(cancel_message.as_str()),
+            )
+            .map(|choice| candidates[choice])
+        }
     }
+}
 
-    let path = concat!(env!("HOME"), "/.rustflix/users.bc");
-    let path = Path::new(path);
-    let mut users: Vec<User> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
-    };
-
+/// Handles the updating of an existing user
+///
+/// # Arguments
+///
+/// * `update_user` - The arguments for the user update
+pub fn handle_update_user(update_user: UpdateUser) {
     let user_query = UserQuery {
         id: update_user.query_id,
         name: update_user.query_name.clone(),
+        handle: update_user.query_handle.clone(),
         email: update_user.query_email.clone(),
+        name_contains: update_user.query_name_contains.clone(),
+        email_contains: update_user.query_email_contains.clone(),
+        ignore_case: update_user.ignore_case,
+        match_mode: update_user.match_mode,
     };
 
-    let user = find_user(&users, &user_query);
-
-    if let Err(e) = user {
-        match e {
-            FindError::NoUserFound => eprintln!("Update failed. No user found from given query."),
-            FindError::MultipleUsersFound(counts) => {
-                eprintln!("Update failed. Multiple users found from given query.");
-                if update_user.query_id.is_some() {
-                    eprintln!("ID matches: {}", counts.id);
-                }
-                if update_user.query_name.is_some() {
-                    eprintln!("Name matches: {}", counts.name);
-                }
-                if update_user.query_email.is_some() {
-                    eprintln!("Email matches: {}", counts.email);
-                }
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
+    }
+
+    repository::retry(|| {
+        let mut users = Repository::<User>::load();
+
+        let mut user = match select_user(&users, &user_query, &t!("action.update")) {
+            Some(user) => user.clone(),
+            None => return Ok(()),
+        };
+        if !session::require_self_or_role(user.id, Role::Moderator, &t!("role.update_other_users"))
+        {
+            return Ok(());
+        }
+
+        let og_user_state = user.clone();
+
+        let mut violations = Violations::new();
+        if let Some(ref name) = update_user.new_name {
+            violations.name(&t!("field.name"), name);
+        }
+        if let Some(ref email) = update_user.new_email {
+            violations.email(email);
+        }
+        if let Some(ref handle) = update_user.new_handle {
+            violations.check(validate_handle(handle));
+        }
+        if let Some(ref bio) = update_user.new_bio {
+            violations.text(&t!("field.bio"), bio);
+        }
+        if !violations.report(&t!("user.not_updated")) {
+            return Ok(());
+        }
+
+        if let Some(ref name) = update_user.new_name {
+            user.name = formatting::normalize(name)
+        }
+
+        if let Some(ref email) = update_user.new_email {
+            let Some(email) = check_email(email, update_user.verify_dns) else {
+                return Ok(());
+            };
+            if users
+                .with_email(&email)
+                .iter()
+                .any(|user| user.id != og_user_state.id)
+            {
+                error!("{}. {}", t!("user.not_updated"), t!("user.email_taken"));
+                return Ok(());
+            }
+            user.email = email
+        }
+
+        if let Some(ref handle) = update_user.new_handle {
+            if users
+                .iter()
+                .any(|user| user.handle.as_ref() == Some(handle) && user.id != og_user_state.id)
+            {
+                error!(
+                    "{}. {}",
+                    t!("user.not_updated"),
+                    t!(
+                        "user.handle_taken",
+                        handle = handle,
+                        suggestion = suggest_handle(&users, handle)
+                    )
+                );
+                return Ok(());
             }
+            user.handle = Some(handle.clone());
+        }
+
+        if let Some(ref avatar) = update_user.new_avatar {
+            user.avatar = Some(avatar.clone());
+        }
+
+        if let Some(ref bio) = update_user.new_bio {
+            user.bio = Some(bio.clone());
+        }
+
+        if let Some(ref birthdate) = update_user.new_birthdate {
+            user.birthdate = Some(birthdate.clone());
+        }
+
+        if let Some(ref country) = update_user.new_country {
+            user.country = Some(country.clone());
+        }
+
+        if let Some(maturity) = update_user.new_maturity {
+            user.maturity = maturity;
+        }
+
+        users.replace(user.clone());
+        users.try_save()?;
+
+        info!("{}", t!("user.updated"));
+        if update_user.new_email.is_some() {
+            info!(
+                "{}",
+                t!(
+                    "user.email_changed",
+                    old = og_user_state.email,
+                    new = user.email
+                )
+            );
+        }
+        if update_user.new_name.is_some() {
+            info!(
+                "{}",
+                t!(
+                    "record.name_changed",
+                    old = og_user_state.name,
+                    new = user.name
+                )
+            );
         }
+        Ok(())
+    });
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteUser {
+    #[command(flatten)]
+    pub query: UserQuery,
+    #[command(flatten)]
+    pub on_delete: OnDelete,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
+
+/// Handles deleting a user. They are only deleted while no video, watch history entry, or
+/// subscription refers to them, unless `--cascade` or `--orphan` says what to do with those
+///
+/// # Arguments
+///
+/// * `delete_user` - The arguments for the user deletion
+pub fn handle_delete_user(delete_user: DeleteUser) {
+    if !session::require_role(Role::Admin, &t!("role.delete_users")) {
+        return;
+    }
+
+    let user_query = delete_user.query;
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
         return;
     }
 
-    let user = user.unwrap();
+    repository::retry(|| {
+        let mut users = Repository::<User>::load();
+
+        let user = match select_user(&users, &user_query, &t!("action.delete")) {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+
+        let policy = delete_user.on_delete.policy();
+        let references = References::find(Target::User(user.id));
+        if policy == Policy::Refuse && !references.is_empty() {
+            error!(
+                "{}",
+                t!(
+                    "user.referred_to",
+                    name = user.name,
+                    references = references.describe()
+                )
+            );
+            return Ok(());
+        }
 
-    let user_index = users.iter().position(|u| u == user);
+        if !utilities::confirm_bulk(
+            &t!("user.confirm_delete"),
+            Some(format!("{:?}\n{}", user, references.summary(policy)).as_str()),
+            Some(&t!("user.delete_cancelled")),
+            Some(true),
+            references.affected(policy),
+            &delete_user.confirm_count,
+        ) {
+            return Ok(());
+        };
+
+        let id = user.id;
+        users.remove(id);
+
+        users.try_save()?;
+        references.resolve(policy);
+
+        info!("{}", t!("user.deleted"));
+        Ok(())
+    });
+}
 
-    if user_index.is_none() {
-        panic!("User was found but its index wasn't. This should never happen.");
+#[derive(Debug, Args)]
+pub struct DeleteManyUsers {
+    #[command(flatten)]
+    pub query: UserQuery,
+    #[command(flatten)]
+    pub on_delete: OnDelete,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
+
+/// Handles deleting every user matching a query, e.g. `--email-contains @example.com`. The users
+/// are listed first, and the number of records deleted has to be typed to confirm, however few.
+/// Every store changed is written at once at the end, so either all of the users are deleted or
+/// none are
+///
+/// # Arguments
+///
+/// * `delete_many` - The arguments for the deletion
+pub fn handle_delete_many_users(delete_many: DeleteManyUsers) {
+    if !session::require_role(Role::Admin, &t!("role.delete_users")) {
+        return;
+    }
+
+    let user_query = delete_many.query;
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
     }
 
-    let user_index = user_index.unwrap();
+    storage::begin_batch();
+    let mut users = Repository::<User>::load();
+    let matched: Vec<User> = users
+        .iter()
+        .filter(|user| user_query.matches(user))
+        .cloned()
+        .collect();
+    if matched.is_empty() {
+        error!("{}", t!("user.not_found"));
+        storage::end_batch();
+        return;
+    }
 
-    let og_user_state = users[user_index].clone();
+    let policy = delete_many.on_delete.policy();
+    let mut affected = HashSet::new();
+    let mut refusals = vec![];
+    for user in &matched {
+        let references = References::find(Target::User(user.id));
+        println!("{:?}", user);
+        if !references.is_empty() {
+            println!("  {}", references.summary(policy));
+        }
+        if policy == Policy::Refuse && !references.is_empty() {
+            refusals.push(t!(
+                "user.referred_to",
+                name = user.name,
+                references = references.describe()
+            ));
+        }
+        affected.extend(references.affected_records(policy));
+    }
+    for refusal in &refusals {
+        error!("{}", refusal);
+    }
+    if !refusals.is_empty() {
+        storage::end_batch();
+        return;
+    }
 
-    if let Some(ref name) = update_user.new_name {
-        users[user_index].name = name.clone()
+    let affected = integrity::count_affected(&affected);
+    if !utilities::confirm_typed_count(
+        &t!(
+            "user.confirm_delete_many",
+            count = matched.len(),
+            others = affected - matched.len()
+        ),
+        None,
+        Some(&t!("user.delete_cancelled")),
+        affected,
+        &delete_many.confirm_count,
+    ) {
+        storage::end_batch();
+        return;
     }
 
-    if let Some(ref email) = update_user.new_email {
-        users[user_index].email = email.clone()
+    for user in &matched {
+        users.remove(user.id);
+    }
+    users.save();
+    // Found again for each user, as resolving the references of one changes the stores
+    for user in &matched {
+        References::find(Target::User(user.id)).resolve(policy);
     }
+    storage::end_batch();
 
-    let file = File::create(path).unwrap();
-    bincode::serialize_into(file, &users).unwrap();
+    info!("{}", t!("user.deleted_many", count = matched.len()));
+}
 
-    println!("User updated successfully.");
-    if update_user.new_email.is_some() {
-        println!(
-            "Email changed from {} to {}",
-            og_user_state.email, users[user_index].email
-        );
+#[derive(Debug, Args)]
+pub struct ShowUser {
+    /// Show all users
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["id", "name", "handle", "email", "name_contains", "email_contains"]
+    )]
+    pub all: bool,
+    #[command(flatten)]
+    pub query: UserQuery,
+    #[command(flatten)]
+    pub pagination: Pagination,
+    /// Only print the given comma-separated fields
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub fields: Vec<UserField>,
+    /// Include suspended users
+    #[arg(long, default_value_t = false)]
+    pub include_suspended: bool,
+}
+
+/// Handles listing users, streaming them from the store so only those printed are kept in memory
+///
+/// # Arguments
+///
+/// * `show_user` - The arguments for the listing
+pub fn handle_list_users(show_user: ShowUser) {
+    if !show_user.all && show_user.query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
     }
-    if update_user.new_name.is_some() {
-        println!(
-            "Name changed from {} to {}",
-            og_user_state.name, users[user_index].name
-        );
+
+    let query = &show_user.query;
+    let mut found_users = listing::filter(storage::stream::<User>(Store::Users), |user| {
+        (show_user.include_suspended || !user.suspended) && (show_user.all || query.matches(user))
+    })
+    .peekable();
+
+    if !show_user.all && found_users.peek().is_none() {
+        if show_user.pagination.count {
+            println!("0");
+            return;
+        }
+        error!("{}", t!("user.not_found"));
+        return;
     }
+
+    print_users(found_users, &show_user.pagination, &show_user.fields);
 }
 
-pub fn handle_delete_user(user_query: UserQuery) {
-    if user_query.id.is_none() && user_query.name.is_none() && user_query.email.is_none() {
-        eprintln!("No query given. Please provide an ID, name, or email");
+/// Handles showing every detail of a single user
+///
+/// # Arguments
+///
+/// * `user_query` - The query for the user to show
+pub fn handle_show_user(user_query: UserQuery) {
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
         return;
     }
 
-    let path = concat!(env!("HOME"), "/.rustflix/users.bc");
-    let path = Path::new(path);
-    let mut users: Vec<User> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
+    let users = Repository::<User>::load();
+
+    let user = match select_user(&users, &user_query, &t!("action.show")) {
+        Some(user) => user,
+        None => return,
     };
 
-    let user = find_user(&users, &user_query);
-
-    if let Err(e) = user {
-        match e {
-            FindError::NoUserFound => eprintln!("Delete failed. No user found from given query."),
-            FindError::MultipleUsersFound(counts) => {
-                eprintln!("Delete failed. Multiple users found from given query.");
-                if user_query.id.is_some() {
-                    eprintln!("ID matches: {}", counts.id);
-                }
-                if user_query.name.is_some() {
-                    eprintln!("Name matches: {}", counts.name);
-                }
-                if user_query.email.is_some() {
-                    eprintln!("Email matches: {}", counts.email);
-                }
-            }
+    listing::print_details(&[
+        (t!("field.name"), user.name.clone()),
+        (t!("field.handle"), user.field(UserField::Handle)),
+        (t!("field.id"), user.id.to_string()),
+        (t!("field.email"), user.email.clone()),
+        (t!("field.role"), user.field(UserField::Role)),
+        (t!("field.bio"), user.field(UserField::Bio)),
+        (t!("field.birthdate"), user.field(UserField::Birthdate)),
+        (t!("field.country"), user.field(UserField::Country)),
+        (t!("field.maturity"), user.field(UserField::Maturity)),
+        (t!("field.interests"), user.interests.join(", ")),
+        (
+            t!("field.suspended"),
+            if user.suspended {
+                t!("prompt.yes")
+            } else {
+                String::new()
+            },
+        ),
+        (t!("field.avatar"), user.field(UserField::Avatar)),
+        (
+            t!("field.password"),
+            if user.password.is_some() {
+                t!("user.password_given")
+            } else {
+                t!("user.password_missing")
+            },
+        ),
+        (
+            t!("field.created"),
+            user.created_at.map(format_timestamp).unwrap_or_default(),
+        ),
+        (
+            t!("field.updated"),
+            user.updated_at.map(format_timestamp).unwrap_or_default(),
+        ),
+    ]);
+}
+
+/// Prints the page of users requested, or only their number if `--count` was given
+///
+/// # Arguments
+///
+/// * `users` - Every user matching the query
+/// * `pagination` - The page of users to print
+/// * `fields` - The fields to print. Every field is printed if empty
+fn print_users(users: impl Iterator<Item = User>, pagination: &Pagination, fields: &[UserField]) {
+    if pagination.count {
+        println!("{}", users.count());
+        return;
+    }
+
+    if fields.is_empty() {
+        for user in pagination.apply(users) {
+            println!("{:?}", user);
         }
         return;
     }
 
-    let user = user.unwrap();
+    let mut table = Table::new(listing::field_names(fields));
+    for user in pagination.apply(users) {
+        table.row(fields.iter().map(|field| user.field(*field)).collect());
+    }
+    table.print();
+}
 
-    let user_index = users.iter().position(|u| u == user);
+/// Handles the export of every user
+///
+/// # Arguments
+///
+/// * `export` - The arguments for the export
+pub fn handle_export_users(export: Export) {
+    let users: Vec<User> = storage::load(Store::Users);
 
-    if user_index.is_none() {
-        panic!("User was found but its index wasn't. This should never happen.");
+    if let Err(e) = transfer::export(&export, &users) {
+        error!("{}", t!("transfer.export_failed", error = e));
+        return;
     }
 
-    if !utilities::confirm(
-        "Are you sure you want to remove this user?",
-        Some(format!("{:?}", user).as_str()),
-        Some("User deletion cancelled."),
-        Some(true),
+    if let Some(path) = export.output {
+        info!(
+            "{}",
+            t!("user.exported", count = users.len(), path = path.display())
+        );
+    }
+}
+
+/// Handles the import of users from a file
+///
+/// # Arguments
+///
+/// * `import` - The arguments for the import
+pub fn handle_import_users(import: Import) {
+    if !session::require_role(Role::Admin, &t!("role.import_users")) {
+        return;
+    }
+
+    let imported: Vec<User> = match transfer::import(&import) {
+        Ok(imported) => imported,
+        Err(e) => {
+            error!("{}", t!("transfer.import_failed", error = e));
+            return;
+        }
+    };
+
+    let mut users: Vec<User> = storage::load(Store::Users);
+
+    if import.replace {
+        if !users.is_empty()
+            && !utilities::confirm(
+                &t!("user.confirm_replace", count = users.len()),
+                None,
+                Some(&t!("user.import_cancelled")),
+                Some(true),
+                utilities::prompt_timeout(),
+            )
+        {
+            return;
+        }
+        users.clear();
+    }
+
+    let mut ids: HashSet<u32> = users.iter().map(|user| user.id).collect();
+    let mut emails: HashSet<String> = users
+        .iter()
+        .map(|user| user.email.to_ascii_lowercase())
+        .collect();
+    let mut added = 0;
+    let mut duplicates = 0;
+    for user in imported {
+        if ids.contains(&user.id) {
+            warn!("{}", t!("user.duplicate_id", id = user.id));
+            duplicates += 1;
+            continue;
+        }
+        if emails.contains(&user.email.to_ascii_lowercase()) {
+            warn!("{}", t!("user.duplicate_email", email = user.email));
+            duplicates += 1;
+            continue;
+        }
+        ids.insert(user.id);
+        emails.insert(user.email.to_ascii_lowercase());
+        users.push(user);
+        added += 1;
+    }
+
+    storage::save(Store::Users, &users);
+
+    info!("{}", t!("user.imported", count = added));
+    if duplicates > 0 {
+        info!("{}", t!("transfer.skipped", count = duplicates));
+    }
+}
+
+/// Hashes a password with a new random salt
+fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+/// Checks a password against the hash kept on a user
+///
+/// # Returns
+///
+/// `false` if the user has no password or the password is wrong
+fn verify_password(user: &User, password: &str) -> bool {
+    let Some(hash) = &user.password else {
+        return false;
+    };
+    let Ok(hash) = PasswordHash::new(hash) else {
+        warn!("{}", t!("user.invalid_hash", name = user.name));
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+/// Handles setting the password of a user, which ends any session they are logged in with
+///
+/// # Arguments
+///
+/// * `user_query` - The query for the user
+pub fn handle_set_password(user_query: UserQuery) {
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
+    }
+
+    let mut users = Repository::<User>::load();
+
+    let mut user = match select_user(&users, &user_query, &t!("action.update")) {
+        Some(user) => user.clone(),
+        None => return,
+    };
+    if !session::require_self_or_role(
+        user.id,
+        Role::Admin,
+        &t!("role.set_passwords_of_other_users"),
     ) {
         return;
+    }
+
+    let Some(password) = utilities::prompt_password(&t!("user.new_password")) else {
+        return;
     };
+    if password.is_empty() {
+        error!("{}", t!("user.password_empty"));
+        return;
+    }
+    if utilities::is_terminal()
+        && utilities::prompt_password(&t!("user.repeat_password")).as_ref() != Some(&password)
+    {
+        error!("{}", t!("user.passwords_differ"));
+        return;
+    }
 
-    let user_index = user_index.unwrap();
+    user.password = Some(hash_password(&password));
+    user.session = None;
 
-    users.remove(user_index);
+    users.replace(user);
+    users.save();
 
-    let file = File::create(path).unwrap();
-    bincode::serialize_into(file, &users).unwrap();
+    info!("{}", t!("user.password_set"));
+}
 
-    println!("User deleted successfully.");
+#[derive(Debug, Args)]
+pub struct SetRole {
+    /// The role to give the user
+    #[arg(value_enum)]
+    pub role: Role,
+    #[command(flatten)]
+    pub query: UserQuery,
 }
 
-fn find_users(users: &Vec<User>, user_query: &UserQuery) -> Result<Vec<User>, FindError> {
-    let mut found_users: Vec<User> = vec![];
+/// Handles giving a user a role
+///
+/// # Arguments
+///
+/// * `set_role` - The arguments for setting the role
+pub fn handle_set_role(set_role: SetRole) {
+    if !session::require_role(Role::Admin, &t!("role.give_users_roles")) {
+        return;
+    }
 
-    for user in users {
-        if user_query.id.is_some() && user.id == user_query.id.unwrap() {
-            found_users.push(user.clone());
-            continue;
+    if set_role.query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
+    }
+
+    let mut users = Repository::<User>::load();
+
+    let mut user = match select_user(&users, &set_role.query, &t!("action.update")) {
+        Some(user) => user.clone(),
+        None => return,
+    };
+    // Commands needing a role can't be run logged out once anyone has one, so they have to be
+    // able to log in
+    if set_role.role > Role::Viewer && user.password.is_none() {
+        error!("{}", t!("user.role_needs_password", name = user.name));
+        return;
+    }
+    user.role = set_role.role;
+    let name = user.name.clone();
+
+    users.replace(user);
+    users.save();
+
+    info!(
+        "{}",
+        t!(
+            "user.role_set",
+            name = name,
+            role = listing::field_names(&[set_role.role]).concat()
+        )
+    );
+}
+
+/// Handles suspending or reactivating a user. Suspending a user also ends their session
+///
+/// # Arguments
+///
+/// * `user_query` - The query for the user
+/// * `suspended` - Whether to suspend the user rather than reactivate them
+pub fn handle_set_suspended(user_query: UserQuery, suspended: bool) {
+    let action = match suspended {
+        true => t!("action.suspend"),
+        false => t!("action.reactivate"),
+    };
+    let doing = match suspended {
+        true => t!("role.suspend_users"),
+        false => t!("role.reactivate_users"),
+    };
+    if !session::require_role(Role::Moderator, &doing) {
+        return;
+    }
+
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
+    }
+
+    let mut users = Repository::<User>::load();
+
+    let mut user = match select_user(&users, &user_query, &action) {
+        Some(user) => user.clone(),
+        None => return,
+    };
+
+    if user.suspended == suspended {
+        match suspended {
+            true => warn!("{}", t!("user.already_suspended", name = user.name)),
+            false => warn!("{}", t!("user.already_active", name = user.name)),
         }
+        return;
+    }
 
-        if user_query.name.is_some() && user.name == user_query.name.clone().unwrap() {
-            found_users.push(user.clone());
-            continue;
+    user.suspended = suspended;
+    user.session = None;
+    let name = user.name.clone();
+
+    users.replace(user);
+    users.save();
+
+    match suspended {
+        true => info!("{}", t!("user.suspended", name = name)),
+        false => info!("{}", t!("user.reactivated", name = name)),
+    }
+}
+
+/// Checks that the given user, if any, isn't suspended
+///
+/// # Arguments
+///
+/// * `user` - The ID of the user acting, if known
+///
+/// # Returns
+///
+/// `false` after reporting an error if the user is suspended
+pub fn check_not_suspended(user: Option<u32>) -> bool {
+    let Some(user) = user else {
+        return true;
+    };
+    let users: Vec<User> = storage::load(Store::Users);
+    match users.iter().find(|u| u.id == user) {
+        Some(user) if user.suspended => {
+            error!("{}", t!("user.is_suspended", name = user.name));
+            false
         }
+        _ => true,
+    }
+}
 
-        if user_query.email.is_some() && user.email == user_query.email.clone().unwrap() {
-            found_users.push(user.clone());
-            continue;
+/// Checks that the given user, if any, may watch a video, given its age restriction
+///
+/// # Arguments
+///
+/// * `user` - The ID of the user watching, if known
+/// * `video` - The video being watched
+/// * `override_parental` - Whether an admin chose to let the user watch anyway
+///
+/// # Returns
+///
+/// `false` after reporting an error if the user may not watch the video
+pub fn check_parental(user: Option<u32>, video: &Video, override_parental: bool) -> bool {
+    if !video.age_restricted {
+        return true;
+    }
+    if override_parental {
+        return session::require_role(Role::Admin, &t!("role.override_parental_controls"));
+    }
+    let Some(user) = user else {
+        return true;
+    };
+    let users: Vec<User> = storage::load(Store::Users);
+    match users.iter().find(|u| u.id == user) {
+        Some(user) if user.is_minor() => {
+            error!(
+                "{}",
+                t!("user.age_restricted", video = video.name, name = user.name)
+            );
+            false
         }
+        _ => true,
     }
+}
 
-    if found_users.is_empty() {
-        return Err(FindError::NoUserFound);
+#[derive(Debug, Args)]
+pub struct MergeUsers {
+    /// The ID of the duplicate user, which is deleted once merged
+    #[arg(long)]
+    pub from: u32,
+    /// The ID of the user to keep
+    #[arg(long)]
+    pub to: u32,
+}
+
+/// Handles merging a duplicate user into another, moving everything that refers to them (videos,
+/// watch history, subscriptions, playlists and notifications) before deleting them
+///
+/// # Arguments
+///
+/// * `merge_users` - The arguments for the merge
+pub fn handle_merge_users(merge_users: MergeUsers) {
+    if !session::require_role(Role::Admin, &t!("role.merge_users")) {
+        return;
     }
 
-    Ok(found_users)
+    if merge_users.from == merge_users.to {
+        error!("{}", t!("user.merge_self"));
+        return;
+    }
+
+    repository::retry(|| {
+        let mut users = Repository::<User>::load();
+        let Some(from) = users.get(merge_users.from).cloned() else {
+            error!("{}", t!("user.not_found_id", id = merge_users.from));
+            return Ok(());
+        };
+        let Some(to) = users.get(merge_users.to).cloned() else {
+            error!("{}", t!("user.not_found_id", id = merge_users.to));
+            return Ok(());
+        };
+
+        let references = References::find(Target::User(from.id));
+        let mut preview = t!(
+            "user.merge_preview",
+            from = format!("{:?}", from),
+            to = format!("{:?}", to),
+            moving = references.describe(),
+            notifications = references.notification_count()
+        );
+        let videos: Vec<Video> = storage::load(Store::Videos);
+        for video in videos.iter().filter(|video| video.owner == Some(from.id)) {
+            preview.push_str(&format!("\n  {:?}", video));
+        }
+
+        if !utilities::confirm(
+            &t!("user.confirm_merge", from = from.name, to = to.name),
+            Some(preview.as_str()),
+            Some(&t!("user.merge_cancelled")),
+            Some(true),
+            utilities::prompt_timeout(),
+        ) {
+            return Ok(());
+        }
+
+        users.remove(from.id);
+        users.try_save()?;
+        storage::begin_batch();
+        references.merge_into(to.id);
+        storage::end_batch();
+
+        info!("{}", t!("user.merged", from = from.name, to = to.name));
+        Ok(())
+    });
 }
 
 #[derive(Debug, Args)]
-pub struct ShowUser {
-    /// Show all users
-    #[arg(
-        short,
-        long,
-        default_value_t = false,
-        conflicts_with = "id",
-        conflicts_with = "name",
-        conflicts_with = "email"
-    )]
-    pub all: bool,
-    /// The ID of the user to query
-    #[arg(long, default_value = None)]
-    pub id: Option<u32>,
-    /// The name of the user to query
-    #[arg(long, default_value = None)]
-    pub name: Option<String>,
-    /// The email address of the user to query
-    #[arg(long, default_value = None)]
-    pub email: Option<String>,
+pub struct ExportUserData {
+    /// The ID of the user whose data to export
+    #[arg(long)]
+    pub id: u32,
+    /// The file to write the JSON bundle to. Defaults to stdout
+    #[arg(short, long, default_value = None)]
+    pub output: Option<PathBuf>,
 }
 
-pub fn handle_list_users(show_user: ShowUser) {
-    let path = concat!(env!("HOME"), "/.rustflix/users.bc");
-    let path = Path::new(path);
-    let users: Vec<User> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
+/// Handles exporting everything referencing a user as a single JSON bundle
+///
+/// Password and session hashes are left out, as they are secrets rather than data about the user.
+///
+/// # Arguments
+///
+/// * `export` - The arguments for the export
+pub fn handle_export_user_data(export: ExportUserData) {
+    let users: Vec<User> = storage::load(Store::Users);
+    let Some(user) = users.iter().find(|user| user.id == export.id) else {
+        error!("{}", t!("user.not_found_id", id = export.id));
+        return;
+    };
+    let user = User {
+        password: None,
+        session: None,
+        ..user.clone()
     };
 
-    if show_user.all {
-        for user in users {
-            println!("{:?}", user);
+    let history: Vec<WatchEntry> = storage::load(Store::History);
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    let subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+    let bundle = json!({
+        "user": user,
+        "watch_history": history
+            .iter()
+            .filter(|entry| entry.user == Some(user.id))
+            .collect::<Vec<_>>(),
+        "owned_videos": videos
+            .iter()
+            .filter(|video| video.owner == Some(user.id))
+            .collect::<Vec<_>>(),
+        "subscriptions": subscriptions
+            .iter()
+            .filter(|subscription| subscription.user == user.id)
+            .map(|subscription| subscription.channel)
+            .collect::<Vec<_>>(),
+    });
+
+    let result = match &export.output {
+        Some(path) => File::create(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                serde_json::to_writer_pretty(file, &bundle).map_err(|e| e.to_string())
+            }),
+        None => {
+            println!("{}", serde_json::to_string_pretty(&bundle).unwrap());
+            Ok(())
         }
+    };
+    if let Err(e) = result {
+        error!("{}", t!("transfer.export_failed", error = e));
         return;
     }
 
-    if show_user.id.is_none() && show_user.name.is_none() && show_user.email.is_none() {
-        eprintln!("No query given. Please provide an ID, name, or email");
+    if let Some(path) = export.output {
+        info!(
+            "{}",
+            t!(
+                "user.exported_data",
+                name = user.name,
+                path = path.display()
+            )
+        );
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct EraseUser {
+    /// The ID of the user to erase
+    #[arg(long)]
+    pub id: u32,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
+
+/// Handles erasing a user from every store, deleting their account, subscriptions, notifications
+/// and playlists, and anonymizing their watch history and the videos they own
+///
+/// # Arguments
+///
+/// * `erase_user` - The arguments for the erasure
+pub fn handle_erase_user(erase_user: EraseUser) {
+    if !session::require_role(Role::Admin, &t!("role.erase_users")) {
         return;
     }
 
-    let user_query = UserQuery {
-        id: show_user.id,
-        name: show_user.name,
-        email: show_user.email,
+    let id = erase_user.id;
+    repository::retry(|| {
+        let mut users = Repository::<User>::load();
+        let Some(user) = users.get(id).cloned() else {
+            error!("{}", t!("user.not_found_id", id = id));
+            return Ok(());
+        };
+
+        let mut history: Vec<WatchEntry> = storage::load(Store::History);
+        let mut videos: Vec<Video> = storage::load(Store::Videos);
+        let history_entries = history
+            .iter()
+            .filter(|entry| entry.user == Some(id))
+            .count();
+        let owned_videos = videos
+            .iter()
+            .filter(|video| video.owner == Some(id))
+            .count();
+        let mut subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+        let subscribed = subscriptions
+            .iter()
+            .filter(|subscription| subscription.user == id || subscription.channel == id)
+            .count();
+        let mut notifications: Vec<Notification> = storage::load(Store::Notifications);
+        let notified = notifications
+            .iter()
+            .filter(|notification| notification.user == id)
+            .count();
+        let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+        let made = playlists
+            .iter()
+            .filter(|playlist| playlist.owner == id)
+            .count();
+        let followed = playlists
+            .iter()
+            .filter(|playlist| playlist.followers.contains(&id))
+            .count();
+
+        if !utilities::confirm_bulk(
+            &t!("user.confirm_erase"),
+            Some(
+                t!(
+                    "user.erase_preview",
+                    user = format!("{:?}", user),
+                    history = history_entries,
+                    videos = owned_videos,
+                    subscriptions = subscribed,
+                    notifications = notified,
+                    made = made,
+                    followed = followed
+                )
+                .as_str(),
+            ),
+            Some(&t!("user.erase_cancelled")),
+            Some(false),
+            1 + history_entries + owned_videos + subscribed + notified + made + followed,
+            &erase_user.confirm_count,
+        ) {
+            return Ok(());
+        }
+
+        // Every store is changed in memory first, so the stores are only touched once the whole
+        // erasure is worked out. The user goes first, so nothing else is written if they changed
+        // meanwhile
+        for entry in history.iter_mut().filter(|entry| entry.user == Some(id)) {
+            entry.user = None;
+        }
+        for video in videos.iter_mut().filter(|video| video.owner == Some(id)) {
+            video.owner = None;
+        }
+        subscriptions.retain(|subscription| subscription.user != id && subscription.channel != id);
+        notifications.retain(|notification| notification.user != id);
+        playlists.retain(|playlist| playlist.owner != id);
+        for playlist in &mut playlists {
+            playlist.followers.retain(|follower| *follower != id);
+        }
+
+        users.remove(id);
+        users.try_save()?;
+        storage::begin_batch();
+        storage::save(Store::History, &history);
+        storage::save(Store::Videos, &videos);
+        storage::save(Store::Subscriptions, &subscriptions);
+        if notified > 0 {
+            storage::save(Store::Notifications, &notifications);
+        }
+        if made + followed > 0 {
+            storage::save(Store::Playlists, &playlists);
+        }
+        storage::end_batch();
+        if session::current_user_id() == Some(id) {
+            session::end();
+        }
+
+        info!("{}", t!("user.erased", name = user.name));
+        Ok(())
+    });
+}
+
+/// Handles logging in as a user, so later commands act as them
+///
+/// # Arguments
+///
+/// * `user_query` - The query for the user logging in
+pub fn handle_login(user_query: UserQuery) {
+    if user_query.is_empty() {
+        error!("{}", t!("user.no_query"));
+        return;
+    }
+
+    let mut users = Repository::<User>::load();
+
+    let mut user = match select_user(&users, &user_query, &t!("action.login")) {
+        Some(user) => user.clone(),
+        None => return,
+    };
+
+    if user.suspended {
+        error!("{}", t!("user.login_suspended", name = user.name));
+        return;
+    }
+
+    if user.password.is_none() {
+        error!("{}", t!("user.no_password", name = user.name));
+        return;
+    }
+
+    let Some(password) = utilities::prompt_password(&t!("user.password")) else {
+        return;
     };
+    if !verify_password(&user, &password) {
+        error!("{}", t!("user.wrong_password"));
+        return;
+    }
+
+    user.session = Some(session::start(user.id));
+    let name = user.name.clone();
 
-    let found_users = find_users(&users, &user_query);
+    users.replace(user);
+    users.save();
 
-    if let Err(FindError::NoUserFound) = found_users {
-        eprintln!("No user found from given query.");
+    info!("{}", t!("user.logged_in", name = name));
+}
+
+/// Handles logging out of the current session
+pub fn handle_logout() {
+    let Some(user_id) = session::end() else {
+        info!("{}", t!("user.not_logged_in"));
         return;
+    };
+
+    let mut users: Vec<User> = storage::load(Store::Users);
+    if let Some(user) = users.iter_mut().find(|user| user.id == user_id) {
+        user.session = None;
+        storage::save(Store::Users, &users);
     }
 
-    let found_users = found_users.unwrap();
+    info!("{}", t!("user.logged_out"));
+}
 
-    for user in found_users {
-        println!("{:?}", user);
+/// Ranks users by how closely their names or email addresses match the search text
+///
+/// # Arguments
+///
+/// * `users` - The list of users to search
+/// * `text` - The text to search for
+///
+/// # Returns
+///
+/// Every user paired with its best score, best match first
+fn rank_users<'a>(users: &'a [User], text: &str) -> Vec<(f64, &'a User)> {
+    listing::rank(users, |user| {
+        listing::fuzzy_score(&user.name, text).max(listing::fuzzy_score(&user.email, text))
+    })
+}
+
+/// Handles a fuzzy search for users
+///
+/// # Arguments
+///
+/// * `search` - The arguments for the search
+pub fn handle_search_users(search: Search) {
+    let users: Vec<User> = storage::load(Store::Users);
+
+    let results: Vec<(f64, &User)> = rank_users(&users, &search.text)
+        .into_iter()
+        .filter(|(score, _)| *score >= search.min_score)
+        .take(search.limit)
+        .collect();
+
+    if results.is_empty() {
+        error!("{}", t!("user.no_resemblance", text = search.text));
+        return;
+    }
+
+    for (score, user) in results {
+        println!("{:.2}  {:?}", score, user);
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct OnboardUser {
+    /// The ID of the user to onboard
+    #[arg(long)]
+    pub id: u32,
+    /// The genres the user is interested in, instead of asking for them, e.g. `Drama,Comedy`
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    pub genres: Option<Vec<String>>,
+    /// The number of the most used genres to offer
+    #[arg(long, default_value_t = 10)]
+    pub count: usize,
+}
+
+/// Reads the interests picked from the offered genres
+///
+/// # Arguments
+///
+/// * `input` - Comma-separated numbers of offered genres or names of any genre
+/// * `offered` - The genres offered, numbered from 1
+/// * `genres` - Every defined genre
+///
+/// # Returns
+///
+/// The genres picked as they were defined, or a message explaining why the input is invalid
+fn parse_interests(
+    input: &str,
+    offered: &[String],
+    genres: &[String],
+) -> Result<Vec<String>, String> {
+    let mut interests: Vec<String> = vec![];
+    for pick in input
+        .split(',')
+        .map(str::trim)
+        .filter(|pick| !pick.is_empty())
+    {
+        let genre = match pick.parse::<usize>() {
+            Ok(number) if (1..=offered.len()).contains(&number) => &offered[number - 1],
+            _ => genre_subcommands::find_genre(genres, pick)
+                .ok_or_else(|| t!("user.unknown_interest", name = pick))?,
+        };
+        if !interests.contains(genre) {
+            interests.push(genre.clone());
+        }
+    }
+    if interests.is_empty() {
+        return Err(t!("user.no_interests"));
+    }
+    Ok(interests)
+}
+
+/// Handles onboarding a user who hasn't watched anything yet, asking which of the most used
+/// genres interest them so they can be recommended videos right away
+///
+/// # Arguments
+///
+/// * `onboard_user` - The arguments for the onboarding
+pub fn handle_onboard_user(onboard_user: OnboardUser) {
+    let genres: Vec<String> = storage::load(Store::Genres);
+    if genres.is_empty() {
+        warn!("{}", t!("user.no_genres"));
+        return;
+    }
+    let users: Vec<User> = storage::load(Store::Users);
+    let Some(user) = users.iter().find(|user| user.id == onboard_user.id) else {
+        error!("{}", t!("user.not_found_id", id = onboard_user.id));
+        return;
+    };
+    if !session::require_self_or_role(user.id, Role::Moderator, &t!("role.onboard_other_users")) {
+        return;
+    }
+
+    let interests = match onboard_user.genres {
+        Some(names) => match parse_interests(&names.join(","), &[], &genres) {
+            Ok(interests) => interests,
+            Err(e) => {
+                error!("{}", t!("user.interests_not_saved", error = e));
+                return;
+            }
+        },
+        None => {
+            let videos: Vec<Video> = storage::load(Store::Videos);
+            let mut offered = genre_subcommands::genres_with_prefix(&genres, &videos, "");
+            offered.truncate(onboard_user.count);
+
+            println!("{}", t!("user.which_genres", name = user.name));
+            if !user.interests.is_empty() {
+                println!(
+                    "{}",
+                    t!("user.current_interests", genres = user.interests.join(", "))
+                );
+            }
+            for (number, genre) in offered.iter().enumerate() {
+                println!("[{}] {}", number + 1, genre);
+            }
+            let Some(input) = utilities::prompt(&t!("user.pick_interests"), |input| {
+                parse_interests(input, &offered, &genres).map(|_| ())
+            }) else {
+                info!("{}", t!("user.onboarding_cancelled"));
+                return;
+            };
+            parse_interests(&input, &offered, &genres).expect("Interests were validated")
+        }
+    };
+
+    repository::retry(|| {
+        let mut users = Repository::<User>::load();
+        let Some(mut user) = users.get(onboard_user.id).cloned() else {
+            error!("{}", t!("user.not_found_id", id = onboard_user.id));
+            return Ok(());
+        };
+        user.interests = interests.clone();
+        let name = user.name.clone();
+        users.replace(user);
+        users.try_save()?;
+
+        info!(
+            "{}",
+            t!(
+                "user.interested",
+                name = name,
+                genres = interests.join(", ")
+            )
+        );
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interests() {
+        let genres: Vec<String> = ["Drama", "Comedy", "Horror"]
+            .iter()
+            .map(|genre| genre.to_string())
+            .collect();
+        let offered = &genres[..2];
+        assert_eq!(
+            parse_interests("2, horror,1,Comedy", offered, &genres),
+            Ok(vec![
+                "Comedy".to_string(),
+                "Horror".to_string(),
+                "Drama".to_string()
+            ])
+        );
+        assert_eq!(
+            parse_interests("3", offered, &genres),
+            Err("Unknown genre \"3\"".to_string())
+        );
+        assert!(parse_interests(" , ", offered, &genres).is_err());
+    }
+
+    #[test]
+    fn test_public() {
+        let old = User {
+            id: 1,
+            password: Some("$argon2id$hash".to_string()),
+            session: Some("abc".to_string()),
+            ..Default::default()
+        };
+        let public = old.public();
+        assert!(public.get("password").is_none() && public.get("session").is_none());
+        assert_eq!(public["id"], 1);
+
+        let mut sent = User {
+            id: 1,
+            password: Some("chosen by the client".to_string()),
+            ..Default::default()
+        };
+        sent.keep_private_fields(Some(&old));
+        assert_eq!((sent.password, sent.session), (old.password, old.session));
+    }
+
+    #[test]
+    fn test_age() {
+        assert_eq!(age("2000-06-15", "2018-06-14"), Some(17));
+        assert_eq!(age("2000-06-15", "2018-06-15T00:00:00Z"), Some(18));
+        assert_eq!(age("2000-06-15", "1999-01-01"), None);
+        assert_eq!(age("June 2000", "2018-06-15"), None);
+    }
+
+    #[test]
+    fn test_find_user_overlapping_fields() {
+        let user = |id: u32, name: &str, email: &str| User {
+            id,
+            name: name.to_string(),
+            email: email.to_string(),
+            ..Default::default()
+        };
+        let users = Repository::new(vec![
+            user(1, "alice", "alice@example.com"),
+            user(2, "bob", "bob@example.com"),
+        ]);
+        let mut query = UserQuery {
+            id: Some(1),
+            name: Some("alice".to_string()),
+            handle: None,
+            email: Some("alice@example.com".to_string()),
+            name_contains: None,
+            email_contains: None,
+            ignore_case: false,
+            match_mode: MatchMode::Any,
+        };
+        assert_eq!(find_user(&users, &query).unwrap().id, 1);
+
+        query.email = None;
+        query.email_contains = Some("example".to_string());
+        let Err(FindError::MultipleUsersFound(matches)) = find_user(&users, &query) else {
+            panic!("Both users should match");
+        };
+        assert_eq!(
+            matches,
+            MatchedQueries {
+                id: 1,
+                name: 1,
+                email_contains: 2,
+                overlapping: 1,
+                ..Default::default()
+            }
+        );
     }
 }
@@ -1,15 +1,280 @@
-use crate::utilities;
-use clap::Args;
-use rand::Rng;
+use super::genre_subcommands;
+use super::series_subcommands;
+use super::user_subcommands::Role;
+use super::user_subcommands::User;
+use crate::batch;
+use crate::filter::Filter;
+use crate::formatting;
+use crate::formatting::Table;
+use crate::integrity::{OnDelete, Policy, References, Target};
+use crate::listing::{self, Pagination, Search};
+use crate::media;
+use crate::metadata;
+use crate::query::{self, MatchMode};
+use crate::repository::{self, Record, Repository};
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::transfer::{self, Export, Import};
+use crate::utilities::{self, ConfirmCount};
+use crate::validation::Violations;
+use clap::{Args, Parser, ValueEnum};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
 pub struct Video {
     pub id: u32,
     pub name: String,
     pub views: u32,
+    /// What the video is about
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The length of the video in seconds
+    #[serde(default)]
+    pub duration: Option<u32>,
+    /// When the video was created, in seconds since the Unix epoch. Set when the video is first
+    /// saved, so only unknown for videos created before it was recorded
+    #[serde(default)]
+    pub uploaded_at: Option<u64>,
+    /// Who can find the video
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Whether the video is only for adults, so minors can't play it or add views to it
+    #[serde(default)]
+    pub age_restricted: bool,
+    /// The ID of the user who owns the video, if any
+    #[serde(default)]
+    pub owner: Option<u32>,
+    /// The genres of the video, each defined with `rustflix genre create`
+    #[serde(default, with = "crate::transfer::comma_separated")]
+    pub genres: Vec<String>,
+    /// The ID of the series the video is an episode of, if any
+    #[serde(default)]
+    pub series: Option<u32>,
+    /// The season of the series the video is in
+    #[serde(default)]
+    pub season: Option<u32>,
+    /// The number of the episode within its season
+    #[serde(default)]
+    pub episode: Option<u32>,
+    /// The absolute path of the media file of the video, if any
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// The width of the video in pixels, as probed from its media file
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// The height of the video in pixels, as probed from its media file
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// The codec the video is encoded with, as probed from its media file
+    #[serde(default)]
+    pub codec: Option<String>,
+    /// The absolute path of the thumbnail image of the video, if any
+    #[serde(default)]
+    pub thumbnail: Option<PathBuf>,
+    /// When the video last changed, in seconds since the Unix epoch. Set whenever the video is
+    /// saved changed
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+    /// How many times the video was changed, so concurrent changes don't overwrite each other
+    #[serde(default)]
+    pub revision: u32,
+}
+
+/// Who can find a video
+///
+/// # Variants
+///
+/// * `Public` - Listed for everyone
+/// * `Unlisted` - Left out of `video list --all`, but found when asked for by ID or name
+/// * `Private` - Left out of every listing, and only its owner can add views to it
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum Visibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+}
+
+// Written by hand so lengths and dates read naturally and details that were never given are left out
+impl fmt::Debug for Video {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Video");
+        debug
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("views", &format_args!("{}", formatting::count(self.views)));
+        if let Some(description) = &self.description {
+            debug.field("description", description);
+        }
+        if let Some(duration) = self.duration {
+            debug.field("duration", &format_args!("{}", format_duration(duration)));
+        }
+        if let Some(uploaded_at) = self.uploaded_at {
+            debug.field(
+                "uploaded_at",
+                &format_args!("{}", format_timestamp(uploaded_at)),
+            );
+        }
+        if self.visibility != Visibility::Public {
+            debug.field("visibility", &self.visibility);
+        }
+        if self.age_restricted {
+            debug.field("age_restricted", &self.age_restricted);
+        }
+        if let Some(owner) = self.owner {
+            debug.field("owner", &owner);
+        }
+        if !self.genres.is_empty() {
+            debug.field("genres", &self.genres);
+        }
+        if let (Some(series), Some(season), Some(episode)) =
+            (self.series, self.season, self.episode)
+        {
+            debug.field(
+                "episode",
+                &format_args!("S{:02}E{:02} of {}", season, episode, series),
+            );
+        }
+        if let Some(file) = &self.file {
+            debug.field("file", file);
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            debug.field("resolution", &format_args!("{}x{}", width, height));
+        }
+        if let Some(codec) = &self.codec {
+            debug.field("codec", codec);
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            debug.field("thumbnail", thumbnail);
+        }
+        debug.finish()
+    }
+}
+
+/// The fields of a video that can be printed on their own
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum VideoField {
+    Id,
+    Name,
+    Views,
+    Description,
+    Duration,
+    UploadedAt,
+    Visibility,
+    AgeRestricted,
+    Owner,
+    Genres,
+    Series,
+    Season,
+    Episode,
+    File,
+    Resolution,
+    Codec,
+    Thumbnail,
+}
+
+impl Video {
+    /// Adds views to the video, stopping at the most a view count can hold instead of wrapping
+    /// around to 0
+    pub fn add_views(&mut self, views: u32) {
+        self.views = self.views.saturating_add(views);
+    }
+
+    /// Formats the given field of the video for printing
+    pub fn field(&self, field: VideoField) -> String {
+        match field {
+            VideoField::Id => self.id.to_string(),
+            VideoField::Name => self.name.clone(),
+            VideoField::Views => formatting::count(self.views),
+            VideoField::Description => self.description.clone().unwrap_or_default(),
+            VideoField::Duration => self.duration.map(format_duration).unwrap_or_default(),
+            VideoField::UploadedAt => self.uploaded_at.map(format_timestamp).unwrap_or_default(),
+            VideoField::Visibility => listing::field_names(&[self.visibility]).concat(),
+            VideoField::AgeRestricted => self.age_restricted.to_string(),
+            VideoField::Owner => self
+                .owner
+                .map(|owner| owner.to_string())
+                .unwrap_or_default(),
+            VideoField::Genres => self.genres.join(","),
+            VideoField::Series => self.series.map(|id| id.to_string()).unwrap_or_default(),
+            VideoField::Season => self.season.map(|n| n.to_string()).unwrap_or_default(),
+            VideoField::Episode => self.episode.map(|n| n.to_string()).unwrap_or_default(),
+            VideoField::File => self
+                .file
+                .as_ref()
+                .map(|file| file.display().to_string())
+                .unwrap_or_default(),
+            VideoField::Resolution => match (self.width, self.height) {
+                (Some(width), Some(height)) => format!("{}x{}", width, height),
+                _ => String::new(),
+            },
+            VideoField::Codec => self.codec.clone().unwrap_or_default(),
+            VideoField::Thumbnail => self
+                .thumbnail
+                .as_ref()
+                .map(|thumbnail| thumbnail.display().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Parses the length of a video, written either as `1:30:00` or `5:20`, or with units as `90m`
+/// or `1h 30m`
+///
+/// # Arguments
+///
+/// * `text` - The length as written on the command line
+///
+/// # Returns
+///
+/// The length in seconds, or an error message if it can't be read
+pub fn parse_duration(text: &str) -> Result<u32, String> {
+    let seconds = if text.contains(':') {
+        let parts: Vec<&str> = text.split(':').collect();
+        if parts.len() > 3 {
+            return Err(t!("video.invalid_length", text = text));
+        }
+        let mut seconds: u64 = 0;
+        for part in parts {
+            let part: u64 = part
+                .trim()
+                .parse()
+                .map_err(|_| t!("video.invalid_length", text = text))?;
+            seconds = seconds * 60 + part;
+        }
+        seconds
+    } else {
+        humantime::parse_duration(text)
+            .map_err(|e| t!("video.invalid_length_because", text = text, error = e))?
+            .as_secs()
+    };
+
+    u32::try_from(seconds).map_err(|_| t!("video.length_too_long", text = text))
+}
+
+/// Formats the length of a video as `h:mm:ss`, or `m:ss` if it is shorter than an hour, unless
+/// `[format] durations` says otherwise
+pub fn format_duration(seconds: u32) -> String {
+    formatting::duration(seconds)
+}
+
+/// Formats a time given in seconds since the Unix epoch as an RFC 3339 date, e.g.
+/// `2023-06-25T12:00:00Z`, unless `[format] timestamps` says otherwise
+pub fn format_timestamp(timestamp: u64) -> String {
+    formatting::timestamp(timestamp)
+}
+
+/// The current time in seconds since the Unix epoch, as recorded when a video is created
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
 }
 
 #[derive(Debug, Args)]
@@ -20,12 +285,304 @@ pub struct VideoQuery {
     /// The name of the video to query
     #[arg(long, default_value = None)]
     pub name: Option<String>,
+    /// Text contained in the name of the video to query
+    #[arg(long, default_value = None)]
+    pub name_contains: Option<String>,
+    /// Ignore case when matching names
+    #[arg(long, default_value_t = false)]
+    pub ignore_case: bool,
+    /// Whether a video must match any or all of the given fields
+    #[arg(long = "match", value_enum, default_value_t = MatchMode::All)]
+    pub match_mode: MatchMode,
+}
+
+impl Record for Video {
+    const STORE: Store = Store::Videos;
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    fn set_revision(&mut self, revision: u32) {
+        self.revision = revision;
+    }
+}
+
+impl VideoQuery {
+    /// Determines if no field to query by was given
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none() && self.name.is_none() && self.name_contains.is_none()
+    }
+
+    /// Determines which fields of the query match the given video
+    ///
+    /// # Arguments
+    ///
+    /// * `video` - The video to test against the query
+    ///
+    /// # Returns
+    ///
+    /// Every field of the query matching the video
+    fn matching_fields(&self, video: &Video) -> Vec<QueryField> {
+        let mut fields = vec![];
+
+        if let Some(id) = self.id {
+            if video.id == id {
+                fields.push(QueryField::Id);
+            }
+        }
+
+        if let Some(name) = &self.name {
+            if query::text_equals(&video.name, name, self.ignore_case) {
+                fields.push(QueryField::Name);
+            }
+        }
+
+        if let Some(name) = &self.name_contains {
+            if query::text_contains(&video.name, name, self.ignore_case) {
+                fields.push(QueryField::NameContains);
+            }
+        }
+
+        fields
+    }
+
+    /// The number of fields given in the query
+    fn given_fields(&self) -> usize {
+        [
+            self.id.is_some(),
+            self.name.is_some(),
+            self.name_contains.is_some(),
+        ]
+        .iter()
+        .filter(|given| **given)
+        .count()
+    }
+
+    /// Determines if the given video matches the query as a whole, following its match mode
+    ///
+    /// # Arguments
+    ///
+    /// * `video` - The video to test against the query
+    fn matches(&self, video: &Video) -> bool {
+        self.match_mode
+            .is_match(self.matching_fields(video).len(), self.given_fields())
+    }
+}
+
+/// The fields a video can be queried by
+enum QueryField {
+    Id,
+    Name,
+    NameContains,
 }
 
 #[derive(Debug, Args)]
 pub struct CreateVideo {
     /// The name of the video
-    pub name: String,
+    #[arg(required_unless_present_any = ["interactive", "from_stdin"])]
+    pub name: Option<String>,
+    /// Ask for each field in turn instead of taking them as arguments
+    #[arg(short, long, default_value_t = false, conflicts_with = "name")]
+    pub interactive: bool,
+    /// What the video is about
+    #[arg(long, default_value = None)]
+    pub description: Option<String>,
+    /// The length of the video, e.g. `90m` or `1:30:00`
+    #[arg(long, default_value = None, value_parser = parse_duration)]
+    pub duration: Option<u32>,
+    /// Who can find the video
+    #[arg(long, value_enum, default_value_t = Visibility::Public)]
+    pub visibility: Visibility,
+    /// Only let adults play the video or add views to it
+    #[arg(long, default_value_t = false)]
+    pub age_restricted: bool,
+    /// The ID of the user who owns the video
+    #[arg(long, default_value = None)]
+    pub owner: Option<u32>,
+    /// The comma-separated genres of the video
+    #[arg(long = "genre", value_delimiter = ',')]
+    pub genres: Vec<String>,
+    /// Define any genre given that doesn't exist yet instead of refusing it
+    #[arg(long, default_value_t = false)]
+    pub allow_new_genre: bool,
+    /// The ID of the series the video is an episode of
+    #[arg(long, default_value = None, requires_all = ["season", "episode"])]
+    pub series: Option<u32>,
+    /// The season of the series the video is in
+    #[arg(long, default_value = None, requires = "series")]
+    pub season: Option<u32>,
+    /// The number of the episode within its season
+    #[arg(long, default_value = None, requires = "series")]
+    pub episode: Option<u32>,
+    /// The media file of the video
+    #[arg(long, default_value = None)]
+    pub file: Option<PathBuf>,
+    /// Read the duration, resolution and codec of the video from its media file using ffprobe
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "file",
+        conflicts_with = "duration"
+    )]
+    pub probe: bool,
+    /// Create a video per line of stdin instead, given as JSON Lines, e.g.
+    /// `{"name": "Big Buck Bunny", "duration": "10m", "genres": "animation,comedy"}`, or as CSV
+    /// with a header naming the fields
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["name", "interactive", "description", "duration", "owner", "genres", "series", "file"]
+    )]
+    pub from_stdin: bool,
+}
+
+/// A video to create, as read from a line of stdin. Only the name is required
+///
+/// # Fields
+///
+/// * `name` - The name of the video
+/// * `description` - What the video is about
+/// * `duration` - The length of the video, e.g. `90m` or `1:30:00`
+/// * `visibility` - Who can find the video, e.g. `unlisted`
+/// * `age_restricted` - Whether only adults can play the video
+/// * `owner` - The ID of the user who owns the video
+/// * `genres` - The comma-separated genres of the video
+/// * `series` - The ID of the series the video is an episode of
+/// * `season` - The season of the series the video is in
+/// * `episode` - The number of the episode within its season
+/// * `file` - The media file of the video
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NewVideo {
+    name: String,
+    description: Option<String>,
+    duration: Option<String>,
+    visibility: Option<String>,
+    age_restricted: Option<bool>,
+    owner: Option<u32>,
+    genres: Option<String>,
+    series: Option<u32>,
+    season: Option<u32>,
+    episode: Option<u32>,
+    file: Option<PathBuf>,
+}
+
+impl NewVideo {
+    /// Turns the video read into the arguments to create it with, as if they were given on the
+    /// command line
+    ///
+    /// # Arguments
+    ///
+    /// * `allow_new_genre` - Whether to define any genre given that doesn't exist yet
+    ///
+    /// # Returns
+    ///
+    /// The arguments, or an error message if a field is invalid
+    fn into_args(self, allow_new_genre: bool) -> Result<CreateVideo, String> {
+        if self.series.is_some() && (self.season.is_none() || self.episode.is_none()) {
+            return Err(t!("video.episode_numbers"));
+        }
+        Ok(CreateVideo {
+            name: Some(self.name),
+            interactive: false,
+            description: self.description,
+            duration: self.duration.as_deref().map(parse_duration).transpose()?,
+            visibility: match self.visibility {
+                Some(visibility) => Visibility::from_str(&visibility, true)
+                    .map_err(|_| t!("video.invalid_visibility", visibility = visibility))?,
+                None => Visibility::Public,
+            },
+            age_restricted: self.age_restricted.unwrap_or_default(),
+            owner: self.owner,
+            genres: self
+                .genres
+                .iter()
+                .flat_map(|genres| genres.split(','))
+                .map(|genre| genre.trim().to_string())
+                .filter(|genre| !genre.is_empty())
+                .collect(),
+            allow_new_genre,
+            series: self.series,
+            season: self.season,
+            episode: self.episode,
+            file: self.file,
+            probe: false,
+            from_stdin: false,
+        })
+    }
+}
+
+/// Fills in the duration, resolution and codec of a video by probing its media file
+///
+/// # Arguments
+///
+/// * `video` - The video to probe, which must have a media file
+///
+/// # Returns
+///
+/// `false` after reporting an error if the file could not be probed
+fn probe_video(video: &mut Video) -> bool {
+    let file = video.file.as_ref().unwrap();
+    let probe = match media::probe(file) {
+        Ok(probe) => probe,
+        Err(e) => {
+            error!(
+                "{}",
+                t!("video.could_not_probe", path = file.display(), error = e)
+            );
+            return false;
+        }
+    };
+    video.duration = probe.duration.or(video.duration);
+    video.width = probe.width;
+    video.height = probe.height;
+    video.codec = probe.codec;
+    true
+}
+
+/// Checks that a file exists, so it can be attached to a video
+///
+/// # Returns
+///
+/// The absolute path of the file, or `None` after reporting an error if it isn't a file
+pub fn resolve_file(file: &Path) -> Option<PathBuf> {
+    match file.canonicalize() {
+        Ok(path) if path.is_file() => Some(path),
+        Ok(_) => {
+            error!("{}", t!("video.not_a_file", path = file.display()));
+            None
+        }
+        Err(e) => {
+            error!(
+                "{}",
+                t!("video.cannot_attach", path = file.display(), error = e)
+            );
+            None
+        }
+    }
+}
+
+/// Checks that a user with the given ID exists, so it can own a video
+///
+/// # Returns
+///
+/// `false` after reporting an error if there is no such user
+fn owner_exists(owner: u32) -> bool {
+    let users: Vec<User> = storage::load(Store::Users);
+    if !users.iter().any(|user| user.id == owner) {
+        error!("{}", t!("user.not_found_id", id = owner));
+        return false;
+    }
+    true
 }
 
 /// Determines if the list of videos contains a video with the given ID
@@ -48,7 +605,7 @@ fn has_id(videos: &Vec<Video>, id: u32) -> bool {
     false
 }
 
-/// Generates an unused ID for a new video
+/// Generates an unused ID for a new video, following the ID strategy of the stores
 ///
 /// # Arguments
 ///
@@ -56,13 +613,8 @@ fn has_id(videos: &Vec<Video>, id: u32) -> bool {
 ///
 /// # Returns
 /// A valid ID that is not already in use by a video
-fn generate_valid_id(videos: &Vec<Video>) -> u32 {
-    let mut rng = rand::thread_rng();
-    let mut id = rng.gen_range(0..=std::u32::MAX);
-    while has_id(videos, id) {
-        id = rng.gen_range(0..=std::u32::MAX);
-    }
-    id
+pub fn generate_valid_id(videos: &Vec<Video>) -> u32 {
+    metadata::generate_id(Store::Videos, |id| has_id(videos, id))
 }
 
 /// Handles the creation of a new video
@@ -71,31 +623,133 @@ fn generate_valid_id(videos: &Vec<Video>) -> u32 {
 ///
 /// * `create_video` - The arguments for the video creation
 pub fn handle_create_video(create_video: CreateVideo) {
-    let path = concat!(env!("HOME"), "/.rustflix/videos.bc");
-    let path = Path::new(path);
-    let mut videos: Vec<Video> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
+    if !session::require_role(Role::Moderator, &t!("role.create_videos")) {
+        return;
+    }
+
+    if create_video.from_stdin {
+        let records = match transfer::read_stdin::<NewVideo>() {
+            Ok(records) => records,
+            Err(e) => {
+                error!("{}", t!("transfer.stdin_failed", error = e));
+                return;
+            }
+        };
+        batch::create_each(records, &t!("transfer.videos"), |video| {
+            match video.into_args(create_video.allow_new_genre) {
+                Ok(args) => handle_create_video(args),
+                Err(e) => error!("{}. {}", t!("video.not_created"), e),
+            }
+        });
+        return;
+    }
+
+    let mut violations = Violations::new();
+    if let Some(ref name) = create_video.name {
+        violations.name(&t!("field.name"), name);
+    }
+    if let Some(ref description) = create_video.description {
+        violations.text(&t!("field.description"), description);
+    }
+    if !violations.report(&t!("video.not_created")) {
+        return;
+    }
+
+    if let Some(owner) = create_video.owner {
+        if !owner_exists(owner) {
+            return;
+        }
+    }
+
+    let genres =
+        match genre_subcommands::resolve_genres(&create_video.genres, create_video.allow_new_genre)
+        {
+            Some(genres) => genres,
+            None => return,
+        };
+
+    let file = match create_video.file {
+        Some(ref file) => match resolve_file(file) {
+            Some(file) => Some(file),
+            None => return,
+        },
+        None => None,
+    };
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+
+    let name = if create_video.interactive {
+        match prompt_new_video() {
+            Some(name) => name,
+            None => return,
+        }
     } else {
-        vec![]
+        formatting::normalize(&create_video.name.unwrap())
     };
 
-    let video = Video {
-        id: generate_valid_id(&videos),
-        name: create_video.name,
+    let id = generate_valid_id(&videos);
+
+    if let (Some(series), Some(season), Some(episode)) = (
+        create_video.series,
+        create_video.season,
+        create_video.episode,
+    ) {
+        if !series_subcommands::validate_episode(&videos, id, series, season, episode) {
+            return;
+        }
+    }
+
+    let mut video = Video {
+        id,
+        name,
         views: 0,
+        description: create_video.description,
+        duration: create_video.duration,
+        uploaded_at: Some(now()),
+        visibility: create_video.visibility,
+        age_restricted: create_video.age_restricted,
+        owner: create_video.owner,
+        genres,
+        series: create_video.series,
+        season: create_video.season,
+        episode: create_video.episode,
+        file,
+        ..Default::default()
     };
 
-    videos.push(video.clone());
+    if create_video.probe && !probe_video(&mut video) {
+        return;
+    }
 
-    let file = File::create(path).unwrap();
+    videos.push(video.clone());
 
-    bincode::serialize_into(file, &videos).unwrap();
+    storage::save(Store::Videos, &videos);
 
-    println!("Video created successfully");
+    info!("{}", t!("video.created"));
     println!("ID: {}", video.id);
 }
 
+/// Asks for each field of a new video, validating them as they are entered
+///
+/// # Returns
+///
+/// The name of the new video, or `None` if creation was cancelled
+fn prompt_new_video() -> Option<String> {
+    let name = utilities::prompt(&t!("field.name"), utilities::validate_name)?;
+
+    if !utilities::confirm(
+        &t!("video.confirm_create"),
+        Some(format!("{}: {}", t!("field.name"), name).as_str()),
+        Some(&t!("video.create_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return None;
+    }
+
+    Some(name)
+}
+
 #[derive(Debug, Args)]
 pub struct UpdateVideo {
     /// The ID of the video to update
@@ -104,6 +758,15 @@ pub struct UpdateVideo {
     /// The name of the video to update
     #[arg(long, default_value = None)]
     pub query_name: Option<String>,
+    /// Text contained in the name of the video to update
+    #[arg(long, default_value = None)]
+    pub query_name_contains: Option<String>,
+    /// Ignore case when matching names
+    #[arg(long, default_value_t = false)]
+    pub ignore_case: bool,
+    /// Whether a video must match any or all of the given fields
+    #[arg(long = "match", value_enum, default_value_t = MatchMode::All)]
+    pub match_mode: MatchMode,
 
     /// The new name of the video
     #[arg(long, default_value = None)]
@@ -112,6 +775,50 @@ pub struct UpdateVideo {
     /// The new number of views of the video
     #[arg(long, default_value = None)]
     pub new_views: Option<u32>,
+
+    /// The new description of the video
+    #[arg(long, default_value = None)]
+    pub new_description: Option<String>,
+
+    /// The new length of the video, e.g. `90m` or `1:30:00`
+    #[arg(long, default_value = None, value_parser = parse_duration)]
+    pub new_duration: Option<u32>,
+
+    /// Who can find the video from now on
+    #[arg(long, value_enum, visible_alias = "visibility", default_value = None)]
+    pub new_visibility: Option<Visibility>,
+
+    /// Whether only adults may play the video or add views to it from now on
+    #[arg(long, visible_alias = "age-restricted", default_value = None)]
+    pub new_age_restricted: Option<bool>,
+
+    /// The ID of the user who owns the video from now on
+    #[arg(long, default_value = None)]
+    pub new_owner: Option<u32>,
+
+    /// The comma-separated genres replacing those of the video
+    #[arg(long, visible_alias = "genre", value_delimiter = ',', num_args = 1..)]
+    pub new_genres: Option<Vec<String>>,
+
+    /// Define any genre given that doesn't exist yet instead of refusing it
+    #[arg(long, default_value_t = false)]
+    pub allow_new_genre: bool,
+
+    /// The ID of the series the video is an episode of from now on
+    #[arg(long, visible_alias = "series", default_value = None)]
+    pub new_series: Option<u32>,
+
+    /// The season of the series the video is in from now on
+    #[arg(long, visible_alias = "season", default_value = None)]
+    pub new_season: Option<u32>,
+
+    /// The number of the episode within its season from now on
+    #[arg(long, visible_alias = "episode", default_value = None)]
+    pub new_episode: Option<u32>,
+
+    /// The media file of the video from now on
+    #[arg(long, visible_alias = "file", default_value = None)]
+    pub new_file: Option<PathBuf>,
 }
 
 /// Error returned from `find_video`
@@ -132,41 +839,101 @@ pub enum FindError {
 ///
 /// * `id` - The number of matches for the ID query
 /// * `name` - The number of matches for the name query
-#[derive(Debug)]
+/// * `name_contains` - The number of matches for the name substring query
+/// * `overlapping` - The number of videos matched by more than one field, which are counted once
+///   for each of them above
+#[derive(Debug, Default, PartialEq)]
 pub struct MatchedQueries {
     pub id: u32,
     pub name: u32,
+    pub name_contains: u32,
+    pub overlapping: u32,
+}
+
+impl MatchedQueries {
+    /// Prints the number of matches for each field given in the query
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query the matches were counted for
+    pub fn print(&self, query: &VideoQuery) {
+        if query.id.is_some() {
+            warn!("{}", t!("query.id_matches", count = self.id));
+        }
+        if query.name.is_some() {
+            warn!("{}", t!("query.name_matches", count = self.name));
+        }
+        if query.name_contains.is_some() {
+            warn!(
+                "{}",
+                t!("query.name_contains_matches", count = self.name_contains)
+            );
+        }
+        if self.overlapping > 0 {
+            warn!("{}", t!("video.overlapping", count = self.overlapping));
+        }
+    }
+}
+
+/// Narrows down the videos that may match a query using the indexes of the repository, so only
+/// those have to be tested against it
+///
+/// An ID or name in the query is looked up directly when every field must match, or when it is
+/// the only field given. Otherwise, every video may match.
+///
+/// # Arguments
+///
+/// * `videos` - The videos to search
+/// * `query` - The query to search for
+///
+/// # Returns
+///
+/// The videos that may match the query, in the order they are kept
+fn candidates<'a>(videos: &'a Repository<Video>, query: &VideoQuery) -> Vec<&'a Video> {
+    if query.match_mode == MatchMode::All || query.given_fields() == 1 {
+        if let Some(id) = query.id {
+            return videos.with_id(id);
+        }
+        if let Some(name) = &query.name {
+            return videos.with_name(name);
+        }
+    }
+    videos.iter().collect()
 }
 
 /// Finds a video in the given list of videos matching the given query
 ///
 /// # Arguments
 ///
-/// * `videos` - The list of videos to search
+/// * `videos` - The videos to search
 /// * `query` - The query to search for
 ///
 /// # Returns
 ///
 /// The video matching the given query. If multiple or none are found, returns a `FindError` variant matching the error case.
-pub fn find_video<'a>(videos: &'a Vec<Video>, query: &VideoQuery) -> Result<&'a Video, FindError> {
+pub fn find_video<'a>(
+    videos: &'a Repository<Video>,
+    query: &VideoQuery,
+) -> Result<&'a Video, FindError> {
     let mut found_videos: Vec<&Video> = vec![];
-    let mut id_matches = 0;
-    let mut name_matches = 0;
+    let mut matches = MatchedQueries::default();
 
-    for video in videos {
-        if let Some(id) = query.id {
-            if video.id == id {
-                found_videos.push(video);
-                id_matches += 1;
-                continue;
-            }
+    // Each video is matched once, however many fields it matches
+    for video in candidates(videos, query) {
+        if !query.matches(video) {
+            continue;
         }
 
-        if let Some(name) = &query.name {
-            if video.name == *name {
-                found_videos.push(video);
-                name_matches += 1;
-                continue;
+        found_videos.push(video);
+        let fields = query.matching_fields(video);
+        if fields.len() > 1 {
+            matches.overlapping += 1;
+        }
+        for field in fields {
+            match field {
+                QueryField::Id => matches.id += 1,
+                QueryField::Name => matches.name += 1,
+                QueryField::NameContains => matches.name_contains += 1,
             }
         }
     }
@@ -176,181 +943,472 @@ pub fn find_video<'a>(videos: &'a Vec<Video>, query: &VideoQuery) -> Result<&'a
     }
 
     if found_videos.len() > 1 {
-        return Err(FindError::MultipleVideosFound(MatchedQueries {
-            id: id_matches,
-            name: name_matches,
-        }));
+        return Err(FindError::MultipleVideosFound(matches));
     }
 
     Ok(found_videos[0])
 }
 
+/// Finds the single video matching the given query, letting the user pick one if several match
+///
+/// # Arguments
+///
+/// * `videos` - The list of videos to search
+/// * `query` - The query to search for
+/// * `action` - The name of the action being performed, used in messages
+///
+/// # Returns
+///
+/// The video matching the query, or `None` if none was found or the user cancelled
+pub fn select_video<'a>(
+    videos: &'a Repository<Video>,
+    query: &VideoQuery,
+    action: &str,
+) -> Option<&'a Video> {
+    match find_video(videos, query) {
+        Ok(video) => Some(video),
+        Err(FindError::NoVideoFound) => {
+            error!("{}", t!("video.query_failed", action = action));
+            None
+        }
+        Err(FindError::MultipleVideosFound(counts)) => {
+            warn!("{}", t!("video.multiple_found"));
+            counts.print(query);
+
+            let candidates: Vec<&Video> = candidates(videos, query)
+                .into_iter()
+                .filter(|video| query.matches(video))
+                .collect();
+            let cancel_message = t!("query.cancelled", action = action);
+            utilities::choose(
+                &t!("video.which"),
+                &candidates,
+                Some(cancel_message.as_str()),
+            )
+            .map(|choice| candidates[choice])
+        }
+    }
+}
+
+/// Validates the changes given to `video update`
+///
+/// # Returns
+///
+/// `false` after reporting every invalid change if any is
+fn validate_changes(update_video: &UpdateVideo) -> bool {
+    let mut violations = Violations::new();
+    if let Some(ref name) = update_video.new_name {
+        violations.name(&t!("field.name"), name);
+    }
+    if let Some(views) = update_video.new_views {
+        violations.views(views);
+    }
+    if let Some(ref description) = update_video.new_description {
+        violations.text(&t!("field.description"), description);
+    }
+    violations.report(&t!("video.not_updated"))
+}
+
+/// Makes the changes given to `video update` to a video
+///
+/// # Arguments
+///
+/// * `videos` - The videos, used to check the episode of a series is free
+/// * `video` - The video to change
+/// * `update_video` - The changes to make
+///
+/// # Returns
+///
+/// `false` after reporting an error if a change is invalid, in which case the video may have been
+/// changed in part
+fn apply_changes(
+    videos: &Repository<Video>,
+    video: &mut Video,
+    update_video: &UpdateVideo,
+) -> bool {
+    if let Some(ref name) = update_video.new_name {
+        video.name = formatting::normalize(name)
+    }
+
+    if let Some(views) = update_video.new_views {
+        video.views = views;
+    }
+
+    if let Some(ref description) = update_video.new_description {
+        video.description = Some(description.clone());
+    }
+
+    if let Some(duration) = update_video.new_duration {
+        video.duration = Some(duration);
+    }
+
+    if let Some(visibility) = update_video.new_visibility {
+        video.visibility = visibility;
+    }
+
+    if let Some(age_restricted) = update_video.new_age_restricted {
+        video.age_restricted = age_restricted;
+    }
+
+    if let Some(owner) = update_video.new_owner {
+        if !owner_exists(owner) {
+            return false;
+        }
+        video.owner = Some(owner);
+    }
+
+    if let Some(ref genres) = update_video.new_genres {
+        match genre_subcommands::resolve_genres(genres, update_video.allow_new_genre) {
+            Some(genres) => video.genres = genres,
+            None => return false,
+        }
+    }
+
+    if update_video.new_series.is_some()
+        || update_video.new_season.is_some()
+        || update_video.new_episode.is_some()
+    {
+        let series = update_video.new_series.or(video.series);
+        let season = update_video.new_season.or(video.season);
+        let episode = update_video.new_episode.or(video.episode);
+        let (Some(series), Some(season), Some(episode)) = (series, season, episode) else {
+            error!("{}", t!("video.episode_incomplete"));
+            return false;
+        };
+        if !series_subcommands::validate_episode(videos, video.id, series, season, episode) {
+            return false;
+        }
+        video.series = Some(series);
+        video.season = Some(season);
+        video.episode = Some(episode);
+    }
+
+    if let Some(ref file) = update_video.new_file {
+        match resolve_file(file) {
+            Some(file) => video.file = Some(file),
+            None => return false,
+        }
+    }
+    true
+}
+
 /// Handles the updating of an existing video
 ///
 /// # Arguments
 ///
 /// * `update_video` - The arguments for the video update
 pub fn handle_update_video(update_video: UpdateVideo) {
-    if update_video.query_id.is_none() && update_video.query_name.is_none() {
-        eprintln!("No query given. Please provide an ID or name");
+    if !session::require_role(Role::Moderator, &t!("role.update_videos")) {
         return;
     }
 
-    let path = concat!(env!("HOME"), "/.rustflix/videos.bc");
-    let path = Path::new(path);
-    let mut videos: Vec<Video> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
-    };
+    if update_video.new_views.is_some()
+        && !session::require_role(Role::Admin, &t!("role.set_view_counts"))
+    {
+        return;
+    }
 
     let video_query = VideoQuery {
         id: update_video.query_id,
         name: update_video.query_name.clone(),
+        name_contains: update_video.query_name_contains.clone(),
+        ignore_case: update_video.ignore_case,
+        match_mode: update_video.match_mode,
     };
 
-    let video = find_video(&videos, &video_query);
-
-    if let Err(e) = video {
-        match e {
-            FindError::NoVideoFound => eprintln!("Update failed. No video found from given query."),
-            FindError::MultipleVideosFound(counts) => {
-                eprintln!("Update failed. Multiple videos found from given query.");
-                if update_video.query_id.is_some() {
-                    eprintln!("ID matches: {}", counts.id);
-                }
-                if update_video.query_name.is_some() {
-                    eprintln!("Name matches: {}", counts.name);
-                }
-            }
-        }
+    if video_query.is_empty() {
+        error!("{}", t!("video.no_query"));
         return;
     }
 
-    let video = video.unwrap();
-
-    let video_index = videos.iter().position(|u| u == video);
-
-    if video_index.is_none() {
-        panic!("Video was found but its index wasn't. This should never happen.");
+    if !validate_changes(&update_video) {
+        return;
     }
 
-    let video_index = video_index.unwrap();
-
-    let og_video_state = videos[video_index].clone();
+    repository::retry(|| {
+        let mut videos = Repository::<Video>::load();
+
+        let mut video = match select_video(&videos, &video_query, &t!("action.update")) {
+            Some(video) => video.clone(),
+            None => return Ok(()),
+        };
+
+        let og_video_state = video.clone();
+
+        if let Some(views) = update_video.new_views {
+            if !utilities::confirm(
+                &t!("video.confirm_views", name = video.name, views = views),
+                None,
+                Some(&t!("video.update_cancelled")),
+                Some(true),
+                utilities::prompt_timeout(),
+            ) {
+                return Ok(());
+            }
+        }
 
-    if let Some(ref name) = update_video.new_name {
-        videos[video_index].name = name.clone()
-    }
+        if !apply_changes(&videos, &mut video, &update_video) {
+            return Ok(());
+        }
 
-    if let Some(views) = update_video.new_views {
-        if !utilities::confirm(
-            format!(
-                "Are you sure you want to set the views of {} to {}?",
-                videos[video_index].name, views
-            )
-            .as_str(),
-            None,
-            Some("Video update aborted."),
-            Some(true),
-        ) {
-            return;
+        videos.replace(video.clone());
+        videos.try_save()?;
+
+        info!("{}", t!("video.updated"));
+        if update_video.new_name.is_some() {
+            info!(
+                "{}",
+                t!(
+                    "record.name_changed",
+                    old = og_video_state.name,
+                    new = video.name
+                )
+            );
         }
+        Ok(())
+    });
+}
+
+/// The fields `video update-many --set` can change
+const SETTABLE_FIELDS: [&str; 12] = [
+    "name",
+    "views",
+    "description",
+    "duration",
+    "visibility",
+    "age_restricted",
+    "owner",
+    "genres",
+    "series",
+    "season",
+    "episode",
+    "file",
+];
+
+/// Parses a change given to `--set`, e.g. `visibility=private`
+///
+/// # Returns
+///
+/// The field and its new value, or an error message if the field can't be set
+fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    let Some((field, value)) = assignment.split_once('=') else {
+        return Err(t!("video.invalid_assignment", assignment = assignment));
+    };
+    let field = field.trim().replace('-', "_");
+    if !SETTABLE_FIELDS.contains(&field.as_str()) {
+        return Err(t!(
+            "video.not_settable",
+            field = field,
+            fields = SETTABLE_FIELDS.join(", ")
+        ));
     }
+    Ok((field, value.trim().to_string()))
+}
 
-    let file = File::create(path).unwrap();
-    bincode::serialize_into(file, &videos).unwrap();
+#[derive(Debug, Args)]
+pub struct UpdateManyVideos {
+    /// Which videos to update, e.g. `views > 1000 && name contains "old"`. Fields are compared
+    /// with ==, !=, >, >=, <, <=, or contains, and combined with &&, ||, !, and parentheses. Text
+    /// is compared ignoring case
+    #[arg(long = "where")]
+    pub filter: Filter,
+    /// A change to make to every video matched, e.g. `visibility=private`. Can be given more than
+    /// once
+    #[arg(long = "set", required = true, value_parser = parse_assignment)]
+    pub changes: Vec<(String, String)>,
+    /// Define any genre given that doesn't exist yet instead of refusing it
+    #[arg(long, default_value_t = false)]
+    pub allow_new_genre: bool,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
 
-    println!("Video updated successfully.");
-    if update_video.new_name.is_some() {
-        println!(
-            "Name changed from {} to {}",
-            og_video_state.name, videos[video_index].name
-        );
+/// The changes of `video update-many`, parsed as the options `video update` takes for them, so
+/// they are read and checked the same way
+#[derive(Debug, Parser)]
+#[command(no_binary_name = true)]
+struct Changes {
+    #[command(flatten)]
+    update_video: UpdateVideo,
+}
+
+impl UpdateManyVideos {
+    /// Turns the changes given to `--set` into the arguments of `video update` making them
+    ///
+    /// # Returns
+    ///
+    /// The arguments, or an error message if a value is invalid for its field
+    fn update_video(&self) -> Result<UpdateVideo, String> {
+        let mut arguments = vec![];
+        for (field, value) in &self.changes {
+            arguments.push(format!("--new-{}", field.replace('_', "-")));
+            arguments.push(value.clone());
+        }
+        if self.allow_new_genre {
+            arguments.push("--allow-new-genre".to_string());
+        }
+        Changes::try_parse_from(arguments)
+            .map(|changes| changes.update_video)
+            .map_err(|e| e.render().to_string().trim_end().to_string())
     }
 }
 
-pub fn handle_delete_video(video_query: VideoQuery) {
-    if video_query.id.is_none() && video_query.name.is_none() {
-        eprintln!("No query given. Please provide an ID or name");
+/// Handles updating every video matching a filter, showing them and asking to confirm first, and
+/// writing them all at once
+///
+/// # Arguments
+///
+/// * `update_many` - The arguments for the update
+pub fn handle_update_many_videos(update_many: UpdateManyVideos) {
+    if !session::require_role(Role::Moderator, &t!("role.update_videos")) {
         return;
     }
 
-    let path = concat!(env!("HOME"), "/.rustflix/videos.bc");
-    let path = Path::new(path);
-    let mut videos: Vec<Video> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
-    };
-
-    let video = find_video(&videos, &video_query);
-
-    if let Err(e) = video {
-        match e {
-            FindError::NoVideoFound => eprintln!("Delete failed. No video found from given query."),
-            FindError::MultipleVideosFound(counts) => {
-                eprintln!("Delete failed. Multiple videos found from given query.");
-                if video_query.id.is_some() {
-                    eprintln!("ID matches: {}", counts.id);
-                }
-                if video_query.name.is_some() {
-                    eprintln!("Name matches: {}", counts.name);
-                }
-            }
+    let update_video = match update_many.update_video() {
+        Ok(update_video) => update_video,
+        Err(e) => {
+            error!("{}", e);
+            return;
         }
+    };
+    if update_video.new_views.is_some()
+        && !session::require_role(Role::Admin, &t!("role.set_view_counts"))
+    {
         return;
     }
-
-    let video = video.unwrap();
-
-    let video_index = videos.iter().position(|u| u == video);
-
-    if video_index.is_none() {
-        panic!("Video was found but its index wasn't. This should never happen.");
+    if !validate_changes(&update_video) {
+        return;
     }
 
-    if !utilities::confirm(
-        "Are you sure you want to delete this video?",
-        Some(format!("{:?}", video).as_str()),
-        Some("Video deletion cancelled."),
-        Some(true),
-    ) {
+    let fields = match serde_json::to_value(Video::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect::<Vec<_>>(),
+        _ => vec![],
+    };
+    if let Some(field) = update_many.filter.unknown_field(&fields) {
+        error!(
+            "{}",
+            t!("video.no_field", field = field, fields = fields.join(", "))
+        );
         return;
     }
 
-    let video_index = video_index.unwrap();
+    repository::retry(|| {
+        let mut videos = Repository::<Video>::load();
+        let matched: Vec<Video> = videos
+            .iter()
+            .filter(|video| {
+                serde_json::to_value(video).is_ok_and(|video| update_many.filter.matches(&video))
+            })
+            .cloned()
+            .collect();
+        if matched.is_empty() {
+            warn!("{}", t!("video.none_matched"));
+            return Ok(());
+        }
 
-    videos.remove(video_index);
+        // The videos are always shown, even when the update is confirmed beforehand
+        for video in &matched {
+            println!("{:?}", video);
+        }
+        let changes: Vec<String> = update_many
+            .changes
+            .iter()
+            .map(|(field, value)| format!("{}: {}", field, value))
+            .collect();
+        if !utilities::confirm_bulk(
+            &t!("video.confirm_update_many", count = matched.len()),
+            Some(&changes.join("\n")),
+            Some(&t!("video.update_cancelled")),
+            Some(true),
+            matched.len(),
+            &update_many.confirm_count,
+        ) {
+            return Ok(());
+        }
 
-    let file = File::create(path).unwrap();
-    bincode::serialize_into(file, &videos).unwrap();
+        let count = matched.len();
+        for mut video in matched {
+            if !apply_changes(&videos, &mut video, &update_video) {
+                return Ok(());
+            }
+            videos.replace(video);
+        }
+        videos.try_save()?;
 
-    println!("Video deleted successfully.");
+        info!("{}", t!("video.updated_many", count = count));
+        Ok(())
+    });
 }
 
-fn find_videos(videos: &Vec<Video>, video_query: &VideoQuery) -> Result<Vec<Video>, FindError> {
-    let mut found_videos: Vec<Video> = vec![];
+#[derive(Debug, Args)]
+pub struct DeleteVideo {
+    #[command(flatten)]
+    pub query: VideoQuery,
+    #[command(flatten)]
+    pub on_delete: OnDelete,
+    #[command(flatten)]
+    pub confirm_count: ConfirmCount,
+}
 
-    for video in videos {
-        if video_query.id.is_some() && video.id == video_query.id.unwrap() {
-            found_videos.push(video.clone());
-            continue;
+/// Handles deleting a video. It is only deleted while no watch history entry refers to it, unless
+/// `--cascade` or `--orphan` says what to do with them
+///
+/// # Arguments
+///
+/// * `delete_video` - The arguments for the video deletion
+pub fn handle_delete_video(delete_video: DeleteVideo) {
+    if !session::require_role(Role::Admin, &t!("role.delete_videos")) {
+        return;
+    }
+
+    let video_query = delete_video.query;
+    if video_query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
+    }
+
+    repository::retry(|| {
+        let mut videos = Repository::<Video>::load();
+
+        let video = match select_video(&videos, &video_query, &t!("action.delete")) {
+            Some(video) => video,
+            None => return Ok(()),
+        };
+
+        let policy = delete_video.on_delete.policy();
+        let references = References::find(Target::Video(video.id));
+        if policy == Policy::Refuse && !references.is_empty() {
+            error!(
+                "{}",
+                t!(
+                    "video.referred_to",
+                    name = video.name,
+                    references = references.describe()
+                )
+            );
+            return Ok(());
         }
 
-        if video_query.name.is_some() && video.name == video_query.name.clone().unwrap() {
-            found_videos.push(video.clone());
-            continue;
+        if !utilities::confirm_bulk(
+            &t!("video.confirm_delete"),
+            Some(format!("{:?}\n{}", video, references.summary(policy)).as_str()),
+            Some(&t!("video.delete_cancelled")),
+            Some(true),
+            references.affected(policy),
+            &delete_video.confirm_count,
+        ) {
+            return Ok(());
         }
-    }
 
-    if found_videos.is_empty() {
-        return Err(FindError::NoVideoFound);
-    }
+        let id = video.id;
+        videos.remove(id);
+
+        videos.try_save()?;
+        references.resolve(policy);
 
-    Ok(found_videos)
+        info!("{}", t!("video.deleted"));
+        Ok(())
+    });
 }
 
 #[derive(Debug, Args)]
@@ -360,62 +1418,274 @@ pub struct ListVideo {
         short,
         long,
         default_value_t = false,
-        conflicts_with = "id",
-        conflicts_with = "name"
+        conflicts_with_all = ["id", "name", "name_contains"]
     )]
     pub all: bool,
-    /// The ID of the video to query
-    #[arg(long, default_value = None)]
-    pub id: Option<u32>,
-    /// The name of the video to query
-    #[arg(long, default_value = None)]
-    pub name: Option<String>,
+    #[command(flatten)]
+    pub query: VideoQuery,
+    #[command(flatten)]
+    pub pagination: Pagination,
+    /// Only print the given comma-separated fields
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub fields: Vec<VideoField>,
+    /// Include unlisted and private videos
+    #[arg(long, default_value_t = false)]
+    pub include_private: bool,
 }
 
+/// Handles listing videos, streaming them from the store so only those printed are kept in memory
+///
+/// # Arguments
+///
+/// * `show_video` - The arguments for the listing
 pub fn handle_list_videos(show_video: ListVideo) {
-    let path = concat!(env!("HOME"), "/.rustflix/videos.bc");
-    let path = Path::new(path);
-    let videos: Vec<Video> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
-    };
+    if !show_video.all && show_video.query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
+    }
+
+    let query = &show_video.query;
+    let mut found_videos = listing::filter(storage::stream::<Video>(Store::Videos), |video| {
+        // Unlisted videos are only left out when listing everything
+        let visible = match video.visibility {
+            Visibility::Public => true,
+            Visibility::Unlisted => !show_video.all,
+            Visibility::Private => false,
+        };
+        (show_video.include_private || visible) && (show_video.all || query.matches(video))
+    })
+    .peekable();
+
+    if !show_video.all && found_videos.peek().is_none() {
+        if show_video.pagination.count {
+            println!("0");
+            return;
+        }
+        error!("{}", t!("video.not_found"));
+        return;
+    }
+
+    print_videos(found_videos, &show_video.pagination, &show_video.fields);
+}
 
-    if show_video.all {
-        for video in videos {
+/// Prints the page of videos requested, or only their number if `--count` was given
+///
+/// # Arguments
+///
+/// * `videos` - Every video matching the query
+/// * `pagination` - The page of videos to print
+/// * `fields` - The fields to print. Every field is printed if empty
+fn print_videos(
+    videos: impl Iterator<Item = Video>,
+    pagination: &Pagination,
+    fields: &[VideoField],
+) {
+    if pagination.count {
+        println!("{}", videos.count());
+        return;
+    }
+
+    if fields.is_empty() {
+        for video in pagination.apply(videos) {
             println!("{:?}", video);
         }
         return;
     }
 
-    if show_video.id.is_none() && show_video.name.is_none() {
-        eprintln!("No query given. Please provide an ID or name");
+    let mut table = Table::new(listing::field_names(fields));
+    for video in pagination.apply(videos) {
+        table.row(fields.iter().map(|field| video.field(*field)).collect());
+    }
+    table.print();
+}
+
+/// Handles the export of every video
+///
+/// # Arguments
+///
+/// * `export` - The arguments for the export
+pub fn handle_export_videos(export: Export) {
+    let videos: Vec<Video> = storage::load(Store::Videos);
+
+    if let Err(e) = transfer::export(&export, &videos) {
+        error!("{}", t!("transfer.export_failed", error = e));
         return;
     }
 
-    let video_query = VideoQuery {
-        id: show_video.id,
-        name: show_video.name,
+    if let Some(path) = export.output {
+        info!(
+            "{}",
+            t!(
+                "video.exported",
+                count = videos.len(),
+                path = path.display()
+            )
+        );
+    }
+}
+
+/// Handles the import of videos from a file
+///
+/// # Arguments
+///
+/// * `import` - The arguments for the import
+pub fn handle_import_videos(import: Import) {
+    if !session::require_role(Role::Admin, &t!("role.import_videos")) {
+        return;
+    }
+
+    let imported: Vec<Video> = match transfer::import(&import) {
+        Ok(imported) => imported,
+        Err(e) => {
+            error!("{}", t!("transfer.import_failed", error = e));
+            return;
+        }
+    };
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+
+    if import.replace {
+        if !videos.is_empty()
+            && !utilities::confirm(
+                &t!("video.confirm_replace", count = videos.len()),
+                None,
+                Some(&t!("video.import_cancelled")),
+                Some(true),
+                utilities::prompt_timeout(),
+            )
+        {
+            return;
+        }
+        videos.clear();
+    }
+
+    let mut added = 0;
+    let mut duplicates = 0;
+    for video in imported {
+        if has_id(&videos, video.id) {
+            warn!("{}", t!("video.duplicate_id", id = video.id));
+            duplicates += 1;
+            continue;
+        }
+        videos.push(video);
+        added += 1;
+    }
+
+    storage::save(Store::Videos, &videos);
+
+    info!("{}", t!("video.imported", count = added));
+    if duplicates > 0 {
+        info!("{}", t!("transfer.skipped", count = duplicates));
+    }
+}
+
+/// Handles checking that the media file of every video still exists, printing those that don't
+pub fn handle_verify_videos() {
+    let videos: Vec<Video> = storage::load(Store::Videos);
+
+    let mut checked = 0;
+    let mut missing = 0;
+    for video in &videos {
+        let Some(file) = &video.file else {
+            continue;
+        };
+        checked += 1;
+        if !file.is_file() {
+            missing += 1;
+            println!("{}\t{}\t{}", video.id, video.name, file.display());
+        }
+    }
+
+    if missing > 0 {
+        warn!(
+            "{}",
+            t!("video.files_missing", missing = missing, checked = checked)
+        );
+    } else {
+        info!("{}", t!("video.files_found", checked = checked));
+    }
+}
+
+/// Handles reading the duration, resolution and codec of a video from its media file
+///
+/// # Arguments
+///
+/// * `video_query` - The query for the video to probe
+pub fn handle_probe_video(video_query: VideoQuery) {
+    if !session::require_role(Role::Moderator, &t!("role.update_videos")) {
+        return;
+    }
+
+    if video_query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
+    }
+
+    let mut videos = Repository::<Video>::load();
+
+    let mut video = match select_video(&videos, &video_query, &t!("action.probe")) {
+        Some(video) => video.clone(),
+        None => return,
     };
 
-    let found_videos = find_videos(&videos, &video_query);
+    if video.file.is_none() {
+        error!("{}", t!("video.no_file"));
+        return;
+    }
 
-    if let Err(FindError::NoVideoFound) = found_videos {
-        eprintln!("No video found from given query.");
+    if !probe_video(&mut video) {
         return;
     }
 
-    let found_videos = found_videos.unwrap();
+    videos.replace(video.clone());
+    videos.save();
 
-    for video in found_videos {
-        println!("{:?}", video);
+    info!("{}", t!("video.probed"));
+    println!("{:?}", video);
+}
+
+/// Ranks videos by how closely their names match the search text
+///
+/// # Arguments
+///
+/// * `videos` - The list of videos to search
+/// * `text` - The text to search for
+///
+/// # Returns
+///
+/// Every video paired with its score, best match first
+fn rank_videos<'a>(videos: &'a [Video], text: &str) -> Vec<(f64, &'a Video)> {
+    listing::rank(videos, |video| listing::fuzzy_score(&video.name, text))
+}
+
+/// Handles a fuzzy search for videos
+///
+/// # Arguments
+///
+/// * `search` - The arguments for the search
+pub fn handle_search_videos(search: Search) {
+    let videos: Vec<Video> = storage::load(Store::Videos);
+
+    let results: Vec<(f64, &Video)> = rank_videos(&videos, &search.text)
+        .into_iter()
+        .filter(|(score, _)| *score >= search.min_score)
+        .take(search.limit)
+        .collect();
+
+    if results.is_empty() {
+        error!("{}", t!("video.no_resemblance", text = search.text));
+        return;
+    }
+
+    for (score, video) in results {
+        println!("{:.2}  {:?}", score, video);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metadata::IdStrategy;
 
     fn make_videos() -> Vec<Video> {
         vec![
@@ -423,26 +1693,31 @@ mod tests {
                 id: 2829304751,
                 name: "test".to_string(),
                 views: 0,
+                ..Default::default()
             },
             Video {
                 id: 1525162981,
                 name: "test2".to_string(),
                 views: 0,
+                ..Default::default()
             },
             Video {
                 id: 986712257,
                 name: "test3".to_string(),
                 views: 0,
+                ..Default::default()
             },
             Video {
                 id: 2453202404,
                 name: "test4".to_string(),
                 views: 0,
+                ..Default::default()
             },
             Video {
                 id: 4036985520,
                 name: "test5".to_string(),
                 views: 0,
+                ..Default::default()
             },
         ]
     }
@@ -454,12 +1729,90 @@ mod tests {
         assert!(!has_id(&videos, 1));
     }
 
+    #[test]
+    fn test_rank_videos() {
+        let videos = make_videos();
+        let ranked = rank_videos(&videos, "tset3");
+        assert_eq!(ranked[0].1.name, "test3");
+        assert!(ranked[0].0 > ranked[1].0);
+    }
+
+    #[test]
+    fn test_find_video_match_mode() {
+        let videos = make_videos();
+        let mut query = VideoQuery {
+            id: Some(2829304751),
+            name: Some("test2".to_string()),
+            name_contains: None,
+            ignore_case: false,
+            match_mode: MatchMode::All,
+        };
+        let videos = Repository::new(videos);
+        assert!(matches!(
+            find_video(&videos, &query),
+            Err(FindError::NoVideoFound)
+        ));
+
+        query.match_mode = MatchMode::Any;
+        assert!(matches!(
+            find_video(&videos, &query),
+            Err(FindError::MultipleVideosFound(_))
+        ));
+
+        query.name = Some("TEST".to_string());
+        query.ignore_case = true;
+        query.match_mode = MatchMode::All;
+        assert_eq!(find_video(&videos, &query).unwrap().id, 2829304751);
+    }
+
+    #[test]
+    fn test_find_video_overlapping_fields() {
+        let videos = Repository::new(make_videos());
+        let mut query = VideoQuery {
+            id: Some(2829304751),
+            name: Some("test".to_string()),
+            name_contains: None,
+            ignore_case: false,
+            match_mode: MatchMode::Any,
+        };
+        assert_eq!(find_video(&videos, &query).unwrap().id, 2829304751);
+
+        query.name = None;
+        query.name_contains = Some("test".to_string());
+        let Err(FindError::MultipleVideosFound(matches)) = find_video(&videos, &query) else {
+            panic!("Every video should match");
+        };
+        assert_eq!(
+            matches,
+            MatchedQueries {
+                id: 1,
+                name: 0,
+                name_contains: 5,
+                overlapping: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90m"), Ok(5400));
+        assert_eq!(parse_duration("1h 30m"), Ok(5400));
+        assert_eq!(parse_duration("1:30:00"), Ok(5400));
+        assert_eq!(parse_duration("5:20"), Ok(320));
+        assert!(parse_duration("1:2:3:4").is_err());
+        assert!(parse_duration("soon").is_err());
+        assert_eq!(format_duration(5400), "1:30:00");
+        assert_eq!(format_duration(320), "5:20");
+    }
+
     #[test]
     fn test_generate_valid_id() {
         let videos = make_videos();
         for _ in 0..100 {
-            let id = generate_valid_id(&videos);
+            let id = metadata::pick_id(IdStrategy::Random, 1, |id| has_id(&videos, id));
             assert!(!has_id(&videos, id));
         }
+        let id = metadata::pick_id(IdStrategy::Sequential, 2829304751, |id| has_id(&videos, id));
+        assert_eq!(id, 2829304752);
     }
 }
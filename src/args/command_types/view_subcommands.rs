@@ -1,119 +1,281 @@
-use crate::args::command_types::video_subcommands::{find_video, FindError, Video, VideoQuery};
+use crate::args::command_types::user_subcommands;
+use crate::args::command_types::video_subcommands::{
+    self, select_video, Video, VideoField, VideoQuery, Visibility,
+};
+use crate::formatting::{self, Table};
+use crate::listing;
+use crate::repository::Repository;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{events, view_log};
 use clap::Args;
-use std::fs::File;
-use std::path::Path;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::time::Instant;
+
+/// The player `video play` uses when none is configured
+const DEFAULT_PLAYER: &str = "mpv";
+
+/// A time a video was played with `video play`
+///
+/// # Fields
+///
+/// * `video` - The ID of the video played
+/// * `user` - The ID of the user who played it, if given
+/// * `started_at` - When playback started, in seconds since the Unix epoch
+/// * `seconds` - How long the player was open for
+/// * `finished` - Whether the player exited successfully, which counts as a view
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchEntry {
+    pub video: u32,
+    pub user: Option<u32>,
+    pub started_at: u64,
+    pub seconds: u64,
+    pub finished: bool,
+}
+
+impl WatchEntry {
+    /// The share of the video watched, between 0 and 1
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The length of the video in seconds, if known
+    ///
+    /// # Returns
+    ///
+    /// The share, or `None` if the video has no duration or the play wasn't timed, as for
+    /// imported plays
+    pub fn completion(&self, duration: Option<u32>) -> Option<f64> {
+        match (duration, self.seconds) {
+            (Some(duration), seconds) if duration > 0 && seconds > 0 => {
+                Some((seconds as f64 / duration as f64).min(1.0))
+            }
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Args)]
 pub struct AddViews {
-    /// The name of the video to add views to
-    #[arg(long, default_value = None)]
-    pub name: Option<String>,
-    /// The ID of the video to add views to
-    #[arg(long, default_value = None)]
-    pub id: Option<u32>,
+    #[command(flatten)]
+    pub query: VideoQuery,
     /// The number of views to add
     #[arg(default_value_t = 1)]
     pub number_to_add: u32,
+    /// The ID of the user adding the views, defaulting to the logged in user. Only the owner of a
+    /// private video can add views to it
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Add the views even if the video is age-restricted and the user is a minor. Only for admins
+    #[arg(long, default_value_t = false)]
+    pub override_parental: bool,
 }
 
 pub fn handle_add_views(add_views: AddViews) {
-    let path = concat!(env!("HOME"), "/.rustflix/videos.bc");
-    let path = Path::new(path);
-    let mut videos: Vec<Video> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
-    };
+    let mut videos = Repository::<Video>::load();
 
-    if add_views.name.is_none() && add_views.id.is_none() {
-        eprintln!("You must specify either a name or an ID");
+    if add_views.query.is_empty() {
+        error!("{}", t!("video.no_query"));
         return;
     }
 
-    let video_query = VideoQuery {
-        name: add_views.name.clone(),
-        id: add_views.id,
+    let mut video = match select_video(&videos, &add_views.query, &t!("action.update")) {
+        Some(video) => video.clone(),
+        None => return,
     };
 
-    let video = find_video(&videos, &video_query);
-
-    if let Err(e) = video {
-        match e {
-            FindError::NoVideoFound => eprintln!("Update failed. No video found from given query."),
-            FindError::MultipleVideosFound(counts) => {
-                eprintln!("Update failed. Multiple videos found from given query.");
-                if add_views.id.is_some() {
-                    eprintln!("ID matches: {}", counts.id);
-                }
-                if add_views.name.is_some() {
-                    eprintln!("Name matches: {}", counts.name);
-                }
-            }
+    let user = add_views.user.or_else(session::current_user);
+    if !user_subcommands::check_not_suspended(user) {
+        return;
+    }
+
+    if video.visibility == Visibility::Private && (video.owner.is_none() || video.owner != user) {
+        error!("{}", t!("view.private_add", name = video.name));
+        return;
+    }
+
+    if !user_subcommands::check_parental(user, &video, add_views.override_parental) {
+        return;
+    }
+
+    let name = video.name.clone();
+    if view_log::available() {
+        if let Err(e) = view_log::append(video.id, add_views.number_to_add) {
+            error!("{}", e);
+            return;
         }
+        events::views_added(&video, add_views.number_to_add);
+    } else {
+        video.add_views(add_views.number_to_add);
+        videos.replace(video);
+        videos.save();
+    }
+
+    info!(
+        "{}",
+        t!("view.added", count = add_views.number_to_add, name = name)
+    );
+}
+
+#[derive(Debug, Args)]
+pub struct PlayVideo {
+    #[command(flatten)]
+    pub query: VideoQuery,
+    /// The ID of the user watching, defaulting to the logged in user. Only the owner of a private
+    /// video can play it
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Play the video even if it is age-restricted and the user is a minor. Only for admins
+    #[arg(long, default_value_t = false)]
+    pub override_parental: bool,
+}
+
+/// Handles playing the media file of a video, recording it in the watch history
+///
+/// # Arguments
+///
+/// * `play_video` - The arguments for playing the video
+/// * `player` - The command to play the file with, from the config file
+pub fn handle_play_video(play_video: PlayVideo, player: Option<String>) {
+    if play_video.query.is_empty() {
+        error!("{}", t!("video.no_query"));
         return;
     }
 
-    let video = video.unwrap();
+    let videos = Repository::<Video>::load();
 
-    let video_index = videos.iter().position(|u| u == video);
+    let video = match select_video(&videos, &play_video.query, &t!("action.play")) {
+        Some(video) => video,
+        None => return,
+    };
 
-    if video_index.is_none() {
-        panic!("Video was found but its index wasn't. This should never happen.");
+    let user = play_video.user.or_else(session::current_user);
+    if !user_subcommands::check_not_suspended(user) {
+        return;
     }
 
-    let video_index = video_index.unwrap();
+    if video.visibility == Visibility::Private && (video.owner.is_none() || video.owner != user) {
+        error!("{}", t!("view.private_play", name = video.name));
+        return;
+    }
 
-    println!(
-        "Successfully added {} views to {}",
-        add_views.number_to_add,
-        video.clone().name
-    );
+    if !user_subcommands::check_parental(user, video, play_video.override_parental) {
+        return;
+    }
 
-    let current_views = videos[video_index].views;
-    videos[video_index].views = current_views + add_views.number_to_add;
+    let Some(file) = &video.file else {
+        error!("{}", t!("view.no_file"));
+        return;
+    };
+    if !file.is_file() {
+        error!("{}", t!("view.file_missing", file = file.display()));
+        return;
+    }
 
-    let file = File::create(path).unwrap();
-    bincode::serialize_into(file, &videos).unwrap();
-}
+    let player = player.unwrap_or(DEFAULT_PLAYER.to_string());
+    let mut words = player.split_whitespace();
+    let Some(program) = words.next() else {
+        error!("{}", t!("view.player_empty"));
+        return;
+    };
 
-pub fn handle_show_views(video_query: VideoQuery) {
-    let path = concat!(env!("HOME"), "/.rustflix/videos.bc");
-    let path = Path::new(path);
-    let videos: Vec<Video> = if path.exists() {
-        let file = File::open(path).unwrap();
-        bincode::deserialize_from(file).unwrap()
-    } else {
-        vec![]
+    debug!("Playing {} with {}", file.display(), player);
+    let started_at = video_subcommands::now();
+    let start = Instant::now();
+    let status = match Command::new(program).args(words).arg(file).status() {
+        Ok(status) => status,
+        Err(e) => {
+            error!("{}", t!("view.player_failed", player = program, error = e));
+            return;
+        }
     };
 
-    if video_query.name.is_none() && video_query.id.is_none() {
-        eprintln!("You must specify either a name or an ID");
+    let video_id = video.id;
+    let entry = WatchEntry {
+        video: video_id,
+        user,
+        started_at,
+        seconds: start.elapsed().as_secs(),
+        finished: status.success(),
+    };
+
+    let mut history: Vec<WatchEntry> = storage::load(Store::History);
+    history.push(entry);
+    storage::save(Store::History, &history);
+
+    if !status.success() {
+        warn!(
+            "{}",
+            t!("view.player_exited", player = program, status = status)
+        );
         return;
     }
 
-    let video = find_video(&videos, &video_query);
+    // Reloaded, since the videos may have changed while the player was open
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let Some(video) = videos.iter_mut().find(|video| video.id == video_id) else {
+        warn!("{}", t!("view.deleted_while_playing"));
+        return;
+    };
+    let name = video.name.clone();
 
-    if let Err(e) = video {
-        match e {
-            FindError::NoVideoFound => {
-                eprintln!("No video found with the specified name or ID");
-                return;
-            }
-            FindError::MultipleVideosFound(matches) => {
-                eprintln!("Multiple videos found with the specified name or ID");
-                if video_query.id.is_some() {
-                    eprintln!("ID matches: {}", matches.id);
-                }
-                if video_query.name.is_some() {
-                    eprintln!("Name matches: {}", matches.name);
-                }
-                return;
-            }
+    if view_log::available() {
+        if let Err(e) = view_log::append(video_id, 1) {
+            error!("{}", e);
+            return;
         }
+        events::views_added(video, 1);
+    } else {
+        video.add_views(1);
+        storage::save(Store::Videos, &videos);
+    }
+
+    info!("{}", t!("view.finished", name = name));
+}
+
+#[derive(Debug, Args)]
+pub struct ShowViews {
+    #[command(flatten)]
+    pub query: VideoQuery,
+    /// Only print the given comma-separated fields of the video
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub fields: Vec<VideoField>,
+}
+
+pub fn handle_show_views(show_views: ShowViews) {
+    let video_query = show_views.query;
+    let videos = Repository::<Video>::load();
+
+    if video_query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
     }
 
-    let video = video.unwrap();
+    let video = match select_video(&videos, &video_query, &t!("action.show")) {
+        Some(video) => video,
+        None => return,
+    };
 
-    println!("{} has {} views", video.name, video.views);
+    if !show_views.fields.is_empty() {
+        let values: Vec<String> = show_views
+            .fields
+            .iter()
+            .map(|field| video.field(*field))
+            .collect();
+        let mut table = Table::new(listing::field_names(&show_views.fields));
+        table.row(values);
+        table.print();
+        return;
+    }
+
+    println!(
+        "{}",
+        t!(
+            "view.count",
+            name = video.name,
+            views = formatting::count(video.views)
+        )
+    );
 }
@@ -0,0 +1,607 @@
+use crate::args::command_types::video_subcommands;
+use crate::events::{self, EventSubscriber};
+use crate::output::{self, Style};
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{config, encryption, session};
+use clap::{Args, Subcommand, ValueEnum};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Fields holding secrets, written to the audit log as `HIDDEN` instead of their value
+const SECRETS: [&str; 3] = ["password", "session", "hash"];
+
+/// Written to the audit log in place of a secret
+pub const HIDDEN: &str = "[hidden]";
+
+/// Options whose values are secret, written to the audit log as `HIDDEN` in the command
+const SECRET_OPTIONS: [&str; 2] = ["--token", "--api-key"];
+
+/// Fields kept up to date by the stores themselves, which don't count as changes on their own
+const BOOKKEEPING: [&str; 2] = ["updated_at", "revision"];
+
+/// The kinds of records the audit log records changes to
+///
+/// # Variants
+///
+/// * `User` - A user
+/// * `Video` - A video, including the views added to it
+/// * `Apikey` - An API key for clients of the server
+/// * `Genre` - A genre videos may have
+/// * `Series` - A series of videos
+/// * `History` - An entry of the watch history
+/// * `Subscription` - A user subscribing to a channel
+/// * `Playlist` - A playlist of videos
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    User,
+    Video,
+    Apikey,
+    Genre,
+    Series,
+    History,
+    Subscription,
+    Playlist,
+}
+
+impl Entity {
+    /// The kind of records held by a store, or `None` for stores whose changes aren't recorded
+    pub fn of(store: Store) -> Option<Self> {
+        match store {
+            Store::Users => Some(Entity::User),
+            Store::Videos => Some(Entity::Video),
+            Store::ApiKeys => Some(Entity::Apikey),
+            Store::Genres => Some(Entity::Genre),
+            Store::Series => Some(Entity::Series),
+            Store::History => Some(Entity::History),
+            Store::Subscriptions => Some(Entity::Subscription),
+            Store::Playlists => Some(Entity::Playlist),
+            Store::Metadata | Store::Notifications => None,
+        }
+    }
+
+    /// The store holding records of the kind
+    pub fn store(&self) -> Store {
+        match self {
+            Entity::User => Store::Users,
+            Entity::Video => Store::Videos,
+            Entity::Apikey => Store::ApiKeys,
+            Entity::Genre => Store::Genres,
+            Entity::Series => Store::Series,
+            Entity::History => Store::History,
+            Entity::Subscription => Store::Subscriptions,
+            Entity::Playlist => Store::Playlists,
+        }
+    }
+
+    /// The name of the kind, as written in messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Entity::User => "user",
+            Entity::Video => "video",
+            Entity::Apikey => "API key",
+            Entity::Genre => "genre",
+            Entity::Series => "series",
+            Entity::History => "watch history entry",
+            Entity::Subscription => "subscription",
+            Entity::Playlist => "playlist",
+        }
+    }
+}
+
+/// What happened to a record
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A change to a record, as written to the audit log
+///
+/// # Fields
+///
+/// * `at` - When the change was made, in seconds since the Unix epoch
+/// * `user` - The ID of the user logged in when the change was made, if any
+/// * `command` - The command that made the change
+/// * `run` - Identifies the run of the command, shared by every change it made
+/// * `undoes` - The run whose changes this change undid, if it was made by `undo`
+/// * `entity` - The kind of record changed
+/// * `id` - The ID of the record, for kinds of records that have one
+/// * `action` - Whether the record was created, updated, or deleted
+/// * `fields` - The fields that changed, if the record was updated
+/// * `old` - The record before the change, unless it was created
+/// * `new` - The record after the change, unless it was deleted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: u64,
+    pub user: Option<u32>,
+    pub command: String,
+    #[serde(default)]
+    pub run: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<u64>,
+    pub entity: Entity,
+    pub id: Option<u32>,
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Identifies the current run of a command in the audit log, picked when first needed
+static RUN: AtomicU64 = AtomicU64::new(0);
+
+/// The run being undone by the changes being made, if any
+static UNDOING: AtomicU64 = AtomicU64::new(0);
+
+/// Starts a new run, so the changes made from now on are undone separately from those before.
+/// Used by the server and daemon, which make changes on behalf of many commands
+pub fn start_run() {
+    RUN.store(0, Ordering::Relaxed);
+}
+
+/// Identifies the current run, picking a new random ID if there is none
+fn run() -> u64 {
+    match RUN.load(Ordering::Relaxed) {
+        0 => {
+            let run = rand::random::<u64>().max(1);
+            RUN.store(run, Ordering::Relaxed);
+            run
+        }
+        run => run,
+    }
+}
+
+/// Marks the changes made from now on as undoing the given run
+pub fn set_undoing(run: u64) {
+    UNDOING.store(run, Ordering::Relaxed);
+}
+
+/// The path of the audit log, which holds an entry as a line of JSON for every change made to a
+/// record. While encryption is enabled, entries are written encrypted like the stores, as hex
+pub fn path() -> PathBuf {
+    storage::data_dir().join("audit.log")
+}
+
+/// The ID of a record, if it has one
+fn id_of(record: &Value) -> Option<u32> {
+    record.get("id")?.as_u64().map(|id| id as u32)
+}
+
+/// The fields that differ between two versions of a record. `BOOKKEEPING` fields don't count, and
+/// neither do fields the old version doesn't have, which records gain when saved by a newer
+/// version of rustflix
+fn changed_fields(old: &Value, new: &Value) -> Vec<String> {
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return match old == new {
+            true => vec![],
+            false => vec!["value".to_string()],
+        };
+    };
+    new.iter()
+        .filter(|(field, value)| {
+            !BOOKKEEPING.contains(&field.as_str())
+                && old.get(*field).is_some_and(|old| old != *value)
+        })
+        .map(|(field, _)| field.clone())
+        .collect()
+}
+
+/// A change to a record: the action taken, the fields changed, and the record before and after it
+pub type Change = (Action, Vec<String>, Option<Value>, Option<Value>);
+
+/// Works out the changes that turn one list of records into another
+///
+/// Records are matched by ID, or as a whole for stores without one.
+///
+/// # Arguments
+///
+/// * `old` - The records before the change
+/// * `new` - The records after the change
+///
+/// # Returns
+///
+/// Each change, in the order of the new records, followed by the records deleted
+pub fn diff(old: &[Value], new: &[Value]) -> Vec<Change> {
+    let key = |record: &Value| match id_of(record) {
+        Some(id) => id.to_string(),
+        None => record.to_string(),
+    };
+    let old_records: HashMap<String, &Value> =
+        old.iter().map(|record| (key(record), record)).collect();
+    let new_keys: HashSet<String> = new.iter().map(key).collect();
+
+    let mut changes = vec![];
+    for record in new {
+        match old_records.get(&key(record)) {
+            None => changes.push((Action::Created, vec![], None, Some(record.clone()))),
+            Some(old) => {
+                let fields = changed_fields(old, record);
+                if !fields.is_empty() {
+                    changes.push((
+                        Action::Updated,
+                        fields,
+                        Some((*old).clone()),
+                        Some(record.clone()),
+                    ));
+                }
+            }
+        }
+    }
+    changes.extend(
+        old.iter()
+            .filter(|record| !new_keys.contains(&key(record)))
+            .map(|record| (Action::Deleted, vec![], Some(record.clone()), None)),
+    );
+    changes
+}
+
+/// Replaces the secrets of a record with `HIDDEN`
+pub fn hide_secrets(mut record: Value) -> Value {
+    if let Some(fields) = record.as_object_mut() {
+        for secret in SECRETS {
+            if let Some(value) = fields.get_mut(secret).filter(|value| !value.is_null()) {
+                *value = Value::from(HIDDEN);
+            }
+        }
+    }
+    record
+}
+
+/// Replaces the secrets in the arguments of a command with `HIDDEN`: the values of
+/// `SECRET_OPTIONS`, and the value of a secret setting given to `config set`
+fn redact(args: Vec<String>) -> Vec<String> {
+    let mut redacted: Vec<String> = vec![];
+    let mut hide_next = false;
+    for arg in args {
+        if hide_next {
+            redacted.push(HIDDEN.to_string());
+            hide_next = false;
+            continue;
+        }
+        let option = arg
+            .split_once('=')
+            .map_or(arg.as_str(), |(option, _)| option);
+        if SECRET_OPTIONS.contains(&option) {
+            match arg.contains('=') {
+                true => redacted.push(format!("{}={}", option, HIDDEN)),
+                false => {
+                    hide_next = true;
+                    redacted.push(arg);
+                }
+            }
+            continue;
+        }
+        hide_next = redacted.ends_with(&["config".to_string(), "set".to_string()])
+            && config::SECRETS.contains(&arg.rsplit('.').next().unwrap_or_default());
+        redacted.push(arg);
+    }
+    redacted
+}
+
+/// The command being run, as it was typed, with its secrets hidden
+fn command() -> String {
+    redact(env::args().skip(1).collect())
+        .into_iter()
+        .map(|arg| match arg.contains(char::is_whitespace) {
+            true => format!("{:?}", arg),
+            false => arg,
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Appends entries to the audit log
+fn append(entries: &[AuditEntry]) {
+    if entries.is_empty() {
+        return;
+    }
+    let lines: String = entries
+        .iter()
+        .map(|entry| {
+            let line = serde_json::to_string(entry).unwrap();
+            match encryption::enabled() {
+                true => encryption::encrypt(line.as_bytes())
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect(),
+                false => line,
+            }
+        })
+        .map(|line| line + "\n")
+        .collect();
+    let result = fs::create_dir_all(storage::data_dir()).and_then(|_| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path())?
+            .write_all(lines.as_bytes())
+    });
+    if let Err(e) = result {
+        warn!(
+            "{}",
+            t!("audit.could_not_write", path = path().display(), error = e)
+        );
+    }
+}
+
+/// Records every change made to a store in the audit log
+pub struct AuditLog;
+
+impl EventSubscriber for AuditLog {
+    fn handle(&self, change: &events::Change) {
+        record_changes(change.store, change.old, change.new);
+    }
+}
+
+/// Records the changes made by overwriting a store in the audit log
+///
+/// # Arguments
+///
+/// * `store` - The store overwritten
+/// * `old` - The records before they were overwritten
+/// * `new` - The records written
+fn record_changes(store: Store, old: &[Value], new: &[Value]) {
+    let Some(entity) = Entity::of(store) else {
+        return;
+    };
+    let (at, user, command, run) = (
+        video_subcommands::now(),
+        session::current_user_id(),
+        command(),
+        run(),
+    );
+    let undoes = Some(UNDOING.load(Ordering::Relaxed)).filter(|run| *run != 0);
+    let entries: Vec<AuditEntry> = diff(old, new)
+        .into_iter()
+        .map(|(action, fields, old, new)| AuditEntry {
+            at,
+            user,
+            command: command.clone(),
+            run,
+            undoes,
+            entity,
+            id: old.as_ref().or(new.as_ref()).and_then(id_of),
+            action,
+            fields,
+            old: old.map(hide_secrets),
+            new: new.map(hide_secrets),
+        })
+        .collect();
+    append(&entries);
+}
+
+/// Reads every entry of the audit log, oldest first
+pub fn read() -> Vec<AuditEntry> {
+    let Ok(log) = fs::read_to_string(path()) else {
+        return vec![];
+    };
+    log.lines()
+        .filter_map(|line| match parse(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("{}", t!("audit.invalid_line", error = e));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Reads an entry from a line of the audit log, decrypting it if it was written encrypted
+fn parse(line: &str) -> Result<AuditEntry, String> {
+    if line.starts_with('{') {
+        return serde_json::from_str(line).map_err(|e| e.to_string());
+    }
+    let bytes = (0..line.len())
+        .step_by(2)
+        .map(|i| {
+            line.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(t!("audit.unreadable_line"))?;
+    let decrypted = encryption::decrypt(&bytes)?;
+    serde_json::from_slice(&decrypted).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Args)]
+pub struct AuditCommand {
+    #[clap(subcommand)]
+    pub subcommand: AuditSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditSubcommand {
+    /// List the changes made to records, oldest first
+    List(ListAudit),
+}
+
+#[derive(Debug, Args)]
+pub struct ListAudit {
+    /// Only list changes to this kind of record
+    #[arg(long, value_enum, default_value = None)]
+    pub entity: Option<Entity>,
+    /// Only list changes to the record with this ID
+    #[arg(long, default_value = None, requires = "entity")]
+    pub id: Option<u32>,
+    /// Only list changes made while this user was logged in
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// Only list this many of the latest changes
+    #[arg(long, default_value = None)]
+    pub limit: Option<usize>,
+}
+
+impl AuditEntry {
+    /// The record changed, before the change unless it was created
+    pub fn record(&self) -> Option<&Value> {
+        self.old.as_ref().or(self.new.as_ref())
+    }
+
+    /// Describes the change, e.g. `updated video 5`
+    pub fn summary(&self) -> String {
+        let id = match (self.id, self.record()) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(record)) => record.to_string(),
+            (None, None) => String::new(),
+        };
+        let record = match self.entity {
+            Entity::User => t!("audit.user", id = id),
+            Entity::Video => t!("audit.video", id = id),
+            Entity::Apikey => t!("audit.apikey", id = id),
+            Entity::Genre => t!("audit.genre", id = id),
+            Entity::Series => t!("audit.series", id = id),
+            Entity::History => t!("audit.history", id = id),
+            Entity::Subscription => t!("audit.subscription", id = id),
+            Entity::Playlist => t!("audit.playlist", id = id),
+        };
+        match self.action {
+            Action::Created => t!("audit.created", record = record),
+            Action::Updated => t!("audit.updated", record = record),
+            Action::Deleted => t!("audit.deleted", record = record),
+        }
+    }
+
+    /// Describes how each field changed, e.g. `name: "Old" -> "New"`
+    pub fn field_changes(&self) -> Vec<String> {
+        let value = |record: &Option<Value>, field: &str| {
+            record
+                .as_ref()
+                .and_then(|record| record.get(field))
+                .map_or("nothing".to_string(), Value::to_string)
+        };
+        self.fields
+            .iter()
+            .map(|field| {
+                format!(
+                    "{}: {} -> {}",
+                    field,
+                    value(&self.old, field),
+                    value(&self.new, field)
+                )
+            })
+            .collect()
+    }
+}
+
+/// Handles listing the changes made to records, with the fields each update changed
+///
+/// # Arguments
+///
+/// * `list_audit` - The arguments for the listing
+pub fn handle_list_audit(list_audit: ListAudit) {
+    let entries: Vec<AuditEntry> = read()
+        .into_iter()
+        .filter(|entry| {
+            list_audit
+                .entity
+                .is_none_or(|entity| entry.entity == entity)
+        })
+        .filter(|entry| list_audit.id.is_none_or(|id| entry.id == Some(id)))
+        .filter(|entry| list_audit.user.is_none_or(|user| entry.user == Some(user)))
+        .collect();
+    if entries.is_empty() {
+        error!("{}", t!("audit.none_found"));
+        return;
+    }
+
+    let skipped = list_audit
+        .limit
+        .map_or(0, |limit| entries.len().saturating_sub(limit));
+    for entry in &entries[skipped..] {
+        let user = entry
+            .user
+            .map_or(String::new(), |user| t!("audit.by_user", user = user));
+        println!(
+            "{}",
+            t!(
+                "audit.entry",
+                at = output::paint_stdout(
+                    &video_subcommands::format_timestamp(entry.at),
+                    Style::Header
+                ),
+                summary = entry.summary(),
+                by = user,
+                command = entry.command
+            )
+        );
+        for change in entry.field_changes() {
+            println!("    {}", change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_audit_diff() {
+        let old = [
+            json!({"id": 1, "name": "a", "views": 3, "updated_at": 1}),
+            json!({"id": 2, "name": "b"}),
+            json!({"id": 3, "name": "c"}),
+        ];
+        let new = [
+            json!({"id": 1, "name": "a", "views": 5, "updated_at": 9}),
+            json!({"id": 2, "name": "b", "genres": "", "updated_at": 9}),
+            json!({"id": 4, "name": "d"}),
+        ];
+        let changes: Vec<(Action, Vec<String>, Option<u32>)> = diff(&old, &new)
+            .into_iter()
+            .map(|(action, fields, old, new)| {
+                (action, fields, old.or(new).as_ref().and_then(id_of))
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (Action::Updated, vec!["views".to_string()], Some(1)),
+                (Action::Created, vec![], Some(4)),
+                (Action::Deleted, vec![], Some(3)),
+            ]
+        );
+
+        let genres = diff(&[json!("Drama")], &[json!("Drama"), json!("Horror")]);
+        assert_eq!(genres.len(), 1);
+        assert_eq!(genres[0].3, Some(json!("Horror")));
+
+        assert_eq!(
+            hide_secrets(json!({"name": "a", "password": "hash", "session": null})),
+            json!({"name": "a", "password": HIDDEN, "session": null})
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let args = |args: &str| args.split(' ').map(str::to_string).collect::<Vec<String>>();
+        assert_eq!(
+            redact(args("--token abc video list")),
+            args(&format!("--token {} video list", HIDDEN))
+        );
+        assert_eq!(
+            redact(args("import jellyfin --url u --api-key=abc")),
+            args(&format!("import jellyfin --url u --api-key={}", HIDDEN))
+        );
+        assert_eq!(
+            redact(args("config set s3.secret_key abc")),
+            args(&format!("config set s3.secret_key {}", HIDDEN))
+        );
+        assert_eq!(
+            redact(args("config set rate_limit.views 5")),
+            args("config set rate_limit.views 5")
+        );
+    }
+}
@@ -0,0 +1,437 @@
+use crate::storage::{self, Store, FORMAT_VERSION};
+use crate::t;
+use crate::view_log;
+use log::{debug, info, warn};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The name of the manifest within a backup archive, which always comes first
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// The zstd compression level backups are written with
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// Describes what a backup archive holds, so it can be checked before anything is restored
+///
+/// # Fields
+///
+/// * `version` - The version of rustflix that wrote the backup
+/// * `format_version` - The format the store files in the backup are written in
+/// * `created_at` - When the backup was made, in seconds since the Unix epoch
+/// * `stores` - Every store in the backup. Stores that had not been created yet are left out
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub format_version: u16,
+    pub created_at: u64,
+    pub stores: Vec<ManifestEntry>,
+}
+
+/// A store file within a backup archive
+///
+/// # Fields
+///
+/// * `store` - The store the file backs
+/// * `file` - The name of the file within the archive
+/// * `size` - The size of the file in bytes
+/// * `sha256` - The SHA-256 hash of the file, as hex
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub store: Store,
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Backups made automatically before stores are changed, as set in the `[backup.auto]` section
+/// of the config file
+///
+/// # Fields
+///
+/// * `interval` - How long to wait after a backup before making the next, e.g. `12h` or `1day`
+/// * `keep_last` - How many automatic backups to keep. Older ones are deleted
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct AutoBackup {
+    #[serde(
+        default = "default_interval",
+        deserialize_with = "deserialize_interval",
+        serialize_with = "serialize_interval"
+    )]
+    pub interval: Duration,
+    #[serde(default = "default_keep_last")]
+    pub keep_last: usize,
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+fn default_keep_last() -> usize {
+    7
+}
+
+fn serialize_interval<S: Serializer>(
+    interval: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&humantime::format_duration(*interval).to_string())
+}
+
+fn deserialize_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    humantime::parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+/// The settings of the `[backup]` section of the config file
+///
+/// # Fields
+///
+/// * `auto` - Automatic backups, if they are turned on
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub auto: Option<AutoBackup>,
+}
+
+/// The automatic backups to make, if any
+static AUTO: OnceLock<AutoBackup> = OnceLock::new();
+
+/// Whether this run already checked for a due automatic backup
+static CHECKED: AtomicBool = AtomicBool::new(false);
+
+/// Turns on automatic backups for this run
+pub fn set_auto(auto: AutoBackup) {
+    AUTO.set(auto).expect("Automatic backups set twice");
+}
+
+/// The directory automatic backups are kept in
+pub fn backups_dir() -> PathBuf {
+    storage::data_dir().join("backups")
+}
+
+/// A store and the contents of its file, as read from a backup
+pub type StoreFile = (Store, Vec<u8>);
+
+/// Writes every store file in the data directory to a zstd-compressed tar archive, after a
+/// manifest describing them
+///
+/// # Arguments
+///
+/// * `path` - The archive to write
+///
+/// # Returns
+///
+/// The manifest written, or an error message if the archive could not be written
+pub fn create(path: &Path) -> Result<Manifest, String> {
+    // Views still in the log belong in the backup, so they are written to the video store first
+    if view_log::path().exists() && view_log::available() {
+        view_log::compact();
+    }
+
+    let mut files = vec![];
+    for store in Store::ALL {
+        let store_path = storage::store_path(store);
+        if !store_path.exists() {
+            continue;
+        }
+        let bytes = fs::read(&store_path)
+            .map_err(|e| t!("backup.unreadable", path = store_path.display(), error = e))?;
+        files.push((store, bytes));
+    }
+
+    let manifest = Manifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        format_version: FORMAT_VERSION,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        stores: files
+            .iter()
+            .map(|(store, bytes)| ManifestEntry {
+                store: *store,
+                file: store.file_name().to_string(),
+                size: bytes.len() as u64,
+                sha256: storage::checksum(bytes),
+            })
+            .collect(),
+    };
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let encoder = zstd::Encoder::new(file, COMPRESSION_LEVEL)
+        .map_err(|e| e.to_string())?
+        .auto_finish();
+    let mut archive = tar::Builder::new(encoder);
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest).unwrap();
+    for (name, bytes) in std::iter::once((MANIFEST_NAME, manifest_bytes.as_slice())).chain(
+        files
+            .iter()
+            .map(|(store, bytes)| (store.file_name(), bytes.as_slice())),
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.created_at);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, name, bytes)
+            .map_err(|e| e.to_string())?;
+    }
+    archive.into_inner().map_err(|e| e.to_string())?;
+
+    Ok(manifest)
+}
+
+/// Reads a backup archive and checks it against its manifest, without restoring anything
+///
+/// # Arguments
+///
+/// * `path` - The archive to read
+///
+/// # Returns
+///
+/// The manifest and the contents of every store file in the archive, or a message explaining why
+/// the archive can't be restored
+pub fn read(path: &Path) -> Result<(Manifest, Vec<StoreFile>), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+    let mut archive = tar::Archive::new(decoder);
+
+    let mut manifest: Option<Manifest> = None;
+    let mut contents: HashMap<String, Vec<u8>> = HashMap::new();
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let name = entry
+            .path()
+            .map_err(|e| e.to_string())?
+            .to_string_lossy()
+            .to_string();
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        if name == MANIFEST_NAME {
+            manifest = Some(
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| t!("backup.invalid_manifest", error = e))?,
+            );
+        } else {
+            contents.insert(name, bytes);
+        }
+    }
+
+    let manifest = manifest.ok_or(t!("backup.no_manifest"))?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(t!(
+            "backup.too_new",
+            version = manifest.version,
+            format = manifest.format_version
+        ));
+    }
+
+    let mut stores = vec![];
+    for entry in &manifest.stores {
+        if entry.file != entry.store.file_name() {
+            return Err(t!(
+                "backup.wrong_file",
+                file = entry.file,
+                store = format!("{:?}", entry.store),
+                kept_in = entry.store.file_name()
+            ));
+        }
+        let bytes = contents
+            .remove(&entry.file)
+            .ok_or_else(|| t!("backup.missing_file", file = entry.file))?;
+        if bytes.len() as u64 != entry.size || storage::checksum(&bytes) != entry.sha256 {
+            return Err(t!("backup.mismatch", file = entry.file));
+        }
+        stores.push((entry.store, bytes));
+    }
+    if let Some(name) = contents.keys().next() {
+        return Err(t!("backup.unlisted_file", file = name));
+    }
+
+    Ok((manifest, stores))
+}
+
+/// Lists the automatic backups, oldest first
+///
+/// # Returns
+///
+/// The time each backup was made, in seconds since the Unix epoch, and its path
+fn auto_backups() -> Vec<(u64, PathBuf)> {
+    let Ok(entries) = fs::read_dir(backups_dir()) else {
+        return vec![];
+    };
+    let mut backups: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let time = name
+                .strip_prefix("rustflix-")?
+                .strip_suffix(".tar.zst")?
+                .parse()
+                .ok()?;
+            Some((time, path))
+        })
+        .collect();
+    backups.sort();
+    backups
+}
+
+/// Reads the copies of a store kept in the automatic backups, newest first, if automatic backups
+/// are turned on. Backups that can't be read are skipped
+///
+/// # Returns
+///
+/// The time each backup was made, in seconds since the Unix epoch, and the contents of the store
+/// file in it
+pub fn auto_copies(store: Store) -> impl Iterator<Item = (u64, Vec<u8>)> {
+    let backups = match AUTO.get() {
+        Some(_) => auto_backups(),
+        None => vec![],
+    };
+    backups
+        .into_iter()
+        .rev()
+        .filter_map(move |(made_at, path)| {
+            let (_, stores) = read(&path)
+                .map_err(|e| {
+                    warn!(
+                        "{}",
+                        t!("backup.skipping", path = path.display(), error = e)
+                    )
+                })
+                .ok()?;
+            let (_, bytes) = stores
+                .into_iter()
+                .find(|(backed_up, _)| *backed_up == store)?;
+            Some((made_at, bytes))
+        })
+}
+
+/// Backs up every store into the backups directory if automatic backups are turned on and the
+/// last one is older than the interval, then deletes the oldest beyond the number to keep
+///
+/// Called before any store file is written, so the backup holds the stores as they were before the
+/// change. Only checks once per run.
+pub fn auto_backup_if_due() {
+    let Some(auto) = AUTO.get() else {
+        return;
+    };
+    if CHECKED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    if let Some((last, _)) = auto_backups().last() {
+        if now.saturating_sub(*last) < auto.interval.as_secs() {
+            debug!("The last automatic backup is recent enough");
+            return;
+        }
+    }
+    back_up_to_dir(now, auto.keep_last);
+}
+
+/// Backs up every store into the backups directory, as a scheduled task of the daemon, keeping as
+/// many backups as automatic backups do
+pub fn scheduled() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let keep_last = AUTO
+        .get()
+        .map_or_else(default_keep_last, |auto| auto.keep_last);
+    back_up_to_dir(now, keep_last);
+}
+
+/// Backs up every store into the backups directory, then deletes the oldest backups beyond the
+/// number to keep
+///
+/// # Arguments
+///
+/// * `now` - The current time in seconds since the Unix epoch, which names the backup
+/// * `keep_last` - How many backups to keep
+fn back_up_to_dir(now: u64, keep_last: usize) {
+    if let Err(e) = fs::create_dir_all(backups_dir()) {
+        warn!("{}", t!("backup.auto_failed", error = e));
+        return;
+    }
+    let path = backups_dir().join(format!("rustflix-{}.tar.zst", now));
+    match create(&path) {
+        Ok(_) => info!("{}", t!("backup.auto_made", path = path.display())),
+        Err(e) => {
+            warn!("{}", t!("backup.auto_failed", error = e));
+            return;
+        }
+    }
+
+    let backups = auto_backups();
+    let excess = backups.len().saturating_sub(keep_last);
+    for (_, old) in &backups[..excess] {
+        debug!("Deleting old automatic backup {}", old.display());
+        if let Err(e) = fs::remove_file(old) {
+            warn!(
+                "{}",
+                t!("backup.undeletable", path = old.display(), error = e)
+            );
+        }
+    }
+}
+
+/// Replaces every store file in the data directory with those of a backup read with `read`, along
+/// with their checksums. Stores missing from the backup are removed
+///
+/// # Arguments
+///
+/// * `stores` - The store files of the backup
+pub fn restore(stores: &[StoreFile]) -> Result<(), String> {
+    if storage::dry_run() {
+        // Saving the records shows how every store would change, without writing any of them
+        for store in Store::ALL {
+            let records: Vec<Value> = match stores.iter().find(|(backed_up, _)| *backed_up == store)
+            {
+                Some((_, bytes)) => storage::decode(bytes)?,
+                None => vec![],
+            };
+            storage::save(store, &records);
+        }
+        return Ok(());
+    }
+    fs::create_dir_all(storage::data_dir()).map_err(|e| e.to_string())?;
+    for store in Store::ALL {
+        let path = storage::store_path(store);
+        let checksum_path = storage::checksum_path(store);
+        let result = match stores.iter().find(|(backed_up, _)| *backed_up == store) {
+            Some((_, bytes)) => storage::replace_file(&path, bytes).and_then(|_| {
+                storage::replace_file(&checksum_path, storage::checksum(bytes).as_bytes())
+            }),
+            None if path.exists() => {
+                fs::remove_file(&path).and_then(|_| match checksum_path.exists() {
+                    true => fs::remove_file(&checksum_path),
+                    false => Ok(()),
+                })
+            }
+            None => Ok(()),
+        };
+        result.map_err(|e| t!("backup.unrestorable", path = path.display(), error = e))?;
+    }
+    // Views logged before the backup was made were compacted into it, and those added since
+    // don't belong to the restored videos
+    view_log::clear().map_err(|e| {
+        t!(
+            "backup.undeletable",
+            path = view_log::path().display(),
+            error = e
+        )
+    })
+}
@@ -0,0 +1,191 @@
+use crate::args::command_types::{UserSubcommand, VideoSubcommand, ViewSubcommand};
+use crate::args::{self, CommandType, RustflixArgs};
+use crate::transfer::Lines;
+use crate::{logging, storage, t};
+use clap::{Args, Parser};
+use log::{error, info};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::process;
+
+#[derive(Debug, Args)]
+pub struct Batch {
+    /// The JSON Lines file of operations to apply, e.g. `{"op": "video create", "args": ["Big Buck Bunny", "--duration", "10m"]}`. Read from stdin if not given
+    #[arg(long, default_value = None)]
+    pub file: Option<PathBuf>,
+}
+
+/// An operation of a batch: a command and its arguments, as they would be written on the command
+/// line
+///
+/// # Fields
+///
+/// * `op` - The command, one of `user create`, `user update`, `video create`, `video update`, or
+///   `view add`
+/// * `args` - The arguments of the command
+#[derive(Debug, Deserialize)]
+struct Operation {
+    op: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// Parses an operation into the command it stands for
+///
+/// # Returns
+///
+/// The command, or an error message if it is invalid or not one a batch can run
+fn parse(operation: &Operation) -> Result<CommandType, String> {
+    let arguments = std::iter::once("rustflix")
+        .chain(operation.op.split_whitespace())
+        .chain(operation.args.iter().map(String::as_str));
+    let command = RustflixArgs::try_parse_from(arguments)
+        .map_err(|e| e.render().to_string().trim_end().to_string())?
+        .command_type;
+
+    let allowed = match &command {
+        CommandType::User(user) => matches!(
+            user.subcommand,
+            UserSubcommand::Create(_) | UserSubcommand::Update(_)
+        ),
+        CommandType::Video(video) => matches!(
+            video.subcommand,
+            VideoSubcommand::Create(_) | VideoSubcommand::Update(_)
+        ),
+        CommandType::View(view) => matches!(view.subcommand, ViewSubcommand::Add(_)),
+        _ => false,
+    };
+    match allowed {
+        true => Ok(command),
+        false => Err(t!("batch.not_allowed", op = operation.op)),
+    }
+}
+
+/// Handles applying many operations at once, loading each store they touch once and writing it
+/// once at the end, instead of once per operation
+///
+/// Every operation is reported as it is applied. An operation fails if it logs an error, in which
+/// case the batch goes on with the next one and exits with a failure at the end, after writing
+/// what the others changed.
+///
+/// # Arguments
+///
+/// * `batch` - The arguments for the batch
+/// * `player` - The player from the config file, which video commands are handed
+pub fn handle_batch(batch: Batch, player: Option<String>) {
+    let reader: Box<dyn BufRead> = match &batch.file {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                error!(
+                    "{}",
+                    t!("batch.could_not_open", path = path.display(), error = e)
+                );
+                return;
+            }
+        },
+        None => Box::new(io::stdin().lock()),
+    };
+
+    storage::begin_batch();
+    let mut applied = 0;
+    let mut failed = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let number = index + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                error!("{}", t!("batch.could_not_read", line = number, error = e));
+                failed += 1;
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = serde_json::from_str::<Operation>(&line)
+            .map_err(|e| t!("batch.invalid_operation", error = e))
+            .and_then(|operation| parse(&operation));
+        let command = match command {
+            Ok(command) => command,
+            Err(e) => {
+                error!("{}", e);
+                println!("{}\tfailed", number);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let errors = logging::error_count();
+        match command {
+            CommandType::User(user) => args::handle_user_command(user),
+            CommandType::Video(video) => args::handle_video_command(video, player.clone()),
+            CommandType::View(view) => args::handle_view_command(view),
+            _ => unreachable!("Only allowed commands are parsed"),
+        }
+        if logging::error_count() == errors {
+            println!("{}\tok", number);
+            applied += 1;
+        } else {
+            println!("{}\tfailed", number);
+            failed += 1;
+        }
+    }
+    storage::end_batch();
+
+    if failed > 0 {
+        error!("{}", t!("batch.failed", failed = failed, applied = applied));
+        process::exit(1);
+    }
+    info!("{}", t!("batch.applied", count = applied));
+}
+
+/// Creates a record for each line read from stdin, loading the store once and writing it once at
+/// the end, as `--from-stdin` does
+///
+/// Every line is reported as it is created. A line fails if it can't be read as a record or its
+/// creation logs an error, in which case the others are still created, and the command exits with
+/// a failure at the end, after writing them.
+///
+/// # Arguments
+///
+/// * `records` - The records read, by the line they start on, or why they couldn't be read
+/// * `noun` - What is created, plural, e.g. `users`
+/// * `create` - Creates the record of a line, logging an error if it can't be
+pub fn create_each<T>(records: Lines<T>, noun: &str, mut create: impl FnMut(T)) {
+    storage::begin_batch();
+    let mut created = 0;
+    let mut failed = 0;
+    for (number, record) in records {
+        let errors = logging::error_count();
+        match record {
+            Ok(record) => create(record),
+            Err(e) => error!("{}", t!("batch.line", line = number, error = e)),
+        }
+        if logging::error_count() == errors {
+            println!("{}\tok", number);
+            created += 1;
+        } else {
+            println!("{}\tfailed", number);
+            failed += 1;
+        }
+    }
+    storage::end_batch();
+
+    if failed > 0 {
+        error!(
+            "{}",
+            t!(
+                "batch.lines_failed",
+                failed = failed,
+                created = created,
+                noun = noun
+            )
+        );
+        process::exit(1);
+    }
+    info!("{}", t!("batch.created", count = created, noun = noun));
+}
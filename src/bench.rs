@@ -0,0 +1,211 @@
+use crate::args::command_types::video_subcommands::Video;
+use crate::formatting::Table;
+use crate::repository::Repository;
+use crate::s3::{self, S3Config};
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{encryption, remote};
+use clap::Args;
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::hint;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Args)]
+pub struct Bench {
+    /// The number of synthetic videos to fill the store with
+    #[arg(long, default_value_t = 10_000)]
+    pub records: u32,
+    /// How many times to run each operation
+    #[arg(long, default_value_t = 10)]
+    pub runs: u32,
+}
+
+/// Where the benchmark keeps its synthetic store, away from the real one
+///
+/// # Variants
+///
+/// * `Files` - A directory of its own in the temporary directory
+/// * `Bucket` - Under a prefix of its own in the configured bucket
+enum Backend {
+    Files(PathBuf),
+    Bucket(S3Config),
+}
+
+impl Backend {
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Files(_) => "files",
+            Backend::Bucket(_) => "s3",
+        }
+    }
+
+    fn load<T: Serialize + DeserializeOwned>(&self, store: Store) -> Vec<T> {
+        match self {
+            Backend::Files(_) => storage::load_file(store),
+            Backend::Bucket(config) => s3::load(config, store),
+        }
+    }
+
+    /// Writes the synthetic store without going through `storage::save`, so saving it sends no
+    /// webhooks, audit entries, or notifications about the synthetic records
+    fn save<T: Serialize>(&self, store: Store, records: &Vec<T>) {
+        let bytes = storage::encode(records);
+        match self {
+            Backend::Files(_) => storage::write_file(store, &bytes),
+            Backend::Bucket(config) => s3::write(config, store, &bytes),
+        }
+    }
+
+    /// Removes the synthetic store
+    fn clean_up(&self) {
+        match self {
+            Backend::Files(path) => {
+                if let Err(e) = fs::remove_dir_all(path) {
+                    warn!(
+                        "{}",
+                        t!("bench.could_not_remove", path = path.display(), error = e)
+                    );
+                }
+            }
+            Backend::Bucket(config) => s3::delete(config, Store::Videos),
+        }
+    }
+}
+
+/// Makes a synthetic video
+fn video(id: u32) -> Video {
+    Video {
+        id,
+        name: format!("Video {}", id),
+        views: id % 1000,
+        description: Some(format!("The synthetic video number {}", id)),
+        duration: Some(60 + id % 7200),
+        genres: vec!["Benchmark".to_string()],
+        ..Default::default()
+    }
+}
+
+/// Runs an operation the given number of times
+///
+/// # Returns
+///
+/// How long all runs took together
+fn time(runs: u32, mut operation: impl FnMut(u32)) -> Duration {
+    let start = Instant::now();
+    for run in 0..runs {
+        operation(run);
+    }
+    start.elapsed()
+}
+
+/// Handles measuring how fast the active backend creates, lists, finds, and updates records, on a
+/// synthetic store of videos kept apart from the real stores
+///
+/// Stores kept on a server with `--remote` can't be measured, as the benchmark would replace them.
+///
+/// # Arguments
+///
+/// * `bench` - The arguments for the benchmark
+pub fn handle_bench(bench: Bench) {
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("bench.remote", remote = remote));
+        return;
+    }
+    if bench.records == 0 || bench.runs == 0 {
+        error!("{}", t!("bench.nothing_to_run"));
+        return;
+    }
+
+    let backend = match s3::config() {
+        Some(config) => Backend::Bucket(S3Config {
+            prefix: format!("{}rustflix-bench-{}/", config.prefix, process::id()),
+            ..config.clone()
+        }),
+        None => {
+            let path = env::temp_dir().join(format!("rustflix-bench-{}", process::id()));
+            storage::set_data_dir(path.clone());
+            Backend::Files(path)
+        }
+    };
+    let starting = if encryption::enabled() {
+        t!(
+            "bench.starting_encrypted",
+            backend = backend.name(),
+            count = bench.records
+        )
+    } else {
+        t!(
+            "bench.starting",
+            backend = backend.name(),
+            count = bench.records
+        )
+    };
+    info!("{}", starting);
+
+    let start = Instant::now();
+    let videos: Vec<Video> = (1..=bench.records).map(video).collect();
+    backend.save(Store::Videos, &videos);
+    let fill = start.elapsed();
+    drop(videos);
+
+    let mut next_id = bench.records;
+    let results = [
+        (
+            "create",
+            time(bench.runs, |_| {
+                let mut videos: Vec<Video> = backend.load(Store::Videos);
+                next_id += 1;
+                videos.push(video(next_id));
+                backend.save(Store::Videos, &videos);
+            }),
+        ),
+        (
+            "list",
+            time(bench.runs, |_| {
+                backend.load::<Video>(Store::Videos);
+            }),
+        ),
+        (
+            "find",
+            time(bench.runs, |run| {
+                let videos = Repository::<Video>::new(backend.load(Store::Videos));
+                let name = format!("Video {}", run.wrapping_mul(7919) % bench.records + 1);
+                hint::black_box(videos.with_name(&name));
+            }),
+        ),
+        (
+            "update",
+            time(bench.runs, |run| {
+                let mut videos = Repository::<Video>::new(backend.load(Store::Videos));
+                let mut updated = video(run % bench.records + 1);
+                updated.views += 1;
+                videos.replace(updated);
+                backend.save(Store::Videos, &videos.into_records());
+            }),
+        ),
+    ];
+    backend.clean_up();
+
+    info!("{}", t!("bench.filled", time = format!("{:?}", fill)));
+    let mut table = Table::new(
+        ["operation", "runs", "total", "per run", "runs/s"]
+            .map(str::to_string)
+            .to_vec(),
+    );
+    for (operation, total) in results {
+        table.row(vec![
+            operation.to_string(),
+            bench.runs.to_string(),
+            format!("{:.2?}", total),
+            format!("{:.2?}", total / bench.runs),
+            format!("{:.1}", bench.runs as f64 / total.as_secs_f64()),
+        ]);
+    }
+    table.print();
+}
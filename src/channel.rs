@@ -0,0 +1,318 @@
+use crate::args::command_types::user_subcommands::User;
+use crate::args::command_types::video_subcommands::{self, Video, Visibility};
+use crate::storage::{self, Store};
+use crate::t;
+use clap::{Args, Subcommand, ValueEnum};
+use log::error;
+use std::cmp::Reverse;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[derive(Debug, Args)]
+pub struct ChannelCommand {
+    #[clap(subcommand)]
+    pub subcommand: ChannelSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ChannelSubcommand {
+    /// Print a feed of the latest public videos of a channel, for feed and podcast readers to follow its uploads
+    Feed(ChannelFeed),
+}
+
+/// The formats a channel's feed can be written in
+///
+/// # Variants
+///
+/// * `Rss` - RSS 2.0
+/// * `Atom` - Atom 1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FeedFormat {
+    #[default]
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    /// The media type of feeds in this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ChannelFeed {
+    /// The ID of the user whose channel to write the feed of
+    #[arg(long)]
+    pub id: u32,
+    /// The format to write the feed in
+    #[arg(long, value_enum, default_value_t = FeedFormat::Rss)]
+    pub format: FeedFormat,
+    /// The most videos in the feed
+    #[arg(long, default_value_t = 20)]
+    pub count: usize,
+    /// The address the feed is served at, linked from it
+    #[arg(long, default_value = None)]
+    pub link: Option<String>,
+}
+
+/// Picks out the public videos a channel uploaded, the latest first
+///
+/// # Arguments
+///
+/// * `videos` - Every video
+/// * `channel` - The ID of the user whose channel it is
+/// * `count` - The most videos to pick
+pub fn recent_uploads(videos: &[Video], channel: u32, count: usize) -> Vec<&Video> {
+    let mut uploads: Vec<&Video> = videos
+        .iter()
+        .filter(|video| video.owner == Some(channel) && video.visibility == Visibility::Public)
+        .collect();
+    uploads.sort_by_key(|video| Reverse(video.uploaded_at));
+    uploads.truncate(count);
+    uploads
+}
+
+/// Writes the feed of a channel
+///
+/// # Arguments
+///
+/// * `format` - The format to write the feed in
+/// * `channel` - The user whose channel it is
+/// * `uploads` - The videos in the feed, the latest first
+/// * `link` - The address the feed is served at, if known
+/// * `now` - The current time in seconds since the Unix epoch, when the feed was built
+///
+/// # Returns
+///
+/// The feed as an XML document
+pub fn render(
+    format: FeedFormat,
+    channel: &User,
+    uploads: &[&Video],
+    link: Option<&str>,
+    now: u64,
+) -> String {
+    let description = channel
+        .bio
+        .clone()
+        .unwrap_or_else(|| format!("Videos uploaded by {}", channel.name));
+    let mut xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string();
+    match format {
+        FeedFormat::Rss => {
+            xml += "<rss version=\"2.0\">\n  <channel>\n";
+            xml += &format!("    <title>{}</title>\n", escape(&channel.name));
+            if let Some(link) = link {
+                xml += &format!("    <link>{}</link>\n", escape(link));
+            }
+            xml += &format!("    <description>{}</description>\n", escape(&description));
+            xml += &format!("    <lastBuildDate>{}</lastBuildDate>\n", rfc2822(now));
+            for video in uploads {
+                xml += "    <item>\n";
+                xml += &format!("      <title>{}</title>\n", escape(&video.name));
+                xml += &format!(
+                    "      <guid isPermaLink=\"false\">urn:rustflix:video:{}</guid>\n",
+                    video.id
+                );
+                if let Some(uploaded_at) = video.uploaded_at {
+                    xml += &format!("      <pubDate>{}</pubDate>\n", rfc2822(uploaded_at));
+                }
+                if let Some(description) = &video.description {
+                    xml += &format!("      <description>{}</description>\n", escape(description));
+                }
+                for genre in &video.genres {
+                    xml += &format!("      <category>{}</category>\n", escape(genre));
+                }
+                xml += "    </item>\n";
+            }
+            xml += "  </channel>\n</rss>\n";
+        }
+        FeedFormat::Atom => {
+            let updated = uploads
+                .iter()
+                .filter_map(|video| video.uploaded_at)
+                .max()
+                .unwrap_or(now);
+            xml += "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n";
+            xml += &format!("  <title>{}</title>\n", escape(&channel.name));
+            xml += &format!("  <subtitle>{}</subtitle>\n", escape(&description));
+            xml += &format!("  <id>urn:rustflix:channel:{}</id>\n", channel.id);
+            xml += &format!("  <updated>{}</updated>\n", rfc3339(updated));
+            if let Some(link) = link {
+                xml += &format!("  <link rel=\"self\" href=\"{}\"/>\n", escape(link));
+            }
+            xml += &format!(
+                "  <author>\n    <name>{}</name>\n  </author>\n",
+                escape(&channel.name)
+            );
+            for video in uploads {
+                xml += "  <entry>\n";
+                xml += &format!("    <title>{}</title>\n", escape(&video.name));
+                xml += &format!("    <id>urn:rustflix:video:{}</id>\n", video.id);
+                xml += &format!(
+                    "    <updated>{}</updated>\n",
+                    rfc3339(video.updated_at.or(video.uploaded_at).unwrap_or(now))
+                );
+                if let Some(uploaded_at) = video.uploaded_at {
+                    xml += &format!("    <published>{}</published>\n", rfc3339(uploaded_at));
+                }
+                if let Some(description) = &video.description {
+                    xml += &format!("    <summary>{}</summary>\n", escape(description));
+                }
+                for genre in &video.genres {
+                    xml += &format!("    <category term=\"{}\"/>\n", escape(genre));
+                }
+                xml += "  </entry>\n";
+            }
+            xml += "</feed>\n";
+        }
+    }
+    xml
+}
+
+/// Escapes the characters of text that XML gives a meaning to
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Formats a time as RSS expects, e.g. `Sun, 25 Jun 2023 12:00:00 GMT`
+///
+/// # Arguments
+///
+/// * `timestamp` - The time in seconds since the Unix epoch
+fn rfc3339(timestamp: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)).to_string()
+}
+
+/// Formats a time as RFC 2822 dates are written in RSS feeds, e.g. `Sun, 25 Jun 2023 12:00:00 GMT`
+///
+/// # Arguments
+///
+/// * `timestamp` - The time in seconds since the Unix epoch
+fn rfc2822(timestamp: u64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    // e.g. 2023-06-25T12:00:00Z
+    let rfc3339 = rfc3339(timestamp);
+    let month: usize = rfc3339[5..7].parse().unwrap();
+    format!(
+        "{}, {} {} {} {} GMT",
+        DAYS[(timestamp / (24 * 60 * 60) % 7) as usize],
+        &rfc3339[8..10],
+        MONTHS[month - 1],
+        &rfc3339[0..4],
+        &rfc3339[11..19]
+    )
+}
+
+/// Builds the feed of a channel from the stores
+///
+/// # Arguments
+///
+/// * `id` - The ID of the user whose channel it is
+/// * `format` - The format to write the feed in
+/// * `count` - The most videos in the feed
+/// * `link` - The address the feed is served at, if known
+///
+/// # Returns
+///
+/// The feed, or `None` if no user has the given ID
+pub fn feed(id: u32, format: FeedFormat, count: usize, link: Option<&str>) -> Option<String> {
+    let users: Vec<User> = storage::load(Store::Users);
+    let channel = users.iter().find(|user| user.id == id)?;
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    let uploads = recent_uploads(&videos, id, count);
+    Some(render(
+        format,
+        channel,
+        &uploads,
+        link,
+        video_subcommands::now(),
+    ))
+}
+
+/// Handles printing the feed of a channel
+///
+/// # Arguments
+///
+/// * `channel_feed` - The arguments for the feed
+pub fn handle_channel_feed(channel_feed: ChannelFeed) {
+    match feed(
+        channel_feed.id,
+        channel_feed.format,
+        channel_feed.count,
+        channel_feed.link.as_deref(),
+    ) {
+        Some(feed) => print!("{}", feed),
+        None => error!("{}", t!("user.not_found_id", id = channel_feed.id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_feed() {
+        assert_eq!(rfc2822(1687694400), "Sun, 25 Jun 2023 12:00:00 GMT");
+        assert_eq!(
+            escape("Tom & \"Jerry\" <3"),
+            "Tom &amp; &quot;Jerry&quot; &lt;3"
+        );
+
+        let videos = [
+            Video {
+                id: 1,
+                name: "Old".to_string(),
+                owner: Some(7),
+                uploaded_at: Some(1),
+                ..Default::default()
+            },
+            Video {
+                id: 2,
+                name: "New".to_string(),
+                owner: Some(7),
+                uploaded_at: Some(2),
+                ..Default::default()
+            },
+            Video {
+                id: 3,
+                name: "Hidden".to_string(),
+                owner: Some(7),
+                visibility: Visibility::Private,
+                ..Default::default()
+            },
+            Video {
+                id: 4,
+                name: "Other".to_string(),
+                owner: Some(8),
+                ..Default::default()
+            },
+        ];
+        let uploads = recent_uploads(&videos, 7, 20);
+        assert_eq!(
+            uploads.iter().map(|video| video.id).collect::<Vec<_>>(),
+            [2, 1]
+        );
+
+        let channel = User {
+            id: 7,
+            name: "Ann".to_string(),
+            ..Default::default()
+        };
+        let rss = render(FeedFormat::Rss, &channel, &uploads, None, 0);
+        assert!(rss.contains("<title>Ann</title>"));
+        assert_eq!(rss.matches("<item>").count(), 2);
+        let atom = render(FeedFormat::Atom, &channel, &uploads[..1], None, 0);
+        assert!(atom.contains("<id>urn:rustflix:video:2</id>"));
+        assert!(atom.contains("<updated>1970-01-01T00:00:02Z</updated>"));
+    }
+}
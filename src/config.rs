@@ -0,0 +1,815 @@
+mod schema;
+
+use crate::backup::BackupConfig;
+use crate::desktop::DesktopConfig;
+use crate::dry_run;
+use crate::encryption::EncryptionConfig;
+use crate::feed::FeedConfig;
+use crate::formatting::{self, FormatConfig};
+use crate::milestones::MilestoneConfig;
+use crate::rate_limit::RateLimit;
+use crate::recommend::WatchTimeConfig;
+use crate::s3::S3Config;
+use crate::scheduler::Job;
+use crate::sinks::Sink;
+use crate::storage::StorageConfig;
+use crate::t;
+use crate::utilities;
+use crate::webhooks::Webhook;
+use clap::{Args, Subcommand};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::{env, fmt, fs};
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// Settings read from the config file
+///
+/// # Fields
+///
+/// * `aliases` - Names that expand to other arguments, e.g. `top = "video list --all --limit 10"`
+/// * `remote` - The URL of a rustflix server to use instead of local files, as with `--remote`
+/// * `token` - The API key token to send to the remote server, as with `--token`
+/// * `rate_limit` - How many views each client of `serve` may add, e.g. `[rate_limit] views = 100`
+/// * `player` - The command `video play` opens media files with, e.g. `player = "vlc --fullscreen"`
+/// * `backup` - Automatic backups of the stores, e.g. `[backup.auto] interval = "1day"`
+/// * `s3` - An S3-compatible bucket to keep the stores in instead of the data directory
+/// * `encryption` - The key to encrypt the stores with, e.g. `[encryption] passphrase = "..."`
+/// * `storage` - How store files are read, e.g. `[storage] mmap = false`
+/// * `strict` - Whether every command runs as with `--strict`, e.g. `strict = true`
+/// * `feed` - How much each kind of video counts towards `feed`, e.g. `[feed] trending = 2.0`
+/// * `watch_time` - How much plays count towards trending and recommendations by how much of the
+///   video was watched, e.g. `[watch_time] exponent = 2.0`
+/// * `milestones` - The view counts to celebrate videos reaching, e.g.
+///   `[milestones] views = [100, 1000]`
+/// * `desktop_notifications` - Which notifications pop up on the desktop while the daemon or
+///   `tui` runs, e.g. `[desktop_notifications] enabled = true`
+/// * `webhooks` - URLs to post changes to, each in a `[[webhooks]]` section, e.g.
+///   `url = "http://localhost:9000/hook"` and `events = ["video_created"]`
+/// * `sinks` - ntfy topics, Matrix rooms, and Discord webhooks to post milestones and uploads to,
+///   each in a `[[sinks]]` section, e.g. `kind = "discord"` and `url = "https://discord.com/..."`
+/// * `schedule` - Tasks the daemon runs on a schedule, each in a `[[schedule]]` section, e.g.
+///   `task = "backup"` and `cron = "0 3 * * *"`
+/// * `defaults` - Arguments used by commands unless given on the command line, e.g.
+///   `[defaults.video.list] limit = 10`, or `[defaults] color = "never"` for every command
+/// * `format` - How times, lengths, and view counts are printed, e.g. `[format] counts = "compact"`
+/// * `profile` - The profile used when `--profile` isn't given, e.g. `profile = "work"`
+/// * `locale` - The language messages are printed in, e.g. `locale = "es"`, instead of the one
+///   `LANG` gives
+/// * `profiles` - Named sets of settings that override the others when chosen with `--profile`,
+///   each in a `[profiles.<name>]` section, e.g. `[profiles.work] storage.data_dir = "/srv/rustflix"`
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub aliases: HashMap<String, String>,
+    pub remote: Option<String>,
+    pub token: Option<String>,
+    pub rate_limit: Option<RateLimit>,
+    pub player: Option<String>,
+    pub backup: BackupConfig,
+    pub s3: Option<S3Config>,
+    pub encryption: Option<EncryptionConfig>,
+    pub storage: StorageConfig,
+    pub strict: bool,
+    pub feed: FeedConfig,
+    pub watch_time: WatchTimeConfig,
+    pub milestones: MilestoneConfig,
+    pub desktop_notifications: DesktopConfig,
+    pub webhooks: Vec<Webhook>,
+    pub sinks: Vec<Sink>,
+    pub schedule: Vec<Job>,
+    pub defaults: Table,
+    pub format: FormatConfig,
+    pub profile: Option<String>,
+    pub locale: Option<String>,
+    pub profiles: HashMap<String, Table>,
+}
+
+/// The path of the user's config file, `~/.config/rustflix/config.toml` unless `RUSTFLIX_CONFIG`
+/// gives another
+pub fn config_path() -> PathBuf {
+    match env::var_os(CONFIG_VAR) {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => utilities::home_dir().join(".config/rustflix/config.toml"),
+    }
+}
+
+/// Where a setting came from, each overriding those before it
+///
+/// # Variants
+///
+/// * `Default` - Built into rustflix
+/// * `File` - A config file, `/etc/rustflix/config.toml` before the user's own
+/// * `Env` - An environment variable, e.g. `RUSTFLIX_RATE_LIMIT__VIEWS=100`
+/// * `Profile` - The profile chosen, from the `[profiles.<name>]` section of the config files
+/// * `Flag` - A flag given to the command, e.g. `--remote`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Profile(String),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Env(name) => write!(f, "{}", name),
+            Origin::Profile(name) => write!(f, "profile {}", name),
+            Origin::Flag(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+/// The config file shared by every user, read before their own
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/rustflix/config.toml";
+
+/// The prefix of environment variables overriding settings. Nested settings are separated by
+/// `__`, e.g. `RUSTFLIX_STORAGE__MMAP=true`
+const ENV_PREFIX: &str = "RUSTFLIX_";
+
+/// The environment variable giving the path of the user's config file
+const CONFIG_VAR: &str = "RUSTFLIX_CONFIG";
+
+/// Environment variables that set a nested setting under a shorter name, always taken as text
+const ENV_SHORTHANDS: [(&str, &str); 2] = [
+    ("RUSTFLIX_DATA_DIR", "storage.data_dir"),
+    ("RUSTFLIX_BACKEND", "storage.backend"),
+];
+
+/// The settings given as flags, which override every other layer
+///
+/// # Fields
+///
+/// * `remote` - The URL given with `--remote`
+/// * `token` - The token given with `--token`
+/// * `strict` - Whether `--strict` was given
+/// * `profile` - The name of the profile given with `--profile`
+#[derive(Debug, Default)]
+pub struct Flags {
+    pub remote: Option<String>,
+    pub token: Option<String>,
+    pub strict: bool,
+    pub profile: Option<String>,
+}
+
+impl Flags {
+    /// The settings the flags set, as they are written in the config file
+    fn layer(&self) -> Vec<(Table, Origin)> {
+        let mut layers = vec![];
+        if let Some(remote) = &self.remote {
+            layers.push((
+                table("remote", remote.as_str().into()),
+                Origin::Flag("--remote"),
+            ));
+        }
+        if let Some(token) = &self.token {
+            layers.push((
+                table("token", token.as_str().into()),
+                Origin::Flag("--token"),
+            ));
+        }
+        if self.strict {
+            layers.push((table("strict", true.into()), Origin::Flag("--strict")));
+        }
+        if let Some(profile) = &self.profile {
+            layers.push((
+                table("profile", profile.as_str().into()),
+                Origin::Flag("--profile"),
+            ));
+        }
+        layers
+    }
+}
+
+/// Builds a table holding a single setting
+fn table(key: &str, value: Value) -> Table {
+    Table::from_iter([(key.to_string(), value)])
+}
+
+/// Reads a config file
+///
+/// # Returns
+///
+/// The settings in the file, or `None` if it doesn't exist or can't be read
+fn read_file(path: &Path) -> Option<Table> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => {
+            debug!("No config file found at {}", path.display());
+            return None;
+        }
+    };
+    match schema::check(&contents) {
+        Ok((table, diagnostics)) => {
+            for diagnostic in diagnostics {
+                match diagnostic.line {
+                    Some(line) => warn!(
+                        "{}:{}:{}: {}",
+                        path.display(),
+                        line,
+                        diagnostic.column,
+                        diagnostic.message
+                    ),
+                    None => warn!("{}: {}", path.display(), diagnostic.message),
+                }
+            }
+            Some(table)
+        }
+        Err(e) => {
+            warn!(
+                "{}",
+                t!("config.invalid_file", path = path.display(), error = e)
+            );
+            None
+        }
+    }
+}
+
+/// Reads the settings in the environment variables starting with `RUSTFLIX_`, other than
+/// `RUSTFLIX_CONFIG`. Values are read as TOML, e.g. `true` or `[100, 1000]`, or else as text
+fn env_layers() -> Vec<(Table, Origin)> {
+    let mut layers = vec![];
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != CONFIG_VAR)
+        .collect();
+    vars.sort();
+    for (name, raw) in vars {
+        let shorthand = ENV_SHORTHANDS
+            .iter()
+            .find(|(shorthand, _)| *shorthand == name);
+        let (key, value) = match shorthand {
+            Some((_, key)) => (key.to_string(), Value::String(raw)),
+            None => (
+                name[ENV_PREFIX.len()..].to_lowercase().replace("__", "."),
+                toml::from_str::<Table>(&format!("value = {}", raw))
+                    .ok()
+                    .and_then(|mut parsed| parsed.remove("value"))
+                    .unwrap_or(Value::String(raw)),
+            ),
+        };
+        let layer = key
+            .rsplit('.')
+            .fold(value, |value, part| Value::Table(table(part, value)));
+        if let Value::Table(layer) = layer {
+            layers.push((layer, Origin::Env(name)));
+        }
+    }
+    layers
+}
+
+/// Merges the settings of a layer over those of the layers before it, replacing values and
+/// merging tables
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Lists the settings in a table as dotted keys, e.g. `backup.auto.interval`, along with their
+/// values. Lists of tables, such as `webhooks`, count as a single setting each
+fn settings(table: &Table, prefix: &str, found: &mut Vec<(String, Value)>) {
+    for (key, value) in table {
+        let key = match prefix {
+            "" => key.clone(),
+            prefix => format!("{}.{}", prefix, key),
+        };
+        match value {
+            Value::Table(table) => settings(table, &key, found),
+            value => found.push((key, value.clone())),
+        }
+    }
+}
+
+/// Settings merged from layers, along with the layer each came from
+///
+/// # Fields
+///
+/// * `merged` - The settings of every layer merged
+/// * `origins` - The layer each setting came from, by its dotted key
+#[derive(Debug, Clone, Default)]
+struct Layered {
+    merged: Table,
+    origins: HashMap<String, Origin>,
+}
+
+impl Layered {
+    /// Merges a layer over the settings so far, or ignores it with a warning if it would make
+    /// them invalid
+    fn push(&mut self, layer: Table, origin: Origin) {
+        let mut candidate = self.merged.clone();
+        merge(&mut candidate, layer.clone());
+        if let Err(e) = Config::deserialize(candidate.clone()) {
+            warn!(
+                "{}",
+                t!("config.invalid_layer", origin = origin, error = e.message())
+            );
+            return;
+        }
+        self.merged = candidate;
+        let mut set = vec![];
+        settings(&layer, "", &mut set);
+        for (key, _) in set {
+            self.origins.insert(key, origin.clone());
+        }
+    }
+}
+
+/// The settings of the config files and environment variables, read once
+static FILES_AND_ENV: OnceLock<Layered> = OnceLock::new();
+
+/// Reads the settings of the config files and environment variables, the first time they are
+/// needed
+fn files_and_env() -> &'static Layered {
+    FILES_AND_ENV.get_or_init(|| {
+        let mut layered = Layered::default();
+        for path in [PathBuf::from(SYSTEM_CONFIG_PATH), config_path()] {
+            if let Some(layer) = read_file(&path) {
+                layered.push(layer, Origin::File(path.to_path_buf()));
+            }
+        }
+        for (layer, origin) in env_layers() {
+            layered.push(layer, origin);
+        }
+        layered
+    })
+}
+
+/// Reads every layer of settings and merges them: the defaults, `/etc/rustflix/config.toml`, the
+/// user's config file, the `RUSTFLIX_` environment variables, the profile chosen, then the flags. A
+/// layer that would make the settings invalid is ignored with a warning
+///
+/// # Arguments
+///
+/// * `flags` - The settings given as flags
+///
+/// # Returns
+///
+/// The settings, and the layer each setting that isn't a default came from
+pub fn layered(flags: &Flags) -> (Config, HashMap<String, Origin>) {
+    let mut layered = files_and_env().clone();
+    let profile = flags.profile.clone().or_else(|| {
+        let profile = layered.merged.get("profile")?.as_str()?;
+        Some(profile.to_string())
+    });
+    if let Some(settings) = profile.as_ref().and_then(|profile| {
+        lookup(&layered.merged, "profiles")?
+            .get(profile)?
+            .as_table()
+    }) {
+        layered.push(settings.clone(), Origin::Profile(profile.unwrap()));
+    }
+    for (layer, origin) in flags.layer() {
+        layered.push(layer, origin);
+    }
+    (
+        Config::deserialize(layered.merged).unwrap_or_default(),
+        layered.origins,
+    )
+}
+
+/// Loads the settings of every config file, environment variable, and flag, as layered by
+/// `layered`
+///
+/// # Arguments
+///
+/// * `flags` - The settings given as flags
+///
+/// # Returns
+///
+/// The settings, or the defaults where none were given
+pub fn load(flags: &Flags) -> Config {
+    layered(flags).0
+}
+
+/// Loads the settings of the config files and environment variables alone, for the aliases and
+/// defaults that are needed before the arguments are parsed
+pub fn before_parsing() -> Config {
+    Config::deserialize(files_and_env().merged.clone()).unwrap_or_default()
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigCommand {
+    #[clap(subcommand)]
+    pub subcommand: ConfigSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigSubcommand {
+    /// Show every setting in effect, merged from the defaults, /etc/rustflix/config.toml, the user's config file, RUSTFLIX_ environment variables, and flags
+    Show(ShowConfig),
+    /// Print the value in effect of a setting, e.g. `backup.auto.interval`
+    Get(GetConfig),
+    /// Set a setting in the user's config file, checking the value is valid for it
+    Set(SetConfig),
+    /// Remove a setting from the user's config file, going back to the value of the layers before it
+    Unset(UnsetConfig),
+}
+
+#[derive(Debug, Args)]
+pub struct ShowConfig {
+    /// Also show where each setting came from
+    #[arg(long, default_value_t = false)]
+    pub origin: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct GetConfig {
+    /// The dotted key of the setting, e.g. `rate_limit.views`
+    pub key: String,
+}
+
+#[derive(Debug, Args)]
+pub struct SetConfig {
+    /// The dotted key of the setting, e.g. `rate_limit.views`
+    pub key: String,
+    /// The value to set, as TOML, e.g. `100`, `true` or `[1000, 10000]`. Anything else is taken as text
+    pub value: String,
+}
+
+#[derive(Debug, Args)]
+pub struct UnsetConfig {
+    /// The dotted key of the setting, e.g. `rate_limit.views`
+    pub key: String,
+}
+
+/// The names of settings whose values are secret, and never shown
+pub const SECRETS: [&str; 3] = ["token", "passphrase", "secret_key"];
+
+/// Hides the secrets in a setting, including those within lists of tables such as `sinks`
+fn hide_secrets(key: &str, value: Value) -> Value {
+    let secret = |key: &str| SECRETS.contains(&key.rsplit('.').next().unwrap_or_default());
+    match value {
+        _ if secret(key) => Value::String("[hidden]".to_string()),
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Table(table) => Value::Table(
+                        table
+                            .into_iter()
+                            .map(|(key, value)| {
+                                let value = hide_secrets(&key, value);
+                                (key, value)
+                            })
+                            .collect(),
+                    ),
+                    value => value,
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Handles showing every setting in effect, optionally with where each came from
+///
+/// # Arguments
+///
+/// * `show_config` - The arguments for showing the settings
+/// * `flags` - The settings given as flags
+pub fn handle_show_config(show_config: ShowConfig, flags: &Flags) {
+    let (config, origins) = layered(flags);
+    let Ok(Value::Table(effective)) = Value::try_from(&config) else {
+        error!("{}", t!("config.could_not_show"));
+        return;
+    };
+    let mut found = vec![];
+    settings(&effective, "", &mut found);
+
+    if !show_config.origin {
+        for (key, value) in found {
+            let value = hide_secrets(&key, value);
+            println!("{} = {}", key, value);
+        }
+        return;
+    }
+    let mut table =
+        formatting::Table::new(["setting", "value", "origin"].map(str::to_string).to_vec());
+    for (key, value) in found {
+        let origin = origins.get(&key).cloned().unwrap_or(Origin::Default);
+        table.row(vec![
+            key.clone(),
+            hide_secrets(&key, value).to_string(),
+            origin.to_string(),
+        ]);
+    }
+    table.print();
+}
+
+/// Finds a setting among settings by its dotted key
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let (parents, last) = match key.rsplit_once('.') {
+        Some((parents, last)) => (Some(parents), last),
+        None => (None, key),
+    };
+    let table = match parents {
+        Some(parents) => lookup(table, parents)?.as_table()?,
+        None => table,
+    };
+    table.get(last)
+}
+
+/// Checks that a config file is valid and that it sets the given setting, which it wouldn't if
+/// the setting doesn't exist
+///
+/// # Arguments
+///
+/// * `document` - The config file
+/// * `key` - The dotted key of the setting, if it should be set
+///
+/// # Returns
+///
+/// Why the config file is invalid, if it is
+fn validate(document: &DocumentMut, key: Option<&str>) -> Result<(), String> {
+    let mut table = read_file(Path::new(SYSTEM_CONFIG_PATH)).unwrap_or_default();
+    merge(
+        &mut table,
+        toml::from_str(&document.to_string()).map_err(|e| e.message().to_string())?,
+    );
+    let config = Config::deserialize(table.clone()).map_err(|e| e.message().to_string())?;
+    let profile = |name: &str| {
+        let mut merged = table.clone();
+        merge(&mut merged, config.profiles[name].clone());
+        Config::deserialize(merged)
+            .map_err(|e| t!("config.invalid_profile", name = name, error = e.message()))
+    };
+    for name in config.profiles.keys() {
+        profile(name)?;
+    }
+    let Some(key) = key else {
+        return Ok(());
+    };
+
+    // Settings within a profile are checked against those the profile overrides
+    let (config, key) = match key
+        .strip_prefix("profiles.")
+        .and_then(|key| key.split_once('.'))
+    {
+        Some((name, key)) => (profile(name)?, key),
+        None => (config, key),
+    };
+    let Ok(Value::Table(effective)) = Value::try_from(&config) else {
+        return Err(t!("config.could_not_read_back"));
+    };
+    match lookup(&effective, key) {
+        Some(_) => Ok(()),
+        None => Err(t!("config.no_setting", key = key)),
+    }
+}
+
+/// Sets or removes a setting in a config file, keeping its comments and layout
+///
+/// # Arguments
+///
+/// * `contents` - The config file
+/// * `key` - The dotted key of the setting
+/// * `value` - The value to set, or `None` to remove the setting
+///
+/// # Returns
+///
+/// The edited config file, or why it couldn't be edited
+pub fn edit(contents: &str, key: &str, value: Option<Item>) -> Result<String, String> {
+    let mut document: DocumentMut = contents
+        .parse()
+        .map_err(|e: toml_edit::TomlError| e.message().to_string())?;
+    let parts: Vec<&str> = key.split('.').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(t!("config.invalid_key", key = format!("{:?}", key)));
+    }
+    let (last, parents) = parts.split_last().unwrap();
+    let setting = value.is_some();
+
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for (i, part) in parents.iter().enumerate() {
+        if table.get(part).is_none() {
+            if !setting {
+                return Err(t!("config.not_in_file", key = key));
+            }
+            let mut parent = toml_edit::Table::new();
+            parent.set_implicit(true);
+            table.insert(part, Item::Table(parent));
+        }
+        table = table
+            .get_mut(part)
+            .and_then(Item::as_table_like_mut)
+            .ok_or_else(|| t!("config.not_a_table", key = parts[..=i].join(".")))?;
+    }
+    match value {
+        Some(value) => {
+            table.insert(last, value);
+        }
+        None => {
+            if table.remove(last).is_none() {
+                return Err(t!("config.not_in_file", key = key));
+            }
+        }
+    }
+
+    validate(&document, setting.then_some(key))?;
+    Ok(document.to_string())
+}
+
+/// Reads a value given on the command line as TOML, e.g. `100`, `true` or `[1000, 10000]`, or
+/// else as text
+pub fn parse_value(raw: &str) -> Item {
+    Item::Value(
+        raw.parse::<toml_edit::Value>()
+            .unwrap_or_else(|_| raw.into()),
+    )
+}
+
+/// Edits the user's config file, creating it if it doesn't exist
+///
+/// # Arguments
+///
+/// * `key` - The dotted key of the setting
+/// * `value` - The value to set, or `None` to remove the setting
+///
+/// # Returns
+///
+/// Whether the config file was edited
+pub fn edit_config_file(key: &str, value: Option<Item>) -> bool {
+    let path = config_path();
+    let contents = fs::read_to_string(&path).unwrap_or_default();
+    let edited = match edit(&contents, key, value) {
+        Ok(edited) => edited,
+        Err(e) => {
+            error!(
+                "{}",
+                t!("config.could_not_edit", path = path.display(), error = e)
+            );
+            return false;
+        }
+    };
+    if dry_run::skip_file_write(&path, &contents, &edited) {
+        return true;
+    }
+    if let Err(e) = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, edited))
+    {
+        error!(
+            "{}",
+            t!("config.could_not_write", path = path.display(), error = e)
+        );
+        return false;
+    }
+    true
+}
+
+/// Handles printing the value in effect of a setting, or of every setting within a table
+///
+/// # Arguments
+///
+/// * `get_config` - The arguments for getting the setting
+/// * `flags` - The settings given as flags
+pub fn handle_get_config(get_config: GetConfig, flags: &Flags) {
+    let (config, _) = layered(flags);
+    let Ok(Value::Table(effective)) = Value::try_from(&config) else {
+        error!("{}", t!("config.could_not_read"));
+        return;
+    };
+    match lookup(&effective, &get_config.key) {
+        Some(Value::String(text)) => println!("{}", text),
+        Some(Value::Table(table)) => {
+            let mut found = vec![];
+            settings(table, &get_config.key, &mut found);
+            for (key, value) in found {
+                println!("{} = {}", key, value);
+            }
+        }
+        Some(value) => println!("{}", value),
+        None => error!("{}", t!("config.not_set", key = get_config.key)),
+    }
+}
+
+/// Handles setting a setting in the user's config file
+///
+/// # Arguments
+///
+/// * `set_config` - The arguments for setting the setting
+pub fn handle_set_config(set_config: SetConfig) {
+    if edit_config_file(&set_config.key, Some(parse_value(&set_config.value))) {
+        info!(
+            "{}",
+            t!("config.set", key = set_config.key, value = set_config.value)
+        );
+    }
+}
+
+/// Handles removing a setting from the user's config file
+///
+/// # Arguments
+///
+/// * `unset_config` - The arguments for removing the setting
+pub fn handle_unset_config(unset_config: UnsetConfig) {
+    if edit_config_file(&unset_config.key, None) {
+        info!(
+            "{}",
+            t!(
+                "config.removed",
+                key = unset_config.key,
+                path = config_path().display()
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let mut merged: Table = toml::from_str(
+            r#"
+            strict = true
+            [backup.auto]
+            interval = "1h"
+            keep_last = 5
+            "#,
+        )
+        .unwrap();
+        merge(
+            &mut merged,
+            table(
+                "backup",
+                Value::Table(table("auto", Value::Table(table("keep_last", 9.into())))),
+            ),
+        );
+        let mut found = vec![];
+        settings(&merged, "", &mut found);
+        assert_eq!(
+            found,
+            [
+                ("backup.auto.interval".to_string(), Value::from("1h")),
+                ("backup.auto.keep_last".to_string(), Value::from(9)),
+                ("strict".to_string(), Value::from(true)),
+            ]
+        );
+        assert!(Config::deserialize(merged).unwrap().strict);
+
+        let sinks = Value::Array(vec![Value::Table(table("token", "secret".into()))]);
+        assert_eq!(
+            hide_secrets("sinks", sinks).to_string(),
+            r#"[{ token = "[hidden]" }]"#
+        );
+
+        let contents = "# Mine\nstrict = true\n";
+        let edited = edit(contents, "rate_limit.views", Some(parse_value("100"))).unwrap();
+        assert_eq!(
+            edited,
+            "# Mine\nstrict = true\n\n[rate_limit]\nviews = 100\n"
+        );
+        assert!(edit(&edited, "rate_limit.views", None).is_err());
+        assert_eq!(edit(&edited, "rate_limit", None).unwrap(), contents);
+        assert!(edit(contents, "rate_limit.views", Some(parse_value("many"))).is_err());
+        assert!(edit(contents, "storage.backend", Some(parse_value("sqlite"))).is_err());
+        assert!(edit(contents, "strict.deep", Some(parse_value("1"))).is_err());
+        assert!(edit(contents, "player", None).is_err());
+        assert!(edit(
+            contents,
+            "profiles.work.storage.data_dir",
+            Some(parse_value("/srv"))
+        )
+        .is_ok());
+        assert!(edit(
+            contents,
+            "profiles.work.storage.engine",
+            Some(parse_value("s3"))
+        )
+        .is_err());
+        assert!(edit(contents, "profiles.work.strict", Some(parse_value("no"))).is_err());
+
+        env::set_var("RUSTFLIX_DATA_DIR", "2024");
+        env::set_var("RUSTFLIX_RATE_LIMIT__VIEWS", "5");
+        let layers = env_layers();
+        let layer = |name: &str| {
+            let found = layers
+                .iter()
+                .find(|(_, origin)| *origin == Origin::Env(name.to_string()));
+            found.unwrap().0.to_string()
+        };
+        assert_eq!(
+            layer("RUSTFLIX_DATA_DIR"),
+            "[storage]\ndata_dir = \"2024\"\n"
+        );
+        assert_eq!(
+            layer("RUSTFLIX_RATE_LIMIT__VIEWS"),
+            "[rate_limit]\nviews = 5\n"
+        );
+    }
+}
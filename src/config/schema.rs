@@ -0,0 +1,320 @@
+use super::Config;
+use std::ops::Range;
+use toml::{Table, Value};
+use toml_edit::ImDocument;
+
+/// Settings that still work but have been replaced, by dotted key with `*` standing for any one
+/// key or index, and what to use instead
+const DEPRECATED: [(&str, &str); 1] = [(
+    "webhooks.*.thresholds",
+    "Use `[milestones] views` with `webhooks = true` instead, which sends milestone_reached events",
+)];
+
+/// How many invalid settings are reported in one file before giving up on it
+const MAX_INVALID: usize = 100;
+
+/// A problem with a setting in a config file
+///
+/// # Fields
+///
+/// * `line` - The line the setting is on, counting from 1, or `None` if it can't be found
+/// * `column` - The column the setting starts at, counting from 1
+/// * `message` - What is wrong with the setting
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: Option<usize>,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Checks the settings in a config file against those rustflix knows, finding unknown settings,
+/// values of the wrong type, and deprecated settings
+///
+/// # Arguments
+///
+/// * `contents` - The contents of the config file
+///
+/// # Returns
+///
+/// The valid settings of the file, with every invalid one left out, and the problems found. An
+/// error message instead if the file isn't valid TOML at all
+pub fn check(contents: &str) -> Result<(Table, Vec<Diagnostic>), String> {
+    let mut table: Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+    let document = ImDocument::parse(contents).map_err(|e| e.to_string())?;
+    let at = |path: &[String], message: String| {
+        let span = locate(&document, path);
+        let (line, column) = match span {
+            Some(span) => {
+                let before = &contents[..span.start];
+                let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+                (
+                    Some(before.matches('\n').count() + 1),
+                    before[line_start..].chars().count() + 1,
+                )
+            }
+            None => (None, 1),
+        };
+        Diagnostic {
+            line,
+            column,
+            message,
+        }
+    };
+
+    let mut diagnostics = vec![];
+    let mut unknown = vec![];
+    for _ in 0..MAX_INVALID {
+        let mut note_unknown = |path: serde_ignored::Path| unknown.push(ignored_path(&path));
+        let deserializer =
+            serde_ignored::Deserializer::new(Value::Table(table.clone()), &mut note_unknown);
+        let Err(e) = serde_path_to_error::deserialize::<_, Config>(deserializer) else {
+            break;
+        };
+        let path = error_path(e.path());
+        let message = format!(
+            "Ignoring {}. {}",
+            match path.is_empty() {
+                true => "the file".to_string(),
+                false => path.join("."),
+            },
+            e.inner().message()
+        );
+        diagnostics.push(at(&path, message));
+        if path.is_empty() || !remove(&mut table, &path) {
+            return Ok((Table::new(), diagnostics));
+        }
+    }
+
+    // Settings are visited again after every invalid one is left out
+    unknown.sort();
+    unknown.dedup();
+    let defaults = Value::try_from(Config::default()).unwrap_or(Value::Table(Table::new()));
+    for path in unknown {
+        let mut message = format!("Unknown setting {}", path.join("."));
+        if let Some(suggestion) = suggest(&defaults, &path) {
+            message.push_str(&format!(". Did you mean {}?", suggestion));
+        }
+        diagnostics.push(at(&path, message));
+    }
+
+    let mut keys = vec![];
+    paths(&Value::Table(table.clone()), &mut vec![], &mut keys);
+    for path in keys {
+        for (pattern, instead) in DEPRECATED {
+            let matches = pattern.split('.').count() == path.len()
+                && pattern
+                    .split('.')
+                    .zip(&path)
+                    .all(|(part, key)| part == "*" || part == key);
+            if matches {
+                diagnostics.push(at(
+                    &path,
+                    format!("{} is deprecated. {}", path.join("."), instead),
+                ));
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    Ok((table, diagnostics))
+}
+
+/// The keys and indexes leading to a setting ignored while deserializing
+fn ignored_path(path: &serde_ignored::Path) -> Vec<String> {
+    let mut parts = vec![];
+    let mut path = path;
+    loop {
+        path = match path {
+            serde_ignored::Path::Root => break,
+            serde_ignored::Path::Seq { parent, index } => {
+                parts.push(index.to_string());
+                parent
+            }
+            serde_ignored::Path::Map { parent, key } => {
+                parts.push(key.clone());
+                parent
+            }
+            serde_ignored::Path::Some { parent }
+            | serde_ignored::Path::NewtypeStruct { parent }
+            | serde_ignored::Path::NewtypeVariant { parent } => parent,
+        };
+    }
+    parts.reverse();
+    parts
+}
+
+/// The keys and indexes leading to the setting a deserializing error is about
+fn error_path(path: &serde_path_to_error::Path) -> Vec<String> {
+    path.iter()
+        .filter_map(|segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+            serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+            serde_path_to_error::Segment::Enum { .. } | serde_path_to_error::Segment::Unknown => {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Every setting in a table by the keys and indexes leading to it, including the tables
+/// themselves
+fn paths(value: &Value, prefix: &mut Vec<String>, found: &mut Vec<Vec<String>>) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key.clone(), value))
+            .collect(),
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (index.to_string(), value))
+            .collect(),
+        _ => return,
+    };
+    for (key, value) in children {
+        prefix.push(key);
+        found.push(prefix.clone());
+        paths(value, prefix, found);
+        prefix.pop();
+    }
+}
+
+/// Removes a setting from a table, or the element of a list holding it
+///
+/// # Returns
+///
+/// Whether the setting was found
+fn remove(table: &mut Table, path: &[String]) -> bool {
+    let Some((key, rest)) = path.split_first() else {
+        return false;
+    };
+    if rest.is_empty() {
+        return table.remove(key).is_some();
+    }
+    match table.get_mut(key) {
+        Some(Value::Table(table)) => remove(table, rest),
+        Some(Value::Array(values)) => match rest[0].parse::<usize>() {
+            Ok(index) if index < values.len() => {
+                values.remove(index);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Finds where a setting is written in a config file
+///
+/// # Returns
+///
+/// The span of the key of the setting, or of the closest table or list holding it that could be
+/// found
+fn locate(document: &ImDocument<&str>, path: &[String]) -> Option<Range<usize>> {
+    enum Node<'a> {
+        Table(&'a toml_edit::Table),
+        Item(&'a toml_edit::Item),
+        Value(&'a toml_edit::Value),
+    }
+    let mut node = Node::Table(document.as_table());
+    let mut span = None;
+    for part in path {
+        let index = part.parse::<usize>().ok();
+        let (found, next) = match node {
+            Node::Table(table) | Node::Item(toml_edit::Item::Table(table)) => {
+                match table.get_key_value(part) {
+                    Some((key, item)) => (key.span(), Node::Item(item)),
+                    None => break,
+                }
+            }
+            Node::Item(toml_edit::Item::ArrayOfTables(tables)) => {
+                match index.and_then(|index| tables.get(index)) {
+                    Some(table) => (table.span(), Node::Table(table)),
+                    None => break,
+                }
+            }
+            Node::Item(toml_edit::Item::Value(value)) | Node::Value(value) => match value {
+                toml_edit::Value::InlineTable(table) => match table.get_key_value(part) {
+                    Some((key, _)) => (key.span(), Node::Value(table.get(part).unwrap())),
+                    None => break,
+                },
+                toml_edit::Value::Array(values) => {
+                    match index.and_then(|index| values.get(index)) {
+                        Some(value) => (value.span(), Node::Value(value)),
+                        None => break,
+                    }
+                }
+                _ => break,
+            },
+            Node::Item(toml_edit::Item::None) => break,
+        };
+        span = found.or(span);
+        node = next;
+    }
+    span
+}
+
+/// Suggests a known setting next to an unknown one whose name is close to it
+fn suggest(defaults: &Value, path: &[String]) -> Option<String> {
+    let (name, parents) = path.split_last()?;
+    let mut table = defaults.as_table()?;
+    for parent in parents {
+        table = table.get(parent)?.as_table()?;
+    }
+    let mut known: Vec<(f64, &String)> = table
+        .keys()
+        .map(|key| (strsim::jaro_winkler(name, key), key))
+        .filter(|(similarity, _)| *similarity > 0.8)
+        .collect();
+    known.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let (_, key) = known.first()?;
+    Some(
+        parents
+            .iter()
+            .chain([*key])
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("."),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let contents = r#"strict = true
+player = 5
+
+[storage]
+mmapp = true
+
+[[webhooks]]
+url = "http://localhost:9000/hook"
+thresholds = [100]
+"#;
+        let (table, diagnostics) = check(contents).unwrap();
+        let found: Vec<(Option<usize>, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    Some(2),
+                    "Ignoring player. invalid type: integer `5`, expected a string"
+                ),
+                (Some(5), "Unknown setting storage.mmapp. Did you mean storage.mmap?"),
+                (
+                    Some(9),
+                    "webhooks.0.thresholds is deprecated. Use `[milestones] views` with `webhooks = true` instead, which sends milestone_reached events"
+                ),
+            ]
+        );
+        assert_eq!(table.get("strict"), Some(&Value::Boolean(true)));
+        assert!(!table.contains_key("player"));
+        assert!(check("strict = ").is_err());
+    }
+}
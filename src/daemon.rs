@@ -0,0 +1,468 @@
+use crate::formatting::{self, Table};
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{audit, remote, s3, scheduler, view_log};
+use clap::{Args, Subcommand};
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Args)]
+pub struct DaemonCommand {
+    #[clap(subcommand)]
+    pub subcommand: Option<DaemonSubcommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DaemonSubcommand {
+    /// Show how often the running daemon answered from its cache, and how long loading the stores took
+    Stats,
+    /// Show the jobs the daemon runs on a schedule, set in the `[[schedule]]` sections of the config file, with when each last ran and will next run
+    Schedule,
+}
+
+/// A request sent to the daemon. Each connection carries a single request as a line of JSON
+///
+/// # Variants
+///
+/// * `Load` - Asks for every record of a store
+/// * `Save` - Replaces every record of a store
+/// * `SaveChecked` - Replaces every record of a store like `Save`, unless a record was added,
+///   removed, or changed since the store was read with the given revisions by ID. Answered with
+///   the ID of such a record, or null once saved
+/// * `Stats` - Asks for the statistics of every store loaded so far
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Load(Store),
+    Save(Store, Value),
+    SaveChecked(Store, Value, BTreeMap<u32, u32>),
+    Stats,
+}
+
+/// The daemon's answer to a request: the records asked for, or an error message
+type Reply = Result<Value, String>;
+
+/// Whether this process is the daemon
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+/// Held while the daemon answers a request or runs a scheduled job, so only one changes the stores
+/// at a time
+static BUSY: Mutex<()> = Mutex::new(());
+
+/// The path of the socket the daemon listens on
+pub fn socket_path() -> PathBuf {
+    storage::data_dir().join("daemon.sock")
+}
+
+/// Whether this process is the daemon, which saves straight to the data directory
+pub fn serving() -> bool {
+    SERVING.load(Ordering::Relaxed)
+}
+
+/// Connects to the daemon if one is running
+pub fn connect() -> Option<UnixStream> {
+    let path = socket_path();
+    // The daemon would wait on itself when saving a store while it answers a request
+    if serving() || !path.exists() {
+        return None;
+    }
+
+    match UnixStream::connect(&path) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            debug!("Not using the daemon at {}. {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Sends a request to the daemon and waits for its reply
+///
+/// Exits the program if the daemon stops answering, as no command can go on without its records.
+fn send(stream: UnixStream, request: &Request) -> Value {
+    let mut writer = &stream;
+    let mut line = serde_json::to_string(request).unwrap();
+    line.push('\n');
+    let mut reply = "".to_string();
+    let result = writer
+        .write_all(line.as_bytes())
+        .and_then(|_| BufReader::new(&stream).read_line(&mut reply));
+    if let Err(e) = result {
+        error!("{}", t!("daemon.lost_connection", error = e));
+        process::exit(1);
+    }
+
+    match serde_json::from_str::<Reply>(&reply) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            error!("{}", t!("daemon.refused", error = message));
+            process::exit(1);
+        }
+        Err(e) => {
+            error!("{}", t!("daemon.invalid_reply", error = e));
+            process::exit(1);
+        }
+    }
+}
+
+/// Fetches every record of the given store from the daemon
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the daemon
+/// * `store` - The store to fetch
+pub fn load<T: DeserializeOwned>(stream: UnixStream, store: Store) -> Vec<T> {
+    let start = Instant::now();
+    let records: Vec<T> = match serde_json::from_value(send(stream, &Request::Load(store))) {
+        Ok(records) => records,
+        Err(e) => {
+            error!("{}", t!("daemon.invalid_records", error = e));
+            process::exit(1);
+        }
+    };
+    debug!(
+        "Loaded {} records from the daemon in {:?}",
+        records.len(),
+        start.elapsed()
+    );
+    records
+}
+
+/// Overwrites the given store through the daemon
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the daemon
+/// * `store` - The store to write
+/// * `records` - The records to write to the store
+pub fn save<T: Serialize>(stream: UnixStream, store: Store, records: &Vec<T>) {
+    let start = Instant::now();
+    let records_value = serde_json::to_value(records).unwrap();
+    send(stream, &Request::Save(store, records_value));
+    debug!(
+        "Saved {} records through the daemon in {:?}",
+        records.len(),
+        start.elapsed()
+    );
+}
+
+/// Overwrites the given store through the daemon, unless someone else changed it since it was read.
+/// The daemon answers one request at a time, so nobody can change the store between the check and
+/// the write
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the daemon
+/// * `store` - The store to write
+/// * `records` - The records to write to the store
+/// * `read` - The revision of every record of the store by ID, as it was read
+///
+/// # Returns
+///
+/// The ID of a record added, removed, or changed since, leaving the store as it was
+pub fn save_checked<T: Serialize>(
+    stream: UnixStream,
+    store: Store,
+    records: &Vec<T>,
+    read: &BTreeMap<u32, u32>,
+) -> Result<(), u32> {
+    let start = Instant::now();
+    let records_value = serde_json::to_value(records).unwrap();
+    let reply = send(
+        stream,
+        &Request::SaveChecked(store, records_value, read.clone()),
+    );
+    if let Some(id) = reply.as_u64() {
+        debug!("Not saving {:?}, as record {} changed", store, id);
+        return Err(id as u32);
+    }
+    debug!(
+        "Saved {} records through the daemon in {:?}",
+        records.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/// The revision of every record of a store by ID, or 0 for records without one
+fn revisions(records: &[Value]) -> BTreeMap<u32, u32> {
+    records
+        .iter()
+        .filter_map(|record| {
+            let id = record.get("id")?.as_u64()? as u32;
+            let revision = record.get("revision").and_then(Value::as_u64).unwrap_or(0);
+            Some((id, revision as u32))
+        })
+        .collect()
+}
+
+/// When the files a store is read from were last changed, and their sizes, or `None` for those
+/// that don't exist
+type FileVersion = Vec<Option<(SystemTime, u64)>>;
+
+/// Gets the version of the files a store is read from: its store file, and the view log for videos
+fn file_version(store: Store) -> FileVersion {
+    let mut paths = vec![storage::store_path(store)];
+    if store == Store::Videos {
+        paths.push(view_log::path());
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+/// A store kept in memory by the daemon
+///
+/// # Fields
+///
+/// * `records` - The records of the store
+/// * `version` - The version of its files when the records were loaded or saved
+struct Cached {
+    records: Vec<Value>,
+    version: FileVersion,
+}
+
+/// How the daemon served a store since it started
+///
+/// # Fields
+///
+/// * `records` - How many records the store has
+/// * `hits` - How many loads were answered from memory
+/// * `loads` - How many times the store was read from its file
+/// * `reloads` - How many of those were because the file was changed by something else
+/// * `load_time` - How long reading the store from its file took in total
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreStats {
+    records: usize,
+    hits: u64,
+    loads: u64,
+    reloads: u64,
+    load_time: Duration,
+}
+
+/// The stores the daemon keeps in memory, each loaded the first time it is asked for
+///
+/// Records are kept as JSON values, so the daemon doesn't need to know the shape of every store.
+/// A store is loaded again when its file changes, so writes made without the daemon, such as by a
+/// command that started before it, are picked up.
+#[derive(Default)]
+struct Stores {
+    records: HashMap<Store, Cached>,
+    stats: HashMap<Store, StoreStats>,
+}
+
+impl Stores {
+    /// Gets the records of a store, loading it from its file unless the records in memory are
+    /// still current
+    fn load(&mut self, store: Store) -> &Vec<Value> {
+        let stats = self.stats.entry(store).or_default();
+        let version = file_version(store);
+        match self.records.get(&store) {
+            Some(cached) if cached.version == version => stats.hits += 1,
+            cached => {
+                if cached.is_some() {
+                    debug!("{} changed on disk. Loading it again", store.file_name());
+                    stats.reloads += 1;
+                }
+                let start = Instant::now();
+                let records = storage::load_file(store);
+                stats.loads += 1;
+                stats.load_time += start.elapsed();
+                self.records.insert(
+                    store,
+                    Cached {
+                        records,
+                        // Loading may have migrated the file or compacted the view log
+                        version: file_version(store),
+                    },
+                );
+            }
+        }
+        let records = &self.records[&store].records;
+        stats.records = records.len();
+        records
+    }
+
+    /// Answers a request, writing any changes through to the data directory
+    fn handle(&mut self, request: Request) -> Reply {
+        match request {
+            Request::Load(Store::ApiKeys)
+            | Request::Save(Store::ApiKeys, _)
+            | Request::SaveChecked(Store::ApiKeys, _, _) => {
+                Err("API keys are not kept by the daemon".to_string())
+            }
+            Request::Load(store) => Ok(Value::Array(self.load(store).clone())),
+            Request::SaveChecked(store, records, read) => {
+                let current = revisions(self.load(store));
+                let changed = read
+                    .keys()
+                    .chain(current.keys())
+                    .find(|id| read.get(id) != current.get(id));
+                match changed {
+                    Some(id) => Ok(Value::from(*id)),
+                    None => self.handle(Request::Save(store, records)),
+                }
+            }
+            Request::Save(store, Value::Array(records)) => {
+                audit::start_run();
+                storage::save_file(store, &records);
+                self.stats.entry(store).or_default().records = records.len();
+                self.records.insert(
+                    store,
+                    Cached {
+                        records,
+                        version: file_version(store),
+                    },
+                );
+                Ok(Value::Null)
+            }
+            Request::Save(_, _) => Err("Expected a list of records".to_string()),
+            Request::Stats => Ok(serde_json::to_value(&self.stats).unwrap()),
+        }
+    }
+}
+
+/// Keeps the stores loaded in memory and serves them over a Unix socket
+///
+/// Other rustflix commands find the socket on their own and go through the daemon instead of
+/// reading the store files. Connections are handled one at a time.
+pub fn handle_daemon() {
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("daemon.remote", remote = remote));
+        return;
+    }
+    if s3::config().is_some() {
+        error!("{}", t!("daemon.s3"));
+        return;
+    }
+
+    let path = socket_path();
+    if connect().is_some() {
+        error!("{}", t!("daemon.already_running", path = path.display()));
+        return;
+    }
+    if path.exists() {
+        debug!("Removing stale socket {}", path.display());
+        fs::remove_file(&path).unwrap();
+    }
+
+    fs::create_dir_all(storage::data_dir()).unwrap();
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                "{}",
+                t!("daemon.could_not_listen", path = path.display(), error = e)
+            );
+            return;
+        }
+    };
+
+    SERVING.store(true, Ordering::Relaxed);
+    let mut stores = Stores::default();
+    info!("{}", t!("daemon.listening", path = path.display()));
+    scheduler::start(&BUSY);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("{}", t!("daemon.could_not_accept", error = e));
+                continue;
+            }
+        };
+
+        let start = Instant::now();
+        let mut line = "".to_string();
+        match BufReader::new(&stream).read_line(&mut line) {
+            // Connections closed without a request only check that the daemon is running
+            Ok(0) => continue,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("{}", t!("daemon.could_not_read", error = e));
+                continue;
+            }
+        }
+
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                debug!("Handling {}", request_name(&request));
+                let _busy = BUSY.lock().unwrap_or_else(|e| e.into_inner());
+                stores.handle(request)
+            }
+            Err(e) => Err(format!("Invalid request. {}", e)),
+        };
+
+        let mut reply = serde_json::to_string(&reply).unwrap();
+        reply.push('\n');
+        if let Err(e) = (&stream).write_all(reply.as_bytes()) {
+            warn!("{}", t!("daemon.could_not_reply", error = e));
+        }
+        debug!("Answered in {:?}", start.elapsed());
+    }
+}
+
+/// Describes a request without its records, for logging
+fn request_name(request: &Request) -> String {
+    match request {
+        Request::Load(store) => format!("load {:?}", store),
+        Request::Save(store, _) => format!("save {:?}", store),
+        Request::SaveChecked(store, _, _) => format!("checked save {:?}", store),
+        Request::Stats => "stats".to_string(),
+    }
+}
+
+/// Handles showing the statistics of the running daemon: for every store loaded so far, how many
+/// loads it answered from memory and how long reading the store file took
+pub fn handle_stats() {
+    let Some(stream) = connect() else {
+        error!("{}", t!("daemon.not_running"));
+        return;
+    };
+    let stats: HashMap<Store, StoreStats> =
+        match serde_json::from_value(send(stream, &Request::Stats)) {
+            Ok(stats) => stats,
+            Err(e) => {
+                error!("{}", t!("daemon.invalid_stats", error = e));
+                return;
+            }
+        };
+    if stats.is_empty() {
+        info!("{}", t!("daemon.nothing_loaded"));
+        return;
+    }
+
+    let mut table = Table::new(
+        ["store", "records", "hits", "loads", "reloads", "load time"]
+            .map(str::to_string)
+            .to_vec(),
+    );
+    for store in Store::ALL {
+        let Some(stats) = stats.get(&store) else {
+            continue;
+        };
+        table.row(vec![
+            store.file_name().to_string(),
+            formatting::count(stats.records as u64),
+            formatting::count(stats.hits),
+            formatting::count(stats.loads),
+            formatting::count(stats.reloads),
+            format!("{:?}", stats.load_time),
+        ]);
+    }
+    table.print();
+}
@@ -0,0 +1,173 @@
+use crate::args::RustflixArgs;
+use crate::t;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, CommandFactory};
+use log::{debug, warn};
+use toml::{Table, Value};
+
+/// Adds the defaults in the `[defaults]` section of the config file for the arguments not given
+/// on the command line, after aliases are expanded
+///
+/// Defaults are keyed by the command they apply to and the name of the argument, e.g.
+/// `[defaults.video.list] limit = 10`, or `[defaults] color = "never"` for arguments every command
+/// takes. A default that is invalid, names no argument, or conflicts with an argument given is
+/// ignored with a warning or left out.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, starting with the program name
+/// * `defaults` - The `[defaults]` section of the config file
+///
+/// # Returns
+///
+/// The command line with an argument added for every default that applies
+pub fn apply(args: Vec<String>, defaults: &Table) -> Vec<String> {
+    if defaults.is_empty() {
+        return args;
+    }
+    let root = RustflixArgs::command();
+    let Ok(matches) = root.clone().try_get_matches_from(&args) else {
+        // Parsing reports what is wrong with the command line itself
+        return args;
+    };
+
+    let mut args = args;
+    let mut scope = (&root, &matches, Some(defaults), String::new());
+    loop {
+        let (command, matches, defaults, path) = scope;
+        for (key, value) in defaults.into_iter().flatten() {
+            if value.is_table() {
+                continue;
+            }
+            let name = format!("{}{}", path, key);
+            let id = key.replace('-', "_");
+            let Some(arg) = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id.as_str())
+            else {
+                warn!("{}", t!("config.default_not_argument", name = name));
+                continue;
+            };
+            if given(matches, arg.get_id().as_str())
+                || command
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|other| given(matches, other.get_id().as_str()))
+            {
+                continue;
+            }
+            let Some(added) = arguments(arg, value) else {
+                warn!("{}", t!("config.default_not_allowed", name = name));
+                continue;
+            };
+
+            let mut candidate = args.clone();
+            candidate.extend(added);
+            match root.clone().try_get_matches_from(&candidate) {
+                Ok(_) => {
+                    debug!("Using the default {} = {}", name, value);
+                    args = candidate;
+                }
+                Err(e) => {
+                    let e = e.to_string();
+                    let reason = e.lines().next().unwrap_or_default();
+                    warn!(
+                        "{}",
+                        t!(
+                            "config.default_ignored",
+                            name = name,
+                            reason = reason.trim_start_matches("error: ")
+                        )
+                    );
+                }
+            }
+        }
+
+        let Some((subcommand, sub_matches)) = matches.subcommand() else {
+            break;
+        };
+        let Some(sub_command) = command.find_subcommand(subcommand) else {
+            break;
+        };
+        let sub_defaults = defaults
+            .and_then(|defaults| defaults.get(sub_command.get_name()))
+            .and_then(Value::as_table);
+        scope = (
+            sub_command,
+            sub_matches,
+            sub_defaults,
+            format!("{}{}.", path, sub_command.get_name()),
+        );
+    }
+    args
+}
+
+/// Whether an argument was given on the command line
+fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id), Some(ValueSource::CommandLine))
+}
+
+/// Writes a default as the arguments that give it on the command line
+///
+/// # Returns
+///
+/// The arguments, or `None` if the argument isn't a flag or option taking such a value
+fn arguments(arg: &Arg, value: &Value) -> Option<Vec<String>> {
+    let long = format!("--{}", arg.get_long()?);
+    let text = |value: &Value| match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    };
+    match (arg.get_action(), value) {
+        (ArgAction::SetTrue, Value::Boolean(set)) | (ArgAction::SetFalse, Value::Boolean(set)) => {
+            Some(if *set { vec![long] } else { vec![] })
+        }
+        (ArgAction::Count, Value::Integer(count)) => {
+            Some(vec![long; usize::try_from(*count).ok()?])
+        }
+        (ArgAction::Set | ArgAction::Append, Value::Array(values)) => Some(
+            values
+                .iter()
+                .map(|value| format!("{}={}", long, text(value)))
+                .collect(),
+        ),
+        (ArgAction::Set | ArgAction::Append, value) => {
+            Some(vec![format!("{}={}", long, text(value))])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_line(line: &str, defaults: &str) -> String {
+        let args = line.split_whitespace().map(String::from).collect();
+        apply(args, &toml::from_str(defaults).unwrap()).join(" ")
+    }
+
+    #[test]
+    fn test_defaults() {
+        let defaults = r#"
+            color = "never"
+            [video.list]
+            limit = 3
+            include_private = true
+            offset = "many"
+            sort_by = "views"
+        "#;
+        assert_eq!(
+            apply_line("rustflix video list --all", defaults),
+            "rustflix video list --all --color=never --include-private --limit=3"
+        );
+        assert_eq!(
+            apply_line("rustflix video list --all --limit 9 --color auto", defaults),
+            "rustflix video list --all --limit 9 --color auto --include-private"
+        );
+        assert_eq!(
+            apply_line("rustflix video list --all --count", defaults),
+            "rustflix video list --all --count --color=never --include-private"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::daemon;
+use crate::events::{self, Change, EventSubscriber};
+use crate::notifications::{Notification, NotificationKind};
+use crate::session;
+use crate::storage::Store;
+use crate::t;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Which notifications pop up on the desktop while the daemon or the browser is running, from the
+/// `[desktop_notifications]` section of the config file. Needs rustflix to be built with the
+/// `desktop-notifications` feature
+///
+/// # Fields
+///
+/// * `enabled` - Whether notifications pop up at all
+/// * `uploads` - Whether channels the user subscribes to uploading a video, digests of their
+///   uploads, and videos added to playlists the user follows pop up
+/// * `milestones` - Whether videos the user owns reaching a milestone pops up
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DesktopConfig {
+    pub enabled: bool,
+    pub uploads: bool,
+    pub milestones: bool,
+}
+
+impl Default for DesktopConfig {
+    fn default() -> Self {
+        DesktopConfig {
+            enabled: false,
+            uploads: true,
+            milestones: true,
+        }
+    }
+}
+
+/// Which notifications pop up on the desktop
+static CONFIG: OnceLock<DesktopConfig> = OnceLock::new();
+
+/// Makes the given notifications pop up on the desktop, warning if they can't
+pub fn set_config(config: DesktopConfig) {
+    if config.enabled && !cfg!(feature = "desktop-notifications") {
+        warn!("{}", t!("config.desktop_unsupported"));
+    }
+    CONFIG.set(config).expect("Desktop notifications set twice");
+}
+
+/// Whether any notification may pop up
+pub fn enabled() -> bool {
+    CONFIG.get().is_some_and(|config| config.enabled)
+}
+
+/// Whether a notification pops up, following the config file. Only those of the logged in user
+/// do, or those of everyone if no one is logged in
+fn wanted(config: &DesktopConfig, notification: &Notification, user: Option<u32>) -> bool {
+    let kind = match notification.kind {
+        NotificationKind::Milestone => config.milestones,
+        NotificationKind::Upload | NotificationKind::Digest | NotificationKind::Playlist => {
+            config.uploads
+        }
+    };
+    config.enabled
+        && kind
+        && !notification.read
+        && user.is_none_or(|user| notification.user == user)
+}
+
+/// Pops up the notifications saved through the daemon. The browser watches for them itself, as
+/// they are saved by other processes
+pub struct Desktop;
+
+impl EventSubscriber for Desktop {
+    fn handle(&self, change: &Change) {
+        if change.store != Store::Notifications || !enabled() || !daemon::serving() {
+            return;
+        }
+        show_new(&events::decode(change.old), &events::decode(change.new));
+    }
+}
+
+/// Pops up the notifications sent since the store last held the old ones
+///
+/// # Arguments
+///
+/// * `old` - The notifications seen already
+/// * `new` - Every notification now
+pub fn show_new(old: &[Notification], new: &[Notification]) {
+    let Some(config) = CONFIG.get().filter(|config| config.enabled) else {
+        return;
+    };
+    let user = session::current_user_id();
+    for notification in new
+        .iter()
+        .filter(|notification| !old.contains(notification))
+        .filter(|notification| wanted(config, notification, user))
+    {
+        show(&notification.message);
+    }
+}
+
+/// Pops up a message on the desktop
+#[cfg(feature = "desktop-notifications")]
+fn show(message: &str) {
+    if let Err(e) = notify_rust::Notification::new()
+        .summary("rustflix")
+        .body(message)
+        .show()
+    {
+        warn!("{}", t!("notification.desktop_failed", error = e));
+    }
+}
+
+/// Pops up a message on the desktop, which needs the `desktop-notifications` feature
+#[cfg(not(feature = "desktop-notifications"))]
+fn show(message: &str) {
+    log::debug!("Not showing the desktop notification {:?}", message);
+}
@@ -0,0 +1,191 @@
+use crate::t;
+use rand::Rng;
+use std::fs;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+/// The DNS record type of mail exchangers
+const TYPE_MX: u16 = 15;
+
+/// The DNS class of internet records
+const CLASS_IN: u16 = 1;
+
+/// The length of the header every DNS message starts with
+const HEADER_LENGTH: usize = 12;
+
+/// How long to wait for the name server to answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Finds the first name server listed in `/etc/resolv.conf`
+fn name_server() -> Result<String, String> {
+    let resolv_conf =
+        fs::read_to_string("/etc/resolv.conf").map_err(|e| t!("dns.no_resolv_conf", error = e))?;
+    resolv_conf
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .map(str::trim)
+        .find(|server| !server.is_empty())
+        .map(|server| match server.contains(':') {
+            true => format!("[{}]:53", server),
+            false => format!("{}:53", server),
+        })
+        .ok_or(t!("dns.no_server"))
+}
+
+/// Determines if a domain has a mail exchanger, by asking the system's name server for its MX
+/// records
+///
+/// # Arguments
+///
+/// * `domain` - The domain to look up, e.g. the part of an email address after the `@`
+///
+/// # Returns
+///
+/// Whether the domain has at least one MX record, or an error message if the name server could not
+/// be asked
+pub fn has_mx(domain: &str) -> Result<bool, String> {
+    let id = rand::thread_rng().gen();
+    let query = build_query(id, domain)?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    socket
+        .send_to(&query, name_server()?)
+        .map_err(|e| t!("dns.unreachable", error = e))?;
+
+    let mut response = [0; 4096];
+    let length = socket
+        .recv(&mut response)
+        .map_err(|e| t!("dns.no_answer", error = e))?;
+    parse_response(id, &response[..length])
+}
+
+/// Builds a query asking for the MX records of a domain
+///
+/// # Arguments
+///
+/// * `id` - The ID the answer will carry
+/// * `domain` - The domain to ask about
+fn build_query(id: u16, domain: &str) -> Result<Vec<u8>, String> {
+    let mut query = vec![];
+    query.extend(id.to_be_bytes());
+    // Recursion desired, with one question and no other records
+    query.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in domain.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(t!("dns.invalid_domain", domain = domain));
+        }
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(TYPE_MX.to_be_bytes());
+    query.extend(CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Skips over a possibly compressed domain name in a DNS message
+///
+/// # Returns
+///
+/// The position right after the name, or `None` if the message ends first
+fn skip_name(message: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(position)?;
+        if length & 0xC0 == 0xC0 {
+            return Some(position + 2);
+        }
+        position += 1;
+        if length == 0 {
+            return Some(position);
+        }
+        position += length as usize;
+    }
+}
+
+/// Reads the answer to a query built by `build_query`
+///
+/// # Arguments
+///
+/// * `id` - The ID the query was sent with
+/// * `message` - The answer of the name server
+fn parse_response(id: u16, message: &[u8]) -> Result<bool, String> {
+    let malformed = || t!("dns.malformed");
+    let read_u16 = |position: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([
+            *message.get(position)?,
+            *message.get(position + 1)?,
+        ]))
+    };
+
+    if message.len() < HEADER_LENGTH {
+        return Err(malformed());
+    }
+    if read_u16(0).ok_or_else(malformed)? != id {
+        return Err(t!("dns.other_query"));
+    }
+    match message[3] & 0x0F {
+        0 => {}
+        // The domain doesn't exist
+        3 => return Ok(false),
+        code => return Err(t!("dns.failed", code = code)),
+    }
+
+    let questions = read_u16(4).ok_or_else(malformed)?;
+    let answers = read_u16(6).ok_or_else(malformed)?;
+
+    let mut position = HEADER_LENGTH;
+    for _ in 0..questions {
+        position = skip_name(message, position).ok_or_else(malformed)? + 4;
+    }
+    for _ in 0..answers {
+        position = skip_name(message, position).ok_or_else(malformed)?;
+        let record_type = read_u16(position).ok_or_else(malformed)?;
+        if record_type == TYPE_MX {
+            return Ok(true);
+        }
+        let data_length = read_u16(position + 8).ok_or_else(malformed)?;
+        position += 10 + data_length as usize;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let query = build_query(0xBEEF, "example.com").unwrap();
+        assert_eq!(&query[12..], b"\x07example\x03com\x00\x00\x0F\x00\x01");
+
+        let mut answer = query.clone();
+        // A response with one answer
+        answer[2] = 0x81;
+        answer[3] = 0x80;
+        answer[7] = 1;
+        // A compressed name pointing at the question, then an MX record
+        answer.extend([
+            0xC0, 12, 0, 15, 0, 1, 0, 0, 0x0E, 0x10, 0, 4, 0, 10, 0xC0, 12,
+        ]);
+        assert_eq!(parse_response(0xBEEF, &answer), Ok(true));
+        assert!(parse_response(0xCAFE, &answer).is_err());
+
+        let mut no_answer = query.clone();
+        no_answer[2] = 0x81;
+        no_answer[3] = 0x80;
+        assert_eq!(parse_response(0xBEEF, &no_answer), Ok(false));
+
+        let mut missing = no_answer.clone();
+        missing[3] = 0x83;
+        assert_eq!(parse_response(0xBEEF, &missing), Ok(false));
+
+        // Answers cut off within the header
+        assert!(parse_response(0xBEEF, &[0xBE, 0xEF]).is_err());
+        assert!(parse_response(0xBEEF, &no_answer[..11]).is_err());
+
+        assert!(build_query(1, "bad..domain").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::audit::{self, Action, Entity};
+use crate::output::{self, Style};
+use crate::storage;
+use crate::t;
+use log::info;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether a write outside of the stores was shown instead of made during the dry run
+static SKIPPED: AtomicBool = AtomicBool::new(false);
+
+/// Starts running the command against a copy of the stores kept in memory, as with `--dry-run`
+pub fn begin() {
+    storage::begin_dry_run();
+}
+
+/// Ends the dry run, printing how every record would have changed as a diff, without writing
+/// anything
+pub fn finish() {
+    let changed = storage::end_dry_run();
+    if changed.is_empty() && !SKIPPED.load(Ordering::Relaxed) {
+        info!("{}", t!("storage.dry_run_unchanged"));
+        return;
+    }
+    for (store, old, new) in changed {
+        let name = match Entity::of(store) {
+            Some(entity) => entity.name(),
+            None => store.file_name().split('.').next().unwrap_or_default(),
+        };
+        println!(
+            "{}",
+            output::paint_stdout(&format!("--- {}", store.file_name()), Style::Header)
+        );
+        for (action, fields, old, new) in audit::diff(&old, &new) {
+            let (old, new) = (old.map(audit::hide_secrets), new.map(audit::hide_secrets));
+            let record = old.as_ref().or(new.as_ref()).unwrap();
+            let label = match record.get("id") {
+                Some(id) => format!("{} {}", name, id),
+                None => name.to_string(),
+            };
+            match action {
+                Action::Created => {
+                    line('+', &format!("created {}", label));
+                    for (field, value) in fields_of(&new) {
+                        line('+', &format!("    {}: {}", field, value));
+                    }
+                }
+                Action::Deleted => {
+                    line('-', &format!("deleted {}", label));
+                    for (field, value) in fields_of(&old) {
+                        line('-', &format!("    {}: {}", field, value));
+                    }
+                }
+                Action::Updated => {
+                    println!("  updated {}", label);
+                    for field in fields {
+                        let value = |record: &Option<Value>| {
+                            record
+                                .as_ref()
+                                .and_then(|record| record.get(&field))
+                                .map_or("nothing".to_string(), Value::to_string)
+                        };
+                        line('-', &format!("    {}: {}", field, value(&old)));
+                        line('+', &format!("    {}: {}", field, value(&new)));
+                    }
+                }
+            }
+        }
+    }
+    info!("{}", t!("storage.dry_run_done"));
+}
+
+/// The fields of a record as they are printed in a diff, or the record itself if it has no fields
+fn fields_of(record: &Option<Value>) -> Vec<(String, String)> {
+    let Some(record) = record.clone() else {
+        return vec![];
+    };
+    match record {
+        Value::Object(fields) => fields
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(field, value)| (field, value.to_string()))
+            .collect(),
+        value => vec![("value".to_string(), value.to_string())],
+    }
+}
+
+/// Prints a line of a diff, colored by whether it is added or removed
+fn line(sign: char, text: &str) {
+    let style = match sign {
+        '+' => Style::Success,
+        _ => Style::Error,
+    };
+    println!(
+        "{}",
+        output::paint_stdout(&format!("{} {}", sign, text), style)
+    );
+}
+
+/// During a dry run, prints what a write outside of the stores would have done, such as starting
+/// a session, instead of letting it happen
+///
+/// # Arguments
+///
+/// * `action` - What the write does, e.g. `start a session as user 5`
+///
+/// # Returns
+///
+/// Whether the write should be skipped
+pub fn skip(action: &str) -> bool {
+    if !storage::dry_run() {
+        return false;
+    }
+    println!("Would {}", action);
+    SKIPPED.store(true, Ordering::Relaxed);
+    true
+}
+
+/// During a dry run, prints how a file outside of the stores, such as the config file, would
+/// change, instead of letting it be written
+///
+/// # Arguments
+///
+/// * `path` - The file
+/// * `old` - The contents of the file now
+/// * `new` - The contents the file would be written with
+///
+/// # Returns
+///
+/// Whether the write should be skipped
+pub fn skip_file_write(path: &Path, old: &str, new: &str) -> bool {
+    if !storage::dry_run() {
+        return false;
+    }
+    SKIPPED.store(true, Ordering::Relaxed);
+    println!(
+        "{}",
+        output::paint_stdout(&format!("--- {}", path.display()), Style::Header)
+    );
+    for (sign, text) in line_diff(old, new) {
+        match sign {
+            ' ' => println!("  {}", text),
+            sign => line(sign, text),
+        }
+    }
+    true
+}
+
+/// Compares two texts line by line
+///
+/// # Returns
+///
+/// Every line of either text, in order, marked `-` if only the old text has it, `+` if only the
+/// new one does, or a space if both do
+fn line_diff<'a>(old: &'a str, new: &'a str) -> Vec<(char, &'a str)> {
+    let (old, new): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    // The length of the longest common subsequence of the lines from each position on
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            lines.push(('+', new[j]));
+            j += 1;
+        } else {
+            lines.push(('-', old[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff() {
+        assert_eq!(
+            line_diff(
+                "strict = true\nplayer = \"vlc\"\n",
+                "player = \"mpv\"\nstrict = true\n"
+            ),
+            [
+                ('+', "player = \"mpv\""),
+                (' ', "strict = true"),
+                ('-', "player = \"vlc\""),
+            ]
+        );
+        assert_eq!(line_diff("", "a = 1"), [('+', "a = 1")]);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{encryption, remote, s3};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// How many bits the filter keeps for each email address, which lets about 1% of the addresses no
+/// user has through
+const BITS_PER_EMAIL: usize = 10;
+
+/// How many bits each email address sets in the filter
+const HASHES: u32 = 7;
+
+/// A bloom filter of the email addresses of every user, ignoring case
+///
+/// It never misses an address a user has, but may let through some that no user has, so only
+/// its misses can be trusted.
+///
+/// # Fields
+///
+/// * `bits` - The bits set by the addresses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Makes an empty filter sized for the given number of email addresses
+    pub fn new(emails: usize) -> Self {
+        BloomFilter {
+            bits: vec![0; (emails * BITS_PER_EMAIL).div_ceil(64).max(1)],
+        }
+    }
+
+    /// The bits an email address sets, from two hashes of it combined as many times as needed
+    fn positions(&self, email: &str) -> impl Iterator<Item = usize> {
+        let hash = Sha256::digest(email.to_ascii_lowercase().as_bytes());
+        let first = u64::from_le_bytes(hash[..8].try_into().unwrap());
+        let second = u64::from_le_bytes(hash[8..16].try_into().unwrap());
+        let len = self.bits.len() as u64 * 64;
+        (0..HASHES as u64).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    /// Adds an email address to the filter
+    pub fn insert(&mut self, email: &str) {
+        for position in self.positions(email).collect::<Vec<_>>() {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    /// Whether a user may have the given email address. If not, no user has it
+    pub fn may_contain(&self, email: &str) -> bool {
+        self.positions(email)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// The filter as it is kept next to the user store
+///
+/// # Fields
+///
+/// * `version` - When the user store file the filter was built from was changed, and its size
+/// * `filter` - The filter of the email addresses in that file
+#[derive(Debug, Serialize, Deserialize)]
+struct Persisted {
+    version: (SystemTime, u64),
+    filter: BloomFilter,
+}
+
+/// The path of the file the filter is kept in
+pub fn path() -> PathBuf {
+    storage::data_dir().join("emails.bloom")
+}
+
+/// When the user store file was last changed, and its size, if it exists
+fn users_version() -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(storage::store_path(Store::Users)).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Whether the filter can be used, which is only when users are kept in plain files in the data
+/// directory. Encrypted stores don't have one, as it would tell which addresses they hold, and
+/// neither do batches, whose new users aren't written until the end
+fn available() -> bool {
+    !storage::in_batch()
+        && remote::url().is_none()
+        && s3::config().is_none()
+        && !encryption::enabled()
+}
+
+/// Checks whether a user may have the given email address, without going through the users
+///
+/// # Returns
+///
+/// * `false` if no user has the email address
+/// * `true` if a user may have it, or the filter can't be used or is out of date, in which case
+///   the users have to be searched
+pub fn may_exist(email: &str) -> bool {
+    if !available() {
+        return true;
+    }
+    let Ok(bytes) = fs::read(path()) else {
+        return true;
+    };
+    match rmp_serde::from_slice::<Persisted>(&bytes) {
+        Ok(persisted) if Some(persisted.version) == users_version() => {
+            persisted.filter.may_contain(email)
+        }
+        Ok(_) => {
+            debug!("{} is out of date. Searching the users", path().display());
+            true
+        }
+        Err(e) => {
+            warn!(
+                "{}",
+                t!("storage.invalid_filter", path = path().display(), error = e)
+            );
+            true
+        }
+    }
+}
+
+/// Builds the filter from the users just written to the user store file
+///
+/// # Arguments
+///
+/// * `users` - The users written
+pub fn rebuild(users: &[Value]) {
+    if encryption::enabled() {
+        return;
+    }
+    let Some(version) = users_version() else {
+        return;
+    };
+
+    let mut filter = BloomFilter::new(users.len());
+    for email in users.iter().filter_map(|user| user.get("email")?.as_str()) {
+        filter.insert(email);
+    }
+    let bytes = rmp_serde::to_vec(&Persisted { version, filter }).unwrap();
+    if let Err(e) = fs::write(path(), bytes) {
+        warn!(
+            "{}",
+            t!(
+                "storage.could_not_write",
+                path = path().display(),
+                error = e
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let emails: Vec<String> = (0..1000)
+            .map(|i| format!("user{}@example.com", i))
+            .collect();
+        let mut filter = BloomFilter::new(emails.len());
+        for email in &emails {
+            filter.insert(email);
+        }
+
+        assert!(emails.iter().all(|email| filter.may_contain(email)));
+        assert!(filter.may_contain("USER1@Example.com"));
+        let missed = (0..1000)
+            .filter(|i| !filter.may_contain(&format!("other{}@example.com", i)))
+            .count();
+        assert!(missed > 950);
+        assert!(!BloomFilter::new(0).may_contain("user@example.com"));
+    }
+}
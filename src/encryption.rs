@@ -0,0 +1,157 @@
+use crate::t;
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Starts every encrypted store file, in place of the magic bytes of a plain one
+const MAGIC: &[u8; 4] = b"RFLE";
+
+/// The length of the salt the key of a file is derived with, which follows the magic bytes
+const SALT_LEN: usize = 16;
+
+/// The length of the AES-GCM nonce, which follows the salt, before the encrypted store file
+const NONCE_LEN: usize = 12;
+
+/// Where the key store files are encrypted with comes from, as set in the `[encryption]` section
+/// of the config file. Exactly one must be given
+///
+/// # Fields
+///
+/// * `passphrase` - A passphrase to derive the key from
+/// * `keyfile` - A file whose contents to derive the key from, e.g. made with
+///   `head -c 32 /dev/urandom > ~/.config/rustflix/key`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub passphrase: Option<String>,
+    pub keyfile: Option<PathBuf>,
+}
+
+/// The passphrase or keyfile contents keys are derived from, if any
+static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Whether store files are encrypted when written. Files are decrypted whenever a secret is set
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The keys derived so far, by the salt they were derived with, as deriving one takes a while
+static KEYS: Mutex<Option<HashMap<[u8; SALT_LEN], [u8; 32]>>> = Mutex::new(None);
+
+/// Makes store files be encrypted with a key derived from the given passphrase or keyfile, and
+/// lets encrypted ones be read
+///
+/// # Returns
+///
+/// An error message if the config gives neither or both, or the keyfile can't be read
+pub fn set_config(config: EncryptionConfig) -> Result<(), String> {
+    let secret = match (config.passphrase, config.keyfile) {
+        (Some(passphrase), None) => passphrase.into_bytes(),
+        (None, Some(keyfile)) => fs::read(&keyfile)
+            .map_err(|e| t!("encrypt.no_keyfile", path = keyfile.display(), error = e))?,
+        _ => return Err(t!("encrypt.passphrase_or_keyfile")),
+    };
+    if secret.is_empty() {
+        return Err(t!("encrypt.empty_secret"));
+    }
+    SECRET.set(secret).expect("Encryption set twice");
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Whether store files are encrypted when written
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Stops encrypting store files for the rest of the run, while still decrypting them
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Whether the contents of a store file are encrypted
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Derives the key for the given salt from the secret, or reuses it if it was derived before
+fn key(salt: [u8; SALT_LEN]) -> Result<[u8; 32], String> {
+    let secret = SECRET.get().ok_or(t!("encrypt.no_secret"))?;
+    let mut keys = KEYS.lock().unwrap();
+    let keys = keys.get_or_insert_with(HashMap::new);
+    if let Some(key) = keys.get(&salt) {
+        return Ok(*key);
+    }
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(secret, &salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    keys.insert(salt, key);
+    Ok(key)
+}
+
+/// Encrypts the contents of a store file with AES-256-GCM
+///
+/// The salt of a key derived earlier in the run is reused, so a run only derives one key.
+///
+/// # Arguments
+///
+/// * `bytes` - The plain contents of the store file
+///
+/// # Returns
+///
+/// The magic bytes, the salt, and the nonce, followed by the encrypted contents
+pub fn encrypt(bytes: &[u8]) -> Vec<u8> {
+    let derived = KEYS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|keys| keys.keys().next().copied());
+    let salt = derived.unwrap_or_else(|| {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    });
+    let key = key(salt).expect("Encryption is only enabled with a secret");
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let encrypted = cipher
+        .encrypt(Nonce::from_slice(&nonce), bytes)
+        .expect("Store files are far smaller than AES-GCM allows");
+
+    let mut output = MAGIC.to_vec();
+    output.extend(salt);
+    output.extend(nonce);
+    output.extend(encrypted);
+    output
+}
+
+/// Decrypts the contents of a store file written by `encrypt`
+///
+/// # Returns
+///
+/// The plain contents of the store file, or an error message if there is no key or it is wrong
+pub fn decrypt(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let rest = bytes
+        .strip_prefix(MAGIC)
+        .ok_or(t!("encrypt.not_encrypted_store"))?;
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        return Err(t!("storage.cut_short"));
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, encrypted) = rest.split_at(NONCE_LEN);
+
+    let key = key(salt.try_into().unwrap())?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), encrypted)
+        .map_err(|_| t!("encrypt.could_not_decrypt"))
+}
@@ -0,0 +1,400 @@
+use crate::args::command_types::user_subcommands::{self, User};
+use crate::args::command_types::video_subcommands::Video;
+use crate::storage::Store;
+use crate::{audit, desktop, milestones, notifications, webhooks};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// A change made to a store
+///
+/// # Variants
+///
+/// * `UserCreated` - A user was added
+/// * `UserUpdated` - A user's name or email changed
+/// * `UserDeleted` - A user was removed
+/// * `VideoCreated` - A video was added
+/// * `VideoUpdated` - A video's details changed
+/// * `VideoDeleted` - A video was removed
+/// * `ViewsAdded` - A video gained views and nothing else about it changed
+/// * `ViewsCrossed` - A video gained views, reaching the given number of views it didn't have
+///   before
+/// * `MilestoneReached` - A video gained views, reaching one of the milestones in the config file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    UserCreated {
+        #[serde(serialize_with = "user_subcommands::serialize_public")]
+        user: User,
+    },
+    UserUpdated {
+        #[serde(serialize_with = "user_subcommands::serialize_public")]
+        user: User,
+    },
+    UserDeleted {
+        #[serde(serialize_with = "user_subcommands::serialize_public")]
+        user: User,
+    },
+    VideoCreated {
+        video: Video,
+    },
+    VideoUpdated {
+        video: Video,
+    },
+    VideoDeleted {
+        video: Video,
+    },
+    ViewsAdded {
+        video: Video,
+        added: u32,
+    },
+    ViewsCrossed {
+        video: Video,
+        threshold: u32,
+    },
+    MilestoneReached {
+        video: Video,
+        views: u32,
+    },
+}
+
+/// The kind of every event, as its `type` is written in JSON
+pub const KINDS: [&str; 9] = [
+    "user_created",
+    "user_updated",
+    "user_deleted",
+    "video_created",
+    "video_updated",
+    "video_deleted",
+    "views_added",
+    "views_crossed",
+    "milestone_reached",
+];
+
+impl Event {
+    /// The kind of the event, as its `type` is written in JSON, e.g. `video_created`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::UserCreated { .. } => KINDS[0],
+            Event::UserUpdated { .. } => KINDS[1],
+            Event::UserDeleted { .. } => KINDS[2],
+            Event::VideoCreated { .. } => KINDS[3],
+            Event::VideoUpdated { .. } => KINDS[4],
+            Event::VideoDeleted { .. } => KINDS[5],
+            Event::ViewsAdded { .. } => KINDS[6],
+            Event::ViewsCrossed { .. } => KINDS[7],
+            Event::MilestoneReached { .. } => KINDS[8],
+        }
+    }
+}
+
+/// Something told about every change made to the stores
+pub trait EventSubscriber: Sync {
+    /// Reacts to a store being overwritten
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change made
+    fn handle(&self, change: &Change);
+}
+
+/// A store being overwritten, as told to every subscriber
+///
+/// # Fields
+///
+/// * `store` - The store overwritten
+/// * `old` - The records before they were overwritten
+/// * `new` - The records written
+/// * `events` - The events the change amounts to, worked out when first asked for
+pub struct Change<'a> {
+    pub store: Store,
+    pub old: &'a [Value],
+    pub new: &'a [Value],
+    events: OnceCell<Vec<Event>>,
+}
+
+impl Change<'_> {
+    /// The events the change amounts to. Only changes to users and videos make any
+    pub fn events(&self) -> &[Event] {
+        self.events.get_or_init(|| {
+            let old = restamp(self.old, self.new);
+            match self.store {
+                Store::Users => user_changes(&decode(&old), &decode(self.new)),
+                Store::Videos => video_changes(&decode(&old), &decode(self.new)),
+                _ => vec![],
+            }
+        })
+    }
+}
+
+/// Everything told about changes to the stores, in the order they are told
+static SUBSCRIBERS: [&dyn EventSubscriber; 6] = [
+    &audit::AuditLog,
+    &Streams,
+    &webhooks::Webhooks,
+    &milestones::Milestones,
+    &notifications::Uploads,
+    &desktop::Desktop,
+];
+
+/// Tells every subscriber about a store being overwritten
+///
+/// # Arguments
+///
+/// * `store` - The store overwritten
+/// * `old` - The records before they were overwritten
+/// * `new` - The records written
+pub fn dispatch(store: Store, old: &[Value], new: &[Value]) {
+    let change = Change {
+        store,
+        old,
+        new,
+        events: OnceCell::new(),
+    };
+    for subscriber in SUBSCRIBERS {
+        subscriber.handle(&change);
+    }
+}
+
+/// Tells every subscriber about views added to a video without saving the video store
+///
+/// # Arguments
+///
+/// * `video` - The video before the views were added
+/// * `added` - The number of views added
+pub fn views_added(video: &Video, added: u32) {
+    let old = serde_json::to_value(video).unwrap();
+    let mut new = old.clone();
+    new["views"] = Value::from(video.views.saturating_add(added));
+    dispatch(Store::Videos, &[old], &[new]);
+}
+
+/// Gives the old records the times the new ones were last changed, so records only stamped with a
+/// new time when saved, as when they gain a field, don't count as changed
+fn restamp(old: &[Value], new: &[Value]) -> Vec<Value> {
+    let stamps: HashMap<u64, &Value> = new
+        .iter()
+        .filter_map(|record| Some((record.get("id")?.as_u64()?, record.get("updated_at")?)))
+        .collect();
+    old.iter()
+        .map(|record| {
+            let mut record = record.clone();
+            if let Some(stamp) = record
+                .get("id")
+                .and_then(Value::as_u64)
+                .and_then(|id| stamps.get(&id))
+            {
+                record["updated_at"] = (*stamp).clone();
+            }
+            record
+        })
+        .collect()
+}
+
+/// Reads records from JSON values, skipping those that can't be read
+pub fn decode<T: DeserializeOwned>(records: &[Value]) -> Vec<T> {
+    records
+        .iter()
+        .filter_map(|record| serde_json::from_value(record.clone()).ok())
+        .collect()
+}
+
+/// The channels of everyone listening for events
+static STREAMS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+
+/// Sends every event to the channels listening, as for the event streams of the server
+struct Streams;
+
+impl EventSubscriber for Streams {
+    fn handle(&self, change: &Change) {
+        if STREAMS.lock().unwrap().is_empty() {
+            return;
+        }
+        for event in change.events() {
+            publish(event.clone());
+        }
+    }
+}
+
+/// Starts listening for events
+///
+/// # Returns
+///
+/// A channel receiving every event published from now on
+pub fn subscribe() -> Receiver<Event> {
+    let (sender, receiver) = mpsc::channel();
+    STREAMS.lock().unwrap().push(sender);
+    receiver
+}
+
+/// Sends the given event to every channel listening, forgetting those that stopped listening
+fn publish(event: Event) {
+    STREAMS
+        .lock()
+        .unwrap()
+        .retain(|stream| stream.send(event.clone()).is_ok());
+}
+
+/// Picks out the view counts a video reached by gaining views
+///
+/// # Arguments
+///
+/// * `counts` - The view counts to look for
+/// * `before` - The views of the video before it gained views
+/// * `after` - The views of the video after it gained views
+pub fn crossed(counts: &[u32], before: u32, after: u32) -> Vec<u32> {
+    counts
+        .iter()
+        .copied()
+        .filter(|count| before < *count && *count <= after)
+        .collect()
+}
+
+/// Works out the events that turn one list of users into another
+///
+/// # Arguments
+///
+/// * `old` - The users before the change
+/// * `new` - The users after the change
+pub fn user_changes(old: &[User], new: &[User]) -> Vec<Event> {
+    let mut events: Vec<Event> = new
+        .iter()
+        .filter_map(|user| match old.iter().find(|old| old.id == user.id) {
+            None => Some(Event::UserCreated { user: user.clone() }),
+            Some(old) if old != user => Some(Event::UserUpdated { user: user.clone() }),
+            Some(_) => None,
+        })
+        .collect();
+    events.extend(
+        old.iter()
+            .filter(|user| !new.iter().any(|new| new.id == user.id))
+            .map(|user| Event::UserDeleted { user: user.clone() }),
+    );
+    events
+}
+
+/// Works out the events that turn one list of videos into another
+///
+/// # Arguments
+///
+/// * `old` - The videos before the change
+/// * `new` - The videos after the change
+pub fn video_changes(old: &[Video], new: &[Video]) -> Vec<Event> {
+    let mut events: Vec<Event> = new
+        .iter()
+        .filter_map(|video| match old.iter().find(|old| old.id == video.id) {
+            None => Some(Event::VideoCreated {
+                video: video.clone(),
+            }),
+            Some(old) if old == video => None,
+            Some(old) if video.views > old.views && old.name == video.name => {
+                Some(Event::ViewsAdded {
+                    video: video.clone(),
+                    added: video.views - old.views,
+                })
+            }
+            Some(_) => Some(Event::VideoUpdated {
+                video: video.clone(),
+            }),
+        })
+        .collect();
+    events.extend(
+        old.iter()
+            .filter(|video| !new.iter().any(|new| new.id == video.id))
+            .map(|video| Event::VideoDeleted {
+                video: video.clone(),
+            }),
+    );
+    events
+}
+
+/// Totals the views gained between one list of videos and another, however else the videos
+/// changed. New videos count all of their views, so views can't be added by renaming a video or
+/// creating one with views already
+///
+/// # Arguments
+///
+/// * `old` - The videos before the change
+/// * `new` - The videos after the change
+pub fn added_views(old: &[Video], new: &[Video]) -> u32 {
+    new.iter()
+        .map(|video| match old.iter().find(|old| old.id == video.id) {
+            Some(old) => video.views.saturating_sub(old.views),
+            None => video.views,
+        })
+        .fold(0, u32::saturating_add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(id: u32, name: &str, views: u32) -> Video {
+        Video {
+            id,
+            name: name.to_string(),
+            views,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_video_changes() {
+        let old = vec![
+            video(1, "Kept", 0),
+            video(2, "Watched", 3),
+            video(3, "Gone", 0),
+        ];
+        let new = vec![
+            video(1, "Kept", 0),
+            video(2, "Watched", 5),
+            video(4, "Added", 0),
+        ];
+
+        assert_eq!(
+            video_changes(&old, &new),
+            vec![
+                Event::ViewsAdded {
+                    video: video(2, "Watched", 5),
+                    added: 2
+                },
+                Event::VideoCreated {
+                    video: video(4, "Added", 0)
+                },
+                Event::VideoDeleted {
+                    video: video(3, "Gone", 0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_video_changes_renamed() {
+        let old = vec![video(1, "Old name", 0)];
+        let new = vec![video(1, "New name", 1)];
+
+        assert_eq!(
+            video_changes(&old, &new),
+            vec![Event::VideoUpdated {
+                video: video(1, "New name", 1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_added_views() {
+        let old = vec![video(1, "Old name", 3), video(2, "Watched", 10)];
+        let new = vec![
+            video(1, "New name", 8),
+            video(2, "Watched", 4),
+            video(3, "Added", u32::MAX),
+        ];
+
+        assert_eq!(added_views(&old, &new), u32::MAX);
+        assert_eq!(added_views(&old, &new[..2]), 5);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::args::command_types::video_subcommands;
+use crate::recommend::{self, Catalog, Reason, Suggestion, SuggestionOutput};
+use crate::session;
+use crate::t;
+use clap::Args;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The seconds in a day
+const DAY: u64 = 24 * 60 * 60;
+
+/// How much each kind of video counts towards the home feed, from the `[feed]` section of the
+/// config file. A weight of 0 leaves that kind out
+///
+/// # Fields
+///
+/// * `subscriptions` - How much being uploaded by a channel the user subscribes to counts
+/// * `recent` - How much being uploaded recently counts, at most. Fades to nothing over `days`
+/// * `trending` - How much being watched often in the last `days` counts, at most
+/// * `genres` - How much having the genres the user watches counts, at most
+/// * `days` - How many days back uploads count as recent and views count towards trending
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FeedConfig {
+    pub subscriptions: f64,
+    pub recent: f64,
+    pub trending: f64,
+    pub genres: f64,
+    pub days: u64,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            subscriptions: 1.0,
+            recent: 0.5,
+            trending: 0.5,
+            genres: 1.0,
+            days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct Feed {
+    /// The ID of the user to show the feed of, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// The number of videos in the feed
+    #[arg(long, default_value_t = 20)]
+    pub count: usize,
+    #[command(flatten)]
+    pub output: SuggestionOutput,
+}
+
+/// Ranks the videos a user hasn't watched yet into their home feed, from the channels they
+/// subscribe to, how recently each video was uploaded, how often it was watched lately, and the
+/// genres they watch
+///
+/// # Arguments
+///
+/// * `catalog` - The records to build the feed from
+/// * `user` - The ID of the user whose feed it is, who must exist
+/// * `weights` - How much each kind of video counts
+/// * `now` - The current time in seconds since the Unix epoch
+/// * `count` - The most videos in the feed
+///
+/// # Returns
+///
+/// The videos in the feed, the highest ranked first
+pub fn build_feed<'a>(
+    catalog: &'a Catalog,
+    user: u32,
+    weights: &FeedConfig,
+    now: u64,
+    count: usize,
+) -> Vec<Suggestion<'a>> {
+    let Some(user) = catalog.users.iter().find(|other| other.id == user) else {
+        return vec![];
+    };
+    let watched = catalog.watched_by(user.id);
+    let (genres, genres_total) = catalog.liked_genres(user, &watched);
+    let channels = catalog.channels_of(user.id);
+
+    let window = weights.days.max(1) * DAY;
+    let plays = recent_plays(catalog, now.saturating_sub(window));
+    let most_plays = plays
+        .values()
+        .map(|(_, weighted)| *weighted)
+        .fold(0.0, f64::max);
+
+    let mut feed: Vec<Suggestion> = catalog
+        .videos
+        .iter()
+        .filter(|video| !watched.contains_key(&video.id) && Catalog::may_recommend(user, video))
+        .filter_map(|video| {
+            let mut parts: Vec<(f64, Reason)> = vec![];
+
+            if let Some(channel) = video.owner.filter(|owner| channels.contains(owner)) {
+                parts.push((
+                    weights.subscriptions,
+                    Reason::Subscribed(catalog.user_name(channel)),
+                ));
+            }
+
+            if let Some(age) = video
+                .uploaded_at
+                .map(|uploaded_at| now.saturating_sub(uploaded_at))
+                .filter(|age| *age < window)
+            {
+                parts.push((
+                    weights.recent * (1.0 - age as f64 / window as f64),
+                    Reason::Recent(age / DAY),
+                ));
+            }
+
+            if let Some((count, weighted)) = plays.get(&video.id).filter(|_| most_plays > 0.0) {
+                parts.push((
+                    weights.trending * weighted / most_plays,
+                    Reason::Trending(*count, weights.days.max(1)),
+                ));
+            }
+
+            parts.extend(Catalog::genre_part(
+                video,
+                &genres,
+                genres_total,
+                weights.genres,
+            ));
+
+            Suggestion::from_parts(video, parts)
+        })
+        .collect();
+    recommend::rank(&mut feed, count);
+    feed
+}
+
+/// Counts the plays of each video since a given time, which make it trending
+///
+/// # Arguments
+///
+/// * `catalog` - The records holding the watch history
+/// * `since` - The earliest time a play counts from, in seconds since the Unix epoch
+///
+/// # Returns
+///
+/// For each video played, by its ID, how many times it was played and how much the plays count by
+/// how much of the video was watched
+pub fn recent_plays(catalog: &Catalog, since: u64) -> HashMap<u32, (usize, f64)> {
+    let weight = catalog.play_weight();
+    let mut plays: HashMap<u32, (usize, f64)> = HashMap::new();
+    for entry in catalog
+        .history
+        .iter()
+        .filter(|entry| entry.started_at >= since)
+    {
+        let (count, weighted) = plays.entry(entry.video).or_default();
+        *count += 1;
+        *weighted += weight(entry);
+    }
+    plays
+}
+
+/// Handles showing the home feed of a user, printing each video with what put it in the feed
+///
+/// # Arguments
+///
+/// * `feed` - The arguments for the feed
+/// * `weights` - How much each kind of video counts, from the config file
+pub fn handle_feed(feed: Feed, weights: FeedConfig) {
+    let Some(id) = feed.user.or_else(session::current_user) else {
+        error!("{}", t!("user.none_given"));
+        return;
+    };
+    let catalog = Catalog::load();
+    let Some(user) = catalog.users.iter().find(|user| user.id == id) else {
+        error!("{}", t!("user.not_found_id", id = id));
+        return;
+    };
+
+    let videos = build_feed(&catalog, id, &weights, video_subcommands::now(), feed.count);
+    recommend::print_suggestions(&videos, &feed.output, &t!("feed.empty", name = user.name));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::command_types::user_subcommands::{Subscription, User};
+    use crate::args::command_types::video_subcommands::Video;
+    use crate::args::command_types::view_subcommands::WatchEntry;
+
+    const NOW: u64 = 100 * DAY;
+
+    fn video(id: u32, uploaded_at: Option<u64>, owner: Option<u32>) -> Video {
+        Video {
+            id,
+            name: format!("Video {}", id),
+            uploaded_at,
+            owner,
+            ..Default::default()
+        }
+    }
+
+    fn watch(user: u32, video: u32, started_at: u64) -> WatchEntry {
+        WatchEntry {
+            video,
+            user: Some(user),
+            started_at,
+            seconds: 60,
+            finished: true,
+        }
+    }
+
+    #[test]
+    fn test_build_feed() {
+        let catalog = Catalog {
+            users: (1..=2)
+                .map(|id| User {
+                    id,
+                    name: format!("User {}", id),
+                    ..Default::default()
+                })
+                .collect(),
+            videos: vec![
+                video(1, Some(NOW - DAY), None),
+                video(2, Some(NOW - 30 * DAY), Some(2)),
+                video(3, Some(NOW - 30 * DAY), None),
+                video(4, Some(NOW - 30 * DAY), None),
+            ],
+            history: vec![watch(2, 3, NOW - DAY), watch(2, 4, NOW - 10 * DAY)],
+            subscriptions: vec![Subscription {
+                user: 1,
+                channel: 2,
+                created_at: None,
+            }],
+            series: vec![],
+        };
+
+        let reasons = |weights: &FeedConfig| -> Vec<(u32, String)> {
+            build_feed(&catalog, 1, weights, NOW, 10)
+                .iter()
+                .map(|item| (item.video.id, item.reason.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            reasons(&FeedConfig::default()),
+            [
+                (2, "From User 2, whom you subscribe to".to_string()),
+                (3, "Watched once in the last 7 days".to_string()),
+                (1, "New since yesterday".to_string()),
+            ]
+        );
+        assert_eq!(
+            reasons(&FeedConfig {
+                subscriptions: 0.0,
+                recent: 2.0,
+                ..Default::default()
+            }),
+            [
+                (1, "New since yesterday".to_string()),
+                (3, "Watched once in the last 7 days".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use crate::query;
+use crate::t;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// How a field is compared with a value in a filter
+///
+/// # Variants
+///
+/// * `Equals` - `==` or `=`. Text is compared ignoring case
+/// * `NotEquals` - `!=`
+/// * `Greater` - `>`
+/// * `GreaterOrEqual` - `>=`
+/// * `Less` - `<`
+/// * `LessOrEqual` - `<=`
+/// * `Contains` - `contains`. Text contains the value, ignoring case
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Equals,
+    NotEquals,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Contains,
+}
+
+/// A value a field is compared with, as written in a filter
+///
+/// # Variants
+///
+/// * `Number` - A number, e.g. `1000` or `1.5`
+/// * `Text` - Quoted text, e.g. `"old"`, or a bare word, e.g. `private`
+/// * `Bool` - `true` or `false`
+/// * `Null` - `null`, which a field that was never given equals
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Null,
+}
+
+/// A filter expression, e.g. `views > 1000 && name contains "old"`, matched against the fields of
+/// a record
+///
+/// # Variants
+///
+/// * `Compare` - Compares a field with a value
+/// * `And` - Matches if both filters do. Written `&&`
+/// * `Or` - Matches if either filter does. Written `||`
+/// * `Not` - Matches if the filter doesn't. Written `!`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Compare(String, Operator, Literal),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+/// A piece of a filter expression
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// Splits a filter expression into its pieces
+///
+/// # Returns
+///
+/// The pieces, or an error message if the expression has a character that can't start one
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' | ')' => {
+                chars.next();
+                match c {
+                    '(' => Token::Open,
+                    _ => Token::Close,
+                }
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => text.extend(chars.next()),
+                        Some(end) if end == c => break,
+                        Some(other) => text.push(other),
+                        None => return Err(t!("filter.unclosed_quote", quote = c, text = text)),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            '&' | '|' | '=' | '!' | '<' | '>' => {
+                let mut symbol = String::new();
+                while let Some(&next) = chars.peek() {
+                    if !"&|=!<>".contains(next) || (symbol == "!" && next != '=') {
+                        break;
+                    }
+                    symbol.push(next);
+                    chars.next();
+                }
+                match symbol.as_str() {
+                    "&&" => Token::And,
+                    "||" => Token::Or,
+                    "!" => Token::Not,
+                    "==" | "=" => Token::Operator(Operator::Equals),
+                    "!=" => Token::Operator(Operator::NotEquals),
+                    ">" => Token::Operator(Operator::Greater),
+                    ">=" => Token::Operator(Operator::GreaterOrEqual),
+                    "<" => Token::Operator(Operator::Less),
+                    "<=" => Token::Operator(Operator::LessOrEqual),
+                    _ => return Err(t!("filter.unknown_operator", operator = symbol)),
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "()\"'&|=!<>".contains(next) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                if word.is_empty() {
+                    return Err(t!("filter.unexpected", text = c));
+                }
+                match word.as_str() {
+                    "contains" => Token::Operator(Operator::Contains),
+                    "and" | "AND" => Token::And,
+                    "or" | "OR" => Token::Or,
+                    "not" | "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Reads a filter from its pieces, by recursive descent. `||` binds loosest, then `&&`, then `!`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => Err(t!("filter.unclosed_parenthesis")),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let operator = match self.next() {
+                    Some(Token::Operator(operator)) => operator,
+                    _ => return Err(t!("filter.no_operator", field = field)),
+                };
+                let literal = match self.next() {
+                    Some(Token::Quoted(text)) => Literal::Text(text),
+                    Some(Token::Word(word)) => match word.as_str() {
+                        "true" => Literal::Bool(true),
+                        "false" => Literal::Bool(false),
+                        "null" => Literal::Null,
+                        _ => match word.parse() {
+                            Ok(number) => Literal::Number(number),
+                            Err(_) => Literal::Text(word),
+                        },
+                    },
+                    _ => return Err(t!("filter.no_value", field = field)),
+                };
+                Ok(Filter::Compare(field, operator, literal))
+            }
+            Some(token) => Err(t!("filter.no_field", token = format!("{:?}", token))),
+            None => Err(t!("filter.ended")),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parses a filter expression, e.g. `views > 1000 && name contains "old"`
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(t!("filter.trailing", token = format!("{:?}", token))),
+        }
+    }
+}
+
+impl Filter {
+    /// Finds a field the filter compares that records don't have
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - The fields records have
+    ///
+    /// # Returns
+    ///
+    /// The first unknown field, or `None` if every field is known
+    pub fn unknown_field<'a>(&'a self, known: &[String]) -> Option<&'a str> {
+        match self {
+            Filter::Compare(field, _, _) => match known.contains(field) {
+                true => None,
+                false => Some(field),
+            },
+            Filter::And(left, right) | Filter::Or(left, right) => left
+                .unknown_field(known)
+                .or_else(|| right.unknown_field(known)),
+            Filter::Not(filter) => filter.unknown_field(known),
+        }
+    }
+
+    /// Determines if a record matches the filter. A comparison between values of different
+    /// types, e.g. text and a number, doesn't match
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record, as a JSON object of its fields
+    pub fn matches(&self, record: &Value) -> bool {
+        match self {
+            Filter::Compare(field, operator, literal) => compare(
+                record.get(field).unwrap_or(&Value::Null),
+                *operator,
+                literal,
+            ),
+            Filter::And(left, right) => left.matches(record) && right.matches(record),
+            Filter::Or(left, right) => left.matches(record) || right.matches(record),
+            Filter::Not(filter) => !filter.matches(record),
+        }
+    }
+}
+
+/// Compares the value of a field with a value from a filter
+fn compare(value: &Value, operator: Operator, literal: &Literal) -> bool {
+    let ordering = match (value, literal) {
+        (Value::Null, Literal::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Literal::Null) => None,
+        (Value::Number(number), Literal::Number(literal)) => number
+            .as_f64()
+            .and_then(|number| number.partial_cmp(literal)),
+        (Value::Bool(value), Literal::Bool(literal)) => Some(value.cmp(literal)),
+        (Value::String(text), Literal::Text(literal)) => {
+            if operator == Operator::Contains {
+                return query::text_contains(text, literal, true);
+            }
+            match query::text_equals(text, literal, true) {
+                true => Some(Ordering::Equal),
+                false => Some(text.to_lowercase().cmp(&literal.to_lowercase())),
+            }
+        }
+        (Value::String(text), Literal::Number(number)) => {
+            return operator == Operator::Contains
+                && query::text_contains(text, &number.to_string(), true);
+        }
+        _ => None,
+    };
+    match (operator, ordering) {
+        (Operator::NotEquals, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) | (Operator::Contains, _) => false,
+        (Operator::Equals, Some(ordering)) => ordering == Ordering::Equal,
+        (Operator::Greater, Some(ordering)) => ordering == Ordering::Greater,
+        (Operator::GreaterOrEqual, Some(ordering)) => ordering != Ordering::Less,
+        (Operator::Less, Some(ordering)) => ordering == Ordering::Less,
+        (Operator::LessOrEqual, Some(ordering)) => ordering != Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter() {
+        let filter: Filter = "views > 1000 && name contains \"old\"".parse().unwrap();
+        assert_eq!(
+            filter,
+            Filter::And(
+                Box::new(Filter::Compare(
+                    "views".to_string(),
+                    Operator::Greater,
+                    Literal::Number(1000.0)
+                )),
+                Box::new(Filter::Compare(
+                    "name".to_string(),
+                    Operator::Contains,
+                    Literal::Text("old".to_string())
+                )),
+            )
+        );
+        assert!(filter.matches(&json!({ "views": 1500, "name": "Old Footage" })));
+        assert!(!filter.matches(&json!({ "views": 900, "name": "Old Footage" })));
+
+        let filter: Filter = "!(visibility == private || owner = null) && age_restricted != true"
+            .parse()
+            .unwrap();
+        let video = json!({ "visibility": "Public", "owner": 1, "age_restricted": false });
+        assert!(filter.matches(&video));
+        assert!(!filter.matches(&json!({ "visibility": "Private", "owner": 1 })));
+        assert_eq!(
+            filter.unknown_field(&["visibility".to_string()]),
+            Some("owner")
+        );
+
+        assert!("views >".parse::<Filter>().is_err());
+        assert!("(views > 1".parse::<Filter>().is_err());
+        assert!("name contains \"old".parse::<Filter>().is_err());
+        assert!("views > 1 views".parse::<Filter>().is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::output::{self, Style};
+use crate::t;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+use std::time::{Duration, UNIX_EPOCH};
+use unicode_normalization::UnicodeNormalization;
+use unicode_width::UnicodeWidthStr;
+
+/// How times, lengths of videos, and view counts are printed for people to read, as in lists,
+/// statistics, and the audit log. Exports and feeds always use their own formats
+///
+/// # Fields
+///
+/// * `timestamps` - A strftime format for times, e.g. `timestamps = "%d %b %Y %H:%M"`. RFC 3339,
+///   e.g. `2023-06-25T12:00:00Z`, if not given
+/// * `local_time` - Whether times are shown in the local time zone instead of UTC
+/// * `durations` - How lengths are written
+/// * `counts` - How view counts and other large numbers are written
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FormatConfig {
+    #[serde(deserialize_with = "deserialize_timestamps")]
+    pub timestamps: Option<String>,
+    pub local_time: bool,
+    pub durations: DurationStyle,
+    pub counts: CountStyle,
+}
+
+/// How lengths of videos are written
+///
+/// # Variants
+///
+/// * `Clock` - As `1:30:00`, or `5:20` if shorter than an hour
+/// * `Units` - As `1h 30m`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationStyle {
+    #[default]
+    Clock,
+    Units,
+}
+
+/// How view counts and other large numbers are written
+///
+/// # Variants
+///
+/// * `Plain` - As `1234567`
+/// * `Grouped` - With the digits grouped in thousands, e.g. `1,234,567` in English
+/// * `Compact` - Rounded to thousands, millions, or billions, e.g. `1.2M` in English
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountStyle {
+    #[default]
+    Plain,
+    Grouped,
+    Compact,
+}
+
+/// Rejects strftime formats chrono can't print, so they are reported when the config is read
+/// instead of when the first time is printed
+fn deserialize_timestamps<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let format = String::deserialize(deserializer)?;
+    if StrftimeItems::new(&format).any(|item| matches!(item, Item::Error)) {
+        return Err(serde::de::Error::custom(t!(
+            "config.invalid_strftime",
+            format = format
+        )));
+    }
+    Ok(Some(format))
+}
+
+/// The formats from the config file
+static FORMAT: OnceLock<FormatConfig> = OnceLock::new();
+
+/// Sets the formats from the config file
+pub fn set_config(config: FormatConfig) {
+    FORMAT.set(config).ok();
+}
+
+fn format_config() -> &'static FormatConfig {
+    FORMAT.get_or_init(FormatConfig::default)
+}
+
+/// Formats a time given in seconds since the Unix epoch as configured in `[format]`
+pub fn timestamp(timestamp: u64) -> String {
+    let config = format_config();
+    let utc = i64::try_from(timestamp)
+        .ok()
+        .and_then(|seconds| DateTime::<Utc>::from_timestamp(seconds, 0));
+    let Some(utc) = utc else {
+        return timestamp.to_string();
+    };
+    match (&config.timestamps, config.local_time) {
+        (None, false) => {
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp))
+                .to_string()
+        }
+        (None, true) => utc
+            .with_timezone(&Local)
+            .format("%Y-%m-%dT%H:%M:%S%:z")
+            .to_string(),
+        (Some(format), false) => utc.format(format).to_string(),
+        (Some(format), true) => utc.with_timezone(&Local).format(format).to_string(),
+    }
+}
+
+/// Formats the length of a video as configured in `[format]`
+pub fn duration(seconds: u32) -> String {
+    format_duration(seconds, format_config().durations)
+}
+
+/// Formats a count, such as the views of a video, as configured in `[format]`
+pub fn count(count: impl Into<u64>) -> String {
+    format_count(count.into(), format_config().counts)
+}
+
+fn format_duration(seconds: u32, style: DurationStyle) -> String {
+    match style {
+        DurationStyle::Clock => {
+            let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+            if hours > 0 {
+                format!("{}:{:02}:{:02}", hours, minutes, seconds)
+            } else {
+                format!("{}:{:02}", minutes, seconds)
+            }
+        }
+        DurationStyle::Units if seconds == 0 => "0s".to_string(),
+        DurationStyle::Units => {
+            humantime::format_duration(Duration::from_secs(seconds.into())).to_string()
+        }
+    }
+}
+
+fn format_count(count: u64, style: CountStyle) -> String {
+    match style {
+        CountStyle::Plain => count.to_string(),
+        CountStyle::Grouped => {
+            let digits = count.to_string();
+            let separator = t!("number.thousands_separator");
+            let mut grouped = String::new();
+            for (i, digit) in digits.chars().enumerate() {
+                if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                    grouped.push_str(&separator);
+                }
+                grouped.push(digit);
+            }
+            grouped
+        }
+        CountStyle::Compact => {
+            let units = [1_000, 1_000_000, 1_000_000_000];
+            let Some(mut unit) = units.iter().rposition(|unit| count >= *unit) else {
+                return count.to_string();
+            };
+            // Rounding can carry into the next unit, as 999,999 is 1M rather than 1000K
+            let tenths = |unit: usize| (count as f64 * 10.0 / units[unit] as f64).round() as u64;
+            if tenths(unit) >= 10_000 && unit + 1 < units.len() {
+                unit += 1;
+            }
+            let tenths = tenths(unit);
+            let number = match tenths % 10 {
+                0 => (tenths / 10).to_string(),
+                tenth => format!("{}{}{}", tenths / 10, t!("number.decimal_mark"), tenth),
+            };
+            match unit {
+                0 => t!("number.thousands", count = number),
+                1 => t!("number.millions", count = number),
+                _ => t!("number.billions", count = number),
+            }
+        }
+    }
+}
+
+/// Puts text in Unicode normalization form C, so the same name typed with a precomposed `é` or
+/// with `e` and a combining accent is stored and matched the same way
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Folds the case of text following Unicode, so `Straße` and `STRASSE` compare equal where
+/// lowercasing alone would keep them apart
+///
+/// The result is normalized too, so it can be compared against other folded text directly.
+pub fn fold_case(text: &str) -> String {
+    normalize(&caseless::default_case_fold_str(&normalize(text)))
+}
+
+/// How many columns text takes up in a terminal, counting CJK characters and emoji as two
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// Pads text with spaces to take up at least the given number of columns in a terminal
+pub fn pad(text: &str, columns: usize) -> String {
+    format!(
+        "{}{}",
+        text,
+        " ".repeat(columns.saturating_sub(width(text)))
+    )
+}
+
+/// How many rows the widths of the columns of a table are measured from. Later rows are padded to
+/// the same widths, so a long table is printed as it goes rather than held until the end
+const SAMPLE_ROWS: usize = 100;
+
+/// A table printed as its rows are added, with its columns lined up
+///
+/// Printed to a terminal, every column is padded to its widest value among the header and the
+/// first `SAMPLE_ROWS` rows, measured in terminal columns rather than characters so CJK and emoji
+/// names line up too. Otherwise, values are separated by tabs, as scripts expect, and every row is
+/// printed right away.
+///
+/// # Fields
+///
+/// * `header` - The names of the columns
+/// * `rows` - The values of each row not printed yet, in the order of the columns
+/// * `aligned` - Whether to pad the columns to line up, rather than separate them by tabs
+/// * `widths` - The widths of the columns, once the header was printed
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    aligned: bool,
+    widths: Option<Vec<usize>>,
+}
+
+impl Table {
+    pub fn new(header: Vec<String>) -> Self {
+        Table {
+            header,
+            rows: vec![],
+            aligned: io::stdout().is_terminal(),
+            widths: None,
+        }
+    }
+
+    /// Adds a row after every other, printing it once the widths of the columns are known
+    pub fn row(&mut self, values: Vec<String>) {
+        self.rows.push(values);
+        if !self.aligned || self.widths.is_some() || self.rows.len() >= SAMPLE_ROWS {
+            self.flush();
+        }
+    }
+
+    /// Measures the widths of the columns from the header and the rows not printed yet
+    fn measure(&self) -> Vec<usize> {
+        let mut widths = vec![0; self.header.len()];
+        for values in std::iter::once(&self.header).chain(&self.rows) {
+            for (column, value) in values.iter().enumerate() {
+                if column < widths.len() {
+                    widths[column] = widths[column].max(width(value));
+                }
+            }
+        }
+        widths
+    }
+
+    /// Lays out a line of the table, without styling. A value wider than its column pushes the
+    /// rest of its line along
+    fn line(&self, values: &[String]) -> String {
+        let Some(widths) = self.widths.as_ref().filter(|_| self.aligned) else {
+            return values.join("\t");
+        };
+        let last = values.len().saturating_sub(1);
+        values
+            .iter()
+            .enumerate()
+            .map(|(column, value)| match column == last {
+                true => value.clone(),
+                false => pad(value, widths.get(column).copied().unwrap_or_default()),
+            })
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    }
+
+    /// Lays out the lines not printed yet, the header first if it wasn't printed, measuring the
+    /// widths of the columns from them then
+    fn take_lines(&mut self) -> Vec<String> {
+        let mut lines = vec![];
+        if self.widths.is_none() {
+            self.widths = Some(self.measure());
+            lines.push(self.line(&self.header));
+        }
+        for values in std::mem::take(&mut self.rows) {
+            lines.push(self.line(&values));
+        }
+        lines
+    }
+
+    /// Prints the lines not printed yet to stdout, with the header styled
+    fn flush(&mut self) {
+        let header = self.widths.is_none();
+        for (index, line) in self.take_lines().into_iter().enumerate() {
+            match header && index == 0 {
+                true => println!("{}", output::paint_stdout(&line, Style::Header)),
+                false => println!("{}", line),
+            }
+        }
+    }
+
+    /// Prints the rest of the table to stdout
+    pub fn print(mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_text() {
+        assert_eq!(normalize("Jose\u{301}"), "Jos\u{e9}");
+        assert_eq!(fold_case("STRASSE"), fold_case("Straße"));
+        assert_eq!(fold_case("JOSE\u{301}"), fold_case("Jos\u{e9}"));
+        assert_eq!(width("東京"), 4);
+        assert_eq!(pad("東京", 6), "東京  ");
+
+        let table = |aligned| {
+            let mut table = Table::new(vec!["name".to_string(), "views".to_string()]);
+            table.aligned = aligned;
+            table.rows.push(vec!["東京".to_string(), "1".to_string()]);
+            table
+                .rows
+                .push(vec!["Tokyo Story".to_string(), "20".to_string()]);
+            table
+        };
+        let mut aligned = table(true);
+        assert_eq!(
+            aligned.take_lines(),
+            ["name         views", "東京         1", "Tokyo Story  20"]
+        );
+        // Rows after the widths were measured keep to them
+        aligned
+            .rows
+            .push(vec!["Late Spring".to_string(), "3".to_string()]);
+        aligned
+            .rows
+            .push(vec!["東京物語 (1953)".to_string(), "4".to_string()]);
+        assert_eq!(
+            aligned.take_lines(),
+            ["Late Spring  3", "東京物語 (1953)  4"]
+        );
+        assert_eq!(table(false).take_lines()[1], "東京\t1");
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(format_count(1_234_567, CountStyle::Plain), "1234567");
+        assert_eq!(format_count(1_234_567, CountStyle::Grouped), "1,234,567");
+        assert_eq!(format_count(123, CountStyle::Grouped), "123");
+        assert_eq!(format_count(1_234_567, CountStyle::Compact), "1.2M");
+        assert_eq!(format_count(999, CountStyle::Compact), "999");
+        assert_eq!(format_count(15_000, CountStyle::Compact), "15K");
+        assert_eq!(format_count(999_999, CountStyle::Compact), "1M");
+        assert_eq!(format_duration(5400, DurationStyle::Clock), "1:30:00");
+        assert_eq!(format_duration(5400, DurationStyle::Units), "1h 30m");
+        assert!(toml::from_str::<FormatConfig>("timestamps = \"%Y %Q\"").is_err());
+    }
+}
@@ -0,0 +1,732 @@
+use crate::args::command_types::playlist_subcommands::Playlist;
+use crate::args::command_types::series_subcommands::Series;
+use crate::args::command_types::user_subcommands::{Role, Subscription, User};
+use crate::args::command_types::video_subcommands::Video;
+use crate::args::command_types::view_subcommands::WatchEntry;
+use crate::dry_run;
+use crate::integrity::{References, Target};
+use crate::notifications::Notification;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{metadata, migration, remote, s3};
+use clap::Args;
+use log::{debug, error, info};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::process;
+
+#[derive(Debug, Args)]
+pub struct Fsck {
+    /// Fix what can be fixed: write missing or outdated checksums of stores that can still be read, and drop references to records that don't exist
+    #[arg(long, default_value_t = false)]
+    pub repair: bool,
+    /// Give every user, video, and series sharing its ID with an earlier one a new ID, dropping
+    /// those that are exact copies of the earlier one instead. References made since it was
+    /// created, such as its views and watch history, move with it
+    #[arg(long, default_value_t = false)]
+    pub reassign_ids: bool,
+}
+
+/// The problems found by a check
+///
+/// # Fields
+///
+/// * `found` - How many problems were found
+/// * `repaired` - How many of them were repaired
+#[derive(Debug, Default)]
+struct Problems {
+    found: usize,
+    repaired: usize,
+}
+
+impl Problems {
+    /// Reports a problem
+    ///
+    /// # Arguments
+    ///
+    /// * `problem` - What is wrong
+    /// * `repaired` - Whether the problem was repaired
+    fn report(&mut self, problem: String, repaired: bool) {
+        self.found += 1;
+        if repaired {
+            self.repaired += 1;
+            println!("{}", t!("fsck.repaired_problem", problem = problem));
+        } else {
+            println!("{}", problem);
+        }
+    }
+}
+
+/// Checks every store file in the data directory against its checksum, and that it can be read
+///
+/// # Arguments
+///
+/// * `problems` - Where to report the problems found
+/// * `repair` - Whether to write missing or outdated checksums, and migrate old store files
+///
+/// # Returns
+///
+/// Whether every store can be read
+fn check_files(problems: &mut Problems, repair: bool) -> bool {
+    let mut readable = true;
+    for store in Store::ALL {
+        let path = storage::store_path(store);
+        let Ok(bytes) = fs::read(&path) else {
+            debug!("{} does not exist", path.display());
+            continue;
+        };
+
+        if let Err(e) = storage::decode::<Value>(&bytes) {
+            if migration::read_unversioned(store, &bytes).is_ok() {
+                if repair {
+                    storage::load_file::<Value>(store);
+                }
+                problems.report(t!("fsck.unversioned", path = path.display()), repair);
+            } else {
+                readable = false;
+                problems.report(
+                    t!("fsck.unreadable", path = path.display(), error = e),
+                    false,
+                );
+            }
+            continue;
+        }
+
+        let checksum_path = storage::checksum_path(store);
+        let problem = match fs::read_to_string(&checksum_path) {
+            Err(_) => t!("fsck.no_checksum", path = path.display()),
+            Ok(checksum) if checksum.trim() != storage::checksum(&bytes) => {
+                t!("fsck.checksum_mismatch", path = path.display())
+            }
+            Ok(_) => continue,
+        };
+        if repair && !dry_run::skip(&format!("write {}", checksum_path.display())) {
+            if let Err(e) = fs::write(&checksum_path, storage::checksum(&bytes)) {
+                error!(
+                    "{}",
+                    t!(
+                        "fsck.could_not_write",
+                        path = checksum_path.display(),
+                        error = e
+                    )
+                );
+                problems.report(problem, false);
+                continue;
+            }
+        }
+        problems.report(problem, repair);
+    }
+    readable
+}
+
+/// The records sharing an ID, told apart by when they were created
+///
+/// # Fields
+///
+/// * `first` - When the record keeping the ID was created, if known
+/// * `later` - When each record given a new ID was created, if known, along with its new ID
+#[derive(Debug, Default)]
+struct Claims {
+    first: Option<u64>,
+    later: Vec<(Option<u64>, u32)>,
+}
+
+impl Claims {
+    /// The record a reference to the shared ID belongs to: the one created last before the
+    /// reference was made. References made at an unknown time stay with the first record
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The shared ID
+    /// * `at` - When the reference was made, if known
+    ///
+    /// # Returns
+    ///
+    /// The ID the reference belongs to, new or shared
+    fn owner(&self, id: u32, at: Option<u64>) -> u32 {
+        let Some(at) = at else {
+            return id;
+        };
+        let mut owner = (self.first.unwrap_or(0), id);
+        for (created, new_id) in &self.later {
+            if let Some(created) = created.filter(|created| *created <= at && *created > owner.0) {
+                owner = (created, *new_id);
+            }
+        }
+        owner.1
+    }
+}
+
+/// The records given new IDs, by their store and the ID they shared
+type Reassigned = HashMap<(Store, u32), Claims>;
+
+/// Checks that no two users, videos, or series share an ID
+///
+/// Records refer to others by ID alone, so references to a shared ID can't be told apart for
+/// sure. Without `reassign` they are left with the first record, which keeps the ID, and counted
+/// so they can be checked by hand.
+///
+/// # Arguments
+///
+/// * `problems` - Where to report the problems found
+/// * `reassign` - Whether to give later records sharing an ID new IDs, or drop them if they are
+///   copies of the first, and save the stores
+/// * `reassigned` - Where to record the records given new IDs, so the references to them can be
+///   moved with `move_references`
+///
+/// # Returns
+///
+/// How many records share an ID with an earlier one
+fn check_ids(problems: &mut Problems, reassign: bool, reassigned: &mut Reassigned) -> usize {
+    let mut duplicates = 0;
+    for (store, kind) in [
+        (Store::Users, t!("fsck.user")),
+        (Store::Videos, t!("fsck.video")),
+        (Store::Series, t!("fsck.series")),
+    ] {
+        let records: Vec<Value> = storage::load(store);
+        let created = |record: &Value| {
+            store
+                .created_field()
+                .and_then(|field| record.get(field))
+                .and_then(Value::as_u64)
+        };
+        let before = duplicates;
+        let mut taken: HashSet<u32> = records
+            .iter()
+            .filter_map(|record| record["id"].as_u64())
+            .map(|id| id as u32)
+            .collect();
+        let mut first: HashMap<u32, usize> = HashMap::new();
+        let mut kept: Vec<Value> = Vec::with_capacity(records.len());
+        for mut record in records {
+            let Some(id) = record["id"].as_u64().map(|id| id as u32) else {
+                kept.push(record);
+                continue;
+            };
+            let Some(&index) = first.get(&id) else {
+                first.insert(id, kept.len());
+                kept.push(record);
+                continue;
+            };
+
+            duplicates += 1;
+            let name = record["name"].as_str().unwrap_or_default().to_string();
+            if kept[index] == record {
+                problems.report(
+                    t!("fsck.stored_twice", kind = kind, id = id, name = name),
+                    reassign,
+                );
+                if !reassign {
+                    kept.push(record);
+                }
+                continue;
+            }
+
+            let references = References::find(match store {
+                Store::Users => Target::User(id),
+                Store::Videos => Target::Video(id),
+                _ => Target::Series(id),
+            });
+            let mut problem = t!(
+                "fsck.shared_id",
+                kind = kind,
+                id = id,
+                name = name,
+                other_kind = kind.to_lowercase(),
+                other = kept[index]["name"].as_str().unwrap_or_default()
+            );
+            if reassign {
+                let new_id = metadata::generate_id(store, |id| taken.contains(&id));
+                taken.insert(new_id);
+                record["id"] = new_id.into();
+                problem = t!(
+                    "fsck.now",
+                    problem = problem,
+                    kind = kind.to_lowercase(),
+                    id = new_id
+                );
+                let claims = reassigned.entry((store, id)).or_default();
+                claims.first = created(&kept[index]);
+                claims.later.push((created(&record), new_id));
+            } else if !references.is_empty() {
+                problem = t!(
+                    "fsck.stay_with_first",
+                    problem = problem,
+                    references = references.describe(),
+                    id = id
+                );
+            }
+            problems.report(problem, reassign);
+            kept.push(record);
+        }
+
+        if reassign && duplicates > before {
+            storage::save(store, &kept);
+        }
+    }
+    duplicates
+}
+
+/// Points the references to IDs shared by several records at the record each belongs to, by when
+/// it was made, now that the records sharing an ID were given new ones
+///
+/// # Arguments
+///
+/// * `reassigned` - The records given new IDs
+/// * `videos` - Every video, referring to their owner and series
+/// * `history` - Every watch history entry, referring to a video and the user who watched it
+/// * `subscriptions` - Every subscription, referring to the user subscribed and their channel
+/// * `notifications` - Every notification, referring to the user notified and a video
+///
+/// # Returns
+///
+/// How many references were moved in each store, leaving out stores with none
+fn move_references(
+    reassigned: &Reassigned,
+    videos: &mut [Video],
+    history: &mut [WatchEntry],
+    subscriptions: &mut [Subscription],
+    notifications: &mut [Notification],
+) -> Vec<(Store, usize)> {
+    let mut moved = HashMap::new();
+    let mut point = |store: Store, target: Store, id: &mut u32, at: Option<u64>| {
+        if let Some(claims) = reassigned.get(&(target, *id)) {
+            let owner = claims.owner(*id, at);
+            if owner != *id {
+                *id = owner;
+                *moved.entry(store).or_insert(0) += 1;
+            }
+        }
+    };
+
+    for video in videos {
+        if let Some(owner) = &mut video.owner {
+            point(Store::Videos, Store::Users, owner, video.uploaded_at);
+        }
+        if let Some(series) = &mut video.series {
+            point(Store::Videos, Store::Series, series, video.uploaded_at);
+        }
+    }
+    for entry in history {
+        point(
+            Store::History,
+            Store::Videos,
+            &mut entry.video,
+            Some(entry.started_at),
+        );
+        if let Some(user) = &mut entry.user {
+            point(Store::History, Store::Users, user, Some(entry.started_at));
+        }
+    }
+    for subscription in subscriptions {
+        let at = subscription.created_at;
+        point(
+            Store::Subscriptions,
+            Store::Users,
+            &mut subscription.user,
+            at,
+        );
+        point(
+            Store::Subscriptions,
+            Store::Users,
+            &mut subscription.channel,
+            at,
+        );
+    }
+    for notification in notifications {
+        let at = notification.created_at;
+        point(
+            Store::Notifications,
+            Store::Users,
+            &mut notification.user,
+            at,
+        );
+        if let Some(video) = &mut notification.video {
+            point(Store::Notifications, Store::Videos, video, at);
+        }
+    }
+
+    let mut moved: Vec<(Store, usize)> = moved.into_iter().collect();
+    moved.sort_by_key(|(store, _)| Store::ALL.iter().position(|other| other == store));
+    moved
+}
+
+/// Moves the references to IDs shared by several records with `move_references`, saving every
+/// store changed
+fn move_stored_references(reassigned: &Reassigned) {
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let mut history: Vec<WatchEntry> = storage::load(Store::History);
+    let mut subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+    let mut notifications: Vec<Notification> = storage::load(Store::Notifications);
+
+    let moved = move_references(
+        reassigned,
+        &mut videos,
+        &mut history,
+        &mut subscriptions,
+        &mut notifications,
+    );
+    for (store, count) in moved {
+        info!(
+            "{}",
+            t!("fsck.moved", count = count, file = store.file_name())
+        );
+        match store {
+            Store::Videos => storage::save(store, &videos),
+            Store::History => storage::save(store, &history),
+            Store::Subscriptions => storage::save(store, &subscriptions),
+            Store::Notifications => storage::save(store, &notifications),
+            _ => unreachable!("Only stores that refer to other records are changed"),
+        }
+    }
+}
+
+/// Checks that every record only refers to records that exist
+///
+/// # Arguments
+///
+/// * `problems` - Where to report the problems found
+/// * `repair` - Whether to drop the references to records that don't exist, and save the stores
+fn check_references(problems: &mut Problems, repair: bool) {
+    let users: Vec<User> = storage::load(Store::Users);
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let genres: Vec<String> = storage::load(Store::Genres);
+    let series: Vec<Series> = storage::load(Store::Series);
+    let mut history: Vec<WatchEntry> = storage::load(Store::History);
+    let mut subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+    let mut playlists: Vec<Playlist> = storage::load(Store::Playlists);
+
+    let user_ids: HashSet<u32> = users.iter().map(|user| user.id).collect();
+    let video_ids: HashSet<u32> = videos.iter().map(|video| video.id).collect();
+    let series_ids: HashSet<u32> = series.iter().map(|series| series.id).collect();
+    let genres: HashSet<&String> = genres.iter().collect();
+
+    let mut videos_changed = false;
+    for video in &mut videos {
+        if let Some(owner) = video.owner.filter(|owner| !user_ids.contains(owner)) {
+            problems.report(
+                t!(
+                    "fsck.video_owner",
+                    id = video.id,
+                    name = video.name,
+                    owner = owner
+                ),
+                repair,
+            );
+            video.owner = None;
+            videos_changed = true;
+        }
+        if let Some(id) = video.series.filter(|id| !series_ids.contains(id)) {
+            problems.report(
+                t!(
+                    "fsck.video_series",
+                    id = video.id,
+                    name = video.name,
+                    series = id
+                ),
+                repair,
+            );
+            video.series = None;
+            video.season = None;
+            video.episode = None;
+            videos_changed = true;
+        }
+        let before = video.genres.len();
+        video.genres.retain(|genre| {
+            let exists = genres.contains(genre);
+            if !exists {
+                problems.report(
+                    t!(
+                        "fsck.video_genre",
+                        id = video.id,
+                        name = video.name,
+                        genre = genre
+                    ),
+                    repair,
+                );
+            }
+            exists
+        });
+        videos_changed |= video.genres.len() != before;
+    }
+
+    let mut history_changed = false;
+    history.retain_mut(|entry| {
+        if !video_ids.contains(&entry.video) {
+            problems.report(
+                t!(
+                    "fsck.view_video",
+                    started_at = entry.started_at,
+                    video = entry.video
+                ),
+                repair,
+            );
+            history_changed = true;
+            return false;
+        }
+        if let Some(user) = entry.user.filter(|user| !user_ids.contains(user)) {
+            problems.report(
+                t!(
+                    "fsck.view_user",
+                    video = entry.video,
+                    started_at = entry.started_at,
+                    user = user
+                ),
+                repair,
+            );
+            entry.user = None;
+            history_changed = true;
+        }
+        true
+    });
+
+    let before = subscriptions.len();
+    subscriptions.retain(|subscription| {
+        let missing = [subscription.user, subscription.channel]
+            .into_iter()
+            .find(|id| !user_ids.contains(id));
+        if let Some(id) = missing {
+            problems.report(
+                t!(
+                    "fsck.subscription",
+                    user = subscription.user,
+                    channel = subscription.channel,
+                    missing = id
+                ),
+                repair,
+            );
+        }
+        missing.is_none()
+    });
+    let subscriptions_changed = subscriptions.len() != before;
+
+    let before = playlists.len();
+    playlists.retain(|playlist| {
+        let exists = user_ids.contains(&playlist.owner);
+        if !exists {
+            problems.report(
+                t!(
+                    "fsck.playlist_owner",
+                    id = playlist.id,
+                    name = playlist.name,
+                    owner = playlist.owner
+                ),
+                repair,
+            );
+        }
+        exists
+    });
+    let mut playlists_changed = playlists.len() != before;
+    for playlist in &mut playlists {
+        let (followers, listed) = (playlist.followers.len(), playlist.videos.len());
+        playlist.followers.retain(|id| {
+            let exists = user_ids.contains(id);
+            if !exists {
+                problems.report(
+                    t!(
+                        "fsck.playlist_follower",
+                        id = playlist.id,
+                        name = playlist.name,
+                        user = id
+                    ),
+                    repair,
+                );
+            }
+            exists
+        });
+        playlist.videos.retain(|id| {
+            let exists = video_ids.contains(id);
+            if !exists {
+                problems.report(
+                    t!(
+                        "fsck.playlist_video",
+                        id = playlist.id,
+                        name = playlist.name,
+                        video = id
+                    ),
+                    repair,
+                );
+            }
+            exists
+        });
+        playlists_changed |=
+            playlist.followers.len() != followers || playlist.videos.len() != listed;
+    }
+
+    if !repair {
+        return;
+    }
+    if videos_changed {
+        storage::save(Store::Videos, &videos);
+    }
+    if history_changed {
+        storage::save(Store::History, &history);
+    }
+    if subscriptions_changed {
+        storage::save(Store::Subscriptions, &subscriptions);
+    }
+    if playlists_changed {
+        storage::save(Store::Playlists, &playlists);
+    }
+}
+
+/// Handles checking the integrity of every store: that its file matches its checksum and can be
+/// read, and that its records only refer to records that exist
+///
+/// Exits with a failure if problems are left unrepaired, so scripts can tell.
+///
+/// # Arguments
+///
+/// * `fsck` - The arguments for the check
+pub fn handle_fsck(fsck: Fsck) {
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("fsck.remote", remote = remote));
+        return;
+    }
+    if fsck.repair && !session::require_role(Role::Admin, &t!("role.repair_stores")) {
+        return;
+    }
+    if fsck.reassign_ids && !session::require_role(Role::Admin, &t!("role.reassign_ids")) {
+        return;
+    }
+
+    let mut problems = Problems::default();
+    let readable = match s3::config() {
+        Some(_) => {
+            debug!("Not checking files, as the stores are kept in a bucket");
+            true
+        }
+        None => check_files(&mut problems, fsck.repair),
+    };
+    let mut duplicates = 0;
+    if readable {
+        let mut reassigned = Reassigned::new();
+        duplicates = check_ids(&mut problems, fsck.reassign_ids, &mut reassigned);
+        if !reassigned.is_empty() {
+            move_stored_references(&reassigned);
+        }
+        check_references(&mut problems, fsck.repair);
+    } else {
+        error!("{}", t!("fsck.unreadable_stores"));
+    }
+
+    let left = problems.found - problems.repaired;
+    if problems.found == 0 {
+        info!("{}", t!("fsck.none_found"));
+    } else if left == 0 {
+        info!("{}", t!("fsck.repaired", count = problems.repaired));
+    } else {
+        if fsck.repair {
+            error!("{}", t!("fsck.left", left = left, found = problems.found));
+        } else {
+            error!("{}", t!("fsck.found", count = problems.found));
+        }
+        if duplicates > 0 && !fsck.reassign_ids {
+            error!("{}", t!("fsck.duplicates", count = duplicates));
+        }
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(video: u32, user: Option<u32>, started_at: u64) -> WatchEntry {
+        WatchEntry {
+            video,
+            user,
+            started_at,
+            seconds: 60,
+            finished: true,
+        }
+    }
+
+    #[test]
+    fn test_move_references() {
+        // Video 1 was uploaded at 100, and another video given ID 1 at 200 is now video 5.
+        // Likewise, user 2 was created at 50, and another user given ID 2 at 300 is now user 6
+        let reassigned = Reassigned::from([
+            (
+                (Store::Videos, 1),
+                Claims {
+                    first: Some(100),
+                    later: vec![(Some(200), 5)],
+                },
+            ),
+            (
+                (Store::Users, 2),
+                Claims {
+                    first: Some(50),
+                    later: vec![(Some(300), 6)],
+                },
+            ),
+        ]);
+        let mut videos = vec![
+            Video {
+                id: 1,
+                owner: Some(2),
+                uploaded_at: Some(100),
+                ..Default::default()
+            },
+            Video {
+                id: 5,
+                owner: Some(2),
+                uploaded_at: Some(400),
+                ..Default::default()
+            },
+        ];
+        let mut history = vec![
+            entry(1, Some(2), 150),
+            entry(1, Some(2), 350),
+            entry(1, None, 0),
+        ];
+        let mut subscriptions = vec![Subscription {
+            user: 2,
+            channel: 3,
+            created_at: None,
+        }];
+        let mut notifications = vec![Notification {
+            user: 2,
+            kind: Default::default(),
+            message: String::new(),
+            video: Some(1),
+            created_at: Some(250),
+            read: false,
+        }];
+
+        let moved = move_references(
+            &reassigned,
+            &mut videos,
+            &mut history,
+            &mut subscriptions,
+            &mut notifications,
+        );
+        assert_eq!(
+            moved,
+            [
+                (Store::Videos, 1),
+                (Store::History, 2),
+                (Store::Notifications, 1)
+            ]
+        );
+        assert_eq!(videos[0].owner, Some(2));
+        assert_eq!(videos[1].owner, Some(6));
+        assert_eq!(
+            history,
+            [
+                entry(1, Some(2), 150),
+                entry(5, Some(6), 350),
+                entry(1, None, 0)
+            ]
+        );
+        assert_eq!(subscriptions[0].user, 2);
+        assert_eq!(
+            (notifications[0].user, notifications[0].video),
+            (2, Some(5))
+        );
+    }
+}
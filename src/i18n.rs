@@ -0,0 +1,235 @@
+use log::warn;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+/// A language the messages of rustflix are translated into
+///
+/// # Variants
+///
+/// * `English` - The language messages are written in first, used for any message missing from
+///   another catalog
+/// * `Spanish` - Español
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    Spanish,
+}
+
+impl Locale {
+    /// Every locale with a catalog
+    const ALL: [Locale; 2] = [Locale::English, Locale::Spanish];
+
+    /// Finds the locale of a language tag, e.g. `es`, `es-MX`, or `es_ES.UTF-8` as `LANG` gives it
+    ///
+    /// # Returns
+    ///
+    /// The locale, or `None` if there is no catalog for the language
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::English),
+            "es" => Some(Locale::Spanish),
+            _ => None,
+        }
+    }
+
+    /// The catalog of messages in the language, as written in `locales/`
+    fn source(self) -> &'static str {
+        match self {
+            Locale::English => include_str!("../locales/en.toml"),
+            Locale::Spanish => include_str!("../locales/es.toml"),
+        }
+    }
+
+    /// The messages in the language by their dotted key, e.g. `prompt.invalid_input`, read the
+    /// first time they are needed
+    fn catalog(self) -> &'static HashMap<String, String> {
+        static CATALOGS: [OnceLock<HashMap<String, String>>; 2] =
+            [OnceLock::new(), OnceLock::new()];
+        let index = Locale::ALL
+            .iter()
+            .position(|locale| *locale == self)
+            .unwrap();
+        CATALOGS[index].get_or_init(|| {
+            let table: Table = toml::from_str(self.source()).expect("Invalid message catalog");
+            let mut messages = HashMap::new();
+            flatten(&table, "", &mut messages);
+            messages
+        })
+    }
+}
+
+/// Lists the messages in a catalog by their dotted keys
+fn flatten(table: &Table, prefix: &str, messages: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = match prefix {
+            "" => key.clone(),
+            prefix => format!("{}.{}", prefix, key),
+        };
+        match value {
+            Value::Table(table) => flatten(table, &key, messages),
+            Value::String(message) => {
+                messages.insert(key, message.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The language messages are printed in
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Chooses the language messages are printed in: the one in the config file, or else the one
+/// `LC_ALL`, `LC_MESSAGES`, or `LANG` gives, or else English
+///
+/// # Arguments
+///
+/// * `configured` - The `locale` setting of the config file, if given
+pub fn set_locale(configured: Option<&str>) {
+    let locale = match configured {
+        Some(tag) => Locale::from_tag(tag).unwrap_or_else(|| {
+            warn!("There are no messages in {}. Using English", tag);
+            Locale::English
+        }),
+        None => ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|tag| !tag.is_empty())
+            .and_then(|tag| Locale::from_tag(&tag))
+            .unwrap_or_default(),
+    };
+    LOCALE.set(locale).expect("Locale set twice");
+}
+
+/// The language messages are printed in
+pub fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+/// Looks up a message in the catalog of the language chosen, falling back to English, and fills
+/// in its placeholders. Use the `t!` macro instead of calling this directly
+///
+/// # Arguments
+///
+/// * `key` - The dotted key of the message, e.g. `user.created`
+/// * `args` - The values of the placeholders in the message, e.g. `{name}`, by name
+///
+/// # Returns
+///
+/// The message, or the key if no catalog has it
+pub fn message(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let Some(message) = locale()
+        .catalog()
+        .get(key)
+        .or_else(|| Locale::English.catalog().get(key))
+    else {
+        return key.to_string();
+    };
+    let mut message = message.clone();
+    for (name, value) in args {
+        message = message.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    message
+}
+
+/// Looks up a message in the catalog of the language chosen, filling in its placeholders, e.g.
+/// `t!("user.created")` or `t!("user.not_found_id", id = 5)`
+///
+/// Every message printed for the person running a command goes through here: prompts, labels,
+/// errors, warnings and results. Only what is meant for debugging or for other programs stays in
+/// English: debug logs, what a dry run would change, what `self-test` found wrong, the column
+/// names of tables, the status lines of `batch`, the responses of `serve` and the daemon, and the
+/// audit log and notifications as they are stored. Messages printed while the config file is
+/// read come out in English as well, as its `locale` setting isn't known yet.
+#[macro_export]
+macro_rules! t {
+    ($key:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message($key, &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),*])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The placeholders in a message, sorted
+    fn placeholders(message: &str) -> Vec<&str> {
+        let mut found: Vec<&str> = message
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}'))
+            .map(|(name, _)| name)
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn test_catalogs() {
+        let english = Locale::English.catalog();
+        for locale in Locale::ALL {
+            let catalog = locale.catalog();
+            for (key, message) in english {
+                let translated = catalog
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{:?} is missing {}", locale, key));
+                assert_eq!(placeholders(translated), placeholders(message), "{}", key);
+            }
+            assert_eq!(
+                catalog.len(),
+                english.len(),
+                "{:?} has unknown keys",
+                locale
+            );
+        }
+
+        assert_eq!(Locale::from_tag("es_ES.UTF-8"), Some(Locale::Spanish));
+        assert_eq!(Locale::from_tag("C"), Some(Locale::English));
+        assert_eq!(Locale::from_tag("fr"), None);
+        assert_eq!(t!("user.not_found_id", id = 5), "No user found with ID 5");
+        assert_eq!(t!("no.such.message"), "no.such.message");
+    }
+
+    /// Adds the sources of a directory and its subdirectories
+    fn sources(dir: &std::path::Path, found: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                sources(&path, found);
+            } else if path.extension().is_some_and(|extension| extension == "rs") {
+                found.push(std::fs::read_to_string(path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys_exist() {
+        let mut found = vec![];
+        sources(
+            &std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &mut found,
+        );
+        let english = Locale::English.catalog();
+        for source in &found {
+            for (start, _) in source.match_indices("t!(\"") {
+                // `format!(` ends the same way
+                let before = source[..start].chars().next_back().unwrap_or(' ');
+                if before.is_alphanumeric() || before == '_' {
+                    continue;
+                }
+                let key = source[start + 4..].split('"').next().unwrap();
+                if key != "no.such.message" {
+                    assert!(english.contains_key(key), "{} is not in the catalog", key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::args::command_types::user_subcommands::{self, User};
+use crate::args::command_types::video_subcommands::{self, Video};
+use crate::config;
+use crate::dry_run;
+use crate::s3;
+use crate::storage::{self, StorageBackend, Store};
+use crate::t;
+use crate::utilities;
+use clap::Args;
+use log::{error, info, warn};
+use std::fs;
+use std::path::PathBuf;
+use toml_edit::Item;
+
+/// The start of a config file written by `init`, before the settings chosen are added
+const STARTER_CONFIG: &str = "\
+# Settings for rustflix. Every setting in effect and where it came from is shown by
+# `rustflix config show --origin`, and settings are changed with `rustflix config set`.
+";
+
+/// The users added with `--demo`, by name and email address
+const DEMO_USERS: [(&str, &str); 2] = [
+    ("Ada Lovelace", "ada@example.com"),
+    ("Alan Turing", "alan@example.com"),
+];
+
+/// The videos added with `--demo`, by name, the index of the demo user owning them, and views
+const DEMO_VIDEOS: [(&str, usize, u32); 4] = [
+    ("Notes on the Analytical Engine", 0, 1843),
+    ("Bernoulli Numbers by Hand", 0, 312),
+    ("Computing Machinery and Intelligence", 1, 1950),
+    ("The Imitation Game, Explained", 1, 4096),
+];
+
+#[derive(Debug, Args)]
+pub struct Init {
+    /// The directory to keep the stores in. Asked for if not given, defaulting to ~/.rustflix
+    #[arg(long, default_value = None)]
+    pub data_dir: Option<PathBuf>,
+    /// Where to keep the stores. Asked for if not given, defaulting to files in the data directory
+    #[arg(long, value_enum, default_value = None)]
+    pub backend: Option<StorageBackend>,
+    /// Add a few users and videos to try commands on
+    #[arg(long, default_value_t = false)]
+    pub demo: bool,
+    /// Start the config file over instead of adding the settings chosen to it
+    #[arg(long, default_value_t = false)]
+    pub overwrite: bool,
+}
+
+/// Asks for the data directory, if it can
+///
+/// # Returns
+///
+/// The directory chosen, or the default if none was
+fn prompt_data_dir(default: PathBuf) -> PathBuf {
+    if !utilities::is_terminal() {
+        return default;
+    }
+    let label = t!("init.data_dir", default = default.display());
+    match utilities::prompt(&label, |_| Ok(())) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => default,
+    }
+}
+
+/// Asks for the backend, if it can
+///
+/// # Returns
+///
+/// The backend chosen, or `None` if the user cancelled
+fn prompt_backend() -> Option<StorageBackend> {
+    if !utilities::is_terminal() {
+        return Some(StorageBackend::Files);
+    }
+    let backends = [StorageBackend::Files, StorageBackend::S3];
+    let choice = utilities::choose(&t!("init.where"), &backends, Some(&t!("init.cancelled")))?;
+    Some(backends[choice])
+}
+
+/// Asks for the details of an S3-compatible bucket
+///
+/// # Returns
+///
+/// The `[s3]` section of the config file, or `None` if any detail could not be read
+fn prompt_s3() -> Option<toml_edit::Table> {
+    let required = |value: &str| match value.is_empty() {
+        true => Err(t!("init.required")),
+        false => Ok(()),
+    };
+    let mut s3 = toml_edit::Table::new();
+    for (key, label) in [
+        ("endpoint", t!("init.endpoint")),
+        ("bucket", t!("init.bucket")),
+        ("region", t!("init.region")),
+        ("access_key", t!("init.access_key")),
+    ] {
+        s3.insert(key, toml_edit::value(utilities::prompt(&label, required)?));
+    }
+    let secret_key = utilities::prompt_password(&t!("init.secret_key"))?;
+    s3.insert("secret_key", toml_edit::value(secret_key));
+    Some(s3)
+}
+
+/// Adds the demo users and videos, unless there are users already
+///
+/// # Returns
+///
+/// Whether the demo data was added
+fn seed_demo() -> bool {
+    let mut users: Vec<User> = storage::load(Store::Users);
+    if !users.is_empty() {
+        warn!("{}", t!("init.demo_skipped"));
+        return false;
+    }
+    let mut owners = vec![];
+    for (name, email) in DEMO_USERS {
+        let user = User {
+            id: user_subcommands::generate_valid_id(&users),
+            name: name.to_string(),
+            email: email.to_string(),
+            handle: Some(user_subcommands::suggest_handle(&users, name)),
+            ..Default::default()
+        };
+        owners.push(user.id);
+        users.push(user);
+    }
+    storage::save(Store::Users, &users);
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    for (name, owner, views) in DEMO_VIDEOS {
+        let video = Video {
+            id: video_subcommands::generate_valid_id(&videos),
+            name: name.to_string(),
+            views,
+            owner: Some(owners[owner]),
+            uploaded_at: Some(video_subcommands::now()),
+            ..Default::default()
+        };
+        videos.push(video);
+    }
+    storage::save(Store::Videos, &videos);
+    true
+}
+
+/// Handles setting rustflix up for the first time: creating the data directory, writing the
+/// settings chosen to the config file, optionally adding demo data, and printing what to do next
+///
+/// # Arguments
+///
+/// * `init` - The arguments for the setup
+pub fn handle_init(init: Init) {
+    let data_dir = match init.data_dir {
+        Some(dir) => dir,
+        None => prompt_data_dir(storage::data_dir().to_path_buf()),
+    };
+    let Some(backend) = init.backend.or_else(prompt_backend) else {
+        return;
+    };
+    let s3 = match backend {
+        StorageBackend::Files => None,
+        StorageBackend::S3 if !utilities::is_terminal() => {
+            error!("{}", t!("init.s3_needs_terminal"));
+            return;
+        }
+        StorageBackend::S3 => match prompt_s3() {
+            Some(s3) => Some(s3),
+            None => return,
+        },
+    };
+
+    if !dry_run::skip(&format!("create {}", data_dir.display())) {
+        if let Err(e) = fs::create_dir_all(&data_dir) {
+            error!(
+                "{}",
+                t!(
+                    "init.could_not_create",
+                    path = data_dir.display(),
+                    error = e
+                )
+            );
+            return;
+        }
+    }
+    info!("{}", t!("init.data_dir_set", path = data_dir.display()));
+
+    let path = config::config_path();
+    // A dry run only shows the settings that would be written
+    let fresh = (init.overwrite || !path.exists()) && !storage::dry_run();
+    if fresh && path.exists() {
+        if let Err(e) = fs::write(&path, "") {
+            error!(
+                "{}",
+                t!("init.could_not_write", path = path.display(), error = e)
+            );
+            return;
+        }
+    }
+    // The s3 section goes first, as the backend can't be set to s3 without it
+    if let Some(s3) = s3 {
+        if !config::edit_config_file("s3", Some(Item::Table(s3))) {
+            return;
+        }
+    }
+    let backend_name = match backend {
+        StorageBackend::Files => "files",
+        StorageBackend::S3 => "s3",
+    };
+    if !config::edit_config_file("storage.backend", Some(toml_edit::value(backend_name))) {
+        return;
+    }
+    if data_dir != utilities::home_dir().join(".rustflix") {
+        let data_dir = data_dir.display().to_string();
+        if !config::edit_config_file("storage.data_dir", Some(toml_edit::value(data_dir))) {
+            return;
+        }
+    }
+    if fresh {
+        let settings = fs::read_to_string(&path).unwrap_or_default();
+        if let Err(e) = fs::write(&path, format!("{}\n{}", STARTER_CONFIG, settings)) {
+            error!(
+                "{}",
+                t!("init.could_not_write", path = path.display(), error = e)
+            );
+            return;
+        }
+    }
+    info!("{}", t!("init.settings_written", path = path.display()));
+
+    let demo = init.demo
+        || (utilities::is_terminal()
+            && utilities::confirm(
+                &t!("init.confirm_demo"),
+                None,
+                None,
+                Some(false),
+                utilities::prompt_timeout(),
+            ));
+    if demo {
+        if backend == StorageBackend::S3 || s3::config().is_some() {
+            warn!("{}", t!("init.demo_needs_files"));
+        } else {
+            storage::set_data_dir(data_dir);
+            if seed_demo() {
+                info!(
+                    "{}",
+                    t!(
+                        "init.demo_added",
+                        users = DEMO_USERS.len(),
+                        videos = DEMO_VIDEOS.len()
+                    )
+                );
+            }
+        }
+    }
+
+    println!();
+    println!("{}", t!("init.next_steps"));
+    for (command, step) in [
+        ("rustflix user create <name> <email>", t!("init.step_user")),
+        (
+            "rustflix video create <name> --owner <id>",
+            t!("init.step_video"),
+        ),
+        ("rustflix video list --all", t!("init.step_list")),
+        ("rustflix config show --origin", t!("init.step_config")),
+    ] {
+        println!("  {:<43}{}", command, step);
+    }
+}
@@ -0,0 +1,702 @@
+use crate::args::command_types::playlist_subcommands::Playlist;
+use crate::args::command_types::user_subcommands::Subscription;
+use crate::args::command_types::video_subcommands::Video;
+use crate::args::command_types::view_subcommands::WatchEntry;
+use crate::notifications::Notification;
+use crate::storage::{self, Store};
+use crate::t;
+use clap::Args;
+use log::warn;
+use std::collections::HashSet;
+
+/// A record other records may refer to
+///
+/// # Variants
+///
+/// * `User` - The user with the given ID, which videos, watch history entries, subscriptions, and
+///   the playlists they made or follow refer to
+/// * `Video` - The video with the given ID, which watch history entries and playlists refer to
+/// * `Series` - The series with the given ID, which its episodes refer to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    User(u32),
+    Video(u32),
+    Series(u32),
+}
+
+/// What deleting a record does with the records referring to it
+///
+/// # Variants
+///
+/// * `Refuse` - Nothing is deleted while any record refers to it
+/// * `Cascade` - The records referring to it are deleted too, along with the watch history of any
+///   video deleted that way. Playlists are only deleted along with the user who made them, and
+///   otherwise lose the videos deleted
+/// * `Orphan` - The records referring to it are kept, unlinked from it where they can be, with a
+///   warning for those left referring to nothing
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Policy {
+    #[default]
+    Refuse,
+    Cascade,
+    Orphan,
+}
+
+#[derive(Debug, Args)]
+pub struct OnDelete {
+    /// Also delete the records referring to it
+    #[arg(long, default_value_t = false, conflicts_with = "orphan")]
+    pub cascade: bool,
+    /// Keep the records referring to it, unlinking them from it where they can be
+    #[arg(long, default_value_t = false)]
+    pub orphan: bool,
+}
+
+impl OnDelete {
+    /// The policy chosen with `--cascade` or `--orphan`
+    pub fn policy(&self) -> Policy {
+        match (self.cascade, self.orphan) {
+            (true, _) => Policy::Cascade,
+            (_, true) => Policy::Orphan,
+            _ => Policy::Refuse,
+        }
+    }
+}
+
+/// A record deleting a target affects, told apart from the others so a record affected by deleting
+/// several targets at once is counted once
+///
+/// # Variants
+///
+/// * `Target` - The target itself
+/// * `Video` - The video with the given ID
+/// * `Entry` - The watch history entry at the given position
+/// * `Subscription` - The subscription at the given position
+/// * `Playlist` - The playlist with the given ID
+/// * `Follow` - The given user following the playlist with the given ID
+/// * `Listing` - The video at the given position of the playlist with the given ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Affected {
+    Target(Target),
+    Video(u32),
+    Entry(usize),
+    Subscription(usize),
+    Playlist(u32),
+    Follow(u32, u32),
+    Listing(u32, usize),
+}
+
+/// Counts the records affected by deleting several targets. The follows and videos of a playlist
+/// deleted along with one of them aren't counted apart from it
+pub fn count_affected(affected: &HashSet<Affected>) -> usize {
+    let playlists: HashSet<u32> = affected
+        .iter()
+        .filter_map(|record| match record {
+            Affected::Playlist(id) => Some(*id),
+            _ => None,
+        })
+        .collect();
+    affected
+        .iter()
+        .filter(|record| match record {
+            Affected::Follow(playlist, _) | Affected::Listing(playlist, _) => {
+                !playlists.contains(playlist)
+            }
+            _ => true,
+        })
+        .count()
+}
+
+/// The stores that may refer to a record about to be deleted, loaded so the references can be
+/// counted and resolved before anything is written
+///
+/// # Fields
+///
+/// * `target` - The record about to be deleted
+/// * `videos` - Every video, unless the target is a video
+/// * `history` - Every watch history entry, which refers to users and videos, and to series
+///   through their episodes
+/// * `subscriptions` - Every subscription, if the target is a user
+/// * `playlists` - Every playlist, which refers to users and videos, and to series through their
+///   episodes
+/// * `notifications` - Every notification, if the target is a user. Only moved by a merge, as they
+///   are not counted as referring to the user
+pub struct References {
+    target: Target,
+    videos: Vec<Video>,
+    history: Vec<WatchEntry>,
+    subscriptions: Vec<Subscription>,
+    playlists: Vec<Playlist>,
+    notifications: Vec<Notification>,
+}
+
+impl References {
+    /// Loads every store that may refer to the given record
+    pub fn find(target: Target) -> Self {
+        References {
+            target,
+            videos: match target {
+                Target::Video(_) => vec![],
+                _ => storage::load(Store::Videos),
+            },
+            history: storage::load(Store::History),
+            subscriptions: match target {
+                Target::User(_) => storage::load(Store::Subscriptions),
+                _ => vec![],
+            },
+            playlists: storage::load(Store::Playlists),
+            notifications: match target {
+                Target::User(_) => storage::load(Store::Notifications),
+                _ => vec![],
+            },
+        }
+    }
+
+    fn refers_video(&self, video: &Video) -> bool {
+        match self.target {
+            Target::User(id) => video.owner == Some(id),
+            Target::Series(id) => video.series == Some(id),
+            Target::Video(_) => false,
+        }
+    }
+
+    fn refers_entry(&self, entry: &WatchEntry) -> bool {
+        match self.target {
+            Target::User(id) => entry.user == Some(id),
+            Target::Video(id) => entry.video == id,
+            Target::Series(_) => false,
+        }
+    }
+
+    fn refers_subscription(&self, subscription: &Subscription) -> bool {
+        match self.target {
+            Target::User(id) => subscription.user == id || subscription.channel == id,
+            _ => false,
+        }
+    }
+
+    /// How many playlists the target made, how many it follows, and how many times it was added to
+    /// one
+    fn playlist_counts(&self) -> (usize, usize, usize) {
+        let (mut made, mut followed, mut entries) = (0, 0, 0);
+        for playlist in &self.playlists {
+            match self.target {
+                Target::User(id) => {
+                    made += usize::from(playlist.owner == id);
+                    followed += playlist.followers.iter().filter(|f| **f == id).count();
+                }
+                Target::Video(id) => {
+                    entries += playlist.videos.iter().filter(|v| **v == id).count();
+                }
+                Target::Series(_) => {}
+            }
+        }
+        (made, followed, entries)
+    }
+
+    /// How many records of each kind refer to the target, leaving out kinds with none
+    ///
+    /// # Returns
+    ///
+    /// The name of each kind, in the plural unless there is one, along with the count
+    pub fn counts(&self) -> Vec<(String, usize)> {
+        let (made, followed, entries) = self.playlist_counts();
+        [
+            (
+                (t!("integrity.video"), t!("integrity.videos")),
+                self.videos.iter().filter(|v| self.refers_video(v)).count(),
+            ),
+            (
+                (t!("integrity.entry"), t!("integrity.entries")),
+                self.history.iter().filter(|e| self.refers_entry(e)).count(),
+            ),
+            (
+                (t!("integrity.subscription"), t!("integrity.subscriptions")),
+                self.subscriptions
+                    .iter()
+                    .filter(|s| self.refers_subscription(s))
+                    .count(),
+            ),
+            ((t!("integrity.playlist"), t!("integrity.playlists")), made),
+            ((t!("integrity.follow"), t!("integrity.follows")), followed),
+            ((t!("integrity.listing"), t!("integrity.listings")), entries),
+        ]
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|((one, many), count)| (if count == 1 { one } else { many }, count))
+        .collect()
+    }
+
+    /// Whether no record refers to the target
+    pub fn is_empty(&self) -> bool {
+        self.counts().is_empty()
+    }
+
+    /// Describes the records referring to the target, e.g. `2 videos and 1 subscription`
+    pub fn describe(&self) -> String {
+        let counts: Vec<String> = self
+            .counts()
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        match counts.split_last() {
+            None => t!("integrity.nothing"),
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => t!("integrity.and", rest = rest.join(", "), last = last),
+        }
+    }
+
+    /// The records deleting the target affects following the policy, including the target
+    pub fn affected_records(&self, policy: Policy) -> HashSet<Affected> {
+        let mut affected = HashSet::from([Affected::Target(self.target)]);
+        let referring: Vec<&Video> = self
+            .videos
+            .iter()
+            .filter(|video| self.refers_video(video))
+            .collect();
+        affected.extend(referring.iter().map(|video| Affected::Video(video.id)));
+        let deleted: HashSet<u32> = match policy {
+            Policy::Cascade => referring.iter().map(|video| video.id).collect(),
+            _ => HashSet::new(),
+        };
+
+        for (index, entry) in self.history.iter().enumerate() {
+            if self.refers_entry(entry) || deleted.contains(&entry.video) {
+                affected.insert(Affected::Entry(index));
+            }
+        }
+        for (index, subscription) in self.subscriptions.iter().enumerate() {
+            if self.refers_subscription(subscription) {
+                affected.insert(Affected::Subscription(index));
+            }
+        }
+        for playlist in &self.playlists {
+            if let Target::User(id) = self.target {
+                if playlist.owner == id {
+                    affected.insert(Affected::Playlist(playlist.id));
+                    continue;
+                }
+                if playlist.followers.contains(&id) {
+                    affected.insert(Affected::Follow(playlist.id, id));
+                }
+            }
+            for (position, video) in playlist.videos.iter().enumerate() {
+                if self.target == Target::Video(*video) || deleted.contains(video) {
+                    affected.insert(Affected::Listing(playlist.id, position));
+                }
+            }
+        }
+        affected
+    }
+
+    /// How many records deleting the target affects following the policy, counting the target
+    pub fn affected(&self, policy: Policy) -> usize {
+        count_affected(&self.affected_records(policy))
+    }
+
+    /// Describes what deleting the target does to the records referring to it, to be confirmed
+    /// before deleting it
+    pub fn summary(&self, policy: Policy) -> String {
+        match policy {
+            _ if self.is_empty() => t!("integrity.unreferenced"),
+            Policy::Cascade => t!("integrity.cascade", references = self.describe()),
+            _ => t!("integrity.orphan", references = self.describe()),
+        }
+    }
+
+    /// Deletes or unlinks the records referring to the target in memory, following the policy
+    ///
+    /// # Returns
+    ///
+    /// The stores changed
+    fn apply(&mut self, policy: Policy) -> Vec<Store> {
+        let mut changed = vec![];
+        match policy {
+            Policy::Refuse => {}
+            Policy::Cascade => {
+                let deleted: HashSet<u32> = self
+                    .videos
+                    .iter()
+                    .filter(|video| self.refers_video(video))
+                    .map(|video| video.id)
+                    .collect();
+                let videos = std::mem::take(&mut self.videos);
+                self.videos = videos
+                    .into_iter()
+                    .filter(|video| !deleted.contains(&video.id))
+                    .collect();
+                let history = std::mem::take(&mut self.history);
+                let before = history.len();
+                self.history = history
+                    .into_iter()
+                    .filter(|entry| !self.refers_entry(entry) && !deleted.contains(&entry.video))
+                    .collect();
+                let subscriptions = std::mem::take(&mut self.subscriptions);
+                let subscribed = subscriptions.len();
+                self.subscriptions = subscriptions
+                    .into_iter()
+                    .filter(|subscription| !self.refers_subscription(subscription))
+                    .collect();
+                let target = self.target;
+                let playlists = std::mem::take(&mut self.playlists);
+                let mut playlists_changed = false;
+                self.playlists = playlists
+                    .into_iter()
+                    .filter(|playlist| {
+                        let made = target == Target::User(playlist.owner);
+                        playlists_changed |= made;
+                        !made
+                    })
+                    .collect();
+                for playlist in &mut self.playlists {
+                    playlists_changed |= unlink_playlist(target, playlist, &deleted);
+                }
+
+                if !deleted.is_empty() {
+                    changed.push(Store::Videos);
+                }
+                if self.history.len() != before {
+                    changed.push(Store::History);
+                }
+                if self.subscriptions.len() != subscribed {
+                    changed.push(Store::Subscriptions);
+                }
+                if playlists_changed {
+                    changed.push(Store::Playlists);
+                }
+            }
+            Policy::Orphan => {
+                let target = self.target;
+                for video in &mut self.videos {
+                    match target {
+                        Target::User(id) if video.owner == Some(id) => video.owner = None,
+                        Target::Series(id) if video.series == Some(id) => {
+                            video.series = None;
+                            video.season = None;
+                            video.episode = None;
+                        }
+                        _ => continue,
+                    }
+                    if !changed.contains(&Store::Videos) {
+                        changed.push(Store::Videos);
+                    }
+                }
+                if let Target::User(id) = target {
+                    for entry in &mut self.history {
+                        if entry.user == Some(id) {
+                            entry.user = None;
+                            if !changed.contains(&Store::History) {
+                                changed.push(Store::History);
+                            }
+                        }
+                    }
+                }
+                for playlist in &mut self.playlists {
+                    if unlink_playlist(target, playlist, &HashSet::new())
+                        && !changed.contains(&Store::Playlists)
+                    {
+                        changed.push(Store::Playlists);
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// How many notifications the target user has, which a merge moves along with the records
+    /// referring to them
+    pub fn notification_count(&self) -> usize {
+        match self.target {
+            Target::User(id) => self.notifications.iter().filter(|n| n.user == id).count(),
+            _ => 0,
+        }
+    }
+
+    /// Points every record referring to the target user at another user instead, in memory.
+    /// Subscriptions and follows both users had are kept once, and a user can't end up subscribed
+    /// to themselves or following their own playlist
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - The ID of the user to point them at
+    ///
+    /// # Returns
+    ///
+    /// The stores changed
+    fn reassign(&mut self, to: u32) -> Vec<Store> {
+        let Target::User(from) = self.target else {
+            return vec![];
+        };
+        let moved = |id: &mut u32| {
+            let matches = *id == from;
+            if matches {
+                *id = to;
+            }
+            matches
+        };
+        let mut changed = vec![];
+        let mut videos_changed = false;
+        for video in &mut self.videos {
+            if let Some(owner) = &mut video.owner {
+                videos_changed |= moved(owner);
+            }
+        }
+        let mut history_changed = false;
+        for entry in &mut self.history {
+            if let Some(user) = &mut entry.user {
+                history_changed |= moved(user);
+            }
+        }
+        let mut subscriptions_changed = false;
+        for subscription in &mut self.subscriptions {
+            subscriptions_changed |=
+                moved(&mut subscription.user) | moved(&mut subscription.channel);
+        }
+        let mut seen = vec![];
+        self.subscriptions.retain(|subscription| {
+            let keep = subscription.user != subscription.channel && !seen.contains(subscription);
+            seen.push(subscription.clone());
+            keep
+        });
+        let mut playlists_changed = false;
+        for playlist in &mut self.playlists {
+            playlists_changed |= moved(&mut playlist.owner);
+            for follower in &mut playlist.followers {
+                playlists_changed |= moved(follower);
+            }
+            let owner = playlist.owner;
+            let mut seen = HashSet::new();
+            playlist
+                .followers
+                .retain(|follower| *follower != owner && seen.insert(*follower));
+        }
+        let mut notifications_changed = false;
+        for notification in &mut self.notifications {
+            notifications_changed |= moved(&mut notification.user);
+        }
+
+        for (store, store_changed) in [
+            (Store::Videos, videos_changed),
+            (Store::History, history_changed),
+            (Store::Subscriptions, subscriptions_changed),
+            (Store::Playlists, playlists_changed),
+            (Store::Notifications, notifications_changed),
+        ] {
+            if store_changed {
+                changed.push(store);
+            }
+        }
+        changed
+    }
+
+    /// Points every record referring to the target user at another user instead, writing every
+    /// store changed
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - The ID of the user to point them at
+    pub fn merge_into(mut self, to: u32) {
+        let changed = self.reassign(to);
+        self.save(changed);
+    }
+
+    fn save(&self, changed: Vec<Store>) {
+        for store in changed {
+            match store {
+                Store::Videos => storage::save(store, &self.videos),
+                Store::History => storage::save(store, &self.history),
+                Store::Subscriptions => storage::save(store, &self.subscriptions),
+                Store::Playlists => storage::save(store, &self.playlists),
+                Store::Notifications => storage::save(store, &self.notifications),
+                _ => unreachable!("Only stores that refer to other records are changed"),
+            }
+        }
+    }
+
+    /// Deletes or unlinks the records referring to the target following the policy, writing every
+    /// store changed. Records left referring to nothing are warned about
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - What to do with the records referring to the target. Must not be `Refuse`
+    ///   unless nothing refers to it
+    pub fn resolve(mut self, policy: Policy) {
+        let changed = self.apply(policy);
+        self.save(changed);
+        if policy == Policy::Orphan && !self.is_empty() {
+            warn!("{}", t!("storage.orphaned", references = self.describe()));
+        }
+    }
+}
+
+/// Unlinks a playlist from a record about to be deleted: a user stops following it, and videos are
+/// taken out of it. A playlist can't be unlinked from the user who made it
+///
+/// # Arguments
+///
+/// * `target` - The record about to be deleted
+/// * `playlist` - The playlist
+/// * `deleted` - The IDs of other videos deleted along with the target
+///
+/// # Returns
+///
+/// Whether the playlist changed
+fn unlink_playlist(target: Target, playlist: &mut Playlist, deleted: &HashSet<u32>) -> bool {
+    let (followers, videos) = (playlist.followers.len(), playlist.videos.len());
+    if let Target::User(id) = target {
+        playlist.followers.retain(|follower| *follower != id);
+    }
+    playlist
+        .videos
+        .retain(|video| target != Target::Video(*video) && !deleted.contains(video));
+    playlist.followers.len() != followers || playlist.videos.len() != videos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(video: u32, user: Option<u32>) -> WatchEntry {
+        WatchEntry {
+            video,
+            user,
+            started_at: 0,
+            seconds: 60,
+            finished: true,
+        }
+    }
+
+    fn references(target: Target) -> References {
+        References {
+            target,
+            videos: vec![
+                Video {
+                    id: 1,
+                    owner: Some(7),
+                    ..Default::default()
+                },
+                Video {
+                    id: 2,
+                    ..Default::default()
+                },
+            ],
+            history: vec![entry(1, None), entry(2, Some(7)), entry(2, None)],
+            subscriptions: vec![Subscription {
+                user: 8,
+                channel: 7,
+                created_at: None,
+            }],
+            playlists: vec![Playlist {
+                id: 1,
+                owner: 8,
+                videos: vec![1, 2],
+                followers: vec![7],
+                ..Default::default()
+            }],
+            notifications: [7, 8]
+                .map(|user| Notification {
+                    user,
+                    kind: Default::default(),
+                    message: String::new(),
+                    video: None,
+                    created_at: None,
+                    read: false,
+                })
+                .to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let mut user = references(Target::User(7));
+        assert_eq!(
+            user.describe(),
+            "1 video, 1 watch history entry, 1 subscription and 1 playlist follow"
+        );
+        assert_eq!(user.affected(Policy::Orphan), 5);
+        assert_eq!(user.affected(Policy::Cascade), 7);
+        assert_eq!(
+            user.apply(Policy::Cascade),
+            [
+                Store::Videos,
+                Store::History,
+                Store::Subscriptions,
+                Store::Playlists
+            ]
+        );
+        assert_eq!(user.videos.len(), 1);
+        assert_eq!(user.history, [entry(2, None)]);
+        assert!(user.subscriptions.is_empty());
+        assert_eq!(user.playlists[0].videos, [2]);
+        assert!(user.playlists[0].followers.is_empty());
+
+        let mut user = references(Target::User(7));
+        assert_eq!(
+            user.apply(Policy::Orphan),
+            [Store::Videos, Store::History, Store::Playlists]
+        );
+        assert_eq!(user.videos[0].owner, None);
+        assert_eq!(user.history[1].user, None);
+        assert_eq!(user.describe(), "1 subscription");
+
+        let mut owner = references(Target::User(8));
+        assert_eq!(owner.describe(), "1 subscription and 1 playlist");
+        owner.apply(Policy::Cascade);
+        assert!(owner.playlists.is_empty());
+
+        let mut video = references(Target::Video(2));
+        assert_eq!(
+            video.counts(),
+            [
+                ("watch history entries".to_string(), 2),
+                ("playlist entry".to_string(), 1)
+            ]
+        );
+        assert_eq!(video.apply(Policy::Orphan), [Store::Playlists]);
+        assert_eq!(video.playlists[0].videos, [1]);
+        assert!(!references(Target::Video(3))
+            .apply(Policy::Cascade)
+            .contains(&Store::History));
+
+        let mut series = references(Target::Series(4));
+        series.videos[0].series = Some(4);
+        assert_eq!(series.describe(), "1 video");
+        assert_eq!(series.affected(Policy::Orphan), 2);
+        assert_eq!(series.affected(Policy::Cascade), 4);
+        assert_eq!(
+            series.apply(Policy::Cascade),
+            [Store::Videos, Store::History, Store::Playlists]
+        );
+        assert_eq!(series.history, [entry(2, Some(7)), entry(2, None)]);
+
+        // Deleting both users counts their subscription once, and the playlist of one of them
+        // along with the follow and video the other gives it
+        let mut both = references(Target::User(7)).affected_records(Policy::Cascade);
+        both.extend(references(Target::User(8)).affected_records(Policy::Cascade));
+        assert_eq!(count_affected(&both), 7);
+    }
+
+    #[test]
+    fn test_reassign_references() {
+        let mut user = references(Target::User(7));
+        assert_eq!(user.notification_count(), 1);
+        assert_eq!(
+            user.reassign(8),
+            [
+                Store::Videos,
+                Store::History,
+                Store::Subscriptions,
+                Store::Playlists,
+                Store::Notifications
+            ]
+        );
+        assert_eq!(user.videos[0].owner, Some(8));
+        assert_eq!(user.history[1].user, Some(8));
+        assert!(user.subscriptions.is_empty());
+        assert!(user.playlists[0].followers.is_empty());
+        assert!(user.notifications.iter().all(|n| n.user == 8));
+        assert!(user.counts().is_empty());
+
+        assert!(references(Target::Video(1)).reassign(8).is_empty());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::args::command_types::user_subcommands::{self, Role, User};
+use crate::args::command_types::video_subcommands::{self, Video};
+use crate::args::command_types::view_subcommands::WatchEntry;
+use crate::dry_run;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use clap::Args;
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// How many Jellyfin ticks make a second
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// The kinds of Jellyfin items that become videos
+const ITEM_TYPES: &str = "Movie,Episode,Video,MusicVideo";
+
+#[derive(Debug, Args)]
+pub struct JellyfinServer {
+    /// The URL of the Jellyfin (or Emby) server, e.g. `http://media:8096`
+    #[arg(long)]
+    pub url: String,
+    /// An API key made in the dashboard of the server
+    #[arg(long)]
+    pub api_key: String,
+}
+
+/// A user of a Jellyfin server
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct JellyfinUser {
+    id: String,
+    name: String,
+}
+
+/// The items of a Jellyfin library, as seen by one user
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct JellyfinItems {
+    items: Vec<JellyfinItem>,
+}
+
+/// A movie, episode, or other video in a Jellyfin library
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct JellyfinItem {
+    id: String,
+    name: String,
+    #[serde(default)]
+    series_name: Option<String>,
+    #[serde(default)]
+    overview: Option<String>,
+    #[serde(default)]
+    run_time_ticks: Option<u64>,
+    #[serde(default)]
+    user_data: Option<JellyfinUserData>,
+}
+
+impl JellyfinItem {
+    /// The name the item has as a rustflix video. Episodes are prefixed with their series, as
+    /// episode names are rarely unique
+    fn video_name(&self) -> String {
+        match &self.series_name {
+            Some(series) => format!("{}: {}", series, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// What a user did with an item
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct JellyfinUserData {
+    #[serde(default)]
+    play_count: u32,
+}
+
+/// Talks to the API of a Jellyfin server
+struct Client<'a> {
+    url: &'a str,
+    api_key: &'a str,
+}
+
+impl Client<'_> {
+    /// Fetches a path of the API
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let url = format!("{}{}", self.url.trim_end_matches('/'), path);
+        debug!("Fetching {}", url);
+        ureq::get(&url)
+            .set("X-Emby-Token", self.api_key)
+            .call()
+            .map_err(|e| request_error(&url, e))?
+            .into_json()
+            .map_err(|e| t!("jellyfin.invalid_response", url = url, error = e))
+    }
+
+    /// Posts to a path of the API, ignoring the response
+    fn post(&self, path: &str) -> Result<(), String> {
+        let url = format!("{}{}", self.url.trim_end_matches('/'), path);
+        debug!("Posting to {}", url);
+        ureq::post(&url)
+            .set("X-Emby-Token", self.api_key)
+            .call()
+            .map(|_| ())
+            .map_err(|e| request_error(&url, e))
+    }
+
+    fn users(&self) -> Result<Vec<JellyfinUser>, String> {
+        self.get("/Users")
+    }
+
+    fn items(&self, user: &JellyfinUser) -> Result<Vec<JellyfinItem>, String> {
+        let items: JellyfinItems = self.get(&format!(
+            "/Users/{}/Items?Recursive=true&IncludeItemTypes={}&Fields=Overview",
+            user.id, ITEM_TYPES
+        ))?;
+        Ok(items.items)
+    }
+}
+
+/// Describes a failed request
+fn request_error(url: &str, e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, response) => t!(
+            "jellyfin.responded_with",
+            url = url,
+            code = code,
+            body = response.into_string().unwrap_or_default()
+        ),
+        ureq::Error::Transport(e) => t!("jellyfin.unreachable", error = e),
+    }
+}
+
+/// Handles importing the users and videos of a Jellyfin server, setting the views of every video
+/// to the number of times it was played there by anyone
+///
+/// Users and videos are matched by name, so importing again only updates view counts. Users made
+/// from Jellyfin users have no email address, which can be set later with `user update`.
+///
+/// # Arguments
+///
+/// * `server` - The server to import from
+pub fn handle_import_jellyfin(server: JellyfinServer) {
+    if !session::require_role(Role::Admin, &t!("role.import_from_jellyfin")) {
+        return;
+    }
+
+    let client = Client {
+        url: &server.url,
+        api_key: &server.api_key,
+    };
+
+    let jellyfin_users = match client.users() {
+        Ok(users) => users,
+        Err(e) => {
+            error!("{}", t!("jellyfin.import_failed", error = e));
+            return;
+        }
+    };
+
+    let mut users: Vec<User> = storage::load(Store::Users);
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let mut created_users = 0;
+    let mut created_videos = 0;
+    let mut updated_videos = 0;
+    // The total play count of every item, along with the item itself
+    let mut plays: HashMap<String, (JellyfinItem, u32)> = HashMap::new();
+
+    for jellyfin_user in &jellyfin_users {
+        if !users.iter().any(|user| user.name == jellyfin_user.name) {
+            users.push(User {
+                id: user_subcommands::generate_valid_id(&users),
+                name: jellyfin_user.name.clone(),
+                handle: Some(user_subcommands::suggest_handle(
+                    &users,
+                    &jellyfin_user.name,
+                )),
+                ..Default::default()
+            });
+            created_users += 1;
+        }
+
+        let items = match client.items(jellyfin_user) {
+            Ok(items) => items,
+            Err(e) => {
+                error!("{}", t!("jellyfin.import_failed", error = e));
+                return;
+            }
+        };
+        for item in items {
+            let play_count = item.user_data.as_ref().map_or(0, |data| data.play_count);
+            plays.entry(item.id.clone()).or_insert((item, 0)).1 += play_count;
+        }
+    }
+
+    for (item, play_count) in plays.into_values() {
+        let name = item.video_name();
+        match videos.iter_mut().find(|video| video.name == name) {
+            Some(video) => {
+                if video.views != play_count {
+                    debug!("{} now has {} views", name, play_count);
+                    video.views = play_count;
+                    updated_videos += 1;
+                }
+            }
+            None => {
+                videos.push(Video {
+                    id: video_subcommands::generate_valid_id(&videos),
+                    name,
+                    views: play_count,
+                    description: item.overview,
+                    duration: item
+                        .run_time_ticks
+                        .map(|ticks| (ticks / TICKS_PER_SECOND) as u32),
+                    ..Default::default()
+                });
+                created_videos += 1;
+            }
+        }
+    }
+
+    storage::save(Store::Users, &users);
+    storage::save(Store::Videos, &videos);
+
+    info!(
+        "{}",
+        t!(
+            "jellyfin.imported",
+            users = created_users,
+            videos = created_videos,
+            views = updated_videos,
+            url = server.url
+        )
+    );
+}
+
+/// Handles marking every video in the watch history as played on a Jellyfin server, for the
+/// Jellyfin user of the same name
+///
+/// Videos and users without a match of the same name on the server are skipped.
+///
+/// # Arguments
+///
+/// * `server` - The server to export to
+pub fn handle_export_jellyfin(server: JellyfinServer) {
+    let client = Client {
+        url: &server.url,
+        api_key: &server.api_key,
+    };
+
+    let jellyfin_users = match client.users() {
+        Ok(users) => users,
+        Err(e) => {
+            error!("{}", t!("jellyfin.export_failed", error = e));
+            return;
+        }
+    };
+
+    let users: Vec<User> = storage::load(Store::Users);
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    let history: Vec<WatchEntry> = storage::load(Store::History);
+
+    let mut marked = 0;
+    for jellyfin_user in &jellyfin_users {
+        let Some(user) = users.iter().find(|user| user.name == jellyfin_user.name) else {
+            debug!("{} is not a rustflix user", jellyfin_user.name);
+            continue;
+        };
+        let items = match client.items(jellyfin_user) {
+            Ok(items) => items,
+            Err(e) => {
+                error!("{}", t!("jellyfin.export_failed", error = e));
+                return;
+            }
+        };
+
+        for item in items {
+            let name = item.video_name();
+            let Some(video) = videos.iter().find(|video| video.name == name) else {
+                continue;
+            };
+            let watched = history.iter().any(|entry| {
+                entry.video == video.id && entry.user == Some(user.id) && entry.finished
+            });
+            let played = item
+                .user_data
+                .as_ref()
+                .is_some_and(|data| data.play_count > 0);
+            if !watched || played {
+                continue;
+            }
+
+            let skipped = dry_run::skip(&format!(
+                "mark {} as played by {}",
+                name, jellyfin_user.name
+            ));
+            if !skipped {
+                let path = format!("/Users/{}/PlayedItems/{}", jellyfin_user.id, item.id);
+                if let Err(e) = client.post(&path) {
+                    warn!("{}", t!("jellyfin.could_not_mark", name = name, error = e));
+                    continue;
+                }
+            }
+            marked += 1;
+        }
+    }
+
+    if !storage::dry_run() {
+        info!(
+            "{}",
+            t!("jellyfin.marked", count = marked, url = server.url)
+        );
+    }
+}
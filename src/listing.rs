@@ -0,0 +1,178 @@
+use crate::formatting;
+use crate::output::{self, Style};
+use crate::t;
+use clap::{Args, ValueEnum};
+use log::warn;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many threads records are searched and filtered with
+static THREADS: AtomicUsize = AtomicUsize::new(1);
+
+/// Searches and filters records with the given number of threads, or with one per core if 0
+pub fn set_threads(threads: usize) {
+    if threads == 1 {
+        return;
+    }
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        warn!(
+            "{}",
+            t!("config.threads_failed", count = threads, error = e)
+        );
+        return;
+    }
+    THREADS.store(rayon::current_num_threads(), Ordering::Relaxed);
+}
+
+/// Whether records are searched and filtered with more than one thread
+fn parallel() -> bool {
+    THREADS.load(Ordering::Relaxed) > 1
+}
+
+/// Keeps the records matching a condition, in order
+///
+/// With a single thread, records are filtered as they are taken, so they can be streamed.
+/// Otherwise, every record is read first and the condition is checked on every thread.
+///
+/// # Arguments
+///
+/// * `records` - The records to filter
+/// * `keep` - Whether to keep a record
+pub fn filter<'a, T: Send + 'a>(
+    records: impl Iterator<Item = T> + 'a,
+    keep: impl Fn(&T) -> bool + Sync + Send + 'a,
+) -> Box<dyn Iterator<Item = T> + 'a> {
+    if !parallel() {
+        return Box::new(records.filter(keep));
+    }
+    let records: Vec<T> = records.collect();
+    let kept: Vec<T> = records.into_par_iter().filter(keep).collect();
+    Box::new(kept.into_iter())
+}
+
+/// Scores every record and sorts them by score, best first, on every thread given with
+/// `--threads`. Records with the same score stay in order
+///
+/// # Arguments
+///
+/// * `records` - The records to rank
+/// * `score` - Scores a record
+pub fn rank<T: Sync>(records: &[T], score: impl Fn(&T) -> f64 + Sync + Send) -> Vec<(f64, &T)> {
+    let by_score = |a: &(f64, &T), b: &(f64, &T)| b.0.total_cmp(&a.0);
+    if !parallel() {
+        let mut ranked: Vec<(f64, &T)> = records
+            .iter()
+            .map(|record| (score(record), record))
+            .collect();
+        ranked.sort_by(by_score);
+        return ranked;
+    }
+    let mut ranked: Vec<(f64, &T)> = records
+        .par_iter()
+        .map(|record| (score(record), record))
+        .collect();
+    ranked.par_sort_by(by_score);
+    ranked
+}
+
+#[derive(Debug, Args)]
+pub struct Pagination {
+    /// The maximum number of records to show
+    #[arg(long, default_value = None)]
+    pub limit: Option<usize>,
+    /// The number of records to skip before showing any
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// Only print the number of matching records
+    #[arg(long, default_value_t = false, conflicts_with_all = ["limit", "offset"])]
+    pub count: bool,
+}
+
+impl Pagination {
+    /// Selects the page of records requested
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - Every record matching the query
+    ///
+    /// # Returns
+    ///
+    /// The records remaining after skipping `offset` records and keeping at most `limit`. Records
+    /// are only taken from `records` as they are needed
+    pub fn apply<T>(&self, records: impl IntoIterator<Item = T>) -> impl Iterator<Item = T> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        records.into_iter().skip(self.offset).take(limit)
+    }
+}
+
+/// Gets the names of the given fields as they are written on the command line
+///
+/// # Arguments
+///
+/// * `fields` - The fields to name
+pub fn field_names<F: ValueEnum>(fields: &[F]) -> Vec<String> {
+    fields
+        .iter()
+        .filter_map(|field| field.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+/// Prints the details of a single record, one labelled value per line. Values left empty are
+/// skipped
+///
+/// # Arguments
+///
+/// * `details` - The labels and values to print
+pub fn print_details(details: &[(String, String)]) {
+    let width = details
+        .iter()
+        .map(|(label, _)| formatting::width(label))
+        .max()
+        .unwrap_or(0);
+    for (label, value) in details {
+        if value.is_empty() {
+            continue;
+        }
+        let label = formatting::pad(&format!("{}:", label), width + 1);
+        println!("{} {}", output::paint_stdout(&label, Style::Header), value);
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct Search {
+    /// The text to search for. Doesn't need to match exactly
+    pub text: String,
+    /// The maximum number of results to show
+    #[arg(long, default_value_t = 5)]
+    pub limit: usize,
+    /// The minimum score, between 0 and 1, a result needs to be shown
+    #[arg(long, default_value_t = 0.4)]
+    pub min_score: f64,
+}
+
+/// Scores how closely a value matches the given search text
+///
+/// The whole value and each of its words are compared against the text, so a search for a single
+/// misspelled word still finds a longer name containing it.
+///
+/// # Arguments
+///
+/// * `value` - The value being searched
+/// * `text` - The text to search for
+///
+/// # Returns
+///
+/// A score between 0 and 1, where 1 is an exact match ignoring case
+pub fn fuzzy_score(value: &str, text: &str) -> f64 {
+    let value = formatting::fold_case(value);
+    let text = formatting::fold_case(text);
+
+    value
+        .split_whitespace()
+        .map(|word| strsim::normalized_levenshtein(word, &text))
+        .fold(strsim::normalized_levenshtein(&value, &text), f64::max)
+}
@@ -0,0 +1,87 @@
+use crate::output::{self, Style};
+use env_logger::Builder;
+use log::{Level, LevelFilter};
+use std::io::Write;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many errors were logged so far
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// How many warnings were logged so far
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets up logging to stderr, showing errors, warnings, and info messages until `set_verbosity` is called
+///
+/// Errors, warnings, and the messages reporting what a command did are printed without a prefix,
+/// colored by level when stderr is colored. Debug and trace messages are prefixed with their level.
+pub fn init() {
+    Builder::new()
+        // Dependencies such as the HTTP server only get to print their own warnings and errors
+        .filter_level(LevelFilter::Warn)
+        .filter_module(env!("CARGO_CRATE_NAME"), LevelFilter::Trace)
+        .format(|buf, record| {
+            let message = record.args().to_string();
+            let line = match record.level() {
+                Level::Error => {
+                    ERRORS.fetch_add(1, Ordering::Relaxed);
+                    output::paint_stderr(&message, Style::Error)
+                }
+                Level::Warn => {
+                    WARNINGS.fetch_add(1, Ordering::Relaxed);
+                    output::paint_stderr(&message, Style::Warning)
+                }
+                Level::Info => output::paint_stderr(&message, Style::Success),
+                level => output::paint_stderr(
+                    &format!("[{}] {}", level.as_str().to_lowercase(), message),
+                    Style::Detail,
+                ),
+            };
+            writeln!(buf, "{}", line)
+        })
+        .init();
+    log::set_max_level(LevelFilter::Info);
+}
+
+/// How many errors were logged so far, so callers can tell whether a handler failed
+pub fn error_count() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+/// How many warnings were logged so far
+pub fn warning_count() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+/// Exits with a failure if any error or warning was logged, so `--strict` commands fail in scripts
+/// whenever something went wrong, e.g. no query was given or nothing matched it
+pub fn exit_if_strict(strict: bool) {
+    let (errors, warnings) = (error_count(), warning_count());
+    if strict && errors + warnings > 0 {
+        log::debug!(
+            "Exiting with a failure in strict mode after {} errors and {} warnings",
+            errors,
+            warnings
+        );
+        process::exit(1);
+    }
+}
+
+/// Changes which messages are logged, following `--quiet` and `--verbose`
+///
+/// # Arguments
+///
+/// * `quiet` - Only print errors and warnings
+/// * `verbose` - The number of times `--verbose` was given. Once shows debug messages, twice or more shows trace messages too
+pub fn set_verbosity(quiet: bool, verbose: u8) {
+    let level = if quiet {
+        LevelFilter::Warn
+    } else {
+        match verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    log::set_max_level(level);
+}
@@ -1,15 +1,170 @@
+mod aliases;
 mod args;
+pub mod audit;
+pub mod backup;
+pub mod batch;
+pub mod bench;
+pub mod channel;
+pub mod config;
+pub mod daemon;
+mod defaults;
+pub mod desktop;
+pub mod dns;
+pub mod dry_run;
+pub mod email_filter;
+pub mod encryption;
+pub mod events;
+pub mod feed;
+pub mod filter;
+pub mod formatting;
+pub mod fsck;
+pub mod i18n;
+pub mod init;
+pub mod integrity;
+pub mod jellyfin;
+pub mod listing;
+pub mod logging;
+pub mod media;
+pub mod metadata;
+pub mod migration;
+pub mod milestones;
+pub mod notifications;
+pub mod output;
+pub mod profile;
+pub mod query;
+pub mod rate_limit;
+pub mod recommend;
+pub mod remote;
+pub mod repository;
+pub mod s3;
+pub mod scheduler;
+pub mod self_test;
+pub mod server;
+pub mod session;
+pub mod sinks;
+pub mod storage;
+pub mod sync;
+pub mod transfer;
+pub mod tui;
+pub mod undo;
 pub mod utilities;
+pub mod validation;
+pub mod view_log;
+pub mod webhooks;
 
+use args::command_types::user_subcommands;
 use args::{CommandType, RustflixArgs};
 use clap::Parser;
+use std::env;
+use std::process;
+use storage::StorageBackend;
 
 fn main() {
-    let args = RustflixArgs::parse();
+    logging::init();
+    let early = config::before_parsing();
+    let args = aliases::expand(env::args().collect(), &early.aliases);
+    let args = RustflixArgs::parse_from(defaults::apply(args, &early.defaults));
+    output::init(args.color);
+    logging::set_verbosity(args.quiet, args.verbose);
+    log::trace!("{:?}", args);
+    utilities::set_assume_yes(args.yes);
+    utilities::set_prompt_timeout(args.prompt_timeout);
+    listing::set_threads(args.threads);
+    storage::set_recover(args.recover);
+    let flags = config::Flags {
+        remote: args.remote,
+        token: args.token,
+        strict: args.strict,
+        profile: args.profile,
+    };
+    let config = config::load(&flags);
+    i18n::set_locale(config.locale.as_deref());
+    if let Some(profile) = &config.profile {
+        if !config.profiles.contains_key(profile) {
+            log::warn!("{}", t!("config.unknown_profile", name = profile));
+        }
+    }
+    if let Some(data_dir) = &config.storage.data_dir {
+        storage::set_data_dir(data_dir.clone());
+    }
+    if let Some(url) = &config.remote {
+        remote::set_url(url);
+    }
+    if let Some(token) = config.token {
+        remote::set_token(token);
+    }
+    if let Some(encryption) = config.encryption {
+        if let Err(e) = encryption::set_config(encryption) {
+            log::error!("{}", t!("config.invalid_encryption", error = e));
+            process::exit(1);
+        }
+    }
+    storage::set_config(&config.storage);
+    recommend::set_watch_time(config.watch_time);
+    webhooks::set_config(config.webhooks);
+    sinks::set_config(config.sinks);
+    scheduler::set_config(config.schedule);
+    milestones::set_config(config.milestones);
+    desktop::set_config(config.desktop_notifications);
+    formatting::set_config(config.format);
+    match (config.storage.backend, config.s3) {
+        (Some(StorageBackend::Files), _) | (None, None) => {}
+        (Some(StorageBackend::S3), None) => {
+            log::error!("{}", t!("config.s3_missing"));
+            process::exit(1);
+        }
+        (_, Some(s3)) => s3::set_config(s3),
+    }
+    if let Some(auto) = config.backup.auto {
+        backup::set_auto(auto);
+    }
+
+    let strict = config.strict;
+    if args.dry_run {
+        dry_run::begin();
+    }
 
     match args.command_type {
         CommandType::User(user_command) => args::handle_user_command(user_command),
-        CommandType::Video(video_command) => args::handle_video_command(video_command),
+        CommandType::Video(video_command) => {
+            args::handle_video_command(video_command, config.player)
+        }
         CommandType::View(view_command) => args::handle_view_command(view_command),
+        CommandType::Tui => tui::handle_tui(),
+        CommandType::Init(init) => init::handle_init(init),
+        CommandType::Serve(serve) => server::handle_serve(serve, config.rate_limit),
+        CommandType::Genre(genre_command) => args::handle_genre_command(genre_command),
+        CommandType::Series(series_command) => args::handle_series_command(series_command),
+        CommandType::Playlist(playlist_command) => args::handle_playlist_command(playlist_command),
+        CommandType::ApiKey(apikey_command) => args::handle_apikey_command(apikey_command),
+        CommandType::Daemon(daemon_command) => args::handle_daemon_command(daemon_command),
+        CommandType::Login(user_query) => user_subcommands::handle_login(user_query),
+        CommandType::Logout => user_subcommands::handle_logout(),
+        CommandType::Import(import_command) => args::handle_import_command(import_command),
+        CommandType::Backup(backup_command) => args::handle_backup_command(backup_command),
+        CommandType::Sync(synchronize) => sync::handle_sync(synchronize),
+        CommandType::Export(export_command) => args::handle_export_command(export_command),
+        CommandType::Encrypt(encrypt_command) => args::handle_encrypt_command(encrypt_command),
+        CommandType::Fsck(fsck) => fsck::handle_fsck(fsck),
+        CommandType::Batch(batch) => batch::handle_batch(batch, config.player),
+        CommandType::IdStrategy(set_id_strategy) => metadata::handle_id_strategy(set_id_strategy),
+        CommandType::Bench(bench) => bench::handle_bench(bench),
+        CommandType::Recommend(recommend_command) => {
+            args::handle_recommend_command(recommend_command)
+        }
+        CommandType::Feed(feed) => feed::handle_feed(feed, config.feed),
+        CommandType::Audit(audit_command) => args::handle_audit_command(audit_command),
+        CommandType::Undo(undo) => undo::handle_undo(undo),
+        CommandType::Channel(channel_command) => args::handle_channel_command(channel_command),
+        CommandType::Webhook(webhook_command) => args::handle_webhook_command(webhook_command),
+        CommandType::Config(config_command) => args::handle_config_command(config_command, &flags),
+        CommandType::Profile(profile_command) => {
+            args::handle_profile_command(profile_command, &flags)
+        }
+        CommandType::SelfTest(self_test) => self_test::handle_self_test(self_test),
+    }
+    if args.dry_run {
+        dry_run::finish();
     }
+    logging::exit_if_strict(strict);
 }
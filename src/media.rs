@@ -0,0 +1,167 @@
+use crate::t;
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// What `ffprobe` found out about a media file
+///
+/// # Fields
+///
+/// * `duration` - The length of the media in whole seconds, if known
+/// * `width` - The width of the first video stream in pixels, if any
+/// * `height` - The height of the first video stream in pixels, if any
+/// * `codec` - The codec of the first video stream, if any
+#[derive(Debug, Default, PartialEq)]
+pub struct Probe {
+    pub duration: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<String>,
+}
+
+/// The parts of the JSON written by `ffprobe -show_format -show_streams` that are used
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    format: Option<ProbeFormat>,
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// Probes a media file with `ffprobe`, which must be on the `PATH`
+///
+/// # Arguments
+///
+/// * `file` - The media file to probe
+///
+/// # Returns
+///
+/// What was found out about the file, or an error message if `ffprobe` could not run or failed
+pub fn probe(file: &Path) -> Result<Probe, String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams"])
+        .arg(file)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => t!("media.not_installed", program = "ffprobe"),
+            _ => t!("media.could_not_run", program = "ffprobe", error = e),
+        })?;
+    if !output.status.success() {
+        return Err(t!(
+            "media.failed",
+            program = "ffprobe",
+            error = String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_probe(&output.stdout)
+}
+
+/// Saves a single frame of a media file as an image with `ffmpeg`, which must be on the `PATH`
+///
+/// # Arguments
+///
+/// * `file` - The media file to take the frame from
+/// * `at` - How far into the media the frame is, in seconds
+/// * `output` - The image file to write. Its extension decides the image format
+///
+/// # Returns
+///
+/// An error message if `ffmpeg` could not run or failed
+pub fn extract_frame(file: &Path, at: u32, output: &Path) -> Result<(), String> {
+    let result = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-ss"])
+        .arg(at.to_string())
+        .arg("-i")
+        .arg(file)
+        .args(["-frames:v", "1"])
+        .arg(output)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => t!("media.not_installed", program = "ffmpeg"),
+            _ => t!("media.could_not_run", program = "ffmpeg", error = e),
+        })?;
+    if !result.status.success() {
+        return Err(t!(
+            "media.failed",
+            program = "ffmpeg",
+            error = String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    if !output.is_file() {
+        return Err(t!("media.not_written", path = output.display()));
+    }
+    Ok(())
+}
+
+/// Reads the JSON written by `ffprobe`
+///
+/// # Arguments
+///
+/// * `json` - The output of `ffprobe`
+fn parse_probe(json: &[u8]) -> Result<Probe, String> {
+    let output: ProbeOutput = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    let duration = output
+        .format
+        .and_then(|format| format.duration)
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .map(|duration| duration.round() as u32);
+    let stream = output
+        .streams
+        .into_iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("video"));
+
+    Ok(match stream {
+        Some(stream) => Probe {
+            duration,
+            width: stream.width,
+            height: stream.height,
+            codec: stream.codec_name,
+        },
+        None => Probe {
+            duration,
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe() {
+        let json = br#"{
+            "streams": [
+                { "codec_type": "audio", "codec_name": "aac" },
+                { "codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080 }
+            ],
+            "format": { "duration": "5399.6" }
+        }"#;
+        assert_eq!(
+            parse_probe(json),
+            Ok(Probe {
+                duration: Some(5400),
+                width: Some(1920),
+                height: Some(1080),
+                codec: Some("h264".to_string()),
+            })
+        );
+        assert_eq!(parse_probe(b"{}"), Ok(Probe::default()));
+    }
+}
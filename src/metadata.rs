@@ -0,0 +1,158 @@
+use crate::storage::{self, Store};
+use crate::t;
+use clap::{Args, ValueEnum};
+use log::info;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// How the IDs of new records are picked
+///
+/// IDs are 32 bits, too few for ULIDs, so sequential IDs are the way to get IDs that sort by when
+/// their records were created.
+///
+/// # Variants
+///
+/// * `Sequential` - Counts up from 1 for each store, never reusing the ID of a deleted record
+/// * `Random` - Picks a random ID no record has, as rustflix always did before
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum IdStrategy {
+    #[default]
+    Sequential,
+    Random,
+}
+
+/// Settings kept with the stores rather than in the config file, so they follow the stores to
+/// every backend. The metadata store holds a single one
+///
+/// # Fields
+///
+/// * `id` - Always 0, so syncing matches the metadata of both sides
+/// * `id_strategy` - How the IDs of new records are picked
+/// * `next_ids` - The next ID to try for each store, by its route, when IDs are sequential
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub id: u32,
+    #[serde(default)]
+    pub id_strategy: IdStrategy,
+    #[serde(default)]
+    pub next_ids: BTreeMap<String, u32>,
+}
+
+/// Loads the metadata of the stores
+///
+/// Stores made before there was metadata keep random IDs, so new records look like the old ones,
+/// while new stores get sequential IDs.
+pub fn load() -> Metadata {
+    let metadata: Vec<Metadata> = storage::load(Store::Metadata);
+    metadata.into_iter().next().unwrap_or_else(|| {
+        let existing = !storage::load::<Value>(Store::Users).is_empty()
+            || !storage::load::<Value>(Store::Videos).is_empty();
+        Metadata {
+            id_strategy: match existing {
+                true => IdStrategy::Random,
+                false => IdStrategy::Sequential,
+            },
+            ..Default::default()
+        }
+    })
+}
+
+/// Overwrites the metadata of the stores
+pub fn save(metadata: Metadata) {
+    storage::save(Store::Metadata, &vec![metadata]);
+}
+
+/// Picks an ID no record has following the given strategy
+///
+/// # Arguments
+///
+/// * `strategy` - How to pick the ID
+/// * `next` - The first ID to try when IDs are sequential
+/// * `taken` - Whether a record already has an ID
+pub fn pick_id(strategy: IdStrategy, next: u32, taken: impl Fn(u32) -> bool) -> u32 {
+    match strategy {
+        IdStrategy::Sequential => {
+            let mut id = next.max(1);
+            while taken(id) {
+                id = id.checked_add(1).unwrap_or(1);
+            }
+            id
+        }
+        IdStrategy::Random => {
+            let mut rng = rand::thread_rng();
+            let mut id = rng.gen_range(0..=u32::MAX);
+            while taken(id) {
+                id = rng.gen_range(0..=u32::MAX);
+            }
+            id
+        }
+    }
+}
+
+/// Picks the ID of a new record of the given store, following the ID strategy of the stores
+///
+/// # Arguments
+///
+/// * `store` - The store the record is added to
+/// * `taken` - Whether a record of the store already has an ID
+pub fn generate_id(store: Store, taken: impl Fn(u32) -> bool) -> u32 {
+    let mut metadata = load();
+    let next = metadata.next_ids.get(store.route()).copied().unwrap_or(1);
+    let id = pick_id(metadata.id_strategy, next, taken);
+    if metadata.id_strategy == IdStrategy::Sequential {
+        metadata
+            .next_ids
+            .insert(store.route().to_string(), id.checked_add(1).unwrap_or(1));
+    }
+    // Saved even when nothing changed, so stores made now keep the strategy they started with
+    save(metadata);
+    id
+}
+
+#[derive(Debug, Args)]
+pub struct SetIdStrategy {
+    /// How to pick the IDs of new records. Shows the current strategy if not given
+    #[arg(value_enum)]
+    pub strategy: Option<IdStrategy>,
+}
+
+/// Handles showing or changing how the IDs of new records are picked
+///
+/// # Arguments
+///
+/// * `set_id_strategy` - The arguments for the change
+pub fn handle_id_strategy(set_id_strategy: SetIdStrategy) {
+    let mut metadata = load();
+    let Some(strategy) = set_id_strategy.strategy else {
+        println!("{:?}", metadata.id_strategy);
+        return;
+    };
+
+    metadata.id_strategy = strategy;
+    save(metadata);
+    info!(
+        "{}",
+        t!("storage.id_strategy", strategy = format!("{:?}", strategy))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_sequential_id() {
+        let taken = [1, 2, 4];
+        let taken = |id| taken.contains(&id);
+        assert_eq!(pick_id(IdStrategy::Sequential, 1, taken), 3);
+        assert_eq!(pick_id(IdStrategy::Sequential, 4, taken), 5);
+        assert_eq!(pick_id(IdStrategy::Sequential, 0, |_| false), 1);
+        assert_eq!(
+            pick_id(IdStrategy::Sequential, u32::MAX, |id| id == u32::MAX),
+            1
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::storage::Store;
+use crate::t;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// The records as they were stored before store files had a version, when they held plain bincode.
+// Bincode can't tell where fields end, so these can never change.
+
+#[derive(Serialize, Deserialize)]
+struct UserV0 {
+    id: u32,
+    name: String,
+    email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VideoV0 {
+    id: u32,
+    name: String,
+    views: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum ScopeV0 {
+    Read,
+    Admin,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ApiKeyV0 {
+    id: u32,
+    name: String,
+    scope: ScopeV0,
+    hash: String,
+}
+
+/// Reads a store file written before store files had a version
+///
+/// # Arguments
+///
+/// * `store` - The store the file belongs to
+/// * `bytes` - The contents of the file
+///
+/// # Returns
+///
+/// The records in a form the current records can be read from, leaving out every field added
+/// since, or an error if the file can't be read
+pub fn read_unversioned(store: Store, bytes: &[u8]) -> Result<Value, bincode::Error> {
+    let records = match store {
+        Store::Users => serde_json::to_value(bincode::deserialize::<Vec<UserV0>>(bytes)?),
+        Store::Videos => serde_json::to_value(bincode::deserialize::<Vec<VideoV0>>(bytes)?),
+        Store::ApiKeys => serde_json::to_value(bincode::deserialize::<Vec<ApiKeyV0>>(bytes)?),
+        Store::Genres
+        | Store::Series
+        | Store::History
+        | Store::Subscriptions
+        | Store::Metadata
+        | Store::Notifications
+        | Store::Playlists => {
+            return Err(Box::new(bincode::ErrorKind::Custom(t!(
+                "storage.unversioned_store",
+                store = format!("{:?}", store)
+            ))))
+        }
+    };
+    Ok(records.unwrap())
+}
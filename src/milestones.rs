@@ -0,0 +1,100 @@
+use crate::args::command_types::video_subcommands::Video;
+use crate::events::{self, Change, Event, EventSubscriber};
+use crate::notifications::{self, NotificationKind};
+use crate::output::{self, Style};
+use crate::sinks::{self, SinkEvent};
+use crate::webhooks;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// The view counts worth celebrating, from the `[milestones]` section of the config file
+///
+/// # Fields
+///
+/// * `views` - The view counts a video reaching is a milestone
+/// * `webhooks` - Whether to send the webhooks a `milestone_reached` event too
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MilestoneConfig {
+    pub views: Vec<u32>,
+    pub webhooks: bool,
+}
+
+impl Default for MilestoneConfig {
+    fn default() -> Self {
+        MilestoneConfig {
+            views: vec![1_000, 10_000, 100_000],
+            webhooks: false,
+        }
+    }
+}
+
+/// The milestones configured
+static CONFIG: OnceLock<MilestoneConfig> = OnceLock::new();
+
+/// Makes the given view counts milestones
+pub fn set_config(config: MilestoneConfig) {
+    CONFIG.set(config).expect("Milestones set twice");
+}
+
+/// The milestones configured, or the defaults if none were
+fn config() -> &'static MilestoneConfig {
+    CONFIG.get_or_init(MilestoneConfig::default)
+}
+
+/// Celebrates the milestones videos reach whenever they gain views
+pub struct Milestones;
+
+impl EventSubscriber for Milestones {
+    fn handle(&self, change: &Change) {
+        if config().views.is_empty() {
+            return;
+        }
+        for event in change.events() {
+            if let Event::ViewsAdded { video, added } = event {
+                check(video, *added);
+            }
+        }
+    }
+}
+
+/// Celebrates every milestone a video reached by gaining views: shows a banner, notifies the
+/// owner of the video, posts to the sinks, and sends the webhooks an event if configured to
+///
+/// # Arguments
+///
+/// * `video` - The video after the views were added
+/// * `added` - The number of views added
+fn check(video: &Video, added: u32) {
+    let config = config();
+    let before = video.views.saturating_sub(added);
+    for milestone in events::crossed(&config.views, before, video.views) {
+        let message = format!("{} reached {} views!", video.name, milestone);
+        info!(
+            "{}",
+            output::paint_stderr(&banner(&message), Style::Success)
+        );
+        if let Some(owner) = video.owner {
+            notifications::notify(
+                NotificationKind::Milestone,
+                &[owner],
+                &message,
+                Some(video.id),
+            );
+        }
+        sinks::post(SinkEvent::Milestone, &message, video);
+        if config.webhooks {
+            webhooks::fire(&[Event::MilestoneReached {
+                video: video.clone(),
+                views: milestone,
+            }]);
+        }
+    }
+}
+
+/// Frames a message in a box of stars
+fn banner(message: &str) -> String {
+    let border = "*".repeat(message.chars().count() + 6);
+    format!("{}\n*  {}  *\n{}", border, message, border)
+}
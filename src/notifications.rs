@@ -0,0 +1,241 @@
+use crate::args::command_types::user_subcommands::{Subscription, User};
+use crate::args::command_types::video_subcommands::{self, Video, Visibility};
+use crate::events::{Change, Event, EventSubscriber};
+use crate::output::{self, Style};
+use crate::session;
+use crate::sinks::{self, SinkEvent};
+use crate::storage::{self, Store};
+use crate::t;
+use clap::Args;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+/// What a notification is about
+///
+/// # Variants
+///
+/// * `Milestone` - A video the user owns reached a milestone
+/// * `Upload` - A channel the user subscribes to uploaded a video
+/// * `Digest` - A summary of the videos the channels the user subscribes to uploaded lately
+/// * `Playlist` - A video was added to a playlist the user follows
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    #[default]
+    Milestone,
+    Upload,
+    Digest,
+    Playlist,
+}
+
+/// Something a user was told about while they weren't looking
+///
+/// # Fields
+///
+/// * `user` - The ID of the user notified
+/// * `kind` - What the notification is about
+/// * `message` - What the user is told
+/// * `video` - The ID of the video the notification is about, if any
+/// * `created_at` - When the notification was sent, in seconds since the Unix epoch
+/// * `read` - Whether the user has seen the notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub user: u32,
+    #[serde(default)]
+    pub kind: NotificationKind,
+    pub message: String,
+    #[serde(default)]
+    pub video: Option<u32>,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    #[serde(default)]
+    pub read: bool,
+}
+
+/// Sends users a notification, kept until they read it
+///
+/// # Arguments
+///
+/// * `kind` - What the notification is about
+/// * `users` - The IDs of the users to notify
+/// * `message` - What to tell them
+/// * `video` - The ID of the video the notification is about, if any
+pub fn notify(kind: NotificationKind, users: &[u32], message: &str, video: Option<u32>) {
+    let mut notifications: Vec<Notification> = storage::load(Store::Notifications);
+    let now = video_subcommands::now();
+    notifications.extend(users.iter().map(|user| Notification {
+        user: *user,
+        kind,
+        message: message.to_string(),
+        video,
+        created_at: Some(now),
+        read: false,
+    }));
+    storage::save(Store::Notifications, &notifications);
+}
+
+/// Tells subscribers about every video uploaded to the channels they subscribe to
+pub struct Uploads;
+
+impl EventSubscriber for Uploads {
+    fn handle(&self, change: &Change) {
+        if change.store != Store::Videos {
+            return;
+        }
+        for event in change.events() {
+            if let Event::VideoCreated { video } = event {
+                uploaded(video);
+            }
+        }
+    }
+}
+
+/// Notifies the subscribers of the owner of a new public video that it was uploaded, and posts
+/// it to the sinks
+fn uploaded(video: &Video) {
+    let Some(owner) = video
+        .owner
+        .filter(|_| video.visibility == Visibility::Public)
+    else {
+        return;
+    };
+    let subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+    let subscribers: Vec<u32> = subscriptions
+        .iter()
+        .filter(|subscription| subscription.channel == owner)
+        .map(|subscription| subscription.user)
+        .collect();
+    let posted = sinks::wanted(SinkEvent::Upload);
+    if subscribers.is_empty() && !posted {
+        return;
+    }
+
+    let users: Vec<User> = storage::load(Store::Users);
+    let channel = users.iter().find(|user| user.id == owner);
+    if posted {
+        let channel = channel.map_or("A channel", |user| user.name.as_str());
+        let message = format!("{} uploaded {}", channel, video.name);
+        sinks::post(SinkEvent::Upload, &message, video);
+    }
+    if !subscribers.is_empty() {
+        let channel = channel.map_or("A channel you subscribe to", |user| user.name.as_str());
+        notify(
+            NotificationKind::Upload,
+            &subscribers,
+            &format!("{} uploaded {}", channel, video.name),
+            Some(video.id),
+        );
+    }
+}
+
+/// Sends every user a digest of the public videos the channels they subscribe to uploaded since a
+/// given time, if they uploaded any
+///
+/// # Arguments
+///
+/// * `since` - When the last digest was sent, in seconds since the Unix epoch
+///
+/// # Returns
+///
+/// The number of users sent a digest
+pub fn send_digests(since: u64) -> usize {
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    let uploads: Vec<&Video> = videos
+        .iter()
+        .filter(|video| video.visibility == Visibility::Public)
+        .filter(|video| {
+            video
+                .uploaded_at
+                .is_some_and(|uploaded_at| uploaded_at >= since)
+        })
+        .collect();
+    if uploads.is_empty() {
+        return 0;
+    }
+
+    let subscriptions: Vec<Subscription> = storage::load(Store::Subscriptions);
+    let mut digests: Vec<(u32, Vec<&str>)> = vec![];
+    for subscription in &subscriptions {
+        let names = uploads
+            .iter()
+            .filter(|video| video.owner == Some(subscription.channel))
+            .map(|video| video.name.as_str());
+        match digests
+            .iter_mut()
+            .find(|(user, _)| *user == subscription.user)
+        {
+            Some((_, digest)) => digest.extend(names),
+            None => digests.push((subscription.user, names.collect())),
+        }
+    }
+    digests.retain(|(_, names)| !names.is_empty());
+
+    let mut notifications: Vec<Notification> = storage::load(Store::Notifications);
+    let now = video_subcommands::now();
+    notifications.extend(digests.iter().map(|(user, names)| Notification {
+        user: *user,
+        kind: NotificationKind::Digest,
+        message: format!(
+            "{} new video{} from your subscriptions: {}",
+            names.len(),
+            if names.len() == 1 { "" } else { "s" },
+            names.join(", ")
+        ),
+        video: None,
+        created_at: Some(now),
+        read: false,
+    }));
+    if !digests.is_empty() {
+        storage::save(Store::Notifications, &notifications);
+    }
+    digests.len()
+}
+
+#[derive(Debug, Args)]
+pub struct ShowNotifications {
+    /// The ID of the user to show the notifications of, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub id: Option<u32>,
+    /// Also show the notifications already read
+    #[arg(long, default_value_t = false)]
+    pub all: bool,
+}
+
+/// Handles showing the notifications of a user, the oldest first, marking them read
+///
+/// # Arguments
+///
+/// * `show_notifications` - The arguments for showing the notifications
+pub fn handle_show_notifications(show_notifications: ShowNotifications) {
+    let Some(user) = show_notifications.id.or_else(session::current_user) else {
+        error!("{}", t!("notification.no_user"));
+        return;
+    };
+    let mut notifications: Vec<Notification> = storage::load(Store::Notifications);
+    let (mut shown, mut unread) = (0, 0);
+    for notification in notifications
+        .iter_mut()
+        .filter(|notification| notification.user == user)
+        .filter(|notification| show_notifications.all || !notification.read)
+    {
+        let at = notification
+            .created_at
+            .map(video_subcommands::format_timestamp)
+            .unwrap_or_default();
+        let message = match notification.read {
+            true => output::paint_stdout(&notification.message, Style::Detail),
+            false => notification.message.clone(),
+        };
+        println!("{} {}", output::paint_stdout(&at, Style::Header), message);
+        unread += !notification.read as usize;
+        notification.read = true;
+        shown += 1;
+    }
+
+    if shown == 0 {
+        info!("{}", t!("notification.none"));
+    }
+    if unread > 0 {
+        storage::save(Store::Notifications, &notifications);
+    }
+}
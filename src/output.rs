@@ -0,0 +1,91 @@
+use clap::ValueEnum;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When output should be colored
+///
+/// # Variants
+///
+/// * `Auto` - Color output going to a terminal, unless `NO_COLOR` is set
+/// * `Always` - Always color output
+/// * `Never` - Never color output
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The kinds of text that are colored differently
+///
+/// # Variants
+///
+/// * `Success` - A message reporting what a command did
+/// * `Error` - A message explaining why a command failed
+/// * `Warning` - A message about something that may need attention
+/// * `Detail` - A debug or trace message
+/// * `Header` - The header row of a table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Success,
+    Error,
+    Warning,
+    Detail,
+    Header,
+}
+
+impl Style {
+    fn code(&self) -> &'static str {
+        match self {
+            Style::Success => "32",
+            Style::Error => "1;31",
+            Style::Warning => "33",
+            Style::Detail => "2",
+            Style::Header => "1;4",
+        }
+    }
+}
+
+static COLOR_STDOUT: AtomicBool = AtomicBool::new(false);
+static COLOR_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Decides whether stdout and stderr are colored, following `--color`
+///
+/// # Arguments
+///
+/// * `choice` - When output should be colored
+pub fn init(choice: ColorChoice) {
+    let (stdout, stderr) = match choice {
+        ColorChoice::Always => (true, true),
+        ColorChoice::Never => (false, false),
+        ColorChoice::Auto => {
+            let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            (
+                !no_color && io::stdout().is_terminal(),
+                !no_color && io::stderr().is_terminal(),
+            )
+        }
+    };
+    COLOR_STDOUT.store(stdout, Ordering::Relaxed);
+    COLOR_STDERR.store(stderr, Ordering::Relaxed);
+}
+
+fn paint(text: &str, style: Style, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Styles text to be printed to stdout, leaving it plain if stdout isn't colored
+pub fn paint_stdout(text: &str, style: Style) -> String {
+    paint(text, style, COLOR_STDOUT.load(Ordering::Relaxed))
+}
+
+/// Styles text to be printed to stderr, leaving it plain if stderr isn't colored
+pub fn paint_stderr(text: &str, style: Style) -> String {
+    paint(text, style, COLOR_STDERR.load(Ordering::Relaxed))
+}
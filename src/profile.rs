@@ -0,0 +1,157 @@
+use crate::config::{self, Flags};
+use crate::formatting;
+use crate::storage::StorageBackend;
+use crate::t;
+use crate::utilities;
+use clap::{Args, Subcommand};
+use log::{error, info, warn};
+use std::path::PathBuf;
+use toml_edit::{Item, Table};
+
+#[derive(Debug, Args)]
+pub struct ProfileCommand {
+    #[clap(subcommand)]
+    pub subcommand: ProfileSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileSubcommand {
+    /// List the profiles in the config files, with where each keeps its records
+    List,
+    /// Add a profile to the user's config file. Any other setting can be added to it with `config set profiles.<name>.<key>`
+    Create(CreateProfile),
+    /// Remove a profile from the user's config file, keeping its records
+    Delete(DeleteProfile),
+}
+
+#[derive(Debug, Args)]
+pub struct CreateProfile {
+    /// The name of the profile, chosen with `--profile <name>`
+    pub name: String,
+    /// The directory the profile keeps its stores in
+    #[arg(long, default_value = None)]
+    pub data_dir: Option<PathBuf>,
+    /// The URL of the rustflix server the profile uses instead of local files
+    #[arg(long, default_value = None)]
+    pub remote: Option<String>,
+    /// Use the profile when `--profile` isn't given
+    #[arg(long, default_value_t = false)]
+    pub default: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteProfile {
+    /// The name of the profile
+    pub name: String,
+}
+
+/// Handles listing the profiles, marking the one in use
+///
+/// # Arguments
+///
+/// * `flags` - The settings given as flags, including the profile chosen
+pub fn handle_list_profiles(flags: &Flags) {
+    let current = config::load(flags);
+    if current.profiles.is_empty() {
+        warn!("{}", t!("profile.none"));
+        return;
+    }
+    let mut names: Vec<&String> = current.profiles.keys().collect();
+    names.sort();
+
+    let mut table = formatting::Table::new(["", "profile", "backend"].map(str::to_string).to_vec());
+    for name in names {
+        let config = config::load(&Flags {
+            profile: Some(name.clone()),
+            ..Default::default()
+        });
+        let s3 = config
+            .s3
+            .filter(|_| config.storage.backend != Some(StorageBackend::Files));
+        let backend = match (&config.remote, &s3, &config.storage.data_dir) {
+            (Some(remote), _, _) => remote.clone(),
+            (None, Some(s3), _) => format!("s3://{}/{}", s3.bucket, s3.prefix),
+            (None, None, Some(data_dir)) => data_dir.display().to_string(),
+            (None, None, None) => "~/.rustflix".to_string(),
+        };
+        let active = current.profile.as_ref() == Some(name);
+        table.row(vec![
+            if active { "*" } else { "" }.to_string(),
+            name.clone(),
+            backend,
+        ]);
+    }
+    table.print();
+}
+
+/// Handles adding a profile to the user's config file
+///
+/// # Arguments
+///
+/// * `create_profile` - The arguments for the profile
+pub fn handle_create_profile(create_profile: CreateProfile) {
+    if create_profile.name.is_empty() || create_profile.name.contains('.') {
+        error!("{}", t!("profile.invalid_name"));
+        return;
+    }
+    if config::load(&Flags::default())
+        .profiles
+        .contains_key(&create_profile.name)
+    {
+        error!("{}", t!("profile.exists", name = create_profile.name));
+        return;
+    }
+
+    let mut profile = Table::new();
+    if let Some(remote) = create_profile.remote {
+        profile.insert("remote", toml_edit::value(remote));
+    }
+    if let Some(data_dir) = create_profile.data_dir {
+        let mut storage = Table::new();
+        storage.set_dotted(true);
+        storage.insert("data_dir", toml_edit::value(data_dir.display().to_string()));
+        profile.insert("storage", Item::Table(storage));
+    }
+    let key = format!("profiles.{}", create_profile.name);
+    if !config::edit_config_file(&key, Some(Item::Table(profile))) {
+        return;
+    }
+    if create_profile.default
+        && !config::edit_config_file("profile", Some(toml_edit::value(&create_profile.name)))
+    {
+        return;
+    }
+    info!("{}", t!("profile.created", name = create_profile.name));
+}
+
+/// Handles removing a profile from the user's config file, along with it being the default
+///
+/// # Arguments
+///
+/// * `delete_profile` - The arguments for removing the profile
+pub fn handle_delete_profile(delete_profile: DeleteProfile) {
+    let config = config::load(&Flags::default());
+    if !config.profiles.contains_key(&delete_profile.name) {
+        error!("{}", t!("profile.not_found", name = delete_profile.name));
+        return;
+    }
+    if !utilities::confirm(
+        &t!("profile.confirm_delete"),
+        Some(&delete_profile.name),
+        Some(&t!("profile.delete_cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return;
+    }
+
+    if config.profile.as_ref() == Some(&delete_profile.name)
+        && !config::edit_config_file("profile", None)
+    {
+        return;
+    }
+    let key = format!("profiles.{}", delete_profile.name);
+    if config::edit_config_file(&key, None) {
+        info!("{}", t!("profile.deleted", name = delete_profile.name));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::formatting;
+use clap::ValueEnum;
+
+/// How the fields of a query are combined
+///
+/// # Variants
+///
+/// * `Any` - A record matches if any field of the query matches it
+/// * `All` - A record matches only if every field of the query matches it
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum MatchMode {
+    Any,
+    #[default]
+    All,
+}
+
+impl MatchMode {
+    /// Determines if a record matches based on how many of the query's fields matched it
+    ///
+    /// # Arguments
+    ///
+    /// * `matched` - The number of query fields matching the record
+    /// * `given` - The number of fields given in the query
+    pub fn is_match(&self, matched: usize, given: usize) -> bool {
+        match self {
+            MatchMode::Any => matched > 0,
+            MatchMode::All => matched > 0 && matched == given,
+        }
+    }
+}
+
+/// Determines if two strings are equal, optionally ignoring case. Both are normalized first, so
+/// differently composed accents still match
+///
+/// # Arguments
+///
+/// * `value` - The string being tested
+/// * `query` - The string to compare against
+/// * `ignore_case` - Whether differences in case should be ignored
+pub fn text_equals(value: &str, query: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        formatting::fold_case(value) == formatting::fold_case(query)
+    } else {
+        formatting::normalize(value) == formatting::normalize(query)
+    }
+}
+
+/// Determines if a string contains another, optionally ignoring case. Both are normalized first,
+/// as with `text_equals`
+///
+/// # Arguments
+///
+/// * `value` - The string being searched
+/// * `query` - The substring to search for
+/// * `ignore_case` - Whether differences in case should be ignored
+pub fn text_contains(value: &str, query: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        formatting::fold_case(value).contains(&formatting::fold_case(query))
+    } else {
+        formatting::normalize(value).contains(&formatting::normalize(query))
+    }
+}
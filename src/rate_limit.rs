@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How many views a single client may add to videos on the server
+///
+/// # Fields
+///
+/// * `views` - The most views a client may add within the window
+/// * `seconds` - The length of the window
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RateLimit {
+    pub views: u32,
+    #[serde(default = "default_seconds")]
+    pub seconds: u64,
+}
+
+fn default_seconds() -> u64 {
+    60
+}
+
+/// Keeps track of the views each client added recently
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limit: Option<RateLimit>,
+    clients: HashMap<String, VecDeque<(Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing the given rate, or anything if `None`
+    pub fn new(limit: Option<RateLimit>) -> RateLimiter {
+        RateLimiter {
+            limit,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Records views added by a client if it stays within the limit
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Identifies the client adding the views
+    /// * `views` - The number of views being added
+    ///
+    /// # Returns
+    ///
+    /// `false` if adding the views would go over the limit, in which case they are not recorded
+    pub fn allow(&mut self, client: &str, views: u32) -> bool {
+        self.allow_at(client, views, Instant::now())
+    }
+
+    fn allow_at(&mut self, client: &str, views: u32, now: Instant) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+
+        let window = Duration::from_secs(limit.seconds);
+        let added = self.clients.entry(client.to_string()).or_default();
+        while let Some((time, _)) = added.front() {
+            if now.duration_since(*time) < window {
+                break;
+            }
+            added.pop_front();
+        }
+
+        let recent = added
+            .iter()
+            .map(|(_, views)| *views)
+            .fold(0, u32::saturating_add);
+        if recent.saturating_add(views) > limit.views {
+            return false;
+        }
+
+        added.push_back((now, views));
+        true
+    }
+
+    /// The number of seconds a client turned away should wait before trying again
+    pub fn retry_after(&self) -> u64 {
+        self.limit.map(|limit| limit.seconds).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allow() {
+        let mut limiter = RateLimiter::new(Some(RateLimit {
+            views: 5,
+            seconds: 60,
+        }));
+        let start = Instant::now();
+
+        assert!(limiter.allow_at("a", 3, start));
+        assert!(!limiter.allow_at("a", 3, start));
+        assert!(limiter.allow_at("a", 2, start));
+        assert!(limiter.allow_at("b", 5, start));
+        assert!(limiter.allow_at("a", 5, start + Duration::from_secs(60)));
+    }
+}
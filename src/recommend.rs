@@ -0,0 +1,1105 @@
+use crate::args::command_types::series_subcommands::Series;
+use crate::args::command_types::user_subcommands::{Subscription, User};
+use crate::args::command_types::video_subcommands::{select_video, Video, VideoQuery, Visibility};
+use crate::args::command_types::view_subcommands::WatchEntry;
+use crate::output::{self, Style};
+use crate::repository::{self, Repository};
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use clap::{Args, ValueEnum};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// How much each play counts by how much of the video was watched, from the `[watch_time]` section
+/// of the config file
+///
+/// A play of which the share `completion` was watched counts
+/// `floor + (1 - floor) * completion ^ exponent`, so by default a video watched to 90% counts
+/// about 0.9 and one abandoned at 5% about 0.15. Plays count fully when completion doesn't matter.
+///
+/// # Fields
+///
+/// * `exponent` - How steeply plays count more the more was watched. 1 counts them in
+///   proportion, 2 favors plays watched nearly to the end, and 0 counts every play fully
+/// * `floor` - The least a play counts, however little was watched
+/// * `unknown` - How much a play counts when how much was watched isn't known, as for imported
+///   plays or videos without a duration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WatchTimeConfig {
+    pub exponent: f64,
+    pub floor: f64,
+    pub unknown: f64,
+}
+
+impl Default for WatchTimeConfig {
+    fn default() -> Self {
+        WatchTimeConfig {
+            exponent: 1.0,
+            floor: 0.1,
+            unknown: 1.0,
+        }
+    }
+}
+
+impl WatchTimeConfig {
+    /// How much a play counts
+    ///
+    /// # Arguments
+    ///
+    /// * `completion` - The share of the video watched, between 0 and 1, if known
+    pub fn weight(&self, completion: Option<f64>) -> f64 {
+        match completion {
+            Some(completion) => {
+                let floor = self.floor.clamp(0.0, 1.0);
+                floor + (1.0 - floor) * completion.powf(self.exponent.max(0.0))
+            }
+            None => self.unknown.max(0.0),
+        }
+    }
+}
+
+static WATCH_TIME: OnceLock<WatchTimeConfig> = OnceLock::new();
+
+/// Weighs plays following the given settings. Must be called before any recommendation is made
+pub fn set_watch_time(config: WatchTimeConfig) {
+    WATCH_TIME
+        .set(config)
+        .expect("Watch time weighting set twice");
+}
+
+/// How plays are weighed, as set from the config file or by default
+fn watch_time() -> &'static WatchTimeConfig {
+    WATCH_TIME.get_or_init(WatchTimeConfig::default)
+}
+
+/// How much being watched by those who watched the same videos counts, at most
+const CO_WATCH_WEIGHT: f64 = 1.0;
+
+/// How much having the genres the user watches counts, at most
+const GENRE_WEIGHT: f64 = 1.0;
+
+/// How much being uploaded by a channel the user subscribes to counts
+const SUBSCRIPTION_WEIGHT: f64 = 1.0;
+
+/// How much sharing genres counts, at most, when finding similar videos
+const SHARED_GENRES_WEIGHT: f64 = 1.0;
+
+/// How much being an episode of the same series counts, when finding similar videos
+const SAME_SERIES_WEIGHT: f64 = 1.0;
+
+/// How much being uploaded by the same channel counts, when finding similar videos
+const SAME_CHANNEL_WEIGHT: f64 = 0.5;
+
+/// How much each genre a user picked as an interest counts, as much as a video with it watched to
+/// the end
+const INTEREST_WEIGHT: f64 = 1.0;
+
+/// How much views count, at most. Little, so popularity mostly breaks ties and fills in for
+/// users who haven't watched anything yet
+const POPULARITY_WEIGHT: f64 = 0.1;
+
+#[derive(Debug, Args)]
+pub struct Recommend {
+    /// The ID of the user to recommend videos to, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// The number of videos to recommend
+    #[arg(long, default_value_t = 10)]
+    pub count: usize,
+    /// How to pick the videos to recommend
+    #[arg(long, value_enum, default_value_t = StrategyName::Blended)]
+    pub strategy: StrategyName,
+    #[command(flatten)]
+    pub output: SuggestionOutput,
+}
+
+#[derive(Debug, Args)]
+pub struct SimilarVideos {
+    #[command(flatten)]
+    pub query: VideoQuery,
+    /// The number of similar videos to show
+    #[arg(long, default_value_t = 10)]
+    pub count: usize,
+    #[command(flatten)]
+    pub output: SuggestionOutput,
+}
+
+#[derive(Debug, Args)]
+pub struct DismissVideo {
+    /// The ID of the user not interested in the video, defaulting to the logged in user
+    #[arg(long, default_value = None)]
+    pub user: Option<u32>,
+    /// The ID of the video to stop recommending
+    #[arg(long)]
+    pub video: u32,
+    /// Take back an earlier dismissal, so the video may be recommended again
+    #[arg(long, default_value_t = false)]
+    pub undo: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SuggestionOutput {
+    /// Group the videos under where they come from, e.g. a video watched or trending videos
+    #[arg(long, default_value_t = false)]
+    pub group: bool,
+    /// Print the videos grouped as JSON, for a frontend to render
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+}
+
+/// Why a video is recommended
+///
+/// # Variants
+///
+/// * `Watched` - Those who watched the video with the given name, which the user watched, watched
+///   it too
+/// * `Genre` - It has the given genre, which the user watches
+/// * `Subscribed` - It was uploaded by the channel with the given name, which the user subscribes
+///   to
+/// * `Popular` - It has many views
+/// * `WatchedTogether` - The given number of users watched both it and the video it is similar to
+/// * `SharedGenres` - It has the given genres, like the video it is similar to
+/// * `SameSeries` - It is an episode of the series with the given name, like the video it is
+///   similar to
+/// * `SameChannel` - It was uploaded by the channel with the given name, like the video it is
+///   similar to
+/// * `Recent` - It was uploaded the given number of days ago
+/// * `Trending` - It was watched the given number of times in the last given number of days
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    Watched(String),
+    Genre(String),
+    Subscribed(String),
+    Popular,
+    WatchedTogether(usize),
+    SharedGenres(Vec<String>),
+    SameSeries(String),
+    SameChannel(String),
+    Recent(u64),
+    Trending(usize, u64),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Reason::Watched(name) => t!("recommend.watched", name = name),
+            Reason::Genre(genre) => t!("recommend.genre", genre = genre),
+            Reason::Subscribed(channel) => t!("recommend.subscribed", channel = channel),
+            Reason::Popular => t!("recommend.popular"),
+            Reason::WatchedTogether(1) => t!("recommend.watched_together_once"),
+            Reason::WatchedTogether(users) => t!("recommend.watched_together", users = users),
+            Reason::SharedGenres(genres) => {
+                t!("recommend.shared_genres", genres = genres.join(", "))
+            }
+            Reason::SameSeries(series) => t!("recommend.same_series", series = series),
+            Reason::SameChannel(channel) => t!("recommend.same_channel", channel = channel),
+            Reason::Recent(0) => t!("recommend.new_today"),
+            Reason::Recent(1) => t!("recommend.new_yesterday"),
+            Reason::Recent(days) => t!("recommend.new", days = days),
+            Reason::Trending(1, days) => t!("recommend.trending_once", days = days),
+            Reason::Trending(plays, days) => {
+                t!("recommend.trending", plays = plays, days = days)
+            }
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// The kind of a source of suggestions, e.g. `trending`, and the heading they are shown under
+pub type Source = (&'static str, String);
+
+impl Reason {
+    /// Where a video comes from, so videos recommended for the same reason can be shown together
+    ///
+    /// # Returns
+    ///
+    /// The kind of source, e.g. `watched`, and a heading for it naming the video, genre, series,
+    /// or channel it is about, e.g. `Because you watched The Matrix`
+    pub fn source(&self) -> Source {
+        match self {
+            Reason::Watched(_) => ("watched", self.to_string()),
+            Reason::Genre(_) => ("genre", self.to_string()),
+            Reason::Subscribed(_) => ("subscriptions", t!("recommend.from_subscriptions")),
+            Reason::Popular => ("popular", t!("recommend.heading_popular")),
+            Reason::WatchedTogether(_) => ("watched_together", t!("recommend.same_users")),
+            Reason::SharedGenres(_) => ("shared_genres", t!("recommend.same_genres")),
+            Reason::SameSeries(series) => ("same_series", t!("recommend.more_of", series = series)),
+            Reason::SameChannel(channel) => {
+                ("same_channel", t!("recommend.more_from", channel = channel))
+            }
+            Reason::Recent(_) => ("recent", t!("recommend.new_uploads")),
+            Reason::Trending(..) => ("trending", t!("recommend.heading_trending")),
+        }
+    }
+}
+
+/// A video recommended to a user
+///
+/// # Fields
+///
+/// * `video` - The video recommended
+/// * `score` - How strongly it is recommended
+/// * `reason` - What counted most towards recommending it
+#[derive(Debug)]
+pub struct Suggestion<'a> {
+    pub video: &'a Video,
+    pub score: f64,
+    pub reason: Reason,
+}
+
+/// The records recommendations are made from
+pub struct Catalog {
+    pub users: Vec<User>,
+    pub videos: Vec<Video>,
+    pub history: Vec<WatchEntry>,
+    pub subscriptions: Vec<Subscription>,
+    pub series: Vec<Series>,
+}
+
+/// What a user likes, worked out once to score every video for them
+///
+/// # Fields
+///
+/// * `watched` - How much each video the user watched counts, by how much of it they watched
+/// * `co_watched` - For each video the user hasn't watched, how much others watched it along with
+///   each video the user watched
+/// * `most_co_watched` - The most any video was watched along with those the user watched
+/// * `genres` - How much each genre counts for the user
+/// * `genres_total` - How much the videos the user watched and the interests they picked count
+///   together, of which each genre counts a share
+/// * `channels` - The IDs of the channels the user subscribes to
+/// * `most_views` - The most views any video has
+pub struct Taste {
+    pub watched: HashMap<u32, f64>,
+    pub co_watched: HashMap<u32, HashMap<u32, f64>>,
+    pub most_co_watched: f64,
+    pub genres: HashMap<String, f64>,
+    pub genres_total: f64,
+    pub channels: HashSet<u32>,
+    pub most_views: u32,
+}
+
+/// A way of scoring the videos a user hasn't watched yet, so the highest scored are recommended
+pub trait Strategy {
+    /// What counts towards recommending a video to a user
+    ///
+    /// # Returns
+    ///
+    /// How much each thing counts, along with the reason it gives for the video
+    fn score(&self, catalog: &Catalog, taste: &Taste, video: &Video) -> Vec<(f64, Reason)>;
+}
+
+/// Recommends from everything known: what those who watched the same videos watched, the genres
+/// the user watches, the channels they subscribe to, and views
+pub struct Blended;
+
+impl Strategy for Blended {
+    fn score(&self, catalog: &Catalog, taste: &Taste, video: &Video) -> Vec<(f64, Reason)> {
+        let mut parts: Vec<(f64, Reason)> = vec![];
+        parts.extend(catalog.co_watch_part(taste, video));
+        parts.extend(Catalog::genre_part(
+            video,
+            &taste.genres,
+            taste.genres_total,
+            GENRE_WEIGHT,
+        ));
+        if let Some(channel) = video.owner.filter(|owner| taste.channels.contains(owner)) {
+            parts.push((
+                SUBSCRIPTION_WEIGHT,
+                Reason::Subscribed(catalog.user_name(channel)),
+            ));
+        }
+        if taste.most_views > 0 {
+            parts.push((
+                POPULARITY_WEIGHT * video.views as f64 / taste.most_views as f64,
+                Reason::Popular,
+            ));
+        }
+        parts
+    }
+}
+
+/// Recommends only from how much the genres of a video overlap with those the user watches
+pub struct GenreOverlap;
+
+impl Strategy for GenreOverlap {
+    fn score(&self, _: &Catalog, taste: &Taste, video: &Video) -> Vec<(f64, Reason)> {
+        Catalog::genre_part(video, &taste.genres, taste.genres_total, GENRE_WEIGHT)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Recommends only what those who watched the same videos as the user watched, known as
+/// collaborative filtering
+pub struct CoWatch;
+
+impl Strategy for CoWatch {
+    fn score(&self, catalog: &Catalog, taste: &Taste, video: &Video) -> Vec<(f64, Reason)> {
+        catalog.co_watch_part(taste, video).into_iter().collect()
+    }
+}
+
+/// The strategies videos can be recommended with, so their results can be compared
+///
+/// # Variants
+///
+/// * `Blended` - Everything known counts, see [`Blended`]
+/// * `Genres` - Only the genres the user watches count, see [`GenreOverlap`]
+/// * `CoWatch` - Only what others who watched the same videos watched counts, see [`CoWatch`]
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum StrategyName {
+    #[default]
+    Blended,
+    Genres,
+    CoWatch,
+}
+
+impl StrategyName {
+    pub fn strategy(self) -> Box<dyn Strategy> {
+        match self {
+            StrategyName::Blended => Box::new(Blended),
+            StrategyName::Genres => Box::new(GenreOverlap),
+            StrategyName::CoWatch => Box::new(CoWatch),
+        }
+    }
+}
+
+impl Catalog {
+    pub fn load() -> Self {
+        Catalog {
+            users: storage::load(Store::Users),
+            videos: storage::load(Store::Videos),
+            history: storage::load(Store::History),
+            subscriptions: storage::load(Store::Subscriptions),
+            series: storage::load(Store::Series),
+        }
+    }
+
+    /// The name of a user, or a stand-in if they no longer exist
+    pub fn user_name(&self, id: u32) -> String {
+        self.users
+            .iter()
+            .find(|user| user.id == id)
+            .map(|user| user.name.clone())
+            .unwrap_or_else(|| t!("recommend.unknown_user", id = id))
+    }
+
+    /// How much each play in the watch history counts, by how much of the video was watched
+    ///
+    /// # Returns
+    ///
+    /// A function weighing a watch history entry following the `[watch_time]` config section
+    pub fn play_weight(&self) -> impl Fn(&WatchEntry) -> f64 + '_ {
+        let durations: HashMap<u32, Option<u32>> = self
+            .videos
+            .iter()
+            .map(|video| (video.id, video.duration))
+            .collect();
+        move |entry| {
+            let duration = durations.get(&entry.video).copied().flatten();
+            watch_time().weight(entry.completion(duration))
+        }
+    }
+
+    /// How much each video a user watched counts, by the play of it in which they watched the most
+    pub fn watched_by(&self, user: u32) -> HashMap<u32, f64> {
+        let weight = self.play_weight();
+        let mut watched: HashMap<u32, f64> = HashMap::new();
+        for entry in self.history.iter().filter(|entry| entry.user == Some(user)) {
+            let most = watched.entry(entry.video).or_default();
+            *most = most.max(weight(entry));
+        }
+        watched
+    }
+
+    /// How much each genre counts for a user, summed over how much each video they watched with
+    /// it counts, with each genre they picked as an interest counting as a video fully watched
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user whose genres to weigh
+    /// * `watched` - How much each video the user watched counts
+    ///
+    /// # Returns
+    ///
+    /// How much each genre counts, and how much the videos watched and interests count together
+    pub fn liked_genres(
+        &self,
+        user: &User,
+        watched: &HashMap<u32, f64>,
+    ) -> (HashMap<String, f64>, f64) {
+        let mut genres: HashMap<String, f64> = HashMap::new();
+        for video in &self.videos {
+            let Some(weight) = watched.get(&video.id) else {
+                continue;
+            };
+            for genre in &video.genres {
+                *genres.entry(genre.clone()).or_default() += weight;
+            }
+        }
+        for interest in &user.interests {
+            *genres.entry(interest.clone()).or_default() += INTEREST_WEIGHT;
+        }
+        let total = watched.values().sum::<f64>() + INTEREST_WEIGHT * user.interests.len() as f64;
+        (genres, total)
+    }
+
+    /// The IDs of the channels a user subscribes to
+    pub fn channels_of(&self, user: u32) -> HashSet<u32> {
+        self.subscriptions
+            .iter()
+            .filter(|subscription| subscription.user == user)
+            .map(|subscription| subscription.channel)
+            .collect()
+    }
+
+    /// How much a video having the genres a user watches counts, explained by the genre they watch
+    /// most
+    ///
+    /// # Arguments
+    ///
+    /// * `video` - The video to score
+    /// * `genres` - How much each genre counts for the user
+    /// * `watched` - How much the videos the user watched and the interests they picked count
+    ///   together
+    /// * `weight` - How much it counts, at most
+    pub fn genre_part(
+        video: &Video,
+        genres: &HashMap<String, f64>,
+        watched: f64,
+        weight: f64,
+    ) -> Option<(f64, Reason)> {
+        let liked = video
+            .genres
+            .iter()
+            .filter_map(|genre| Some((genre, *genres.get(genre.as_str())?)));
+        let share: f64 = liked.clone().map(|(_, count)| count / watched).sum();
+        let (genre, _) = liked.max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        (share > 0.0).then(|| (weight * share.min(1.0), Reason::Genre(genre.clone())))
+    }
+
+    /// Whether a video may be recommended to a user: it is public, not age-restricted if they are
+    /// a minor, and not dismissed by them
+    pub fn may_recommend(user: &User, video: &Video) -> bool {
+        video.visibility == Visibility::Public
+            && !(video.age_restricted && user.is_minor())
+            && !user.dismissed.contains(&video.id)
+    }
+
+    /// Works out what a user likes from the videos they and others watched, the genres they watch
+    /// or picked as interests, and the channels they subscribe to
+    pub fn taste(&self, user: &User) -> Taste {
+        let watched = self.watched_by(user.id);
+
+        // For each video, how much others watched it along with each video the user watched,
+        // counting the plays of both by how much of them was watched
+        let mut watchers: HashMap<u32, HashMap<u32, f64>> = HashMap::new();
+        let weight = self.play_weight();
+        for entry in &self.history {
+            match entry.user {
+                Some(other) if other != user.id => {
+                    let most = watchers
+                        .entry(other)
+                        .or_default()
+                        .entry(entry.video)
+                        .or_default();
+                    *most = most.max(weight(entry));
+                }
+                _ => {}
+            }
+        }
+        let mut co_watched: HashMap<u32, HashMap<u32, f64>> = HashMap::new();
+        for others in watchers.values() {
+            let shared: Vec<(u32, f64)> = others
+                .iter()
+                .filter_map(|(id, theirs)| Some((*id, theirs * watched.get(id)?)))
+                .collect();
+            for (video, theirs) in others.iter().filter(|(id, _)| !watched.contains_key(id)) {
+                let by = co_watched.entry(*video).or_default();
+                for (id, together) in &shared {
+                    *by.entry(*id).or_default() += theirs * together;
+                }
+            }
+        }
+        let most_co_watched = co_watched
+            .values()
+            .map(|by| by.values().sum::<f64>())
+            .fold(0.0, f64::max);
+
+        let (genres, genres_total) = self.liked_genres(user, &watched);
+        Taste {
+            genres,
+            genres_total,
+            channels: self.channels_of(user.id),
+            most_views: self
+                .videos
+                .iter()
+                .map(|video| video.views)
+                .max()
+                .unwrap_or(0),
+            watched,
+            co_watched,
+            most_co_watched,
+        }
+    }
+
+    /// How much a video being watched by those who watched the same videos as a user counts,
+    /// explained by the video they watched it along with most
+    pub fn co_watch_part(&self, taste: &Taste, video: &Video) -> Option<(f64, Reason)> {
+        let by = taste.co_watched.get(&video.id)?;
+        let total: f64 = by.values().sum();
+        let (strongest, _) = by
+            .iter()
+            .max_by(|(a_id, a), (b_id, b)| a.total_cmp(b).then(b_id.cmp(a_id)))?;
+        let strongest = self.videos.iter().find(|other| other.id == *strongest)?;
+        (total > 0.0).then(|| {
+            (
+                CO_WATCH_WEIGHT * total / taste.most_co_watched,
+                Reason::Watched(strongest.name.clone()),
+            )
+        })
+    }
+
+    /// Recommends videos a user hasn't watched yet, scored by the given strategy
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to recommend videos to
+    /// * `count` - The most videos to recommend
+    /// * `strategy` - How to score each video
+    ///
+    /// # Returns
+    ///
+    /// The videos recommended, the most strongly recommended first
+    pub fn recommend(
+        &self,
+        user: &User,
+        count: usize,
+        strategy: &dyn Strategy,
+    ) -> Vec<Suggestion<'_>> {
+        let taste = self.taste(user);
+        let mut suggestions: Vec<Suggestion> = self
+            .videos
+            .iter()
+            .filter(|video| {
+                !taste.watched.contains_key(&video.id) && Self::may_recommend(user, video)
+            })
+            .filter_map(|video| Suggestion::from_parts(video, strategy.score(self, &taste, video)))
+            .collect();
+        rank(&mut suggestions, count);
+        suggestions
+    }
+
+    /// Finds the videos most like a video: those watched by the same users, sharing its genres,
+    /// in the same series, or from the same channel, to build an "up next" list
+    ///
+    /// # Arguments
+    ///
+    /// * `video` - The video to find similar videos to
+    /// * `count` - The most videos to find
+    ///
+    /// # Returns
+    ///
+    /// The similar videos, the most similar first
+    pub fn similar(&self, video: &Video, count: usize) -> Vec<Suggestion<'_>> {
+        let mut viewers: HashMap<u32, HashSet<u32>> = HashMap::new();
+        for entry in &self.history {
+            if let Some(user) = entry.user {
+                viewers.entry(entry.video).or_default().insert(user);
+            }
+        }
+        let empty = HashSet::new();
+        let watched_it = viewers.get(&video.id).unwrap_or(&empty);
+        let together = |other: &Video| {
+            viewers
+                .get(&other.id)
+                .map_or(0, |users| users.intersection(watched_it).count())
+        };
+        let most_together = self
+            .videos
+            .iter()
+            .filter(|other| other.id != video.id)
+            .map(together)
+            .max()
+            .unwrap_or(0);
+
+        let mut suggestions: Vec<Suggestion> = self
+            .videos
+            .iter()
+            .filter(|other| other.id != video.id && other.visibility == Visibility::Public)
+            .filter_map(|other| {
+                let mut parts: Vec<(f64, Reason)> = vec![];
+
+                let users = together(other);
+                if users > 0 {
+                    parts.push((
+                        CO_WATCH_WEIGHT * users as f64 / most_together as f64,
+                        Reason::WatchedTogether(users),
+                    ));
+                }
+
+                let shared: Vec<String> = other
+                    .genres
+                    .iter()
+                    .filter(|genre| video.genres.contains(genre))
+                    .cloned()
+                    .collect();
+                if !shared.is_empty() {
+                    let genres: HashSet<&String> =
+                        video.genres.iter().chain(&other.genres).collect();
+                    parts.push((
+                        SHARED_GENRES_WEIGHT * shared.len() as f64 / genres.len() as f64,
+                        Reason::SharedGenres(shared),
+                    ));
+                }
+
+                if let Some(series) = video.series.filter(|series| other.series == Some(*series)) {
+                    let name = self
+                        .series
+                        .iter()
+                        .find(|s| s.id == series)
+                        .map(|s| s.name.clone())
+                        .unwrap_or_else(|| t!("recommend.unknown_series", id = series));
+                    parts.push((SAME_SERIES_WEIGHT, Reason::SameSeries(name)));
+                }
+
+                if let Some(owner) = video.owner.filter(|owner| other.owner == Some(*owner)) {
+                    parts.push((
+                        SAME_CHANNEL_WEIGHT,
+                        Reason::SameChannel(self.user_name(owner)),
+                    ));
+                }
+
+                Suggestion::from_parts(other, parts)
+            })
+            .collect();
+        rank(&mut suggestions, count);
+        suggestions
+    }
+}
+
+impl<'a> Suggestion<'a> {
+    /// Makes a suggestion from what counts towards it, explained by what counts most
+    ///
+    /// # Returns
+    ///
+    /// The suggestion, or `None` if nothing counts towards it
+    pub fn from_parts(video: &'a Video, parts: Vec<(f64, Reason)>) -> Option<Self> {
+        let score: f64 = parts.iter().map(|(score, _)| score).sum();
+        let (_, reason) = parts.into_iter().max_by(|(a, _), (b, _)| a.total_cmp(b))?;
+        (score > 0.0).then_some(Suggestion {
+            video,
+            score,
+            reason,
+        })
+    }
+}
+
+/// Orders suggestions from the strongest, breaking ties by views, and keeps the given number
+pub fn rank(suggestions: &mut Vec<Suggestion>, count: usize) {
+    suggestions.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.video.views.cmp(&a.video.views))
+            .then(a.video.id.cmp(&b.video.id))
+    });
+    suggestions.truncate(count);
+}
+
+/// Groups suggestions by where they come from, ordering the groups by their highest ranked
+/// suggestion
+///
+/// # Returns
+///
+/// The kind and heading of each source, along with the rank and suggestion of each video from it
+pub fn group<'s, 'a>(
+    suggestions: &'s [Suggestion<'a>],
+) -> Vec<(Source, Vec<(usize, &'s Suggestion<'a>)>)> {
+    let mut groups: Vec<(Source, Vec<(usize, &Suggestion)>)> = vec![];
+    for (rank, suggestion) in suggestions.iter().enumerate() {
+        let source = suggestion.reason.source();
+        match groups.iter_mut().find(|(other, _)| *other == source) {
+            Some((_, members)) => members.push((rank + 1, suggestion)),
+            None => groups.push((source, vec![(rank + 1, suggestion)])),
+        }
+    }
+    groups
+}
+
+/// Describes suggestions grouped by where they come from as JSON, e.g.
+/// `{"groups": [{"source": "trending", "heading": "Trending", "videos": [...]}]}`, each video
+/// having its `rank`, `id`, `name`, `score`, and `reason`
+pub fn to_json(suggestions: &[Suggestion]) -> Value {
+    let groups: Vec<Value> = group(suggestions)
+        .into_iter()
+        .map(|((source, heading), members)| {
+            let videos: Vec<Value> = members
+                .into_iter()
+                .map(|(rank, suggestion)| {
+                    json!({
+                        "rank": rank,
+                        "id": suggestion.video.id,
+                        "name": suggestion.video.name,
+                        "score": suggestion.score,
+                        "reason": suggestion.reason.to_string(),
+                    })
+                })
+                .collect();
+            json!({ "source": source, "heading": heading, "videos": videos })
+        })
+        .collect();
+    json!({ "groups": groups })
+}
+
+/// Prints suggestions as asked: in order, each followed by the reason for it, or grouped under
+/// where they come from, or as JSON
+///
+/// # Arguments
+///
+/// * `suggestions` - The suggestions to print, the highest ranked first
+/// * `output` - How to print them
+/// * `nothing` - The warning to give if there are no suggestions, unless printing JSON
+pub fn print_suggestions(suggestions: &[Suggestion], output: &SuggestionOutput, nothing: &str) {
+    if output.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&to_json(suggestions)).unwrap()
+        );
+        return;
+    }
+    if suggestions.is_empty() {
+        warn!("{}", nothing);
+        return;
+    }
+    if output.group {
+        for (number, ((_, heading), members)) in group(suggestions).into_iter().enumerate() {
+            if number > 0 {
+                println!();
+            }
+            println!("{}", output::paint_stdout(&heading, Style::Header));
+            for (rank, suggestion) in members {
+                println!(
+                    "{}. {} ({})",
+                    rank, suggestion.video.name, suggestion.video.id
+                );
+            }
+        }
+        return;
+    }
+
+    for (rank, suggestion) in suggestions.iter().enumerate() {
+        println!(
+            "{}. {} ({})",
+            rank + 1,
+            suggestion.video.name,
+            suggestion.video.id
+        );
+        println!(
+            "   {}",
+            output::paint_stdout(&suggestion.reason.to_string(), Style::Detail)
+        );
+    }
+}
+
+/// Handles recommending videos to a user, printing each with the reason it was recommended
+///
+/// # Arguments
+///
+/// * `recommend` - The arguments for the recommendations
+pub fn handle_recommend(recommend: Recommend) {
+    let Some(id) = recommend.user.or_else(session::current_user) else {
+        error!("{}", t!("user.none_given"));
+        return;
+    };
+    let catalog = Catalog::load();
+    let Some(user) = catalog.users.iter().find(|user| user.id == id) else {
+        error!("{}", t!("user.not_found_id", id = id));
+        return;
+    };
+
+    let suggestions = catalog.recommend(
+        user,
+        recommend.count,
+        recommend.strategy.strategy().as_ref(),
+    );
+    print_suggestions(
+        &suggestions,
+        &recommend.output,
+        &t!("recommend.nothing", name = user.name),
+    );
+}
+
+/// Handles dismissing a video for a user, or taking a dismissal back with `--undo`. Dismissed
+/// videos are left out of their recommendations and feed
+///
+/// # Arguments
+///
+/// * `dismiss_video` - The arguments for the dismissal
+pub fn handle_dismiss_video(dismiss_video: DismissVideo) {
+    let Some(id) = dismiss_video.user.or_else(session::current_user) else {
+        error!("{}", t!("user.none_given"));
+        return;
+    };
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    let name = match videos.iter().find(|video| video.id == dismiss_video.video) {
+        Some(video) => video.name.clone(),
+        None if dismiss_video.undo => dismiss_video.video.to_string(),
+        None => {
+            error!("{}", t!("video.not_found_id", id = dismiss_video.video));
+            return;
+        }
+    };
+
+    repository::retry(|| {
+        let mut users = Repository::<User>::load();
+        let Some(mut user) = users.get(id).cloned() else {
+            error!("{}", t!("user.not_found_id", id = id));
+            return Ok(());
+        };
+
+        let dismissed = user.dismissed.contains(&dismiss_video.video);
+        match (dismiss_video.undo, dismissed) {
+            (false, true) => {
+                warn!(
+                    "{}",
+                    t!(
+                        "recommend.already_dismissed",
+                        user = user.name,
+                        video = name
+                    )
+                );
+                return Ok(());
+            }
+            (true, false) => {
+                warn!(
+                    "{}",
+                    t!("recommend.not_dismissed", user = user.name, video = name)
+                );
+                return Ok(());
+            }
+            (false, false) => user.dismissed.push(dismiss_video.video),
+            (true, true) => user.dismissed.retain(|video| *video != dismiss_video.video),
+        }
+        let user_name = user.name.clone();
+        users.replace(user);
+        users.try_save()?;
+
+        match dismiss_video.undo {
+            true => info!(
+                "{}",
+                t!("recommend.undismissed", video = name, user = user_name)
+            ),
+            false => info!(
+                "{}",
+                t!("recommend.dismissed", video = name, user = user_name)
+            ),
+        }
+        Ok(())
+    });
+}
+
+/// Handles listing the videos most like a video, printing each with what makes it similar
+///
+/// # Arguments
+///
+/// * `similar_videos` - The arguments for the listing
+pub fn handle_similar_videos(similar_videos: SimilarVideos) {
+    if similar_videos.query.is_empty() {
+        error!("{}", t!("video.no_query"));
+        return;
+    }
+    let catalog = Catalog::load();
+    let videos = Repository::new(catalog.videos.clone());
+    let Some(video) = select_video(
+        &videos,
+        &similar_videos.query,
+        &t!("recommend.finding_similar"),
+    ) else {
+        return;
+    };
+
+    let suggestions = catalog.similar(video, similar_videos.count);
+    print_suggestions(
+        &suggestions,
+        &similar_videos.output,
+        &t!("recommend.nothing_similar", name = video.name),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(id: u32, genres: &[&str], owner: Option<u32>, views: u32) -> Video {
+        Video {
+            id,
+            name: format!("Video {}", id),
+            genres: genres.iter().map(|genre| genre.to_string()).collect(),
+            owner,
+            views,
+            ..Default::default()
+        }
+    }
+
+    fn watch(user: u32, video: u32) -> WatchEntry {
+        WatchEntry {
+            video,
+            user: Some(user),
+            started_at: 0,
+            seconds: 60,
+            finished: true,
+        }
+    }
+
+    #[test]
+    fn test_recommend() {
+        let users: Vec<User> = (1..=3)
+            .map(|id| User {
+                id,
+                name: format!("User {}", id),
+                ..Default::default()
+            })
+            .collect();
+        let catalog = Catalog {
+            videos: vec![
+                video(1, &["Drama"], None, 10),
+                video(2, &[], None, 0),
+                video(3, &["Drama"], None, 0),
+                video(4, &[], Some(3), 0),
+                video(5, &[], None, 100),
+                Video {
+                    visibility: Visibility::Private,
+                    ..video(6, &["Drama"], None, 1000)
+                },
+            ],
+            history: vec![watch(1, 1), watch(2, 1), watch(2, 2)],
+            subscriptions: vec![Subscription {
+                user: 1,
+                channel: 3,
+                created_at: None,
+            }],
+            series: vec![],
+            users,
+        };
+
+        let suggestions = catalog.recommend(&catalog.users[0], 10, &Blended);
+        let reasons: Vec<(u32, String)> = suggestions
+            .iter()
+            .map(|suggestion| (suggestion.video.id, suggestion.reason.to_string()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (2, "Because you watched Video 1".to_string()),
+                (3, "Because you like Drama".to_string()),
+                (4, "From User 3, whom you subscribe to".to_string()),
+                (5, "Popular with everyone".to_string()),
+            ]
+        );
+        assert_eq!(catalog.recommend(&catalog.users[0], 1, &Blended).len(), 1);
+        let dismissing = User {
+            dismissed: vec![2, 4],
+            ..catalog.users[0].clone()
+        };
+        let ids: Vec<u32> = catalog
+            .recommend(&dismissing, 10, &Blended)
+            .iter()
+            .map(|suggestion| suggestion.video.id)
+            .collect();
+        assert_eq!(ids, [3, 5]);
+
+        let only = |strategy: StrategyName| -> Vec<u32> {
+            catalog
+                .recommend(&catalog.users[0], 10, strategy.strategy().as_ref())
+                .iter()
+                .map(|suggestion| suggestion.video.id)
+                .collect()
+        };
+        assert_eq!(only(StrategyName::Genres), [3]);
+        assert_eq!(only(StrategyName::CoWatch), [2]);
+
+        let newcomer = User {
+            id: 4,
+            interests: vec!["Drama".to_string()],
+            ..Default::default()
+        };
+        let reasons: Vec<(u32, String)> = catalog
+            .recommend(&newcomer, 1, &GenreOverlap)
+            .iter()
+            .map(|suggestion| (suggestion.video.id, suggestion.reason.to_string()))
+            .collect();
+        assert_eq!(reasons, [(1, "Because you like Drama".to_string())]);
+
+        let json = to_json(&suggestions);
+        let groups = json["groups"].as_array().unwrap();
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[0]["source"], "watched");
+        assert_eq!(groups[0]["heading"], "Because you watched Video 1");
+        assert_eq!(groups[0]["videos"][0]["id"], 2);
+        assert_eq!(groups[2]["heading"], "From your subscriptions");
+        assert_eq!(groups[3]["videos"][0]["rank"], 4);
+
+        let similar: Vec<(u32, String)> = catalog
+            .similar(&catalog.videos[0], 10)
+            .iter()
+            .map(|suggestion| (suggestion.video.id, suggestion.reason.to_string()))
+            .collect();
+        assert_eq!(
+            similar,
+            [
+                (2, "Watched along with it by 1 user".to_string()),
+                (3, "Also Drama".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_watch_time_weight() {
+        let weights = WatchTimeConfig::default();
+        assert_eq!(weights.weight(None), 1.0);
+        assert!((weights.weight(Some(0.9)) - 0.91).abs() < 1e-9);
+        assert!((weights.weight(Some(0.05)) - 0.145).abs() < 1e-9);
+
+        let play = |user, video, seconds| WatchEntry {
+            seconds,
+            ..watch(user, video)
+        };
+        assert_eq!(play(1, 1, 0).completion(Some(100)), None);
+        assert_eq!(play(1, 1, 150).completion(Some(100)), Some(1.0));
+
+        // Others abandoned video 2 but finished video 3, after watching video 1 like user 1
+        let timed = |id| Video {
+            duration: Some(100),
+            ..video(id, &[], None, 0)
+        };
+        let catalog = Catalog {
+            users: vec![User {
+                id: 1,
+                ..Default::default()
+            }],
+            videos: vec![timed(1), timed(2), timed(3)],
+            history: vec![
+                play(1, 1, 100),
+                play(2, 1, 100),
+                play(2, 2, 5),
+                play(3, 1, 100),
+                play(3, 3, 90),
+            ],
+            subscriptions: vec![],
+            series: vec![],
+        };
+        let ids: Vec<u32> = catalog
+            .recommend(&catalog.users[0], 10, &CoWatch)
+            .iter()
+            .map(|suggestion| suggestion.video.id)
+            .collect();
+        assert_eq!(ids, [3, 2]);
+    }
+}
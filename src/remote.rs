@@ -0,0 +1,118 @@
+use crate::storage::Store;
+use crate::t;
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::process;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// The URL of the rustflix server the stores are kept on, if any
+static REMOTE: OnceLock<String> = OnceLock::new();
+
+/// The API key token sent with every request, if any
+static TOKEN: OnceLock<String> = OnceLock::new();
+
+/// Makes every store be read from and written to the rustflix server at the given URL instead of
+/// the data directory
+///
+/// # Arguments
+///
+/// * `url` - The URL of a running `rustflix serve`, e.g. `http://host:8080`
+pub fn set_url(url: &str) {
+    REMOTE
+        .set(url.trim_end_matches('/').to_string())
+        .expect("Remote URL set twice");
+}
+
+/// The URL of the rustflix server the stores are kept on, or `None` if they are kept locally
+pub fn url() -> Option<&'static str> {
+    REMOTE.get().map(String::as_str)
+}
+
+/// Sets the API key token sent with every request to the server
+pub fn set_token(token: String) {
+    TOKEN.set(token).expect("Token set twice");
+}
+
+/// Adds the API key token to a request, if one was given
+fn authorize(request: ureq::Request) -> ureq::Request {
+    match TOKEN.get() {
+        Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+/// The URL the given store is served at
+fn store_url(remote: &str, store: Store) -> String {
+    format!("{}/{}", remote, store.route())
+}
+
+/// Fetches every record of the given store from the server
+///
+/// Exits the program if the server can't be reached, as no command can go on without its records.
+///
+/// # Arguments
+///
+/// * `remote` - The URL of the server
+/// * `store` - The store to fetch
+pub fn load<T: DeserializeOwned>(remote: &str, store: Store) -> Vec<T> {
+    let url = store_url(remote, store);
+    let start = Instant::now();
+    let records: Vec<T> = match authorize(ureq::get(&url)).call() {
+        Ok(response) => match response.into_json() {
+            Ok(records) => records,
+            Err(e) => {
+                error!("{}", t!("remote.invalid_response", url = url, error = e));
+                process::exit(1);
+            }
+        },
+        Err(e) => request_failed(&url, e),
+    };
+    debug!(
+        "Loaded {} records from {} in {:?}",
+        records.len(),
+        url,
+        start.elapsed()
+    );
+    records
+}
+
+/// Overwrites the given store on the server with the given records
+///
+/// # Arguments
+///
+/// * `remote` - The URL of the server
+/// * `store` - The store to write
+/// * `records` - The records to write to the store
+pub fn save<T: Serialize>(remote: &str, store: Store, records: &Vec<T>) {
+    let url = store_url(remote, store);
+    let start = Instant::now();
+    if let Err(e) = authorize(ureq::put(&url)).send_json(records) {
+        request_failed(&url, e);
+    }
+    debug!(
+        "Saved {} records to {} in {:?}",
+        records.len(),
+        url,
+        start.elapsed()
+    );
+}
+
+/// Reports a failed request and exits
+fn request_failed(url: &str, e: ureq::Error) -> ! {
+    match e {
+        ureq::Error::Status(code, response) => error!(
+            "{}",
+            t!(
+                "remote.responded_with",
+                url = url,
+                code = code,
+                body = response.into_string().unwrap_or_default()
+            )
+        ),
+        // The transport error already names the URL
+        ureq::Error::Transport(e) => error!("{}", t!("remote.unreachable", error = e)),
+    }
+    process::exit(1);
+}
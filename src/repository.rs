@@ -0,0 +1,446 @@
+use crate::formatting;
+use crate::storage::{self, Store};
+use crate::t;
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Deref;
+use std::process;
+
+/// How many times `retry` runs a change again when someone else changed the same record meanwhile
+const MAX_ATTEMPTS: u32 = 5;
+
+/// A record that can be kept in a `Repository` and looked up by ID, name, and email
+pub trait Record: Serialize + DeserializeOwned {
+    /// The store records of this kind are kept in
+    const STORE: Store;
+
+    /// The ID of the record
+    fn id(&self) -> u32;
+
+    /// The name of the record
+    fn name(&self) -> &str;
+
+    /// The email address of the record, for records that have one
+    fn email(&self) -> Option<&str> {
+        None
+    }
+
+    /// How many times the record was changed, so a change made meanwhile by someone else can be
+    /// told apart. 0 for records never changed since revisions were added
+    fn revision(&self) -> u32;
+
+    /// Sets how many times the record was changed
+    fn set_revision(&mut self, revision: u32);
+}
+
+/// A record was changed by someone else between being read and being written
+///
+/// # Fields
+///
+/// * `store` - The store the record is kept in
+/// * `id` - The ID of the record
+#[derive(Debug)]
+pub struct Conflict {
+    pub store: Store,
+    pub id: u32,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            t!(
+                "storage.conflict",
+                id = self.id,
+                file = self.store.file_name()
+            )
+        )
+    }
+}
+
+/// Runs a change that reads records, changes them, and writes them, again from the start each
+/// time it is interrupted by someone else changing the same records, up to `MAX_ATTEMPTS` times
+///
+/// # Arguments
+///
+/// * `attempt` - The change. Returns `Ok` once it is written, or when it gave up with an error
+///   of its own
+pub fn retry(mut attempt: impl FnMut() -> Result<(), Conflict>) {
+    for number in 1..=MAX_ATTEMPTS {
+        match attempt() {
+            Ok(()) => return,
+            Err(conflict) if number < MAX_ATTEMPTS => debug!("{}. Trying again", conflict),
+            Err(conflict) => error!(
+                "{}",
+                t!(
+                    "storage.gave_up",
+                    conflict = conflict,
+                    attempts = MAX_ATTEMPTS
+                )
+            ),
+        }
+    }
+}
+
+/// The positions of records by some key, in the order the records are kept
+type Positions<K> = HashMap<K, Vec<usize>>;
+
+/// The records of a store along with indexes to find them by ID, name, and email without going
+/// through every record
+///
+/// Names and email addresses are indexed with their case folded, so lookups return every record
+/// that may match a query ignoring case. The records can be read through `Deref`, but can only be changed
+/// through the repository, so the indexes always match them, and the repository knows which
+/// records to write back.
+#[derive(Debug)]
+pub struct Repository<T> {
+    records: Vec<T>,
+    by_id: Positions<u32>,
+    by_name: Positions<String>,
+    by_email: Positions<String>,
+    /// The revision each record added, replaced, or removed had when it was read, or `None` for
+    /// records added
+    read_revisions: BTreeMap<u32, Option<u32>>,
+}
+
+/// Adds a position to the list of positions for a key, keeping the list in order
+fn add_position<K: std::hash::Hash + Eq>(positions: &mut Positions<K>, key: K, position: usize) {
+    let list = positions.entry(key).or_default();
+    let index = list.partition_point(|other| *other < position);
+    list.insert(index, position);
+}
+
+/// Removes a position from the list of positions for a key
+fn remove_position<K: std::hash::Hash + Eq>(
+    positions: &mut Positions<K>,
+    key: &K,
+    position: usize,
+) {
+    if let Some(list) = positions.get_mut(key) {
+        list.retain(|other| *other != position);
+        if list.is_empty() {
+            positions.remove(key);
+        }
+    }
+}
+
+impl<T: Record> Repository<T> {
+    /// Indexes the given records
+    pub fn new(records: Vec<T>) -> Self {
+        let mut repository = Repository {
+            records,
+            by_id: HashMap::new(),
+            by_name: HashMap::new(),
+            by_email: HashMap::new(),
+            read_revisions: BTreeMap::new(),
+        };
+        repository.reindex();
+        repository
+    }
+
+    /// Loads and indexes every record of the store records of this kind are kept in
+    pub fn load() -> Self {
+        Self::new(storage::load(T::STORE))
+    }
+
+    /// Writes the records added, replaced, or removed through the repository back to the store
+    /// records of this kind are kept in, stopping if someone else changed any of them since they
+    /// were read
+    ///
+    /// See `try_save`.
+    pub fn save(&mut self) {
+        if let Err(conflict) = self.try_save() {
+            error!("{}", t!("storage.run_again", conflict = conflict));
+            process::exit(1);
+        }
+    }
+
+    /// Writes the records added, replaced, or removed through the repository back to the store
+    /// records of this kind are kept in
+    ///
+    /// The store is read again first, so records changed meanwhile by someone else are kept as
+    /// they are now instead of being overwritten with the copies read earlier. The store stays
+    /// locked from then until it is written, so nobody can change it in between. Through a
+    /// running daemon, which can't be locked, the daemon refuses the write instead if anyone did.
+    /// Each record written gets the next revision. Afterwards, the repository holds the records
+    /// as they were written.
+    ///
+    /// # Returns
+    ///
+    /// A conflict, leaving the store as it was, if someone else changed, added, or removed any of
+    /// the records since they were read. The repository must then be loaded again, as `retry`
+    /// does
+    pub fn try_save(&mut self) -> Result<(), Conflict> {
+        let _lock = storage::lock(T::STORE);
+        let mut latest = Self::new(storage::load(T::STORE));
+        let latest_revisions: BTreeMap<u32, u32> = latest
+            .records
+            .iter()
+            .map(|record| (record.id(), record.revision()))
+            .collect();
+        for (&id, &read) in &self.read_revisions {
+            if latest.get(id).map(Record::revision) != read {
+                return Err(Conflict {
+                    store: T::STORE,
+                    id,
+                });
+            }
+        }
+
+        let read_revisions = std::mem::take(&mut self.read_revisions);
+        let mut changed: HashMap<u32, T> = std::mem::take(&mut self.records)
+            .into_iter()
+            .filter(|record| read_revisions.contains_key(&record.id()))
+            .map(|record| (record.id(), record))
+            .collect();
+        for (id, read) in read_revisions {
+            match changed.remove(&id) {
+                Some(mut record) => {
+                    record.set_revision(read.map_or(1, |revision| revision + 1));
+                    if latest.get(id).is_some() {
+                        latest.replace(record);
+                    } else {
+                        latest.push(record);
+                    }
+                }
+                None => {
+                    latest.remove(id);
+                }
+            }
+        }
+
+        storage::save_checked(T::STORE, &latest.records, &latest_revisions).map_err(|id| {
+            Conflict {
+                store: T::STORE,
+                id,
+            }
+        })?;
+        latest.read_revisions.clear();
+        *self = latest;
+        Ok(())
+    }
+
+    /// Rebuilds every index from scratch
+    fn reindex(&mut self) {
+        self.by_id.clear();
+        self.by_name.clear();
+        self.by_email.clear();
+        for position in 0..self.records.len() {
+            self.index(position);
+        }
+    }
+
+    /// Adds the record at the given position to every index
+    fn index(&mut self, position: usize) {
+        let record = &self.records[position];
+        add_position(&mut self.by_id, record.id(), position);
+        add_position(
+            &mut self.by_name,
+            formatting::fold_case(record.name()),
+            position,
+        );
+        if let Some(email) = record.email() {
+            add_position(&mut self.by_email, formatting::fold_case(email), position);
+        }
+    }
+
+    /// Removes the record at the given position from every index
+    fn unindex(&mut self, position: usize) {
+        let record = &self.records[position];
+        remove_position(&mut self.by_id, &record.id(), position);
+        remove_position(
+            &mut self.by_name,
+            &formatting::fold_case(record.name()),
+            position,
+        );
+        if let Some(email) = record.email() {
+            remove_position(&mut self.by_email, &formatting::fold_case(email), position);
+        }
+    }
+
+    /// The records at the given positions
+    fn at(&self, positions: Option<&Vec<usize>>) -> Vec<&T> {
+        positions
+            .map(|positions| {
+                positions
+                    .iter()
+                    .map(|position| &self.records[*position])
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The record with the given ID, if any
+    pub fn get(&self, id: u32) -> Option<&T> {
+        self.with_id(id).into_iter().next()
+    }
+
+    /// Every record with the given ID. IDs are unique unless a store was edited by hand
+    pub fn with_id(&self, id: u32) -> Vec<&T> {
+        self.at(self.by_id.get(&id))
+    }
+
+    /// Every record with the given name, ignoring case
+    pub fn with_name(&self, name: &str) -> Vec<&T> {
+        self.at(self.by_name.get(&formatting::fold_case(name)))
+    }
+
+    /// Every record with the given email address, ignoring case
+    pub fn with_email(&self, email: &str) -> Vec<&T> {
+        self.at(self.by_email.get(&formatting::fold_case(email)))
+    }
+
+    /// Adds a record after every other
+    pub fn push(&mut self, record: T) {
+        self.read_revisions.entry(record.id()).or_insert(None);
+        self.records.push(record);
+        self.index(self.records.len() - 1);
+    }
+
+    /// Replaces the record with the same ID as the given one
+    ///
+    /// # Returns
+    ///
+    /// The record replaced, or `None` if there is no record with that ID
+    pub fn replace(&mut self, record: T) -> Option<T> {
+        let position = *self.by_id.get(&record.id())?.first()?;
+        self.read_revisions
+            .entry(record.id())
+            .or_insert(Some(self.records[position].revision()));
+        self.unindex(position);
+        let old = std::mem::replace(&mut self.records[position], record);
+        self.index(position);
+        Some(old)
+    }
+
+    /// Removes the record with the given ID
+    ///
+    /// Every record after it moves up, so the indexes are rebuilt.
+    ///
+    /// # Returns
+    ///
+    /// The record removed, or `None` if there is no record with that ID
+    pub fn remove(&mut self, id: u32) -> Option<T> {
+        let position = *self.by_id.get(&id)?.first()?;
+        self.read_revisions
+            .entry(id)
+            .or_insert(Some(self.records[position].revision()));
+        let record = self.records.remove(position);
+        self.reindex();
+        Some(record)
+    }
+
+    /// Gives up the indexes, leaving the records
+    pub fn into_records(self) -> Vec<T> {
+        self.records
+    }
+}
+
+impl<T> Deref for Repository<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::command_types::user_subcommands::User;
+    use std::{env, fs, thread};
+
+    fn user(id: u32, name: &str, email: &str) -> User {
+        User {
+            id,
+            name: name.to_string(),
+            email: email.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_indexes_follow_changes() {
+        let mut users = Repository::new(vec![
+            user(1, "Alice", "alice@example.com"),
+            user(2, "Bob", "bob@example.com"),
+            user(3, "alice", "other@example.com"),
+        ]);
+        assert_eq!(users.with_name("ALICE").len(), 2);
+        assert_eq!(users.with_email("Bob@Example.com")[0].id, 2);
+
+        users.replace(user(2, "Robert", "robert@example.com"));
+        assert!(users.with_name("Bob").is_empty());
+        assert!(users.with_email("bob@example.com").is_empty());
+        assert_eq!(users.with_name("robert")[0].id, 2);
+
+        users.remove(1);
+        assert_eq!(users.with_name("alice")[0].id, 3);
+        assert_eq!(users.get(3).unwrap().email, "other@example.com");
+
+        users.push(user(4, "Alice", "new@example.com"));
+        let ids: Vec<u32> = users
+            .with_name("alice")
+            .iter()
+            .map(|user| user.id)
+            .collect();
+        assert_eq!(ids, [3, 4]);
+        assert!(users.get(1).is_none());
+    }
+
+    #[test]
+    fn test_read_revisions() {
+        let mut first = user(1, "Alice", "alice@example.com");
+        first.revision = 3;
+        let mut users = Repository::new(vec![first, user(2, "Bob", "bob@example.com")]);
+        assert!(users.read_revisions.is_empty());
+
+        let mut changed = users.get(1).unwrap().clone();
+        changed.revision = 9;
+        users.replace(changed.clone());
+        users.replace(changed);
+        users.remove(2);
+        users.push(user(3, "Carol", "carol@example.com"));
+        assert_eq!(
+            users.read_revisions,
+            BTreeMap::from([(1, Some(3)), (2, Some(0)), (3, None)])
+        );
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let _lock = storage::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let data_dir = env::temp_dir().join(format!("rustflix-repository-{}", process::id()));
+        storage::set_data_dir(data_dir.clone());
+
+        let writers: Vec<_> = [100, 200]
+            .into_iter()
+            .map(|first_id| {
+                thread::spawn(move || {
+                    for id in first_id..first_id + 20 {
+                        let user = user(id, &format!("User {}", id), "user@example.com");
+                        loop {
+                            let mut users = Repository::<User>::load();
+                            users.push(user.clone());
+                            if users.try_save().is_ok() {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // Without the lock, a writer could check the store just before the other wrote it, then
+        // overwrite it, losing the user the other added
+        assert_eq!(Repository::<User>::load().len(), 40);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
@@ -0,0 +1,552 @@
+use crate::args::command_types::video_subcommands;
+use crate::feed;
+use crate::formatting::Table;
+use crate::notifications;
+use crate::recommend::Catalog;
+use crate::storage;
+use crate::t;
+use crate::{backup, view_log};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// The seconds in a minute, the smallest step of a schedule
+const MINUTE: u64 = 60;
+
+/// The seconds in a day
+const DAY: u64 = 24 * 60 * MINUTE;
+
+/// How many days of plays count towards the trending videos
+const TRENDING_DAYS: u64 = 7;
+
+/// How many videos are kept in the trending list
+const TRENDING_COUNT: usize = 50;
+
+/// A task the daemon can run on a schedule
+///
+/// # Variants
+///
+/// * `Backup` - Backs up every store into the backups directory, as automatic backups do
+/// * `Digest` - Notifies every user of the videos the channels they subscribe to uploaded since the
+///   last digest
+/// * `Trending` - Ranks the videos played most in the last week into `trending.json` in the data
+///   directory
+/// * `Compact` - Writes the views in the view log to the video store, emptying the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Task {
+    Backup,
+    Digest,
+    Trending,
+    Compact,
+}
+
+impl Task {
+    /// The name of the task, as written in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::Backup => "backup",
+            Task::Digest => "digest",
+            Task::Trending => "trending",
+            Task::Compact => "compact",
+        }
+    }
+}
+
+/// When something runs, from a cron expression such as `30 4 * * 1-5`: the minute, hour, day of
+/// the month, month, and day of the week, in UTC
+///
+/// Each field is `*`, a number, a range such as `1-5`, or a list of those such as `0,30`, any of
+/// which may be followed by a step such as `*/15`. Sunday is both 0 and 7. As with cron, when both
+/// the day of the month and the day of the week are given, either matching is enough.
+/// `@hourly`, `@daily`, `@weekly`, and `@monthly` are short for the usual expressions.
+///
+/// # Fields
+///
+/// * `expression` - The expression as written
+/// * `minutes`, `hours`, `days`, `months`, `weekdays` - The values each field matches, as bits
+/// * `any_day` - Whether the day of the month is `*`
+/// * `any_weekday` - Whether the day of the week is `*`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    pub expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// A moment broken down into the fields of a cron expression
+///
+/// # Fields
+///
+/// * `minute` - The minute of the hour, from 0
+/// * `hour` - The hour of the day, from 0
+/// * `day` - The day of the month, from 1
+/// * `month` - The month of the year, from 1
+/// * `weekday` - The day of the week, from 0 for Sunday
+#[derive(Debug, PartialEq)]
+struct Moment {
+    minute: u64,
+    hour: u64,
+    day: u64,
+    month: u64,
+    weekday: u64,
+}
+
+impl Moment {
+    /// Breaks down a time in UTC
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The time in seconds since the Unix epoch
+    fn at(timestamp: u64) -> Self {
+        let days = timestamp / DAY;
+        // Days since 1 March 0000, so leap days come last in each year
+        let shifted = days + 719_468;
+        let day_of_era = shifted % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        Moment {
+            minute: timestamp / MINUTE % 60,
+            hour: timestamp / (60 * MINUTE) % 24,
+            day: day_of_year - (153 * month_from_march + 2) / 5 + 1,
+            month: (month_from_march + 2) % 12 + 1,
+            // 1 January 1970 was a Thursday
+            weekday: (days + 4) % 7,
+        }
+    }
+}
+
+impl Cron {
+    /// Reads a cron expression
+    ///
+    /// # Arguments
+    ///
+    /// * `expression` - The expression, e.g. `0 3 * * *`
+    ///
+    /// # Returns
+    ///
+    /// The schedule, or why the expression is invalid
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(t!(
+                "scheduler.field_count",
+                schedule = format!("{:?}", expression)
+            ));
+        };
+        let invalid = |e: String| {
+            t!(
+                "scheduler.invalid",
+                schedule = format!("{:?}", expression),
+                error = e
+            )
+        };
+        let mut weekdays_matched = field(weekdays, 0, 7).map_err(invalid)?;
+        if weekdays_matched & 1 << 7 != 0 {
+            weekdays_matched |= 1;
+        }
+        Ok(Cron {
+            expression: expression.to_string(),
+            minutes: field(minutes, 0, 59).map_err(invalid)?,
+            hours: field(hours, 0, 23).map_err(invalid)?,
+            days: field(days, 1, 31).map_err(invalid)?,
+            months: field(months, 1, 12).map_err(invalid)?,
+            weekdays: weekdays_matched,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+
+    /// Whether the schedule runs in the minute of the given time
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The time in seconds since the Unix epoch
+    pub fn matches(&self, timestamp: u64) -> bool {
+        let moment = Moment::at(timestamp);
+        let has = |bits: u64, value: u64| bits & 1 << value != 0;
+        let day = has(self.days, moment.day);
+        let weekday = has(self.weekdays, moment.weekday);
+        has(self.minutes, moment.minute)
+            && has(self.hours, moment.hour)
+            && has(self.months, moment.month)
+            && match (self.any_day, self.any_weekday) {
+                (true, true) => true,
+                (true, false) => weekday,
+                (false, true) => day,
+                (false, false) => day || weekday,
+            }
+    }
+
+    /// Finds the next minute the schedule runs in, after the given time
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The time to look after, in seconds since the Unix epoch
+    ///
+    /// # Returns
+    ///
+    /// The start of the minute, or `None` if the schedule doesn't run in the next few years, as
+    /// with `0 0 31 2 *`
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let start = (timestamp / MINUTE + 1) * MINUTE;
+        (0..5 * 366 * DAY / MINUTE)
+            .map(|minutes| start + minutes * MINUTE)
+            .find(|time| self.matches(*time))
+    }
+}
+
+/// Reads a field of a cron expression
+///
+/// # Arguments
+///
+/// * `text` - The field, e.g. `1-5` or `*/15`
+/// * `min` - The smallest value of the field
+/// * `max` - The largest value of the field
+///
+/// # Returns
+///
+/// The values the field matches, each as the bit of that number
+fn field(text: &str, min: u64, max: u64) -> Result<u64, String> {
+    let number = |text: &str| -> Result<u64, String> {
+        text.parse()
+            .map_err(|_| t!("scheduler.not_a_number", text = format!("{:?}", text)))
+    };
+    let mut matched = 0;
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((_, step)) if number(step)? == 0 => {
+                return Err(t!("scheduler.zero_step", item = format!("{:?}", item)))
+            }
+            Some((range, step)) => (
+                range,
+                number(step)?
+                    .try_into()
+                    .map_err(|_| t!("scheduler.step_too_large", step = format!("{:?}", step)))?,
+            ),
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start < min || end > max || start > end {
+            return Err(t!(
+                "scheduler.out_of_range",
+                item = format!("{:?}", item.split('/').next().unwrap_or_default()),
+                min = min,
+                max = max
+            ));
+        }
+        for value in (start..=end).step_by(step) {
+            matched |= 1 << value;
+        }
+    }
+    Ok(matched)
+}
+
+fn serialize_cron<S: Serializer>(cron: &Cron, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&cron.expression)
+}
+
+fn deserialize_cron<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Cron, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    Cron::parse(&text).map_err(serde::de::Error::custom)
+}
+
+/// A task the daemon runs on a schedule, from a `[[schedule]]` section of the config file
+///
+/// # Fields
+///
+/// * `task` - The task to run, e.g. `backup`
+/// * `cron` - When to run it, e.g. `0 3 * * *` for every day at 3:00 UTC
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Job {
+    pub task: Task,
+    #[serde(
+        deserialize_with = "deserialize_cron",
+        serialize_with = "serialize_cron"
+    )]
+    pub cron: Cron,
+}
+
+/// The jobs configured, if any
+static JOBS: OnceLock<Vec<Job>> = OnceLock::new();
+
+/// Runs the given jobs whenever the daemon is running
+pub fn set_config(jobs: Vec<Job>) {
+    JOBS.set(jobs).expect("Schedule set twice");
+}
+
+/// The jobs configured, or none if none are
+pub fn configured() -> &'static [Job] {
+    JOBS.get().map_or(&[], Vec::as_slice)
+}
+
+/// The file recording when each task last ran
+fn state_path() -> PathBuf {
+    storage::data_dir().join("schedule.json")
+}
+
+/// Reads when each task last ran, in seconds since the Unix epoch
+pub fn last_runs() -> HashMap<Task, u64> {
+    fs::read(state_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Records that a task ran
+fn record_run(task: Task, at: u64) {
+    let mut runs = last_runs();
+    runs.insert(task, at);
+    if let Err(e) = fs::write(state_path(), serde_json::to_vec(&runs).unwrap()) {
+        warn!(
+            "{}",
+            t!(
+                "scheduler.could_not_write",
+                path = state_path().display(),
+                error = e
+            )
+        );
+    }
+}
+
+/// Picks out the jobs due since the last check
+///
+/// # Arguments
+///
+/// * `jobs` - The jobs configured
+/// * `next` - The first minute not checked yet, in seconds since the Unix epoch
+/// * `now` - The current time, in seconds since the Unix epoch
+///
+/// # Returns
+///
+/// Every job due in a minute since, once however many times it was due, so a job missed while the
+/// computer was suspended catches up with a single run
+fn due(jobs: &[Job], next: u64, now: u64) -> Vec<&Job> {
+    jobs.iter()
+        .filter(|job| {
+            (next..=now)
+                .step_by(MINUTE as usize)
+                .any(|minute| job.cron.matches(minute))
+        })
+        .collect()
+}
+
+/// Runs the jobs configured on a thread of their own, checking every minute for those due
+///
+/// # Arguments
+///
+/// * `busy` - Held while a job runs, so it never changes the stores while the daemon does
+pub fn start(busy: &'static Mutex<()>) {
+    let jobs = configured();
+    if jobs.is_empty() {
+        return;
+    }
+    info!("{}", t!("scheduler.running", count = jobs.len()));
+    thread::spawn(move || {
+        let mut checked = video_subcommands::now() / MINUTE * MINUTE;
+        loop {
+            let now = video_subcommands::now();
+            let next = checked + MINUTE;
+            if now < next {
+                thread::sleep(Duration::from_secs(next - now));
+                continue;
+            }
+            // Every minute passed is checked, so no job is skipped if the thread fell behind
+            for job in due(jobs, next, now) {
+                let _busy = busy.lock().unwrap_or_else(|e| e.into_inner());
+                run(job.task);
+            }
+            checked = now / MINUTE * MINUTE;
+        }
+    });
+}
+
+/// Runs a task, recording when it ran
+pub fn run(task: Task) {
+    let now = video_subcommands::now();
+    debug!("Running the scheduled {} task", task.name());
+    match task {
+        Task::Backup => backup::scheduled(),
+        Task::Digest => {
+            let since = last_runs()
+                .get(&Task::Digest)
+                .copied()
+                .unwrap_or(now.saturating_sub(DAY));
+            let sent = notifications::send_digests(since);
+            info!("{}", t!("scheduler.digests_sent", count = sent));
+        }
+        Task::Trending => write_trending(now),
+        Task::Compact => {
+            if view_log::available() {
+                view_log::compact();
+                info!("{}", t!("scheduler.compacted"));
+            } else {
+                debug!("No view log to compact");
+            }
+        }
+    }
+    record_run(task, now);
+}
+
+/// Ranks the videos played most in the last week into `trending.json` in the data directory, for
+/// other tools to show
+fn write_trending(now: u64) {
+    let catalog = Catalog::load();
+    let plays = feed::recent_plays(&catalog, now.saturating_sub(TRENDING_DAYS * DAY));
+    let mut trending: Vec<_> = catalog
+        .videos
+        .iter()
+        .filter_map(|video| Some((video, *plays.get(&video.id)?)))
+        .collect();
+    trending.sort_by(|(_, (_, a)), (_, (_, b))| b.total_cmp(a));
+    trending.truncate(TRENDING_COUNT);
+
+    let videos: Vec<_> = trending
+        .iter()
+        .map(|(video, (plays, score))| {
+            json!({ "id": video.id, "name": video.name, "plays": plays, "score": score })
+        })
+        .collect();
+    let path = storage::data_dir().join("trending.json");
+    let body = json!({ "computed_at": now, "days": TRENDING_DAYS, "videos": videos });
+    match fs::write(&path, serde_json::to_string_pretty(&body).unwrap()) {
+        Ok(()) => info!(
+            "{}",
+            t!(
+                "scheduler.trending",
+                count = videos.len(),
+                path = path.display()
+            )
+        ),
+        Err(e) => error!(
+            "{}",
+            t!(
+                "scheduler.could_not_write",
+                path = path.display(),
+                error = e
+            )
+        ),
+    }
+}
+
+/// Handles showing the jobs in the config file, with when each last ran and will next run
+pub fn handle_show_schedule() {
+    let jobs = configured();
+    if jobs.is_empty() {
+        info!("{}", t!("scheduler.none"));
+        return;
+    }
+    let runs = last_runs();
+    let now = video_subcommands::now();
+    let mut table = Table::new(
+        ["task", "schedule", "last run", "next run"]
+            .map(str::to_string)
+            .to_vec(),
+    );
+    for job in jobs {
+        table.row(vec![
+            job.task.name().to_string(),
+            job.cron.expression.clone(),
+            runs.get(&job.task).map_or("never".to_string(), |at| {
+                video_subcommands::format_timestamp(*at)
+            }),
+            job.cron
+                .next_after(now)
+                .map_or("never".to_string(), video_subcommands::format_timestamp),
+        ]);
+    }
+    table.print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cron() {
+        // Sunday 25 June 2023, 12:00 UTC
+        let noon = 1687694400;
+        assert_eq!(
+            Moment::at(noon),
+            Moment {
+                minute: 0,
+                hour: 12,
+                day: 25,
+                month: 6,
+                weekday: 0,
+            }
+        );
+
+        let cron = Cron::parse("*/15 12 * * 7").unwrap();
+        assert!(cron.matches(noon));
+        assert!(cron.matches(noon + 45 * MINUTE));
+        assert!(!cron.matches(noon + 10 * MINUTE));
+        assert!(!cron.matches(noon + DAY));
+        assert_eq!(cron.next_after(noon), Some(noon + 15 * MINUTE));
+
+        // Either the day of the month or the day of the week
+        let cron = Cron::parse("0 12 1 * 1").unwrap();
+        assert!(!cron.matches(noon));
+        assert!(cron.matches(noon + DAY));
+        assert_eq!(cron.next_after(noon), Some(noon + DAY));
+
+        assert_eq!(
+            Cron::parse("@daily").unwrap().next_after(noon),
+            Some(noon + 12 * 60 * MINUTE)
+        );
+        assert!(Cron::parse("0 3 * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("0 1-5/00 * * *").is_err());
+        assert!(Cron::parse("0 0 31 2 *")
+            .unwrap()
+            .next_after(noon)
+            .is_none());
+
+        let jobs =
+            [("*/15 * * * *", Task::Compact), ("0 3 * * *", Task::Backup)].map(|(cron, task)| {
+                Job {
+                    task,
+                    cron: Cron::parse(cron).unwrap(),
+                }
+            });
+        let tasks = |next, now| {
+            due(&jobs, next, now)
+                .iter()
+                .map(|job| job.task)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tasks(noon, noon), [Task::Compact]);
+        assert!(tasks(noon + MINUTE, noon + 14 * MINUTE).is_empty());
+        // After a day suspended, each job runs once rather than for every time it was due
+        assert_eq!(
+            tasks(noon + MINUTE, noon + DAY),
+            [Task::Compact, Task::Backup]
+        );
+    }
+}
@@ -0,0 +1,522 @@
+use crate::args::command_types::user_subcommands::User;
+use crate::args::command_types::video_subcommands::Video;
+use crate::args::{self, CommandType, RustflixArgs};
+use crate::metadata::{self, IdStrategy, Metadata};
+use crate::repository::{Record, Repository};
+use crate::storage;
+use crate::t;
+use crate::{formatting, utilities};
+use clap::{Args, Parser};
+use log::{error, info, LevelFilter};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashSet};
+use std::process;
+
+/// Names operations are made with. Few enough that records share them, and written in several
+/// ways that must be found as the same name
+const NAMES: [&str; 6] = [
+    "José",
+    "Jose\u{301}",
+    "JOSÉ",
+    "Straße",
+    "東京",
+    "Big Buck Bunny",
+];
+
+/// Email addresses users are made with. Few enough that some are taken
+const EMAILS: [&str; 5] = [
+    "a@example.com",
+    "A@Example.com ",
+    "b@example.com",
+    "jose\u{301}@example.com",
+    "josé@example.com",
+];
+
+#[derive(Debug, Args)]
+pub struct SelfTest {
+    /// The number of random sequences of operations to run
+    #[arg(long, default_value_t = 200)]
+    pub cases: u64,
+    /// The number of operations in each sequence
+    #[arg(long, default_value_t = 100)]
+    pub operations: usize,
+    /// The seed to generate the operations from, to run a failing sequence again. Random if not
+    /// given
+    #[arg(long, default_value = None)]
+    pub seed: Option<u64>,
+}
+
+/// An operation on users, videos, or views, applied by running the command doing it
+///
+/// Records are picked by their position, wrapped around the number of records there are, so every
+/// operation applies to some record once there is one.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    CreateUser {
+        name: String,
+        email: String,
+    },
+    UpdateUser {
+        index: usize,
+        name: String,
+        email: String,
+    },
+    DeleteUser {
+        index: usize,
+    },
+    CreateVideo {
+        name: String,
+    },
+    UpdateVideo {
+        index: usize,
+        name: String,
+    },
+    DeleteVideo {
+        index: usize,
+    },
+    AddViews {
+        index: usize,
+        views: u32,
+    },
+}
+
+impl Operation {
+    /// Generates a random operation
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let name = NAMES[rng.gen_range(0..NAMES.len())].to_string();
+        let email = EMAILS[rng.gen_range(0..EMAILS.len())].to_string();
+        let index = rng.gen_range(0..16);
+        match rng.gen_range(0..7) {
+            0 => Operation::CreateUser { name, email },
+            1 => Operation::UpdateUser { index, name, email },
+            2 => Operation::DeleteUser { index },
+            3 => Operation::CreateVideo { name },
+            4 => Operation::UpdateVideo { index, name },
+            5 => Operation::DeleteVideo { index },
+            // Now and then enough views to reach the most a view count can hold
+            _ => Operation::AddViews {
+                index,
+                views: match rng.gen_bool(0.1) {
+                    true => u32::MAX - rng.gen_range(0..3),
+                    false => rng.gen_range(0..1000),
+                },
+            },
+        }
+    }
+}
+
+/// What the stores are expected to hold after the operations applied so far, worked out without
+/// the commands, which the stores are checked against after each operation
+///
+/// # Fields
+///
+/// * `id_strategy` - How the IDs of new records are picked
+/// * `last_ids` - The highest ID given to a user and to a video so far
+/// * `users` - The name and email address of every user, by ID
+/// * `videos` - The name of every video and the views it was given counted without a limit, by ID
+pub struct World {
+    id_strategy: IdStrategy,
+    last_ids: (u32, u32),
+    users: BTreeMap<u32, (String, String)>,
+    videos: BTreeMap<u32, (String, u64)>,
+}
+
+impl World {
+    pub fn new(id_strategy: IdStrategy) -> Self {
+        World {
+            id_strategy,
+            last_ids: (0, 0),
+            users: BTreeMap::new(),
+            videos: BTreeMap::new(),
+        }
+    }
+
+    /// Whether another user than the given one has an email address, ignoring case
+    fn email_taken(&self, email: &str, except: Option<u32>) -> bool {
+        self.users.iter().any(|(id, (_, other))| {
+            Some(*id) != except && formatting::fold_case(other) == formatting::fold_case(email)
+        })
+    }
+
+    /// Applies an operation by running the command doing it, then expects its change in the
+    /// stores. Operations the command refuses, such as taking an email address another user has,
+    /// are expected to change nothing
+    ///
+    /// # Returns
+    ///
+    /// How the stores differ from what they are expected to hold, if they do
+    pub fn apply(&mut self, operation: &Operation) -> Result<(), String> {
+        let users_before = stored_ids::<User>();
+        let videos_before = stored_ids::<Video>();
+        match operation {
+            Operation::CreateUser { name, email } => {
+                run_command(&["user", "create", name, email]);
+                let normalized = utilities::normalize_email(email);
+                let expected = utilities::validate_email(&normalized).is_ok()
+                    && !self.email_taken(&normalized, None);
+                let id = created::<User>(
+                    self.id_strategy,
+                    &mut self.last_ids.0,
+                    &users_before,
+                    expected,
+                )?;
+                if let Some(id) = id {
+                    self.users
+                        .insert(id, (formatting::normalize(name), normalized));
+                }
+            }
+            Operation::UpdateUser { index, name, email } => {
+                let Some(id) = pick(&self.users, *index) else {
+                    return Ok(());
+                };
+                run_command(&[
+                    "user",
+                    "update",
+                    "--query-id",
+                    &id.to_string(),
+                    "--new-name",
+                    name,
+                    "--new-email",
+                    email,
+                ]);
+                let normalized = utilities::normalize_email(email);
+                if utilities::validate_email(&normalized).is_ok()
+                    && !self.email_taken(&normalized, Some(id))
+                {
+                    self.users
+                        .insert(id, (formatting::normalize(name), normalized));
+                }
+            }
+            Operation::DeleteUser { index } => {
+                if let Some(id) = pick(&self.users, *index) {
+                    run_command(&["user", "delete", "--id", &id.to_string()]);
+                    self.users.remove(&id);
+                }
+            }
+            Operation::CreateVideo { name } => {
+                run_command(&["video", "create", name]);
+                let id =
+                    created::<Video>(self.id_strategy, &mut self.last_ids.1, &videos_before, true)?;
+                if let Some(id) = id {
+                    self.videos.insert(id, (formatting::normalize(name), 0));
+                }
+            }
+            Operation::UpdateVideo { index, name } => {
+                if let Some(id) = pick(&self.videos, *index) {
+                    let query_id = id.to_string();
+                    run_command(&[
+                        "video",
+                        "update",
+                        "--query-id",
+                        &query_id,
+                        "--new-name",
+                        name,
+                    ]);
+                    self.videos.get_mut(&id).unwrap().0 = formatting::normalize(name);
+                }
+            }
+            Operation::DeleteVideo { index } => {
+                if let Some(id) = pick(&self.videos, *index) {
+                    run_command(&["video", "delete", "--id", &id.to_string()]);
+                    self.videos.remove(&id);
+                }
+            }
+            Operation::AddViews { index, views } => {
+                if let Some(id) = pick(&self.videos, *index) {
+                    run_command(&["view", "add", &views.to_string(), "--id", &id.to_string()]);
+                    self.videos.get_mut(&id).unwrap().1 += *views as u64;
+                }
+            }
+        }
+        self.check()
+    }
+
+    /// Checks that the stores hold what they are expected to, and every invariant the records
+    /// must keep
+    ///
+    /// # Returns
+    ///
+    /// The first difference or invariant broken, if any
+    pub fn check(&self) -> Result<(), String> {
+        let users = Repository::<User>::load();
+        let videos = Repository::<Video>::load();
+        check_records(&users)?;
+        check_records(&videos)?;
+
+        let mut emails = HashSet::new();
+        for user in users.iter() {
+            if !emails.insert(formatting::fold_case(&user.email)) {
+                return Err(format!("Several users have the email {}", user.email));
+            }
+            if users.with_email(&user.email).len() != 1 {
+                return Err(format!(
+                    "Looking up {} doesn't find only its user",
+                    user.email
+                ));
+            }
+            match self.users.get(&user.id) {
+                Some((name, email)) if *name == user.name && *email == user.email => {}
+                expected => {
+                    return Err(format!(
+                        "User {} is {} <{}> instead of {:?}",
+                        user.id, user.name, user.email, expected
+                    ))
+                }
+            }
+        }
+        if users.len() != self.users.len() {
+            return Err(format!(
+                "There are {} users instead of {}",
+                users.len(),
+                self.users.len()
+            ));
+        }
+
+        for video in videos.iter() {
+            let Some((name, views)) = self.videos.get(&video.id) else {
+                return Err(format!("Video {} shouldn't exist", video.id));
+            };
+            if *name != video.name {
+                return Err(format!(
+                    "Video {} is named {} instead of {}",
+                    video.id, video.name, name
+                ));
+            }
+            let expected = (*views).min(u32::MAX as u64);
+            if video.views as u64 != expected {
+                return Err(format!(
+                    "Video {} has {} views instead of {}",
+                    video.id, video.views, expected
+                ));
+            }
+        }
+        if videos.len() != self.videos.len() {
+            return Err(format!(
+                "There are {} videos instead of {}",
+                videos.len(),
+                self.videos.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Finds the record a create command added to a store, if it was expected to add one
+///
+/// # Arguments
+///
+/// * `id_strategy` - How the IDs of new records are picked
+/// * `last` - The highest ID given to a record of the store so far
+/// * `before` - The IDs of the records before the command ran
+/// * `expected` - Whether the command was expected to add a record
+///
+/// # Returns
+///
+/// The ID of the record added, or why what was added isn't what was expected
+fn created<T: Record>(
+    id_strategy: IdStrategy,
+    last: &mut u32,
+    before: &[u32],
+    expected: bool,
+) -> Result<Option<u32>, String> {
+    let added: Vec<u32> = stored_ids::<T>()
+        .into_iter()
+        .filter(|id| !before.contains(id))
+        .collect();
+    let id = match (expected, added.as_slice()) {
+        (true, [id]) => *id,
+        (false, []) => return Ok(None),
+        (true, _) => return Err(format!("Expected one record to be added, not {:?}", added)),
+        (false, _) => return Err(format!("Expected nothing to be added, not {:?}", added)),
+    };
+    if id_strategy == IdStrategy::Sequential && id <= *last {
+        return Err(format!(
+            "The ID {} was given after {}, though IDs are sequential",
+            id, last
+        ));
+    }
+    *last = id.max(*last);
+    Ok(Some(id))
+}
+
+/// Runs a command as it would be written on the command line, as `batch` does
+///
+/// # Arguments
+///
+/// * `arguments` - The command and its arguments, without `rustflix`
+fn run_command(arguments: &[&str]) {
+    let arguments = std::iter::once("rustflix").chain(arguments.iter().copied());
+    match RustflixArgs::parse_from(arguments).command_type {
+        CommandType::User(user) => args::handle_user_command(user),
+        CommandType::Video(video) => args::handle_video_command(video, None),
+        CommandType::View(view) => args::handle_view_command(view),
+        _ => unreachable!("Operations only run user, video and view commands"),
+    }
+}
+
+/// The IDs of the records of a kind, as stored
+fn stored_ids<T: Record>() -> Vec<u32> {
+    Repository::<T>::load().iter().map(Record::id).collect()
+}
+
+/// The ID of the record at the given position, wrapped around the number of records there are
+fn pick<T>(records: &BTreeMap<u32, T>, index: usize) -> Option<u32> {
+    match records.len() {
+        0 => None,
+        len => records.keys().nth(index % len).copied(),
+    }
+}
+
+/// Checks that no two records share an ID, and that every record is found by its ID and name
+fn check_records<T: Record>(records: &Repository<T>) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for record in records.iter() {
+        let id = record.id();
+        if !ids.insert(id) {
+            return Err(format!("Several records have the ID {}", id));
+        }
+        if records.get(id).map(Record::id) != Some(id) {
+            return Err(format!("Looking up the ID {} doesn't find its record", id));
+        }
+        if !records
+            .with_name(record.name())
+            .iter()
+            .any(|found| found.id() == id)
+        {
+            return Err(format!(
+                "Looking up {} doesn't find the record with ID {}",
+                record.name(),
+                id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Applies operations in order to empty stores kept in memory, checking the stores after each
+///
+/// # Returns
+///
+/// How many operations were applied when the stores differed from what was expected, along with
+/// how they differed
+pub fn run(operations: &[Operation], id_strategy: IdStrategy) -> Result<(), (usize, String)> {
+    utilities::set_assume_yes(true);
+    storage::begin_scratch();
+    metadata::save(Metadata {
+        id_strategy,
+        ..Default::default()
+    });
+    let mut world = World::new(id_strategy);
+    let result = operations
+        .iter()
+        .enumerate()
+        .try_for_each(|(number, operation)| world.apply(operation).map_err(|e| (number + 1, e)));
+    storage::end_scratch();
+    result
+}
+
+/// Handles fuzzing random sequences of operations, run through the same commands as on the command
+/// line, checking after each operation that the records hold what they should, keep unique IDs and
+/// email addresses, can be looked up, and count views correctly
+///
+/// The commands run against empty stores kept in memory, so the stores are never read or written,
+/// and their messages are hidden. Each case is generated from the seed plus its number,
+/// with even cases picking IDs sequentially and odd ones randomly, so a failing case can be run
+/// again with `--seed` and `--cases`.
+///
+/// # Arguments
+///
+/// * `self_test` - The arguments for the self-test
+pub fn handle_self_test(self_test: SelfTest) {
+    let seed = self_test.seed.unwrap_or_else(rand::random);
+    let level = log::max_level();
+    for case in 0..self_test.cases {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(case));
+        let operations: Vec<Operation> = (0..self_test.operations)
+            .map(|_| Operation::random(&mut rng))
+            .collect();
+        let id_strategy = match case % 2 {
+            0 => IdStrategy::Sequential,
+            _ => IdStrategy::Random,
+        };
+
+        log::set_max_level(LevelFilter::Off);
+        let result = run(&operations, id_strategy);
+        log::set_max_level(level);
+        if let Err((applied, e)) = result {
+            error!(
+                "{}",
+                t!(
+                    "self_test.failed",
+                    case = case,
+                    seed = seed,
+                    applied = applied,
+                    error = e
+                )
+            );
+            for operation in &operations[..applied] {
+                error!("  {:?}", operation);
+            }
+            process::exit(1);
+        }
+    }
+    info!(
+        "{}",
+        t!(
+            "self_test.passed",
+            cases = self_test.cases,
+            operations = self_test.operations,
+            seed = seed
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        let name = prop::sample::select(NAMES.as_slice()).prop_map(str::to_string);
+        let email = prop::sample::select(EMAILS.as_slice()).prop_map(str::to_string);
+        let index = 0..16usize;
+        prop_oneof![
+            (name.clone(), email.clone())
+                .prop_map(|(name, email)| Operation::CreateUser { name, email }),
+            (index.clone(), name.clone(), email)
+                .prop_map(|(index, name, email)| Operation::UpdateUser { index, name, email }),
+            index
+                .clone()
+                .prop_map(|index| Operation::DeleteUser { index }),
+            name.clone()
+                .prop_map(|name| Operation::CreateVideo { name }),
+            (index.clone(), name).prop_map(|(index, name)| Operation::UpdateVideo { index, name }),
+            index
+                .clone()
+                .prop_map(|index| Operation::DeleteVideo { index }),
+            (index, prop_oneof![0..1000u32, (u32::MAX - 2)..=u32::MAX])
+                .prop_map(|(index, views)| Operation::AddViews { index, views }),
+        ]
+    }
+
+    proptest! {
+        // Each operation runs a whole command, so fewer cases than the default
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_operations_keep_invariants(
+            operations in prop::collection::vec(operation(), 0..100),
+            random_ids: bool,
+        ) {
+            let _lock = storage::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let id_strategy = match random_ids {
+                true => IdStrategy::Random,
+                false => IdStrategy::Sequential,
+            };
+            if let Err((applied, e)) = run(&operations, id_strategy) {
+                prop_assert!(false, "After {} operations. {}", applied, e);
+            }
+        }
+    }
+}
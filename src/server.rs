@@ -0,0 +1,975 @@
+use crate::args::command_types::apikey_subcommands::{self, ApiKey, Scope};
+use crate::args::command_types::playlist_subcommands::Playlist;
+use crate::args::command_types::series_subcommands::Series;
+use crate::args::command_types::user_subcommands::{Subscription, User};
+use crate::args::command_types::video_subcommands::Video;
+use crate::args::command_types::view_subcommands::WatchEntry;
+use crate::channel::{self, FeedFormat};
+use crate::events;
+use crate::metadata::Metadata;
+use crate::notifications::Notification;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{audit, remote};
+use clap::Args;
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// The web page served at `/`, showing the stores through the JSON routes
+const DASHBOARD: &str = include_str!("../assets/dashboard.html");
+
+#[derive(Debug, Args)]
+pub struct Serve {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub address: String,
+    /// Write the OpenAPI description of the routes to this file instead of serving
+    #[arg(long, default_value = None)]
+    pub openapi: Option<PathBuf>,
+    /// The most views a single client may add per window, overriding the config's `[rate_limit]`
+    #[arg(long, default_value = None)]
+    pub rate_limit: Option<u32>,
+}
+
+/// A response whose body has been built in memory
+type Reply = Response<Cursor<Vec<u8>>>;
+
+/// How a route answers a request
+///
+/// # Variants
+///
+/// * `Respond` - Builds a response to the request, keeping track of the views each client adds
+/// * `Upgrade` - Upgrades the request to a WebSocket streaming events
+enum Handler {
+    Respond(fn(&mut Request, &mut RateLimiter) -> Reply),
+    Upgrade,
+}
+
+/// The JSON bodies routes send and receive, as named in the OpenAPI description
+///
+/// # Variants
+///
+/// * `Users` - A list of users
+/// * `Videos` - A list of videos
+/// * `Genres` - A list of genre names
+/// * `Series` - A list of series
+/// * `Metadata` - The metadata of the stores
+/// * `History` - A list of watch history entries
+/// * `Subscriptions` - A list of subscriptions
+/// * `Notifications` - A list of notifications
+/// * `Playlists` - A list of playlists
+/// * `Count` - The number of records saved
+/// * `AddViews` - Views to add to a video
+/// * `Video` - A single video
+#[derive(Debug, Clone, Copy)]
+enum Body {
+    Users,
+    Videos,
+    Genres,
+    Series,
+    Metadata,
+    History,
+    Subscriptions,
+    Notifications,
+    Playlists,
+    Count,
+    AddViews,
+    Video,
+}
+
+/// The body of a request adding views to a video
+///
+/// # Fields
+///
+/// * `id` - The ID of the video
+/// * `count` - The number of views to add
+#[derive(Debug, Deserialize)]
+struct AddViews {
+    id: u32,
+    count: u32,
+}
+
+/// A route served by the server
+///
+/// # Fields
+///
+/// * `method` - The method the route answers
+/// * `path` - The path the route answers
+/// * `summary` - What the route does
+/// * `scope` - The scope of API key the route needs, or `None` if it is open to everyone
+/// * `request` - The body the route expects
+/// * `response` - The body the route answers with
+/// * `handler` - How the route answers
+struct Route {
+    method: Method,
+    path: &'static str,
+    summary: &'static str,
+    scope: Option<Scope>,
+    request: Option<Body>,
+    response: Option<Body>,
+    handler: Handler,
+}
+
+/// Every route served by the server
+fn routes() -> Vec<Route> {
+    vec![
+        Route {
+            method: Method::Get,
+            path: "/",
+            summary: "The web dashboard",
+            scope: None,
+            request: None,
+            response: None,
+            handler: Handler::Respond(|_, _| {
+                Response::from_string(DASHBOARD).with_header(
+                    Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap(),
+                )
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/openapi.json",
+            summary: "This description of the API",
+            scope: None,
+            request: None,
+            response: None,
+            handler: Handler::Respond(|_, _| json_response(200, openapi().to_string())),
+        },
+        Route {
+            method: Method::Get,
+            path: "/users",
+            summary: "Fetch every user",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Users),
+            handler: Handler::Respond(|_, _| get_users()),
+        },
+        Route {
+            method: Method::Put,
+            path: "/users",
+            summary: "Replace every user",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Users),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(
+                    Store::Users,
+                    request,
+                    limiter,
+                    |old: &[User], new: &mut [User]| {
+                        for user in new {
+                            user.keep_private_fields(old.iter().find(|old| old.id == user.id));
+                        }
+                    },
+                    |_, _| 0,
+                )
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/videos",
+            summary: "Fetch every video",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Videos),
+            handler: Handler::Respond(|_, _| get_store::<Video>(Store::Videos)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/videos",
+            summary: "Replace every video",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Videos),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::Videos, request, limiter, |_, _| {}, events::added_views)
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/genres",
+            summary: "Fetch every genre",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Genres),
+            handler: Handler::Respond(|_, _| get_store::<String>(Store::Genres)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/genres",
+            summary: "Replace every genre",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Genres),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::Genres, request, limiter, |_, _| {}, |_: &[String], _| 0)
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/series",
+            summary: "Fetch every series",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Series),
+            handler: Handler::Respond(|_, _| get_store::<Series>(Store::Series)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/series",
+            summary: "Replace every series",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Series),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::Series, request, limiter, |_, _| {}, |_: &[Series], _| 0)
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/metadata",
+            summary: "Fetch the metadata of the stores",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Metadata),
+            handler: Handler::Respond(|_, _| get_store::<Metadata>(Store::Metadata)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/metadata",
+            summary: "Replace the metadata of the stores",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Metadata),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(
+                    Store::Metadata,
+                    request,
+                    limiter,
+                    |_, _| {},
+                    |_: &[Metadata], _| 0,
+                )
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/history",
+            summary: "Fetch every watch history entry",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::History),
+            handler: Handler::Respond(|_, _| get_store::<WatchEntry>(Store::History)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/history",
+            summary: "Replace every watch history entry",
+            scope: Some(Scope::Admin),
+            request: Some(Body::History),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::History, request, limiter, |_, _| {}, |_: &[WatchEntry], _| 0)
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/subscriptions",
+            summary: "Fetch every subscription",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Subscriptions),
+            handler: Handler::Respond(|_, _| get_store::<Subscription>(Store::Subscriptions)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/subscriptions",
+            summary: "Replace every subscription",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Subscriptions),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::Subscriptions, request, limiter, |_, _| {}, |_: &[Subscription], _| 0)
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/notifications",
+            summary: "Fetch every notification",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Notifications),
+            handler: Handler::Respond(|_, _| get_store::<Notification>(Store::Notifications)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/notifications",
+            summary: "Replace every notification",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Notifications),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::Notifications, request, limiter, |_, _| {}, |_: &[Notification], _| 0)
+            }),
+        },
+        Route {
+            method: Method::Get,
+            path: "/playlists",
+            summary: "Fetch every playlist",
+            scope: Some(Scope::Read),
+            request: None,
+            response: Some(Body::Playlists),
+            handler: Handler::Respond(|_, _| get_store::<Playlist>(Store::Playlists)),
+        },
+        Route {
+            method: Method::Put,
+            path: "/playlists",
+            summary: "Replace every playlist",
+            scope: Some(Scope::Admin),
+            request: Some(Body::Playlists),
+            response: Some(Body::Count),
+            handler: Handler::Respond(|request, limiter| {
+                put_store(Store::Playlists, request, limiter, |_, _| {}, |_: &[Playlist], _| 0)
+            }),
+        },
+        Route {
+            method: Method::Post,
+            path: "/views",
+            summary: "Add views to a video",
+            scope: Some(Scope::Admin),
+            request: Some(Body::AddViews),
+            response: Some(Body::Video),
+            handler: Handler::Respond(add_views),
+        },
+        Route {
+            method: Method::Get,
+            path: "/channels/:id/feed",
+            summary: "Fetch the RSS feed of the latest public videos of a channel, or its Atom feed with `?format=atom`",
+            scope: Some(Scope::Read),
+            request: None,
+            response: None,
+            handler: Handler::Respond(channel_feed),
+        },
+        Route {
+            method: Method::Get,
+            path: "/events",
+            summary: "Open a WebSocket sent every change as JSON",
+            scope: Some(Scope::Read),
+            request: None,
+            response: None,
+            handler: Handler::Upgrade,
+        },
+    ]
+}
+
+/// Serves the stores over HTTP so other rustflix commands can use them with `--remote`
+///
+/// Each store but the API keys is served as a JSON array at the path `Store::route` gives it, e.g.
+/// `/users` or `/history`. `GET` fetches every record and `PUT` replaces them all. Requests are handled one at a time. A WebSocket opened at `/events`
+/// is sent every change made through the server as it happens, and `/` serves a dashboard built on
+/// top of these. `/channels/<id>/feed` serves the feed of a channel for feed readers, which can
+/// send their token as a `token` query parameter. `/openapi.json` describes every route.
+///
+/// Every route but the dashboard and its description needs the token of an API key, sent as a
+/// bearer token or, for browsers opening the WebSocket, a `token` query parameter. Changing
+/// records needs an admin key.
+///
+/// Views can also be added at `/views`. If a rate limit is set, a client adding more views than it
+/// allows, whether there or by replacing the videos, is refused with a 429.
+///
+/// # Arguments
+///
+/// * `serve` - The arguments for the server
+/// * `rate_limit` - The rate limit from the config file, if any
+pub fn handle_serve(serve: Serve, rate_limit: Option<RateLimit>) {
+    if let Some(path) = serve.openapi {
+        match fs::write(&path, serde_json::to_string_pretty(&openapi()).unwrap()) {
+            Ok(()) => info!("{}", t!("server.openapi_written", path = path.display())),
+            Err(e) => error!(
+                "{}",
+                t!("server.could_not_write", path = path.display(), error = e)
+            ),
+        }
+        return;
+    }
+
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("server.remote", remote = remote));
+        return;
+    }
+
+    let server = match Server::http(&serve.address) {
+        Ok(server) => server,
+        Err(e) => {
+            error!(
+                "{}",
+                t!(
+                    "server.could_not_listen",
+                    address = serve.address,
+                    error = e
+                )
+            );
+            return;
+        }
+    };
+    if storage::load_file::<ApiKey>(Store::ApiKeys).is_empty() {
+        warn!("{}", t!("server.no_api_keys"));
+    }
+    info!("{}", t!("server.listening", address = serve.address));
+
+    let rate_limit = match (serve.rate_limit, rate_limit) {
+        (Some(views), Some(rate_limit)) => Some(RateLimit {
+            views,
+            ..rate_limit
+        }),
+        (Some(views), None) => Some(RateLimit { views, seconds: 60 }),
+        (None, rate_limit) => rate_limit,
+    };
+    if let Some(rate_limit) = rate_limit {
+        info!(
+            "{}",
+            t!(
+                "server.rate_limit",
+                views = rate_limit.views,
+                seconds = rate_limit.seconds
+            )
+        );
+    }
+    let mut limiter = RateLimiter::new(rate_limit);
+
+    let routes = routes();
+    for mut request in server.incoming_requests() {
+        let start = Instant::now();
+        audit::start_run();
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let path = url.split('?').next().unwrap_or_default();
+
+        let route = routes
+            .iter()
+            .find(|route| route.method == method && matches(route.path, path));
+        let authorized = match route.and_then(|route| route.scope) {
+            Some(scope) => authorize(&request, scope),
+            None => Ok(()),
+        };
+        let response = match (route, authorized) {
+            (None, _) if routes.iter().any(|route| matches(route.path, path)) => {
+                error_response(405, "Method not allowed")
+            }
+            (None, _) => error_response(404, "Not found"),
+            (Some(_), Err(response)) => response,
+            (Some(route), Ok(())) => match route.handler {
+                Handler::Respond(respond) => respond(&mut request, &mut limiter),
+                Handler::Upgrade => {
+                    debug!("{} {} opened an event stream", method, url);
+                    stream_events(request);
+                    continue;
+                }
+            },
+        };
+        debug!(
+            "{} {} answered with {} in {:?}",
+            method,
+            url,
+            response.status_code().0,
+            start.elapsed()
+        );
+
+        if let Err(e) = request.respond(response) {
+            warn!(
+                "{}",
+                t!(
+                    "server.could_not_respond",
+                    method = method,
+                    url = url,
+                    error = e
+                )
+            );
+        }
+    }
+}
+
+/// Describes every route in the OpenAPI format, so clients can be generated for the server
+pub fn openapi() -> Value {
+    let mut paths = Map::new();
+    for route in routes() {
+        let mut operation = json!({
+            "summary": route.summary,
+            "responses": {},
+        });
+
+        let mut responses = Map::new();
+        match (&route.handler, route.response) {
+            (Handler::Upgrade, _) => {
+                responses.insert(
+                    "101".to_string(),
+                    json!({ "description": "Switching to a WebSocket" }),
+                );
+            }
+            (_, Some(body)) => {
+                responses.insert(
+                    "200".to_string(),
+                    json!({
+                        "description": "Success",
+                        "content": { "application/json": { "schema": schema(body) } },
+                    }),
+                );
+            }
+            (_, None) => {
+                responses.insert("200".to_string(), json!({ "description": "Success" }));
+            }
+        }
+        if route.request.is_some() {
+            responses.insert("400".to_string(), json!({ "description": "Invalid body" }));
+        }
+        if matches!(route.request, Some(Body::Videos | Body::AddViews)) {
+            responses.insert(
+                "429".to_string(),
+                json!({ "description": "Too many views added" }),
+            );
+        }
+        if let Some(scope) = route.scope {
+            responses.insert(
+                "401".to_string(),
+                json!({ "description": "Missing or unknown API key" }),
+            );
+            if scope == Scope::Admin {
+                responses.insert(
+                    "403".to_string(),
+                    json!({ "description": "API key can't change records" }),
+                );
+            }
+            operation["security"] = json!([{ "bearer": [] }]);
+        }
+        operation["responses"] = Value::Object(responses);
+
+        if let Some(body) = route.request {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema(body) } },
+            });
+        }
+
+        let parameters: Vec<Value> = route
+            .path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                })
+            })
+            .collect();
+        if !parameters.is_empty() {
+            operation["parameters"] = Value::Array(parameters);
+        }
+
+        let method = route.method.to_string().to_lowercase();
+        let path: Vec<String> = route
+            .path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_string(),
+            })
+            .collect();
+        paths.entry(path.join("/")).or_insert_with(|| json!({}))[method] = operation;
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Rustflix",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": {
+                "User": {
+                    "type": "object",
+                    "required": ["id", "name", "email"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "name": { "type": "string" },
+                        "email": { "type": "string" },
+                        "handle": { "type": "string", "nullable": true },
+                        "role": { "type": "string", "enum": ["Viewer", "Moderator", "Admin"] },
+                        "avatar": { "type": "string", "nullable": true },
+                        "bio": { "type": "string", "nullable": true },
+                        "birthdate": { "type": "string", "format": "date", "nullable": true },
+                        "country": { "type": "string", "minLength": 2, "maxLength": 2, "nullable": true },
+                        "suspended": { "type": "boolean" },
+                        "maturity": { "type": "string", "enum": ["Auto", "Adult", "Minor"] },
+                        "created_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "updated_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "revision": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "dismissed": { "type": "string", "description": "Comma-separated IDs of videos never to recommend to the user" },
+                        "interests": { "type": "string", "description": "Comma-separated genre names the user picked when onboarded" },
+                    },
+                },
+                "Series": {
+                    "type": "object",
+                    "required": ["id", "name"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "name": { "type": "string" },
+                        "created_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "updated_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                    },
+                },
+                "Video": {
+                    "type": "object",
+                    "required": ["id", "name", "views"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "name": { "type": "string" },
+                        "views": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "description": { "type": "string", "nullable": true },
+                        "duration": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "uploaded_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "visibility": { "type": "string", "enum": ["Public", "Unlisted", "Private"] },
+                        "age_restricted": { "type": "boolean" },
+                        "owner": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "genres": { "type": "string", "description": "Comma-separated genre names" },
+                        "series": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "season": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "episode": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "file": { "type": "string", "nullable": true },
+                        "width": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "height": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "codec": { "type": "string", "nullable": true },
+                        "thumbnail": { "type": "string", "nullable": true },
+                        "updated_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "revision": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                    },
+                },
+                "WatchEntry": {
+                    "type": "object",
+                    "required": ["video", "started_at", "seconds", "finished"],
+                    "properties": {
+                        "video": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "user": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "started_at": { "type": "integer", "format": "int64", "minimum": 0 },
+                        "seconds": { "type": "integer", "format": "int64", "minimum": 0 },
+                        "finished": { "type": "boolean" },
+                    },
+                },
+                "Subscription": {
+                    "type": "object",
+                    "required": ["user", "channel"],
+                    "properties": {
+                        "user": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "channel": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "created_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                    },
+                },
+                "Notification": {
+                    "type": "object",
+                    "required": ["user", "message"],
+                    "properties": {
+                        "user": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "kind": { "type": "string", "enum": ["milestone", "upload", "digest", "playlist"] },
+                        "message": { "type": "string" },
+                        "video": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX, "nullable": true },
+                        "created_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "read": { "type": "boolean" },
+                    },
+                },
+                "Playlist": {
+                    "type": "object",
+                    "required": ["id", "name", "owner"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "name": { "type": "string" },
+                        "owner": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                        "videos": { "type": "array", "items": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX } },
+                        "shared": { "type": "boolean" },
+                        "followers": { "type": "array", "items": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX } },
+                        "created_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                        "updated_at": { "type": "integer", "format": "int64", "minimum": 0, "nullable": true },
+                    },
+                },
+            },
+        },
+    })
+}
+
+/// The OpenAPI schema of a body
+fn schema(body: Body) -> Value {
+    match body {
+        Body::Users => json!({ "type": "array", "items": { "$ref": "#/components/schemas/User" } }),
+        Body::Videos => {
+            json!({ "type": "array", "items": { "$ref": "#/components/schemas/Video" } })
+        }
+        Body::Genres => json!({ "type": "array", "items": { "type": "string" } }),
+        Body::Series => {
+            json!({ "type": "array", "items": { "$ref": "#/components/schemas/Series" } })
+        }
+        Body::Metadata => json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer", "format": "int64", "minimum": 0, "maximum": 0 },
+                    "id_strategy": { "type": "string", "enum": ["Sequential", "Random"] },
+                    "next_ids": {
+                        "type": "object",
+                        "additionalProperties": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                    },
+                },
+            },
+        }),
+        Body::History => {
+            json!({ "type": "array", "items": { "$ref": "#/components/schemas/WatchEntry" } })
+        }
+        Body::Subscriptions => {
+            json!({ "type": "array", "items": { "$ref": "#/components/schemas/Subscription" } })
+        }
+        Body::Notifications => {
+            json!({ "type": "array", "items": { "$ref": "#/components/schemas/Notification" } })
+        }
+        Body::Playlists => {
+            json!({ "type": "array", "items": { "$ref": "#/components/schemas/Playlist" } })
+        }
+        Body::Count => json!({ "type": "integer" }),
+        Body::AddViews => json!({
+            "type": "object",
+            "required": ["id", "count"],
+            "properties": {
+                "id": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+                "count": { "type": "integer", "format": "int64", "minimum": 0, "maximum": u32::MAX },
+            },
+        }),
+        Body::Video => json!({ "$ref": "#/components/schemas/Video" }),
+    }
+}
+
+/// Gets the API key token a request carries, as a bearer token or a `token` query parameter
+fn token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .or_else(|| query_param(request, "token"))
+}
+
+/// Reads a parameter from the query string of a request
+fn query_param<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    let (_, query) = request.url().split_once('?')?;
+    query.split('&').find_map(|pair| {
+        pair.strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+/// Whether a path is answered by a route, whose segments starting with `:` match any segment
+fn matches(route: &str, path: &str) -> bool {
+    let (route, path): (Vec<&str>, Vec<&str>) =
+        (route.split('/').collect(), path.split('/').collect());
+    route.len() == path.len()
+        && route
+            .iter()
+            .zip(&path)
+            .all(|(route, path)| route.starts_with(':') || route == path)
+}
+
+/// Identifies the client making a request by its API key, or by its address if it has none
+fn client(request: &Request) -> String {
+    match token(request) {
+        Some(token) => apikey_subcommands::hash_token(token),
+        None => request
+            .remote_addr()
+            .map(|address| address.ip().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Checks that a request carries the token of an API key allowing the given scope
+///
+/// # Returns
+///
+/// The response to send instead if it doesn't
+fn authorize(request: &Request, needed: Scope) -> Result<(), Reply> {
+    let Some(token) = token(request) else {
+        return Err(error_response(401, "Missing API key"));
+    };
+
+    let keys: Vec<ApiKey> = storage::load_file(Store::ApiKeys);
+    match apikey_subcommands::find_key(&keys, token) {
+        None => Err(error_response(401, "Unknown API key")),
+        Some(key) if !key.scope.allows(needed) => {
+            Err(error_response(403, "This API key can't change records"))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Responds with every user, leaving out their private fields
+fn get_users() -> Reply {
+    let users: Vec<User> = storage::load(Store::Users);
+    let users: Vec<Value> = users.iter().map(User::public).collect();
+    json_response(200, serde_json::to_string(&users).unwrap())
+}
+
+/// Responds with every record of the given store
+fn get_store<T: Serialize + DeserializeOwned>(store: Store) -> Reply {
+    let records: Vec<T> = storage::load(store);
+    json_response(200, serde_json::to_string(&records).unwrap())
+}
+
+/// Replaces every record of the given store with the ones in the request body, publishing an
+/// event for each change
+///
+/// # Arguments
+///
+/// * `store` - The store to replace
+/// * `request` - The request holding the new records
+/// * `limiter` - Limits the views the client may add
+/// * `keep` - Keeps what clients may not change of the new records as it is in the old ones
+/// * `added_views` - Totals the views gained between the old and new records, which count
+///   toward the rate limit
+fn put_store<T: Serialize + DeserializeOwned>(
+    store: Store,
+    request: &mut Request,
+    limiter: &mut RateLimiter,
+    keep: fn(&[T], &mut [T]),
+    added_views: fn(&[T], &[T]) -> u32,
+) -> Reply {
+    let mut records: Vec<T> = match serde_json::from_reader(request.as_reader()) {
+        Ok(records) => records,
+        Err(e) => return error_response(400, &e.to_string()),
+    };
+    let old_records: Vec<T> = storage::load(store);
+    keep(&old_records, &mut records);
+
+    let added_views = added_views(&old_records, &records);
+    if added_views > 0 && !limiter.allow(&client(request), added_views) {
+        return too_many_views(limiter);
+    }
+
+    storage::save(store, &records);
+    json_response(200, serde_json::to_string(&records.len()).unwrap())
+}
+
+/// Adds views to the video given in the request body
+fn add_views(request: &mut Request, limiter: &mut RateLimiter) -> Reply {
+    let add_views: AddViews = match serde_json::from_reader(request.as_reader()) {
+        Ok(add_views) => add_views,
+        Err(e) => return error_response(400, &e.to_string()),
+    };
+
+    let mut videos: Vec<Video> = storage::load(Store::Videos);
+    let Some(video) = videos.iter_mut().find(|video| video.id == add_views.id) else {
+        return error_response(404, "No video found with the given ID");
+    };
+
+    if !limiter.allow(&client(request), add_views.count) {
+        return too_many_views(limiter);
+    }
+
+    video.add_views(add_views.count);
+    let video = video.clone();
+    storage::save(Store::Videos, &videos);
+    json_response(200, serde_json::to_string(&video).unwrap())
+}
+
+/// Responds with the feed of the channel in the path, in the format given by the `format` query
+/// parameter, RSS if none is
+fn channel_feed(request: &mut Request, _: &mut RateLimiter) -> Reply {
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or_default();
+    let Some(id) = path.split('/').nth(2).and_then(|id| id.parse().ok()) else {
+        return error_response(404, "No user found with the given ID");
+    };
+    let format = match query_param(request, "format") {
+        None | Some("rss") => FeedFormat::Rss,
+        Some("atom") => FeedFormat::Atom,
+        Some(_) => return error_response(400, "Unknown format. Expected rss or atom"),
+    };
+    let link = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Host"))
+        .map(|host| format!("http://{}{}", host.value, path));
+
+    match channel::feed(id, format, 20, link.as_deref()) {
+        Some(feed) => Response::from_string(feed)
+            .with_header(Header::from_bytes("Content-Type", format.content_type()).unwrap()),
+        None => error_response(404, "No user found with the given ID"),
+    }
+}
+
+/// Builds the response refusing a client that added too many views
+fn too_many_views(limiter: &RateLimiter) -> Reply {
+    error_response(429, "Too many views added. Try again later")
+        .with_header(Header::from_bytes("Retry-After", limiter.retry_after().to_string()).unwrap())
+}
+
+/// Builds a response with the given JSON body
+fn json_response(status: u16, body: String) -> Reply {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+/// Builds a response describing an error
+fn error_response(status: u16, message: &str) -> Reply {
+    Response::from_string(message).with_status_code(status)
+}
+
+/// Upgrades a request to a WebSocket and sends it every event as JSON until it is closed
+fn stream_events(request: Request) {
+    let key = match request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+    {
+        Some(header) => header.value.to_string(),
+        None => {
+            let response = error_response(400, "Expected a WebSocket upgrade");
+            if let Err(e) = request.respond(response) {
+                warn!("{}", t!("server.could_not_respond_stream", error = e));
+            }
+            return;
+        }
+    };
+
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes("Upgrade", "websocket").unwrap())
+        .with_header(Header::from_bytes("Connection", "Upgrade").unwrap())
+        .with_header(
+            Header::from_bytes("Sec-WebSocket-Accept", derive_accept_key(key.as_bytes())).unwrap(),
+        );
+    // Subscribe before upgrading so no change made in between is missed
+    let receiver = events::subscribe();
+    let stream = request.upgrade("websocket", response);
+
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        for event in receiver {
+            let message = serde_json::to_string(&event).unwrap();
+            if socket.send(Message::Text(message)).is_err() {
+                break;
+            }
+        }
+        debug!("Event stream closed");
+    });
+}
@@ -0,0 +1,240 @@
+use crate::args::command_types::apikey_subcommands::hash_token;
+use crate::args::command_types::user_subcommands::{Role, User};
+use crate::dry_run;
+use crate::listing;
+use crate::storage;
+use crate::t;
+use log::{debug, error, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// The user logged in with `rustflix login`, as kept in the data directory
+///
+/// # Fields
+///
+/// * `user` - The ID of the logged in user
+/// * `token` - The token proving the login. Only its hash is kept on the user
+#[derive(Debug, Serialize, Deserialize)]
+struct Session {
+    user: u32,
+    token: String,
+}
+
+/// The path of the file holding the current session
+pub fn session_path() -> PathBuf {
+    storage::data_dir().join("session.json")
+}
+
+/// Starts a session for the given user, replacing any current one
+///
+/// # Arguments
+///
+/// * `user` - The user logging in
+///
+/// # Returns
+///
+/// The hash of the new session token, to be kept on the user so the session can be checked
+pub fn start(user: u32) -> String {
+    let mut rng = rand::thread_rng();
+    let token: String = (0..32)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect();
+    let session = Session {
+        user,
+        token: token.clone(),
+    };
+
+    if !dry_run::skip(&format!("start a session as user {}", user)) {
+        fs::create_dir_all(storage::data_dir()).unwrap();
+        fs::write(session_path(), serde_json::to_vec(&session).unwrap()).unwrap();
+    }
+    hash_token(&token)
+}
+
+/// Ends the current session, if any
+///
+/// # Returns
+///
+/// The ID of the user who was logged in
+pub fn end() -> Option<u32> {
+    let user = read().map(|session| session.user);
+    if session_path().exists() && !dry_run::skip("end the session") {
+        fs::remove_file(session_path()).unwrap();
+    }
+    user
+}
+
+/// Finds the ID of the user the session file belongs to, without checking that it is still valid
+pub fn current_user_id() -> Option<u32> {
+    read().map(|session| session.user)
+}
+
+/// Reads the session file
+fn read() -> Option<Session> {
+    let bytes = fs::read(session_path()).ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(session) => Some(session),
+        Err(e) => {
+            warn!("{}", t!("session.invalid_file", error = e));
+            None
+        }
+    }
+}
+
+/// Finds the user logged in with `rustflix login`
+///
+/// # Returns
+///
+/// The ID of the logged in user, or `None` if nobody is logged in or the session is no longer
+/// valid, e.g. because the password was changed since
+pub fn current_user() -> Option<u32> {
+    let session = read()?;
+    let users: Vec<User> = storage::load(storage::Store::Users);
+    let hash = hash_token(&session.token);
+    match users.iter().find(|user| user.id == session.user) {
+        Some(user) if user.session.as_ref() == Some(&hash) => {
+            debug!("Acting as logged in user {}", user.name);
+            Some(user.id)
+        }
+        _ => {
+            warn!("{}", t!("session.expired"));
+            None
+        }
+    }
+}
+
+/// Checks that the logged in user has at least the given role
+///
+/// # Arguments
+///
+/// * `needed` - The role needed
+/// * `action` - What needs the role, used in messages, e.g. `delete videos`
+///
+/// # Returns
+///
+/// `false` after reporting an error if someone is logged in without the role, or if nobody is
+/// logged in while any user has a role above viewer. `true` if nobody is logged in and roles
+/// aren't used, as whoever runs rustflix then owns the data directory anyway
+pub fn require_role(needed: Role, action: &str) -> bool {
+    report(check_role_of(current_user(), needed, action))
+}
+
+/// Checks that the logged in user has at least the given role, as `require_role` does, but hands
+/// back the error instead of reporting it, for the browser to show
+///
+/// # Returns
+///
+/// The message explaining who may do this if the check fails
+pub fn check_role(needed: Role, action: &str) -> Result<(), String> {
+    check_role_of(current_user(), needed, action)
+}
+
+/// Checks that the logged in user is the given user, or else has at least the given role, as
+/// `require_self_or_role` does, but hands back the error instead of reporting it
+///
+/// # Returns
+///
+/// The message explaining who may do this if the check fails
+pub fn check_self_or_role(user: u32, needed: Role, action: &str) -> Result<(), String> {
+    let current = current_user();
+    match current == Some(user) {
+        true => Ok(()),
+        false => check_role_of(current, needed, action),
+    }
+}
+
+/// Checks that the logged in user is the given user, or else has at least the given role, as
+/// with `require_role`
+///
+/// # Arguments
+///
+/// * `user` - The ID of the user who may act without the role
+/// * `needed` - The role needed by anyone else
+/// * `action` - What needs the role, used in messages, e.g. `update other users`
+///
+/// # Returns
+///
+/// `false` after reporting an error if the check fails
+pub fn require_self_or_role(user: u32, needed: Role, action: &str) -> bool {
+    report(check_self_or_role(user, needed, action))
+}
+
+/// Reports the error of a failed role check
+///
+/// # Returns
+///
+/// Whether the check passed
+fn report(checked: Result<(), String>) -> bool {
+    match checked {
+        Ok(()) => true,
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    }
+}
+
+/// Checks that the given logged in user has at least the given role, as `require_role` describes
+///
+/// # Returns
+///
+/// The message explaining who may do this if the check fails
+fn check_role_of(current: Option<u32>, needed: Role, action: &str) -> Result<(), String> {
+    let users: Vec<User> = storage::load(storage::Store::Users);
+    let needed_name = listing::field_names(&[needed]).concat();
+    let Some(user_id) = current else {
+        // Once anyone was given a role, being logged out can't get around it
+        if users.iter().any(|user| user.role > Role::Viewer) {
+            return Err(t!("session.log_in_as", role = needed_name, action = action));
+        }
+        return Ok(());
+    };
+    let role = users
+        .iter()
+        .find(|user| user.id == user_id)
+        .map(|user| user.role)
+        .unwrap_or_default();
+    if role < needed {
+        return Err(t!(
+            "session.role_needed",
+            role = needed_name,
+            action = action
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_role() {
+        let _lock = storage::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        storage::begin_scratch();
+        let user = |id, role| User {
+            id,
+            role,
+            ..Default::default()
+        };
+
+        // Nobody has a role yet, so whoever runs rustflix may do anything
+        storage::save(storage::Store::Users, &vec![user(1, Role::Viewer)]);
+        assert!(check_role_of(None, Role::Admin, "restore backups").is_ok());
+
+        storage::save(
+            storage::Store::Users,
+            &vec![user(1, Role::Admin), user(2, Role::Viewer)],
+        );
+        assert!(check_role_of(Some(1), Role::Admin, "restore backups").is_ok());
+        assert_eq!(
+            check_role_of(Some(2), Role::Moderator, "create users"),
+            Err("Only users with the moderator role or above can create users".to_string())
+        );
+        assert!(check_role_of(Some(2), Role::Admin, "restore backups").is_err());
+        assert!(check_role_of(None, Role::Admin, "restore backups").is_err());
+        storage::end_scratch();
+    }
+}
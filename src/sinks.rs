@@ -0,0 +1,217 @@
+use crate::args::command_types::video_subcommands::Video;
+use crate::t;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for a sink to respond
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The events a sink can be told about
+///
+/// # Variants
+///
+/// * `Milestone` - A video reached one of the milestones in the config file
+/// * `Upload` - A channel uploaded a public video
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkEvent {
+    Milestone,
+    Upload,
+}
+
+/// Where a sink posts its messages
+///
+/// # Variants
+///
+/// * `Ntfy` - An ntfy topic, e.g. `url = "https://ntfy.sh/my-channel"`, with an access token if
+///   the topic is protected
+/// * `Matrix` - A Matrix room, posted to as the user the access token belongs to, e.g.
+///   `homeserver = "https://matrix.org"` and `room = "!abcdef:matrix.org"`
+/// * `Discord` - A Discord webhook, e.g. `url = "https://discord.com/api/webhooks/..."`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Target {
+    Ntfy {
+        url: String,
+        token: Option<String>,
+    },
+    Matrix {
+        homeserver: String,
+        room: String,
+        token: String,
+    },
+    Discord {
+        url: String,
+    },
+}
+
+/// A chat or push service told about milestones and uploads, from a `[[sinks]]` section of the
+/// config file
+///
+/// # Fields
+///
+/// * `target` - Where to post the messages, chosen by the `kind` key
+/// * `events` - The events to post, e.g. `["upload"]`. Every event if empty
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Sink {
+    #[serde(flatten)]
+    pub target: Target,
+    #[serde(default)]
+    pub events: Vec<SinkEvent>,
+}
+
+impl Sink {
+    /// Whether the sink is told about events of the given kind
+    fn wants(&self, event: SinkEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Describes where the sink posts, for messages about it
+    fn name(&self) -> String {
+        match &self.target {
+            Target::Ntfy { url, .. } => t!("sink.ntfy", url = url),
+            Target::Matrix { room, .. } => t!("sink.matrix", room = room),
+            Target::Discord { .. } => t!("sink.discord"),
+        }
+    }
+
+    /// Posts a message to the sink
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - What the message is about
+    /// * `message` - What to post
+    ///
+    /// # Returns
+    ///
+    /// Why the message couldn't be posted, if it couldn't
+    fn post(&self, event: SinkEvent, message: &str) -> Result<(), String> {
+        let result = match &self.target {
+            Target::Ntfy { url, token } => {
+                let tags = match event {
+                    SinkEvent::Milestone => "tada",
+                    SinkEvent::Upload => "movie_camera",
+                };
+                let request = ureq::post(url)
+                    .timeout(TIMEOUT)
+                    .set("Title", "rustflix")
+                    .set("Tags", tags);
+                match token {
+                    Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+                    None => request,
+                }
+                .send_string(message)
+            }
+            Target::Matrix {
+                homeserver,
+                room,
+                token,
+            } => ureq::put(&format!(
+                "{}/_matrix/client/v3/rooms/{}/send/m.room.message/rustflix-{}",
+                homeserver.trim_end_matches('/'),
+                encode(room),
+                rand::random::<u64>()
+            ))
+            .timeout(TIMEOUT)
+            .set("Authorization", &format!("Bearer {}", token))
+            .send_json(json!({ "msgtype": "m.text", "body": message })),
+            Target::Discord { url } => ureq::post(url)
+                .timeout(TIMEOUT)
+                .send_json(json!({ "content": message })),
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, response)) => Err(t!(
+                "sink.rejected",
+                sink = self.name(),
+                code = code,
+                body = response.into_string().unwrap_or_default()
+            )),
+            Err(ureq::Error::Transport(e)) => {
+                Err(t!("sink.unreachable", sink = self.name(), error = e))
+            }
+        }
+    }
+}
+
+/// Escapes the characters of a Matrix room ID or alias that can't appear in a URL path
+fn encode(room: &str) -> String {
+    room.replace('%', "%25")
+        .replace('!', "%21")
+        .replace('#', "%23")
+        .replace(':', "%3A")
+        .replace('/', "%2F")
+}
+
+/// The sinks configured, if any
+static SINKS: OnceLock<Vec<Sink>> = OnceLock::new();
+
+/// Posts milestones and uploads from now on to the given sinks
+pub fn set_config(sinks: Vec<Sink>) {
+    SINKS.set(sinks).expect("Sinks set twice");
+}
+
+/// The sinks configured, or none if none are
+fn configured() -> &'static [Sink] {
+    SINKS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Whether any sink is told about events of the given kind
+pub fn wanted(event: SinkEvent) -> bool {
+    configured().iter().any(|sink| sink.wants(event))
+}
+
+/// Posts a message to every sink told about its kind of event, all at once, warning about those
+/// that couldn't be reached
+///
+/// # Arguments
+///
+/// * `event` - What the message is about
+/// * `message` - What to post
+/// * `video` - The video the message is about
+pub fn post(event: SinkEvent, message: &str, video: &Video) {
+    thread::scope(|scope| {
+        for sink in configured().iter().filter(|sink| sink.wants(event)) {
+            scope.spawn(move || match sink.post(event, message) {
+                Ok(()) => debug!("Posted about {} to {}", video.name, sink.name()),
+                Err(e) => warn!("{}", e),
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_config() {
+        let sinks: Vec<Sink> = toml::from_str::<toml::Table>(
+            r#"
+            [[sinks]]
+            kind = "ntfy"
+            url = "https://ntfy.sh/rustflix"
+
+            [[sinks]]
+            kind = "matrix"
+            homeserver = "https://matrix.org"
+            room = "!abc:matrix.org"
+            token = "secret"
+            events = ["milestone"]
+            "#,
+        )
+        .unwrap()["sinks"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert!(matches!(sinks[0].target, Target::Ntfy { token: None, .. }));
+        assert!(sinks[0].wants(SinkEvent::Upload));
+        assert!(sinks[1].wants(SinkEvent::Milestone));
+        assert!(!sinks[1].wants(SinkEvent::Upload));
+        assert_eq!(encode("!abc:matrix.org"), "%21abc%3Amatrix.org");
+    }
+}
@@ -0,0 +1,1146 @@
+use crate::{
+    backup, daemon, email_filter, encryption, events, migration, remote, s3, t, utilities, view_log,
+};
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use memmap2::Mmap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Starts every store file, so files from before store files had a version can be told apart
+const MAGIC: &[u8; 4] = b"RFLX";
+
+/// The version of the format store files are written in. Follows the magic bytes as a little
+/// endian `u16`, before the records one after another, each encoded as MessagePack and preceded by
+/// its length as a little endian `u32`, so they can be read one at a time. Version 1 held a single
+/// MessagePack list of every record
+///
+/// Fields added to records since need `#[serde(default)]` so older files can still be read.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Starts every zstd frame, so compressed store files can be told apart from uncompressed ones
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Store files smaller than this are written uncompressed, as they would barely shrink
+const COMPRESSION_THRESHOLD: usize = 4096;
+
+/// The zstd compression level store files are written with
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The stores kept in the data directory
+///
+/// # Variants
+///
+/// * `Users` - The store holding every user
+/// * `Videos` - The store holding every video
+/// * `ApiKeys` - The store holding the keys clients of the server use. Always kept locally
+/// * `Genres` - The store holding the names of the genres videos may have
+/// * `Series` - The store holding every series
+/// * `History` - The store holding an entry for every time a video was played
+/// * `Subscriptions` - The store holding which users are subscribed to which channels
+/// * `Metadata` - The store holding settings of the stores themselves, such as how IDs are picked
+/// * `Notifications` - The store holding the notifications users were sent, such as when their
+///   videos reach a milestone
+/// * `Playlists` - The store holding the lists of videos users put together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Store {
+    Users,
+    Videos,
+    ApiKeys,
+    Genres,
+    Series,
+    History,
+    Subscriptions,
+    Metadata,
+    Notifications,
+    Playlists,
+}
+
+impl Store {
+    /// Every store, in the order they are backed up
+    pub const ALL: [Store; 10] = [
+        Store::Users,
+        Store::Videos,
+        Store::ApiKeys,
+        Store::Genres,
+        Store::Series,
+        Store::History,
+        Store::Subscriptions,
+        Store::Metadata,
+        Store::Notifications,
+        Store::Playlists,
+    ];
+
+    /// The name of the file backing the store
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Store::Users => "users.bc",
+            Store::Videos => "videos.bc",
+            Store::ApiKeys => "apikeys.bc",
+            Store::Genres => "genres.bc",
+            Store::Series => "series.bc",
+            Store::History => "history.bc",
+            Store::Subscriptions => "subscriptions.bc",
+            Store::Metadata => "metadata.bc",
+            Store::Notifications => "notifications.bc",
+            Store::Playlists => "playlists.bc",
+        }
+    }
+
+    /// The name of the file next to the store file holding its checksum
+    pub fn checksum_file_name(&self) -> String {
+        format!("{}.sha256", self.file_name())
+    }
+
+    /// The field recording when a record of the store was created, for stores that keep one
+    pub fn created_field(&self) -> Option<&'static str> {
+        match self {
+            Store::Users
+            | Store::Series
+            | Store::Subscriptions
+            | Store::Notifications
+            | Store::Playlists => Some("created_at"),
+            Store::Videos => Some("uploaded_at"),
+            Store::History => Some("started_at"),
+            Store::ApiKeys | Store::Genres | Store::Metadata => None,
+        }
+    }
+
+    /// Whether records of the store have an `updated_at` field recording when they last changed
+    pub fn has_updated_at(&self) -> bool {
+        matches!(
+            self,
+            Store::Users | Store::Videos | Store::Series | Store::Playlists
+        )
+    }
+
+    /// The path the store is served at by `rustflix serve`
+    pub fn route(&self) -> &'static str {
+        match self {
+            Store::Users => "users",
+            Store::Videos => "videos",
+            Store::ApiKeys => "apikeys",
+            Store::Genres => "genres",
+            Store::Series => "series",
+            Store::History => "history",
+            Store::Subscriptions => "subscriptions",
+            Store::Metadata => "metadata",
+            Store::Notifications => "notifications",
+            Store::Playlists => "playlists",
+        }
+    }
+}
+
+/// The directory all stores are kept in
+pub fn data_dir() -> &'static Path {
+    DATA_DIR
+        .read()
+        .unwrap()
+        .unwrap_or_else(|| DEFAULT_DATA_DIR.get_or_init(|| utilities::home_dir().join(".rustflix")))
+}
+
+/// Where the stores are kept by default, `~/.rustflix`
+static DEFAULT_DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where the stores are kept instead of the default data directory, if anywhere
+static DATA_DIR: RwLock<Option<&'static Path>> = RwLock::new(None);
+
+/// Keeps every store in the given directory instead of the default data directory, or the one
+/// set before. Must be called before any store is loaded
+pub fn set_data_dir(path: PathBuf) {
+    *DATA_DIR.write().unwrap() = Some(Box::leak(path.into_boxed_path()));
+}
+
+/// Settings for how store files are read, from the `[storage]` section of the config file
+///
+/// # Fields
+///
+/// * `mmap` - Whether list commands map store files into memory and read records straight from
+///   them, instead of copying them through a buffer. On by default, as rustflix replaces store
+///   files instead of writing into them, so a listing keeps reading the file it mapped. Worth
+///   turning off if something else edits the store files in place, such as a sync tool, as a
+///   mapped file cut short stops the process
+/// * `data_dir` - The directory to keep the stores in instead of `~/.rustflix`, also set by
+///   `RUSTFLIX_DATA_DIR`
+/// * `backend` - Where to keep the stores, also set by `RUSTFLIX_BACKEND`. The `[s3]` bucket if
+///   one is configured, or else the data directory, if not given
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub mmap: bool,
+    pub data_dir: Option<PathBuf>,
+    pub backend: Option<StorageBackend>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            mmap: true,
+            data_dir: None,
+            backend: None,
+        }
+    }
+}
+
+/// Where the stores are kept
+///
+/// # Variants
+///
+/// * `Files` - Files in the data directory, even if an `[s3]` section is configured
+/// * `S3` - Objects in the bucket of the `[s3]` section of the config file
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    Files,
+    S3,
+}
+
+/// Whether store files are mapped into memory when streamed
+static MMAP: AtomicBool = AtomicBool::new(true);
+
+/// Reads store files following the given settings
+pub fn set_config(config: &StorageConfig) {
+    MMAP.store(config.mmap, Ordering::Relaxed);
+}
+
+/// Maps a store file into memory, unless `[storage] mmap` is turned off
+///
+/// # Returns
+///
+/// The mapped file, or `None` if files aren't mapped or this one could not be
+fn map(file: &File, path: &Path) -> Option<Mmap> {
+    if !MMAP.load(Ordering::Relaxed) {
+        return None;
+    }
+    // SAFETY: The mapping is only read, and only while records are decoded from it. rustflix
+    // never writes into a store file, but writes a new one and renames it over the old with
+    // `replace_file`, so the file mapped keeps its contents until it is unmapped. Only something
+    // else editing the file in place could change it underneath, which is why mapping can be
+    // turned off
+    match unsafe { Mmap::map(file) } {
+        Ok(map) => {
+            debug!("Mapped {} into memory", path.display());
+            Some(map)
+        }
+        Err(e) => {
+            warn!(
+                "{}",
+                t!("storage.could_not_map", path = path.display(), error = e)
+            );
+            None
+        }
+    }
+}
+
+/// The path of the file backing the given store
+pub fn store_path(store: Store) -> PathBuf {
+    data_dir().join(store.file_name())
+}
+
+/// The path of the file holding the checksum of the file backing the given store
+pub fn checksum_path(store: Store) -> PathBuf {
+    data_dir().join(store.checksum_file_name())
+}
+
+/// Takes an exclusive lock on the file backing the given store, waiting while another command
+/// holds it, so reading the store and writing it back can't interleave with anyone else doing the
+/// same. The lock is kept on a file of its own next to the store, as the store file is replaced
+/// when written
+///
+/// Stores kept elsewhere, or in memory during a batch, aren't locked. A running daemon checks for
+/// changes made in between itself instead, see `save_checked`.
+///
+/// # Returns
+///
+/// The locked file, which releases the lock when dropped, or `None` if nothing was locked
+pub fn lock(store: Store) -> Option<File> {
+    if in_batch()
+        || remote::url().is_some()
+        || s3::config().is_some()
+        || daemon::connect().is_some()
+    {
+        return None;
+    }
+    let path = data_dir().join(format!("{}.lock", store.file_name()));
+    let file = fs::create_dir_all(data_dir())
+        .and_then(|_| File::create(&path))
+        .and_then(|file| file.lock().map(|_| file));
+    match file {
+        Ok(file) => Some(file),
+        Err(e) => {
+            warn!(
+                "{}",
+                t!("storage.could_not_lock", path = path.display(), error = e)
+            );
+            None
+        }
+    }
+}
+
+/// Hashes the contents of a store file, as kept in its checksum file
+///
+/// # Returns
+///
+/// The SHA-256 hash of the contents, as hex
+pub fn checksum(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// A store kept in memory during a batch
+///
+/// # Fields
+///
+/// * `records` - The records of the store
+/// * `changed` - Whether the records were saved during the batch, and so need writing once it ends
+struct Batched {
+    records: Vec<Value>,
+    changed: bool,
+}
+
+/// The stores loaded during the current batch, or `None` outside of one
+static BATCH: Mutex<Option<HashMap<Store, Batched>>> = Mutex::new(None);
+
+/// Starts a batch, during which every store is only loaded once and saved to memory, until
+/// `end_batch` writes the changed ones. A dry run already keeps every store in memory, so nothing
+/// changes during one
+pub fn begin_batch() {
+    if dry_run() {
+        return;
+    }
+    *BATCH.lock().unwrap() = Some(HashMap::new());
+}
+
+/// Whether a batch is running
+pub fn in_batch() -> bool {
+    BATCH.lock().unwrap().is_some()
+}
+
+/// Ends the current batch, writing every store changed during it. During a dry run, the stores
+/// stay in memory until `end_dry_run`
+pub fn end_batch() {
+    if dry_run() {
+        return;
+    }
+    let Some(mut stores) = BATCH.lock().unwrap().take() else {
+        return;
+    };
+    for store in Store::ALL {
+        if let Some(batched) = stores.remove(&store).filter(|batched| batched.changed) {
+            save_unbatched(store, &batched.records);
+        }
+    }
+}
+
+/// Whether `--dry-run` was given
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Starts a dry run, which runs like a batch that never ends: every change is only saved to
+/// memory, so later steps of the command see it, until `end_dry_run` hands the changes back
+pub fn begin_dry_run() {
+    *BATCH.lock().unwrap() = Some(HashMap::new());
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+/// Starts a dry run on empty stores instead of a copy of the kept ones, so nothing is read from or
+/// written to wherever the stores are kept until `end_scratch` throws them away, as `self-test`
+/// needs
+pub fn begin_scratch() {
+    let stores = Store::ALL
+        .into_iter()
+        .map(|store| {
+            let batched = Batched {
+                records: vec![],
+                changed: false,
+            };
+            (store, batched)
+        })
+        .collect();
+    *BATCH.lock().unwrap() = Some(stores);
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+/// Ends the dry run started by `begin_scratch`, throwing away every store it held
+pub fn end_scratch() {
+    DRY_RUN.store(false, Ordering::Relaxed);
+    *BATCH.lock().unwrap() = None;
+}
+
+/// Held by tests that go through the stores, which the whole process shares, so they take turns
+#[cfg(test)]
+pub static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Whether changes are only being kept in memory, as with `--dry-run`
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Ends the dry run, discarding every change made during it
+///
+/// # Returns
+///
+/// Every store changed, with its records as they are kept and as they would have been saved,
+/// including the times `stamp` would have set
+pub fn end_dry_run() -> Vec<(Store, Vec<Value>, Vec<Value>)> {
+    DRY_RUN.store(false, Ordering::Relaxed);
+    let Some(mut stores) = BATCH.lock().unwrap().take() else {
+        return vec![];
+    };
+    Store::ALL
+        .into_iter()
+        .filter_map(|store| {
+            let batched = stores.remove(&store).filter(|batched| batched.changed)?;
+            let old: Vec<Value> = load_unbatched(store);
+            let new = stamp(store, &batched.records, &old);
+            Some((store, old, new))
+        })
+        .collect()
+}
+
+/// Loads the records of a store kept in memory by the current batch, loading it first if needed
+fn load_batched<T: DeserializeOwned>(store: Store) -> Vec<T> {
+    let kept = BATCH
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|stores| stores.get(&store))
+        .map(|batched| batched.records.clone());
+    let records = match kept {
+        Some(records) => records,
+        None => {
+            let records: Vec<Value> = load_unbatched(store);
+            if let Some(stores) = BATCH.lock().unwrap().as_mut() {
+                stores.insert(
+                    store,
+                    Batched {
+                        records: records.clone(),
+                        changed: false,
+                    },
+                );
+            }
+            records
+        }
+    };
+    serde_json::from_value(Value::Array(records)).unwrap()
+}
+
+/// Loads every record of the given store, from the server if `--remote` was given, from the S3
+/// bucket if one is configured, or from the daemon if one is running. During a batch, each store
+/// is only loaded once
+///
+/// # Arguments
+///
+/// * `store` - The store to load
+///
+/// # Returns
+///
+/// The records of the store, or an empty list if the store has not been created yet
+pub fn load<T: Serialize + DeserializeOwned>(store: Store) -> Vec<T> {
+    if in_batch() {
+        return load_batched(store);
+    }
+    load_unbatched(store)
+}
+
+fn load_unbatched<T: Serialize + DeserializeOwned>(store: Store) -> Vec<T> {
+    if let Some(remote) = remote::url() {
+        return remote::load(remote, store);
+    }
+    if let Some(bucket) = s3::config() {
+        return s3::load(bucket, store);
+    }
+    if let Some(daemon) = daemon::connect() {
+        return daemon::load(daemon, store);
+    }
+
+    load_file(store)
+}
+
+/// Streams the records of the given store one at a time, so commands only looking for some of
+/// them never hold every record in memory
+///
+/// Only plain and compressed files in the current format are streamed. Records from elsewhere, and
+/// files that are encrypted or need migrating, are loaded as a whole first. Files are mapped into
+/// memory instead of read through a buffer unless `[storage] mmap` is turned off.
+///
+/// # Arguments
+///
+/// * `store` - The store to stream
+///
+/// # Returns
+///
+/// The records of the store, in the order they are kept
+pub fn stream<T: Serialize + DeserializeOwned + 'static>(
+    store: Store,
+) -> Box<dyn Iterator<Item = T>> {
+    if in_batch() {
+        return Box::new(load_batched(store).into_iter());
+    }
+    if let Some(remote) = remote::url() {
+        return Box::new(remote::load(remote, store).into_iter());
+    }
+    if let Some(bucket) = s3::config() {
+        return Box::new(s3::load(bucket, store).into_iter());
+    }
+    if let Some(daemon) = daemon::connect() {
+        return Box::new(daemon::load(daemon, store).into_iter());
+    }
+
+    let path = store_path(store);
+    let Ok(mut file) = File::open(&path) else {
+        debug!("{} does not exist yet. Starting empty", path.display());
+        return Box::new(std::iter::empty());
+    };
+    let mut start = [0; 4];
+    let start = file
+        .read_exact(&mut start)
+        .map(|_| start)
+        .unwrap_or_default();
+    if start != *MAGIC && start != *ZSTD_MAGIC {
+        return Box::new(load_file::<T>(store).into_iter());
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let compressed = start == *ZSTD_MAGIC;
+    let mut reader: Box<dyn Read> = match map(&file, &path) {
+        Some(map) if compressed => Box::new(zstd::Decoder::with_buffer(Cursor::new(map)).unwrap()),
+        Some(map) => Box::new(Cursor::new(map)),
+        None if compressed => Box::new(zstd::Decoder::new(file).unwrap()),
+        None => Box::new(BufReader::new(file)),
+    };
+    let mut header = [0; 6];
+    if reader.read_exact(&mut header).is_err()
+        || header[..4] != *MAGIC
+        || u16::from_le_bytes([header[4], header[5]]) != FORMAT_VERSION
+    {
+        return Box::new(load_file::<T>(store).into_iter());
+    }
+
+    // Corrupt files can only be recovered from as a whole
+    if RECOVER.load(Ordering::Relaxed) {
+        return Box::new(load_file::<T>(store).into_iter());
+    }
+    debug!("Streaming records from {}", path.display());
+    let views = match store {
+        Store::Videos => view_log::read(),
+        _ => HashMap::new(),
+    };
+    Box::new(RecordReader::new(reader).map(move |record| match record {
+        Ok(record) if views.is_empty() => record,
+        Ok(record) => view_log::apply_to(record, &views),
+        Err(e) => stop_corrupt(&path, &e),
+    }))
+}
+
+/// Loads every record of the given store from its file in the data directory
+///
+/// Files written before store files had a version are migrated to the current format, and
+/// encrypted files are decrypted.
+///
+/// # Arguments
+///
+/// * `store` - The store to load
+///
+/// # Returns
+///
+/// The records of the store, or an empty list if the store has not been created yet
+pub fn load_file<T: Serialize + DeserializeOwned>(store: Store) -> Vec<T> {
+    let path = store_path(store);
+    if !path.exists() {
+        debug!("{} does not exist yet. Starting empty", path.display());
+        return vec![];
+    }
+
+    let start = Instant::now();
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!(
+                "{}",
+                t!("storage.could_not_read", path = path.display(), error = e)
+            );
+            process::exit(1);
+        }
+    };
+    let records: Vec<T> = if bytes.starts_with(MAGIC)
+        || bytes.starts_with(ZSTD_MAGIC)
+        || encryption::is_encrypted(&bytes)
+    {
+        match decode(&bytes) {
+            Ok(records) => records,
+            Err(e) => recover(store, &bytes, e),
+        }
+    } else {
+        let records = migration::read_unversioned(store, &bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|records| serde_json::from_value(records).map_err(|e| e.to_string()));
+        match records {
+            Ok(records) => {
+                info!(
+                    "{}",
+                    t!(
+                        "storage.migrating",
+                        path = path.display(),
+                        version = FORMAT_VERSION
+                    )
+                );
+                save_file(store, &records);
+                records
+            }
+            Err(e) => recover(store, &bytes, e),
+        }
+    };
+    debug!(
+        "Loaded {} records ({} bytes) from {} in {:?}",
+        records.len(),
+        bytes.len(),
+        path.display(),
+        start.elapsed()
+    );
+    match store {
+        Store::Videos => view_log::apply(records),
+        _ => records,
+    }
+}
+
+/// Whether the records that can still be read are salvaged from corrupt store files, as with
+/// `--recover`
+static RECOVER: AtomicBool = AtomicBool::new(false);
+
+/// Salvages the records that can still be read from corrupt store files instead of stopping
+pub fn set_recover(recover: bool) {
+    RECOVER.store(recover, Ordering::Relaxed);
+}
+
+/// Explains what can be done about a corrupt store file, and stops
+fn stop_corrupt(path: &Path, e: &str) -> ! {
+    error!(
+        "{}",
+        t!("storage.corrupt", path = path.display(), error = e)
+    );
+    error!("{}", t!("storage.how_to_recover"));
+    process::exit(1);
+}
+
+/// Gets the records of a store whose file can't be read
+///
+/// They are taken from the newest automatic backup holding a readable copy of the store, if
+/// automatic backups are turned on, or else salvaged from the file itself with `--recover`. The
+/// corrupt file is then kept next to the store as `<file>.corrupt` and replaced with the records
+/// recovered. Otherwise, the command stops.
+///
+/// # Arguments
+///
+/// * `store` - The store whose file can't be read
+/// * `bytes` - The contents of the file
+/// * `e` - Why the file can't be read
+///
+/// # Returns
+///
+/// The records recovered
+fn recover<T: Serialize + DeserializeOwned>(store: Store, bytes: &[u8], e: String) -> Vec<T> {
+    let path = store_path(store);
+    let from_backup = backup::auto_copies(store).find_map(|(made_at, copy)| {
+        let records = decode(&copy).ok()?;
+        let made_at = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(made_at));
+        warn!(
+            "{}",
+            t!(
+                "storage.from_backup",
+                path = path.display(),
+                error = e,
+                made_at = made_at
+            )
+        );
+        Some(records)
+    });
+    let records = match from_backup {
+        Some(records) => records,
+        None if RECOVER.load(Ordering::Relaxed) => {
+            let (records, lost) = salvage(bytes);
+            if records.is_empty() {
+                error!(
+                    "{}",
+                    t!(
+                        "storage.nothing_recovered",
+                        path = path.display(),
+                        error = e
+                    )
+                );
+                process::exit(1);
+            }
+            let recovered = match lost {
+                0 => t!(
+                    "storage.recovered",
+                    path = path.display(),
+                    error = e,
+                    count = records.len()
+                ),
+                lost => t!(
+                    "storage.recovered_lost",
+                    path = path.display(),
+                    error = e,
+                    count = records.len(),
+                    lost = lost
+                ),
+            };
+            warn!("{}", recovered);
+            records
+        }
+        None => stop_corrupt(&path, &e),
+    };
+
+    let mut aside = path.clone().into_os_string();
+    aside.push(".corrupt");
+    if let Err(e) = fs::copy(&path, &aside) {
+        error!(
+            "{}",
+            t!("storage.could_not_copy", path = path.display(), error = e)
+        );
+        process::exit(1);
+    }
+    // Written as is, as the corrupt records can't be compared with to stamp the recovered ones
+    write_file(store, &encode(&records));
+    info!(
+        "{}",
+        t!(
+            "storage.replaced",
+            path = path.display(),
+            aside = PathBuf::from(aside).display()
+        )
+    );
+    records
+}
+
+/// Reads every record that can still be read from a corrupt store file
+///
+/// Records are read up to where the file is cut short, skipping those that can't be decoded. Only
+/// files in the current format can be read record by record.
+///
+/// # Returns
+///
+/// The records read, and how many others could not be
+fn salvage<T: DeserializeOwned>(bytes: &[u8]) -> (Vec<T>, usize) {
+    let bytes = match encryption::is_encrypted(bytes) {
+        true => match encryption::decrypt(bytes) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(_) => return (vec![], 0),
+        },
+        false => Cow::Borrowed(bytes),
+    };
+    // A cut short zstd frame still decompresses up to where it stops
+    let bytes = match bytes.starts_with(ZSTD_MAGIC) {
+        true => {
+            let mut decompressed = vec![];
+            if let Ok(mut decoder) = zstd::Decoder::new(&bytes[..]) {
+                let _ = decoder.read_to_end(&mut decompressed);
+            }
+            Cow::Owned(decompressed)
+        }
+        false => bytes,
+    };
+    let Some(versioned) = bytes.strip_prefix(MAGIC) else {
+        return (vec![], 0);
+    };
+    if versioned.get(..2) != Some(&FORMAT_VERSION.to_le_bytes()[..]) {
+        return (vec![], 0);
+    }
+
+    let mut records = vec![];
+    let mut lost = 0;
+    for record in RecordReader::<_, Value>::new(&versioned[2..]) {
+        match record.and_then(|record| serde_json::from_value(record).map_err(|e| e.to_string())) {
+            Ok(record) => records.push(record),
+            Err(_) => lost += 1,
+        }
+    }
+    (records, lost)
+}
+
+/// Overwrites the given store with the given records, on the server if `--remote` was given, in
+/// the S3 bucket if one is configured, or through the daemon if one is running. During a batch,
+/// the records are only kept in memory until the batch ends
+///
+/// # Arguments
+///
+/// * `store` - The store to write
+/// * `records` - The records to write to the store
+pub fn save<T: Serialize>(store: Store, records: &Vec<T>) {
+    if let Some(stores) = BATCH.lock().unwrap().as_mut() {
+        let records = match serde_json::to_value(records).unwrap() {
+            Value::Array(records) => records,
+            _ => unreachable!("Records are saved as a list"),
+        };
+        stores.insert(
+            store,
+            Batched {
+                records,
+                changed: true,
+            },
+        );
+        return;
+    }
+    save_unbatched(store, records)
+}
+
+/// Overwrites the given store with the given records like `save`, unless a record was added,
+/// removed, or changed since the store was read. Only a running daemon checks, as the store is
+/// read and written in separate requests to it. Anywhere else, callers hold `lock` from reading
+/// the store until writing it
+///
+/// # Arguments
+///
+/// * `store` - The store to write
+/// * `records` - The records to write to the store
+/// * `read` - The revision of every record of the store by ID, as it was read
+///
+/// # Returns
+///
+/// The ID of a record changed since it was read, leaving the store as it was
+pub fn save_checked<T: Serialize>(
+    store: Store,
+    records: &Vec<T>,
+    read: &BTreeMap<u32, u32>,
+) -> Result<(), u32> {
+    if !in_batch() && remote::url().is_none() && s3::config().is_none() {
+        if let Some(daemon) = daemon::connect() {
+            return daemon::save_checked(daemon, store, records, read);
+        }
+    }
+    save(store, records);
+    Ok(())
+}
+
+fn save_unbatched<T: Serialize>(store: Store, records: &Vec<T>) {
+    if let Some(remote) = remote::url() {
+        return remote::save(remote, store, records);
+    }
+    if let Some(bucket) = s3::config() {
+        return s3::save(bucket, store, records);
+    }
+    if let Some(daemon) = daemon::connect() {
+        return daemon::save(daemon, store, records);
+    }
+
+    save_file(store, records)
+}
+
+/// Sets when records were created and last changed, by comparing them with those they are about
+/// to replace
+///
+/// Records are matched by ID, or as a whole for stores without one. New records get their
+/// creation time unless they have one already, and changed records get a new `updated_at`.
+///
+/// # Arguments
+///
+/// * `store` - The store the records are saved to
+/// * `records` - The records about to be saved
+/// * `old` - The records about to be replaced
+///
+/// # Returns
+///
+/// The records with their times set
+pub fn stamp<T: Serialize>(store: Store, records: &Vec<T>, old: &[Value]) -> Vec<Value> {
+    let Value::Array(mut records) = serde_json::to_value(records).unwrap() else {
+        unreachable!("Records always serialize to a list");
+    };
+    let Some(created_field) = store.created_field() else {
+        return records;
+    };
+
+    let without_times = |record: &Value| {
+        let mut record = record.clone();
+        if let Some(fields) = record.as_object_mut() {
+            fields.remove(created_field);
+            fields.remove("updated_at");
+        }
+        record
+    };
+    let key = |record: &Value| match record.get("id") {
+        Some(id) => id.to_string(),
+        None => without_times(record).to_string(),
+    };
+
+    let old: HashMap<String, Value> = old
+        .iter()
+        .map(|record| (key(record), without_times(record)))
+        .collect();
+
+    let now = Value::from(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    );
+    for record in records.iter_mut() {
+        let Some(fields) = record.as_object() else {
+            continue;
+        };
+        match old.get(&key(record)) {
+            None if fields.get(created_field).is_none_or(Value::is_null) => {
+                record[created_field] = now.clone();
+            }
+            Some(old) if store.has_updated_at() && *old != without_times(record) => {
+                record["updated_at"] = now.clone();
+            }
+            _ => {}
+        }
+    }
+    records
+}
+
+/// Reads records written one after another, each preceded by its length as a little endian `u32`
+struct RecordReader<R, T> {
+    reader: R,
+    record: PhantomData<T>,
+}
+
+impl<R: Read, T> RecordReader<R, T> {
+    fn new(reader: R) -> Self {
+        RecordReader {
+            reader,
+            record: PhantomData,
+        }
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for RecordReader<R, T> {
+    type Item = Result<T, String>;
+
+    fn next(&mut self) -> Option<Result<T, String>> {
+        let mut length = [0; 4];
+        match self.reader.read(&mut length[..1]) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some(Err(e.to_string())),
+        }
+        let cut_short = || Err(t!("storage.cut_short"));
+        if self.reader.read_exact(&mut length[1..]).is_err() {
+            return Some(cut_short());
+        }
+
+        let length = u32::from_le_bytes(length) as usize;
+        let mut record = Vec::with_capacity(length.min(1 << 20));
+        if let Err(e) = (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut record)
+        {
+            return Some(Err(e.to_string()));
+        }
+        if record.len() != length {
+            return Some(cut_short());
+        }
+        Some(rmp_serde::from_slice(&record).map_err(|e| e.to_string()))
+    }
+}
+
+/// Reads the records of a store file written in the current format, wherever it came from,
+/// decrypting and decompressing it first if needed
+///
+/// # Arguments
+///
+/// * `bytes` - The contents of the store file
+///
+/// # Returns
+///
+/// The records, or an error message if the file is not in a versioned format
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<Vec<T>, String> {
+    let bytes = match encryption::is_encrypted(bytes) {
+        true => Cow::Owned(encryption::decrypt(bytes)?),
+        false => Cow::Borrowed(bytes),
+    };
+    let bytes = match bytes.starts_with(ZSTD_MAGIC) {
+        true => Cow::Owned(
+            zstd::decode_all(&bytes[..])
+                .map_err(|e| t!("storage.could_not_decompress", error = e))?,
+        ),
+        false => bytes,
+    };
+    let versioned = bytes.strip_prefix(MAGIC).ok_or(t!("storage.no_version"))?;
+    let version = u16::from_le_bytes(
+        versioned
+            .get(..2)
+            .ok_or(t!("storage.cut_short"))?
+            .try_into()
+            .unwrap(),
+    );
+    if version > FORMAT_VERSION {
+        return Err(t!("storage.too_new", version = version));
+    }
+    match version {
+        1 => rmp_serde::from_slice(&versioned[2..]).map_err(|e| e.to_string()),
+        _ => RecordReader::new(&versioned[2..]).collect(),
+    }
+}
+
+/// Writes records the way they are kept in store files, compressed with zstd once they are large
+/// enough and then encrypted if encryption is enabled
+///
+/// # Arguments
+///
+/// * `records` - The records to write
+pub fn encode<T: Serialize>(records: &Vec<T>) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+    for record in records {
+        let record = rmp_serde::to_vec_named(record).unwrap();
+        bytes.extend((record.len() as u32).to_le_bytes());
+        bytes.extend(record);
+    }
+    if bytes.len() >= COMPRESSION_THRESHOLD {
+        bytes = zstd::encode_all(&bytes[..], COMPRESSION_LEVEL).unwrap();
+    }
+    match encryption::enabled() {
+        true => encryption::encrypt(&bytes),
+        false => bytes,
+    }
+}
+
+/// Overwrites the file in the data directory backing the given store, along with its checksum
+///
+/// # Arguments
+///
+/// * `store` - The store to write
+/// * `records` - The records to write to the store
+pub fn save_file<T: Serialize>(store: Store, records: &Vec<T>) {
+    if dry_run() {
+        return save(store, records);
+    }
+    backup::auto_backup_if_due();
+    let path = store_path(store);
+    let start = Instant::now();
+    let old: Vec<Value> = fs::read(&path)
+        .ok()
+        .and_then(|bytes| decode(&bytes).ok())
+        .unwrap_or_default();
+    let stamped = stamp(store, records, &old);
+    let bytes = encode(&stamped);
+    write_file(store, &bytes);
+    // The views already in the log were recorded when they were added
+    let old: Vec<Value> = match store {
+        Store::Videos => {
+            let views = view_log::applied();
+            old.into_iter()
+                .map(|video| view_log::apply_to(video, &views))
+                .collect()
+        }
+        _ => old,
+    };
+    events::dispatch(store, &old, &stamped);
+    match store {
+        Store::Users => email_filter::rebuild(&stamped),
+        Store::Videos => view_log::forget_applied(),
+        _ => {}
+    }
+    debug!(
+        "Saved {} records ({} bytes) to {} in {:?}",
+        records.len(),
+        bytes.len(),
+        path.display(),
+        start.elapsed()
+    );
+}
+
+/// Writes the contents of the file backing the given store, along with its checksum. Unlike
+/// `save_file`, records are written as given, without stamping them or telling anything about the
+/// change
+///
+/// # Arguments
+///
+/// * `store` - The store to write
+/// * `bytes` - The contents of the file, written with `encode`
+pub fn write_file(store: Store, bytes: &[u8]) {
+    fs::create_dir_all(data_dir()).unwrap();
+    replace_file(&store_path(store), bytes).unwrap();
+    replace_file(&checksum_path(store), checksum(bytes).as_bytes()).unwrap();
+}
+
+/// Replaces a file with the given contents all at once, by writing them to a temporary file in the
+/// same directory and renaming it over the file. Anyone reading the file, or holding it mapped
+/// into memory, sees either the old contents or the new ones, never a mix
+///
+/// # Arguments
+///
+/// * `path` - The file to replace
+/// * `bytes` - The new contents of the file
+pub fn replace_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.{:08x}.tmp", name, rand::random::<u32>()));
+    let result = File::create(&temporary)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temporary, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        let small = vec!["video".to_string()];
+        let large = vec!["video".to_string(); 1000];
+
+        let bytes = encode(&small);
+        assert!(bytes.starts_with(MAGIC));
+        assert_eq!(decode::<String>(&bytes).unwrap(), small);
+
+        let bytes = encode(&large);
+        assert!(bytes.starts_with(ZSTD_MAGIC));
+        assert!(bytes.len() < COMPRESSION_THRESHOLD);
+        assert_eq!(decode::<String>(&bytes).unwrap(), large);
+    }
+
+    #[test]
+    fn test_salvage() {
+        let records: Vec<String> = (0..3).map(|i| format!("record {}", i)).collect();
+        let mut bytes = encode(&records);
+        // Garbles the type of the second record, then cuts the third short
+        bytes[6 + 4 + 9 + 4] = 0xc1;
+        bytes.truncate(bytes.len() - 2);
+        assert!(decode::<String>(&bytes).is_err());
+
+        let (salvaged, lost) = salvage::<String>(&bytes);
+        assert_eq!(salvaged, ["record 0"]);
+        assert_eq!(lost, 2);
+        assert_eq!(salvage::<String>(b"not a store").0.len(), 0);
+    }
+
+    #[test]
+    fn test_records() {
+        let records = vec!["first".to_string(), "second".to_string()];
+        let bytes = encode(&records);
+        let read: Vec<String> = RecordReader::new(&bytes[6..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+        assert!(RecordReader::<_, String>::new(&bytes[6..bytes.len() - 1])
+            .any(|record| record.is_err()));
+
+        // Version 1 kept every record in a single list
+        let mut old = MAGIC.to_vec();
+        old.extend(1u16.to_le_bytes());
+        old.extend(rmp_serde::to_vec_named(&records).unwrap());
+        assert_eq!(decode::<String>(&old).unwrap(), records);
+    }
+}
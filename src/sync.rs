@@ -0,0 +1,519 @@
+use crate::args::command_types::user_subcommands::Role;
+use crate::dry_run;
+use crate::remote;
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities;
+use crate::view_log;
+use clap::{Args, ValueEnum};
+use log::{debug, error, info};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+/// The stores kept in sync. API keys stay with the host they were made for
+const SYNCED_STORES: [Store; 9] = [
+    Store::Users,
+    Store::Videos,
+    Store::Genres,
+    Store::Series,
+    Store::History,
+    Store::Subscriptions,
+    Store::Metadata,
+    Store::Notifications,
+    Store::Playlists,
+];
+
+#[derive(Debug, Args)]
+pub struct Synchronize {
+    /// The other data directory, either a local path or `ssh://host/path`
+    pub target: String,
+    /// How to settle records changed differently on both sides
+    #[arg(long, value_enum, default_value_t = ConflictStrategy::LastWriteWins)]
+    pub conflicts: ConflictStrategy,
+}
+
+/// How to settle a record changed differently on both sides of a sync
+///
+/// # Variants
+///
+/// * `LastWriteWins` - Keep the record updated last, by its `updated_at`. Records without one, or
+///   updated at the same time, are kept from the side whose store was written last
+/// * `Interactive` - Ask which side to keep for every conflicting record
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ConflictStrategy {
+    LastWriteWins,
+    Interactive,
+}
+
+/// Where the other data directory of a sync is
+///
+/// # Variants
+///
+/// * `Local` - A directory on this machine
+/// * `Ssh` - A directory on a host reached with `ssh`, which must be able to log in without asking
+#[derive(Debug)]
+enum Target {
+    Local(PathBuf),
+    Ssh { host: String, path: String },
+}
+
+impl Target {
+    /// Reads a target given on the command line
+    fn parse(target: &str) -> Result<Target, String> {
+        match target.strip_prefix("ssh://") {
+            Some(rest) => {
+                let (host, path) = rest
+                    .split_once('/')
+                    .ok_or(t!("sync.no_path", target = target))?;
+                if host.is_empty() {
+                    return Err(t!("sync.no_host", target = target));
+                }
+                Ok(Target::Ssh {
+                    host: host.to_string(),
+                    path: format!("/{}", path),
+                })
+            }
+            None => Ok(Target::Local(PathBuf::from(target))),
+        }
+    }
+
+    /// Reads the file backing a store
+    ///
+    /// # Returns
+    ///
+    /// The contents of the file and when it was last written, in seconds since the Unix epoch, or
+    /// `None` if the store has not been created there yet
+    fn read(&self, store: Store) -> Result<Option<(Vec<u8>, u64)>, String> {
+        match self {
+            Target::Local(dir) => {
+                let path = dir.join(store.file_name());
+                if !path.exists() {
+                    return Ok(None);
+                }
+                let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+                Ok(Some((bytes, modified(&path))))
+            }
+            Target::Ssh { host, path } => {
+                let file = format!("{}/{}", path, store.file_name());
+                let output = Command::new("ssh")
+                    .arg(host)
+                    .arg(format!(
+                        "test -e {0} || exit 3; stat -c %Y {0} && cat {0}",
+                        quote(&file)
+                    ))
+                    .output()
+                    .map_err(|e| t!("sync.ssh_failed", error = e))?;
+                if output.status.code() == Some(3) {
+                    return Ok(None);
+                }
+                if !output.status.success() {
+                    return Err(t!(
+                        "sync.could_not_read_on",
+                        file = file,
+                        host = host,
+                        error = String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                let newline = output
+                    .stdout
+                    .iter()
+                    .position(|byte| *byte == b'\n')
+                    .ok_or(t!("sync.no_modified", host = host))?;
+                let modified = String::from_utf8_lossy(&output.stdout[..newline])
+                    .trim()
+                    .parse()
+                    .map_err(|_| t!("sync.invalid_modified", host = host))?;
+                Ok(Some((output.stdout[newline + 1..].to_vec(), modified)))
+            }
+        }
+    }
+
+    /// Reads the views logged in the other data directory that are not yet in its video store
+    ///
+    /// # Returns
+    ///
+    /// The number of views added to each video, by its ID
+    fn read_views(&self) -> Result<HashMap<u32, u32>, String> {
+        let log = match self {
+            Target::Local(dir) => match fs::read_to_string(dir.join(view_log::FILE_NAME)) {
+                Ok(log) => log,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+                Err(e) => return Err(e.to_string()),
+            },
+            Target::Ssh { host, path } => {
+                let file = format!("{}/{}", path, view_log::FILE_NAME);
+                let output = Command::new("ssh")
+                    .arg(host)
+                    .arg(format!("test -e {0} || exit 0; cat {0}", quote(&file)))
+                    .output()
+                    .map_err(|e| t!("sync.ssh_failed", error = e))?;
+                if !output.status.success() {
+                    return Err(t!(
+                        "sync.could_not_read_on",
+                        file = file,
+                        host = host,
+                        error = String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+        };
+        // A line still being written is left for the other side to read
+        let complete = log.rfind('\n').map_or(0, |end| end + 1);
+        Ok(view_log::parse(&log[..complete]))
+    }
+
+    /// Overwrites the file backing a store, along with its checksum. Writing the video store also
+    /// removes the view log there, as the views it held are in the records written
+    fn write(&self, store: Store, bytes: &[u8]) -> Result<(), String> {
+        if dry_run::skip(&format!("copy {} to {}", store.file_name(), self)) {
+            return Ok(());
+        }
+        let checksum = storage::checksum(bytes);
+        match self {
+            Target::Local(dir) => {
+                fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                storage::replace_file(&dir.join(store.file_name()), bytes)
+                    .map_err(|e| e.to_string())?;
+                storage::replace_file(&dir.join(store.checksum_file_name()), checksum.as_bytes())
+                    .map_err(|e| e.to_string())?;
+                let log = dir.join(view_log::FILE_NAME);
+                if store == Store::Videos && log.exists() {
+                    fs::remove_file(log).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            Target::Ssh { host, path } => {
+                let file = |name: &str| quote(&format!("{}/{}", path, name));
+                let mut command = format!(
+                    "mkdir -p {} && cat > {} && printf {} > {}",
+                    quote(path),
+                    file(store.file_name()),
+                    checksum,
+                    file(&store.checksum_file_name())
+                );
+                if store == Store::Videos {
+                    command += &format!(" && rm -f {}", file(view_log::FILE_NAME));
+                }
+                let mut child = Command::new("ssh")
+                    .arg(host)
+                    .arg(command)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| t!("sync.ssh_failed", error = e))?;
+                child
+                    .stdin
+                    .take()
+                    .unwrap()
+                    .write_all(bytes)
+                    .map_err(|e| e.to_string())?;
+                let status = child.wait().map_err(|e| e.to_string())?;
+                if !status.success() {
+                    return Err(t!(
+                        "sync.could_not_write_on",
+                        file = store.file_name(),
+                        host = host
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Local(dir) => write!(f, "{}", dir.display()),
+            Target::Ssh { host, path } => write!(f, "{}:{}", host, path),
+        }
+    }
+}
+
+/// Quotes text for the shell `ssh` runs commands with, so paths are passed as they are whatever
+/// characters they hold
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// When a file was last written, in seconds since the Unix epoch, or 0 if unknown
+fn modified(path: &std::path::Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Identifies a record across both sides of a sync: its ID if it has one, or else the whole
+/// record, as with genres and watch history entries
+fn key(record: &Value) -> String {
+    match record.get("id") {
+        Some(id) => id.to_string(),
+        None => record.to_string(),
+    }
+}
+
+/// A record offered to the user when settling a conflict
+struct Choice<'a> {
+    side: String,
+    record: &'a Value,
+}
+
+impl fmt::Debug for Choice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            t!("sync.keep", side = self.side, record = self.record)
+        )
+    }
+}
+
+/// The records of a store on both sides, merged
+///
+/// # Fields
+///
+/// * `records` - The merged records
+/// * `to_local` - How many records were added or changed compared to the local store
+/// * `to_target` - How many records were added or changed compared to the other store
+struct Merged {
+    records: Vec<Value>,
+    to_local: usize,
+    to_target: usize,
+}
+
+/// Merges the records of a store from both sides. Records only on one side are added to the other,
+/// and records changed on both are settled with the given strategy
+///
+/// # Arguments
+///
+/// * `store` - The store being merged, used in messages
+/// * `local` - The local records and when the local store was last written
+/// * `target` - The other records and when the other store was last written
+/// * `strategy` - How to settle conflicts
+///
+/// # Returns
+///
+/// The merged records, or `None` if the user cancelled while settling a conflict
+fn merge(
+    store: Store,
+    local: (Vec<Value>, u64),
+    target: (Vec<Value>, u64),
+    strategy: ConflictStrategy,
+) -> Option<Merged> {
+    let (mut records, local_modified) = local;
+    let (target_records, target_modified) = target;
+    let mut to_local = 0;
+    let mut to_target = records.len();
+
+    for record in target_records {
+        let Some(index) = records.iter().position(|local| key(local) == key(&record)) else {
+            records.push(record);
+            to_local += 1;
+            continue;
+        };
+        to_target -= 1;
+        if records[index] == record {
+            continue;
+        }
+
+        let keep_target = match strategy {
+            ConflictStrategy::LastWriteWins => {
+                let updated_at = |record: &Value| record.get("updated_at").and_then(Value::as_u64);
+                match (updated_at(&records[index]), updated_at(&record)) {
+                    (Some(local), Some(target)) if local != target => target > local,
+                    _ => target_modified > local_modified,
+                }
+            }
+            ConflictStrategy::Interactive => {
+                let choices = [
+                    Choice {
+                        side: t!("sync.local"),
+                        record: &records[index],
+                    },
+                    Choice {
+                        side: t!("sync.other"),
+                        record: &record,
+                    },
+                ];
+                let prompt = t!(
+                    "sync.differs",
+                    store = format!("{:?}", store),
+                    id = key(&record)
+                );
+                utilities::choose(&prompt, &choices, Some(&t!("sync.cancelled")))? == 1
+            }
+        };
+        if keep_target {
+            debug!("Keeping the other {:?} record {}", store, key(&record));
+            records[index] = record;
+            to_local += 1;
+        } else {
+            debug!("Keeping the local {:?} record {}", store, key(&record));
+            to_target += 1;
+        }
+    }
+
+    Some(Merged {
+        records,
+        to_local,
+        to_target,
+    })
+}
+
+/// Handles syncing every store with another data directory, so both end up with the same records
+///
+/// Records are matched by ID. Records missing from one side are copied over, so a record deleted
+/// on one side since the last sync comes back from the other.
+///
+/// # Arguments
+///
+/// * `synchronize` - The arguments for the sync
+pub fn handle_sync(synchronize: Synchronize) {
+    if let Some(remote) = remote::url() {
+        error!("{}", t!("sync.remote", remote = remote));
+        return;
+    }
+    if !session::require_role(Role::Admin, &t!("role.sync_stores")) {
+        return;
+    }
+
+    let target = match Target::parse(&synchronize.target) {
+        Ok(target) => target,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    let mut merged_stores = vec![];
+    for store in SYNCED_STORES {
+        let local: Vec<Value> = storage::load(store);
+        let local_modified = modified(&storage::store_path(store));
+        let (target_records, target_modified) = match target.read(store) {
+            Ok(Some((bytes, modified))) => match storage::decode::<Value>(&bytes) {
+                Ok(records) if store == Store::Videos => match target.read_views() {
+                    Ok(views) => (
+                        records
+                            .into_iter()
+                            .map(|record| view_log::apply_to(record, &views))
+                            .collect(),
+                        modified,
+                    ),
+                    Err(e) => {
+                        error!(
+                            "{}",
+                            t!("sync.could_not_read_views", target = target, error = e)
+                        );
+                        return;
+                    }
+                },
+                Ok(records) => (records, modified),
+                Err(e) => {
+                    error!(
+                        "{}",
+                        t!(
+                            "sync.could_not_read",
+                            file = store.file_name(),
+                            target = target,
+                            error = e
+                        )
+                    );
+                    return;
+                }
+            },
+            Ok(None) => (vec![], 0),
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+
+        let Some(merged) = merge(
+            store,
+            (local, local_modified),
+            (target_records, target_modified),
+            synchronize.conflicts,
+        ) else {
+            return;
+        };
+        info!(
+            "{}",
+            t!(
+                "sync.counts",
+                store = format!("{:?}", store),
+                to_local = merged.to_local,
+                to_target = merged.to_target,
+                target = target
+            )
+        );
+        merged_stores.push((store, merged));
+    }
+
+    for (store, merged) in merged_stores {
+        if merged.to_local > 0 {
+            storage::save(store, &merged.records);
+        }
+        if merged.to_target > 0 {
+            if let Err(e) = target.write(store, &storage::encode(&merged.records)) {
+                error!("{}", t!("sync.failed", error = e));
+                return;
+            }
+        }
+    }
+
+    info!("{}", t!("sync.synced", target = target));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_quote() {
+        let path = "/data/it's $(rm -rf ~) here";
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf %s {}", quote(path)))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), path);
+    }
+
+    #[test]
+    fn test_merge_by_updated_at() {
+        let local = vec![
+            json!({"id": 1, "name": "Newer here", "updated_at": 20}),
+            json!({"id": 2, "name": "Older here", "updated_at": 10}),
+        ];
+        let target = vec![
+            json!({"id": 1, "name": "Older there", "updated_at": 10}),
+            json!({"id": 2, "name": "Newer there", "updated_at": 20}),
+        ];
+
+        // The other store was written last, but each record keeps its latest change
+        let merged = merge(
+            Store::Videos,
+            (local, 100),
+            (target, 200),
+            ConflictStrategy::LastWriteWins,
+        )
+        .unwrap();
+        let names: Vec<&Value> = merged
+            .records
+            .iter()
+            .map(|record| &record["name"])
+            .collect();
+        assert_eq!(names, [&json!("Newer here"), &json!("Newer there")]);
+        assert_eq!((merged.to_local, merged.to_target), (1, 1));
+    }
+}
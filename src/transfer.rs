@@ -0,0 +1,304 @@
+use crate::storage::{self, Store};
+use crate::t;
+use clap::{Args, ValueEnum};
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+/// The file formats records can be exported to and imported from
+///
+/// # Variants
+///
+/// * `Csv` - A header line naming the fields, then a line of comma-separated values per record
+/// * `Jsonl` - A JSON object per line
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Args)]
+pub struct Export {
+    /// The format to export to
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    pub format: Format,
+    /// The file to write to. Defaults to stdout
+    #[arg(short, long, default_value = None)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct Import {
+    /// The file to import from
+    pub file: PathBuf,
+    /// The format of the file
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    pub format: Format,
+    /// Add the imported records to the existing ones, skipping duplicates (default)
+    #[arg(long, default_value_t = false, conflicts_with = "replace")]
+    pub merge: bool,
+    /// Replace all existing records with the imported ones
+    #[arg(long, default_value_t = false)]
+    pub replace: bool,
+}
+
+/// Writes the given records in the format requested by `export`
+///
+/// # Arguments
+///
+/// * `export` - The arguments for the export
+/// * `records` - The records to export
+///
+/// # Returns
+///
+/// An error message if the records could not be written
+pub fn export<T: Serialize>(export: &Export, records: &Vec<T>) -> Result<(), String> {
+    let writer: Box<dyn Write> = match &export.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| e.to_string())?),
+        None => Box::new(io::stdout()),
+    };
+
+    match export.format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for record in records {
+                writer.serialize(record).map_err(|e| e.to_string())?;
+            }
+            writer.flush().map_err(|e| e.to_string())
+        }
+        Format::Jsonl => {
+            let mut writer = io::BufWriter::new(writer);
+            for record in records {
+                serde_json::to_writer(&mut writer, record).map_err(|e| e.to_string())?;
+                writeln!(writer).map_err(|e| e.to_string())?;
+            }
+            writer.flush().map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Reads the records from the file given to `import`
+///
+/// # Arguments
+///
+/// * `import` - The arguments for the import
+///
+/// # Returns
+///
+/// The records read from the file, or an error message naming the offending line
+pub fn import<T: DeserializeOwned>(import: &Import) -> Result<Vec<T>, String> {
+    match import.format {
+        Format::Csv => {
+            let mut reader = csv::Reader::from_path(&import.file).map_err(|e| e.to_string())?;
+            reader
+                .deserialize()
+                .collect::<Result<Vec<T>, csv::Error>>()
+                .map_err(|e| e.to_string())
+        }
+        Format::Jsonl => fs::read_to_string(&import.file)
+            .map_err(|e| e.to_string())?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| t!("transfer.line", line = number + 1, error = e))
+            })
+            .collect(),
+    }
+}
+
+/// Records read a line at a time, by the line each starts on, counting from 1, or why that line
+/// couldn't be read as one
+pub type Lines<T> = Vec<(usize, Result<T, String>)>;
+
+/// Reads records from stdin, as JSON Lines if the first line is a JSON object and as CSV with a
+/// header line otherwise
+///
+/// # Returns
+///
+/// Every record read, or an error message if stdin couldn't be read at all
+pub fn read_stdin<T: DeserializeOwned>() -> Result<Lines<T>, String> {
+    let input = io::read_to_string(io::stdin()).map_err(|e| e.to_string())?;
+    let jsonl = input
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| line.trim_start().starts_with('{'));
+    if jsonl {
+        return Ok(input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                (
+                    number + 1,
+                    serde_json::from_str(line).map_err(|e| e.to_string()),
+                )
+            })
+            .collect());
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let mut records = vec![];
+    for (index, record) in reader.records().enumerate() {
+        // Lines are counted from the header, for when a record can't even be split into fields
+        let fallback = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let number = e
+                    .position()
+                    .map_or(fallback, |position| position.line() as usize);
+                records.push((number, Err(e.to_string())));
+                continue;
+            }
+        };
+        let number = record
+            .position()
+            .map_or(fallback, |position| position.line() as usize);
+        records.push((
+            number,
+            record
+                .deserialize(Some(&headers))
+                .map_err(|e| e.to_string()),
+        ));
+    }
+    Ok(records)
+}
+
+/// Parses the time given to `--since`, either a date like `2024-01-01` or a full RFC 3339 time
+///
+/// # Returns
+///
+/// The time in seconds since the Unix epoch, or a message explaining why it is invalid
+fn parse_since(since: &str) -> Result<u64, String> {
+    let time = match since.len() {
+        10 => format!("{}T00:00:00Z", since),
+        _ => since.to_string(),
+    };
+    humantime::parse_rfc3339_weak(&time)
+        .map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+        .map_err(|_| t!("transfer.invalid_since", since = since))
+}
+
+#[derive(Debug, Args)]
+pub struct ExportChanges {
+    /// Only export records created or changed at or after this time, e.g. `2024-01-01`. Exports
+    /// every record if not given
+    #[arg(long, default_value = None, value_parser = parse_since)]
+    pub since: Option<u64>,
+    /// The format to export to. Only JSON Lines can hold records of every store
+    #[arg(long, value_enum, default_value_t = Format::Jsonl)]
+    pub format: Format,
+    /// The file to write to. Defaults to stdout
+    #[arg(short, long, default_value = None)]
+    pub output: Option<PathBuf>,
+}
+
+/// Determines when a record last changed, from its `updated_at` field or else when it was created
+///
+/// # Arguments
+///
+/// * `store` - The store the record is in
+/// * `record` - The record
+fn changed_at(store: Store, record: &Value) -> Option<u64> {
+    record
+        .get("updated_at")
+        .and_then(Value::as_u64)
+        .or_else(|| record.get(store.created_field()?)?.as_u64())
+}
+
+/// Handles exporting the records of every store, or only those changed since a given time, as a
+/// line per record naming its store, e.g. `{"store":"videos","record":{...}}`
+///
+/// API keys and the metadata of the stores are left out, as are genres when `--since` is given,
+/// as they don't record when they were created.
+///
+/// # Arguments
+///
+/// * `export_changes` - The arguments for the export
+pub fn handle_export_changes(export_changes: ExportChanges) {
+    if export_changes.format != Format::Jsonl {
+        error!("{}", t!("transfer.jsonl_only"));
+        return;
+    }
+
+    let mut lines = vec![];
+    for store in Store::ALL {
+        if store == Store::ApiKeys || store == Store::Metadata {
+            continue;
+        }
+        let records: Vec<Value> = storage::load(store);
+        lines.extend(
+            records
+                .into_iter()
+                .filter(|record| match export_changes.since {
+                    Some(since) => changed_at(store, record).is_some_and(|at| at >= since),
+                    None => true,
+                })
+                .map(|record| json!({ "store": store.route(), "record": record })),
+        );
+    }
+
+    let export = Export {
+        format: export_changes.format,
+        output: export_changes.output,
+    };
+    if let Err(e) = self::export(&export, &lines) {
+        error!("{}", t!("transfer.export_failed", error = e));
+        return;
+    }
+
+    if let Some(path) = export.output {
+        info!(
+            "{}",
+            t!(
+                "transfer.exported",
+                count = lines.len(),
+                path = path.display()
+            )
+        );
+    }
+}
+
+/// Serializes a list of strings as a single comma-separated string, so records holding one can
+/// still be written to CSV
+///
+/// Use with `#[serde(with = "crate::transfer::comma_separated")]`.
+pub mod comma_separated {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer, T: Display>(
+        values: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let values: Vec<String> = values.iter().map(T::to_string).collect();
+        serializer.serialize_str(&values.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: FromStr>(
+        deserializer: D,
+    ) -> Result<Vec<T>, D::Error>
+    where
+        T::Err: Display,
+    {
+        let joined = String::deserialize(deserializer)?;
+        joined
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().map_err(D::Error::custom))
+            .collect()
+    }
+}
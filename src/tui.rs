@@ -0,0 +1,752 @@
+use crate::args::command_types::user_subcommands::{self, Role, User};
+use crate::args::command_types::video_subcommands::{self, Video};
+use crate::desktop;
+use crate::formatting;
+use crate::integrity::{References, Target};
+use crate::notifications::Notification;
+use crate::query;
+use crate::repository::{self, Record, Repository};
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities;
+use log::{error, LevelFilter};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Clear, Paragraph, Row, Table, TableState, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::time::Duration;
+
+/// How long the browser waits for a key before checking for new notifications
+const NOTIFICATION_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The panes of the browser, switched between with tab
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    Users,
+    Videos,
+    Views,
+}
+
+impl Pane {
+    const ALL: [Pane; 3] = [Pane::Users, Pane::Videos, Pane::Views];
+
+    fn title(&self) -> String {
+        match self {
+            Pane::Users => t!("tui.users"),
+            Pane::Videos => t!("tui.videos"),
+            Pane::Views => t!("tui.views"),
+        }
+    }
+
+    fn index(&self) -> usize {
+        Pane::ALL.iter().position(|pane| pane == self).unwrap()
+    }
+}
+
+/// The record a form creates or updates when submitted
+///
+/// # Variants
+///
+/// * `CreateUser` - Creates a new user from a name and email address
+/// * `UpdateUser` - Updates the name and email address of the user with the given ID
+/// * `CreateVideo` - Creates a new video from a name
+/// * `UpdateVideo` - Updates the name and views of the video with the given ID
+/// * `AddViews` - Adds views to the video with the given ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FormKind {
+    CreateUser,
+    UpdateUser(u32),
+    CreateVideo,
+    UpdateVideo(u32),
+    AddViews(u32),
+}
+
+impl FormKind {
+    fn title(&self) -> String {
+        match self {
+            FormKind::CreateUser => t!("tui.new_user"),
+            FormKind::UpdateUser(_) => t!("tui.update_user"),
+            FormKind::CreateVideo => t!("tui.new_video"),
+            FormKind::UpdateVideo(_) => t!("tui.update_video"),
+            FormKind::AddViews(_) => t!("tui.add_views"),
+        }
+    }
+
+    fn labels(&self) -> Vec<String> {
+        match self {
+            FormKind::CreateUser | FormKind::UpdateUser(_) => {
+                vec![t!("field.name"), t!("field.email")]
+            }
+            FormKind::CreateVideo => vec![t!("field.name")],
+            FormKind::UpdateVideo(_) => vec![t!("field.name"), t!("field.views")],
+            FormKind::AddViews(_) => vec![t!("tui.views_to_add")],
+        }
+    }
+}
+
+/// A dialog shown over the panes, capturing all input until closed
+///
+/// # Variants
+///
+/// * `Form` - A set of text fields submitted together
+/// * `ConfirmDelete` - Asks whether the record in the current pane with the given ID should be deleted
+enum Dialog {
+    Form {
+        kind: FormKind,
+        values: Vec<String>,
+        focus: usize,
+        error: Option<String>,
+    },
+    ConfirmDelete {
+        id: u32,
+        description: String,
+    },
+}
+
+impl Dialog {
+    fn form(kind: FormKind, values: Vec<String>) -> Dialog {
+        Dialog::Form {
+            kind,
+            values,
+            focus: 0,
+            error: None,
+        }
+    }
+}
+
+struct App {
+    pane: Pane,
+    users: Vec<User>,
+    videos: Vec<Video>,
+    table: TableState,
+    search: String,
+    searching: bool,
+    dialog: Option<Dialog>,
+    status: String,
+    quit: bool,
+    notifications: Vec<Notification>,
+}
+
+/// Handles the `tui` command, opening the full-screen catalog browser
+pub fn handle_tui() {
+    // Anything logged while the browser is open would be drawn over it
+    let level = log::max_level();
+    log::set_max_level(LevelFilter::Off);
+
+    let mut terminal = ratatui::init();
+    let result = App::new().run(&mut terminal);
+    ratatui::restore();
+
+    log::set_max_level(level);
+    if let Err(e) = result {
+        error!("{}", t!("tui.closed", error = e));
+    }
+}
+
+/// Changes the latest records of a store and writes them, again from the start each time someone
+/// else changed the same records meanwhile, as `repository::retry` does
+///
+/// # Arguments
+///
+/// * `change` - Changes the records, returning a status message, or an error message to show
+///   instead of writing anything
+///
+/// # Returns
+///
+/// The status message and the records as written, or the error message, including when someone
+/// else kept changing the same records
+fn write<T: Record>(
+    mut change: impl FnMut(&mut Repository<T>) -> Result<String, String>,
+) -> Result<(String, Vec<T>), String> {
+    let mut written = Err(String::new());
+    repository::retry(|| {
+        let mut records = Repository::<T>::load();
+        let status = match change(&mut records) {
+            Ok(status) => status,
+            Err(e) => {
+                written = Err(e);
+                return Ok(());
+            }
+        };
+        if let Err(conflict) = records.try_save() {
+            written = Err(conflict.to_string());
+            return Err(conflict);
+        }
+        written = Ok((status, records.into_records()));
+        Ok(())
+    });
+    written
+}
+
+impl App {
+    fn new() -> App {
+        App {
+            pane: Pane::Users,
+            users: storage::load(Store::Users),
+            videos: storage::load(Store::Videos),
+            table: TableState::default().with_selected(Some(0)),
+            search: String::new(),
+            searching: false,
+            dialog: None,
+            status: String::new(),
+            quit: false,
+            notifications: match desktop::enabled() {
+                true => storage::load(Store::Notifications),
+                false => vec![],
+            },
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(NOTIFICATION_INTERVAL)? {
+                self.check_notifications();
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pops up the notifications sent since they were last checked on the desktop
+    fn check_notifications(&mut self) {
+        if !desktop::enabled() {
+            return;
+        }
+        let notifications: Vec<Notification> = storage::load(Store::Notifications);
+        desktop::show_new(&self.notifications, &notifications);
+        self.notifications = notifications;
+    }
+
+    /// The indices of the records shown in the current pane, in display order
+    fn visible(&self) -> Vec<usize> {
+        match self.pane {
+            Pane::Users => (0..self.users.len())
+                .filter(|i| {
+                    query::text_contains(&self.users[*i].name, &self.search, true)
+                        || query::text_contains(&self.users[*i].email, &self.search, true)
+                })
+                .collect(),
+            Pane::Videos | Pane::Views => {
+                let mut visible: Vec<usize> = (0..self.videos.len())
+                    .filter(|i| query::text_contains(&self.videos[*i].name, &self.search, true))
+                    .collect();
+                if self.pane == Pane::Views {
+                    visible.sort_by(|a, b| self.videos[*b].views.cmp(&self.videos[*a].views));
+                }
+                visible
+            }
+        }
+    }
+
+    /// The index of the selected record in `users` or `videos`, depending on the pane
+    fn selected(&self) -> Option<usize> {
+        self.table
+            .selected()
+            .and_then(|row| self.visible().get(row).copied())
+    }
+
+    fn switch_pane(&mut self, pane: Pane) {
+        self.pane = pane;
+        self.table.select(Some(0));
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if self.dialog.is_some() {
+            self.handle_dialog_key(key);
+            return;
+        }
+
+        if self.searching {
+            match key.code {
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.search.clear();
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Char(c) => self.search.push(c),
+                _ => {}
+            }
+            self.table.select(Some(0));
+            return;
+        }
+
+        self.status.clear();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab => self.switch_pane(Pane::ALL[(self.pane.index() + 1) % 3]),
+            KeyCode::BackTab => self.switch_pane(Pane::ALL[(self.pane.index() + 2) % 3]),
+            KeyCode::Char('1') => self.switch_pane(Pane::Users),
+            KeyCode::Char('2') => self.switch_pane(Pane::Videos),
+            KeyCode::Char('3') => self.switch_pane(Pane::Views),
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Home | KeyCode::Char('g') => self.table.select_first(),
+            KeyCode::End | KeyCode::Char('G') => self.table.select_last(),
+            KeyCode::Char('/') => {
+                self.searching = true;
+                self.search.clear();
+            }
+            KeyCode::Char('n') => self.open_create(),
+            KeyCode::Char('e') => self.open_update(),
+            KeyCode::Char('a') => self.open_add_views(),
+            KeyCode::Char('d') => self.open_delete(),
+            _ => {}
+        }
+    }
+
+    fn open_create(&mut self) {
+        self.dialog = Some(match self.pane {
+            Pane::Users => Dialog::form(FormKind::CreateUser, vec![String::new(); 2]),
+            Pane::Videos | Pane::Views => Dialog::form(FormKind::CreateVideo, vec![String::new()]),
+        });
+    }
+
+    fn open_update(&mut self) {
+        let Some(index) = self.selected() else {
+            return;
+        };
+        self.dialog = Some(match self.pane {
+            Pane::Users => {
+                let user = &self.users[index];
+                Dialog::form(
+                    FormKind::UpdateUser(user.id),
+                    vec![user.name.clone(), user.email.clone()],
+                )
+            }
+            Pane::Videos | Pane::Views => {
+                let video = &self.videos[index];
+                Dialog::form(
+                    FormKind::UpdateVideo(video.id),
+                    vec![video.name.clone(), video.views.to_string()],
+                )
+            }
+        });
+    }
+
+    fn open_add_views(&mut self) {
+        if self.pane == Pane::Users {
+            return;
+        }
+        if let Some(index) = self.selected() {
+            self.dialog = Some(Dialog::form(
+                FormKind::AddViews(self.videos[index].id),
+                vec!["1".to_string()],
+            ));
+        }
+    }
+
+    fn open_delete(&mut self) {
+        let Some(index) = self.selected() else {
+            return;
+        };
+        self.dialog = Some(match self.pane {
+            Pane::Users => Dialog::ConfirmDelete {
+                id: self.users[index].id,
+                description: format!("{:?}", self.users[index]),
+            },
+            Pane::Videos | Pane::Views => Dialog::ConfirmDelete {
+                id: self.videos[index].id,
+                description: format!("{:?}", self.videos[index]),
+            },
+        });
+    }
+
+    fn handle_dialog_key(&mut self, key: KeyEvent) {
+        match self.dialog.as_mut().unwrap() {
+            Dialog::ConfirmDelete { id, .. } => {
+                let id = *id;
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => {
+                        self.delete(id);
+                        self.dialog = None;
+                    }
+                    KeyCode::Char('n') | KeyCode::Esc => {
+                        self.status = t!("tui.delete_cancelled");
+                        self.dialog = None;
+                    }
+                    _ => {}
+                }
+            }
+            Dialog::Form {
+                kind,
+                values,
+                focus,
+                error,
+            } => match key.code {
+                KeyCode::Esc => self.dialog = None,
+                KeyCode::Tab | KeyCode::Down => *focus = (*focus + 1) % values.len(),
+                KeyCode::BackTab | KeyCode::Up => {
+                    *focus = (*focus + values.len() - 1) % values.len()
+                }
+                KeyCode::Backspace => {
+                    values[*focus].pop();
+                }
+                KeyCode::Char(c) => values[*focus].push(c),
+                KeyCode::Enter => {
+                    let kind = *kind;
+                    let values = values.clone();
+                    match self.submit(kind, &values) {
+                        Ok(status) => {
+                            self.status = status;
+                            self.dialog = None;
+                        }
+                        Err(e) => {
+                            if let Some(Dialog::Form { error, .. }) = self.dialog.as_mut() {
+                                *error = Some(e);
+                            }
+                        }
+                    }
+                }
+                _ => *error = None,
+            },
+        }
+    }
+
+    /// Applies a submitted form to the stores
+    ///
+    /// # Returns
+    ///
+    /// A status message describing the change, or an error message to show in the form
+    fn submit(&mut self, kind: FormKind, values: &[String]) -> Result<String, String> {
+        match kind {
+            FormKind::CreateUser => session::check_role(Role::Moderator, &t!("role.create_users"))?,
+            FormKind::UpdateUser(id) => {
+                session::check_self_or_role(id, Role::Moderator, &t!("role.update_other_users"))?
+            }
+            FormKind::CreateVideo => {
+                session::check_role(Role::Moderator, &t!("role.create_videos"))?
+            }
+            FormKind::UpdateVideo(_) => {
+                session::check_role(Role::Moderator, &t!("role.update_videos"))?
+            }
+            FormKind::AddViews(_) => session::check_role(Role::Admin, &t!("role.set_view_counts"))?,
+        }
+        let name = formatting::normalize(values[0].trim());
+        match kind {
+            FormKind::CreateUser | FormKind::UpdateUser(_) => {
+                let email = utilities::normalize_email(&values[1]);
+                if name.is_empty() || email.is_empty() {
+                    return Err(t!("tui.name_and_email_empty"));
+                }
+                utilities::validate_email(&email)?;
+                let id = match kind {
+                    FormKind::UpdateUser(id) => Some(id),
+                    _ => None,
+                };
+
+                let (status, users) =
+                    write(|users: &mut Repository<User>| {
+                        if users.with_email(&email).iter().any(|user| {
+                            user.email.eq_ignore_ascii_case(&email) && Some(user.id) != id
+                        }) {
+                            return Err(t!("user.email_taken"));
+                        }
+                        match id {
+                            Some(id) => {
+                                let mut user = users
+                                    .get(id)
+                                    .cloned()
+                                    .ok_or(t!("user.not_found_id", id = id))?;
+                                user.name = name.clone();
+                                user.email = email.clone();
+                                users.replace(user);
+                                Ok(t!("user.updated"))
+                            }
+                            None => {
+                                let user = User {
+                                    id: user_subcommands::generate_valid_id(users),
+                                    handle: Some(user_subcommands::suggest_handle(users, &name)),
+                                    name: name.clone(),
+                                    email: email.clone(),
+                                    ..Default::default()
+                                };
+                                let status = t!("tui.user_created", id = user.id);
+                                users.push(user);
+                                Ok(status)
+                            }
+                        }
+                    })?;
+                self.users = users;
+                Ok(status)
+            }
+            FormKind::CreateVideo => {
+                if name.is_empty() {
+                    return Err(t!("validation.name_empty"));
+                }
+                let (status, videos) = write(|videos: &mut Repository<Video>| {
+                    let video = Video {
+                        id: video_subcommands::generate_valid_id(videos),
+                        name: name.clone(),
+                        views: 0,
+                        uploaded_at: Some(video_subcommands::now()),
+                        ..Default::default()
+                    };
+                    let status = t!("tui.video_created", id = video.id);
+                    videos.push(video);
+                    Ok(status)
+                })?;
+                self.videos = videos;
+                Ok(status)
+            }
+            FormKind::UpdateVideo(id) => {
+                if name.is_empty() {
+                    return Err(t!("validation.name_empty"));
+                }
+                let views: u32 = values[1]
+                    .trim()
+                    .parse()
+                    .map_err(|_| t!("tui.views_not_number"))?;
+                let (status, videos) = write(|videos: &mut Repository<Video>| {
+                    let mut video = videos
+                        .get(id)
+                        .cloned()
+                        .ok_or(t!("video.not_found_id", id = id))?;
+                    if views != video.views {
+                        session::check_role(Role::Admin, &t!("role.set_view_counts"))?;
+                    }
+                    video.name = name.clone();
+                    video.views = views;
+                    videos.replace(video);
+                    Ok(t!("video.updated"))
+                })?;
+                self.videos = videos;
+                Ok(status)
+            }
+            FormKind::AddViews(id) => {
+                let number_to_add: u32 = values[0]
+                    .trim()
+                    .parse()
+                    .map_err(|_| t!("tui.views_not_number"))?;
+                let (status, videos) = write(|videos: &mut Repository<Video>| {
+                    let mut video = videos
+                        .get(id)
+                        .cloned()
+                        .ok_or(t!("video.not_found_id", id = id))?;
+                    video.views = video
+                        .views
+                        .checked_add(number_to_add)
+                        .ok_or(t!("tui.too_many_views"))?;
+                    let status = t!("view.added", count = number_to_add, name = video.name);
+                    videos.replace(video);
+                    Ok(status)
+                })?;
+                self.videos = videos;
+                Ok(status)
+            }
+        }
+    }
+
+    fn delete(&mut self, id: u32) {
+        let checked = match self.pane {
+            Pane::Users => session::check_role(Role::Admin, &t!("role.delete_users")),
+            Pane::Videos | Pane::Views => {
+                session::check_role(Role::Admin, &t!("role.delete_videos"))
+            }
+        };
+        if let Err(e) = checked {
+            self.status = e;
+            return;
+        }
+        let references = References::find(match self.pane {
+            Pane::Users => Target::User(id),
+            Pane::Videos | Pane::Views => Target::Video(id),
+        });
+        if !references.is_empty() {
+            self.status = t!("tui.referred_to", references = references.describe());
+            return;
+        }
+        let deleted = match self.pane {
+            Pane::Users => write(|users: &mut Repository<User>| {
+                users.remove(id);
+                Ok(t!("user.deleted"))
+            })
+            .map(|(status, users)| {
+                self.users = users;
+                status
+            }),
+            Pane::Videos | Pane::Views => write(|videos: &mut Repository<Video>| {
+                videos.remove(id);
+                Ok(t!("video.deleted"))
+            })
+            .map(|(status, videos)| {
+                self.videos = videos;
+                status
+            }),
+        };
+        self.status = deleted.unwrap_or_else(|e| e);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs_area, table_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let tabs = Tabs::new(Pane::ALL.iter().map(|pane| pane.title()))
+            .select(self.pane.index())
+            .highlight_style(Style::new().bold().reversed());
+        frame.render_widget(tabs, tabs_area);
+
+        let visible = self.visible();
+        let (header, widths, rows): (Row, Vec<Constraint>, Vec<Row>) = match self.pane {
+            Pane::Users => (
+                Row::new([t!("field.id"), t!("field.name"), t!("field.email")]),
+                vec![
+                    Constraint::Length(10),
+                    Constraint::Fill(1),
+                    Constraint::Fill(1),
+                ],
+                visible
+                    .iter()
+                    .map(|i| {
+                        let user = &self.users[*i];
+                        Row::new([user.id.to_string(), user.name.clone(), user.email.clone()])
+                    })
+                    .collect(),
+            ),
+            Pane::Videos => (
+                Row::new([t!("field.id"), t!("field.name"), t!("field.views")]),
+                vec![
+                    Constraint::Length(10),
+                    Constraint::Fill(1),
+                    Constraint::Length(10),
+                ],
+                visible
+                    .iter()
+                    .map(|i| {
+                        let video = &self.videos[*i];
+                        Row::new([
+                            video.id.to_string(),
+                            video.name.clone(),
+                            formatting::count(video.views),
+                        ])
+                    })
+                    .collect(),
+            ),
+            Pane::Views => {
+                let most_views = visible
+                    .iter()
+                    .map(|i| self.videos[*i].views)
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                (
+                    Row::new([t!("field.name"), t!("field.views"), String::new()]),
+                    vec![
+                        Constraint::Fill(1),
+                        Constraint::Length(10),
+                        Constraint::Fill(2),
+                    ],
+                    visible
+                        .iter()
+                        .map(|i| {
+                            let video = &self.videos[*i];
+                            let bar_width = (video.views as u64 * 40 / most_views as u64) as usize;
+                            Row::new([
+                                video.name.clone(),
+                                formatting::count(video.views),
+                                "█".repeat(bar_width),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+        };
+        let count = rows.len();
+        let table = Table::new(rows, widths)
+            .header(header.add_modifier(Modifier::BOLD))
+            .block(Block::bordered().title(format!(" {} ({}) ", self.pane.title(), count)))
+            .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        let status = if self.searching {
+            Line::from(format!("/{}█", self.search))
+        } else if !self.status.is_empty() {
+            Line::from(self.status.as_str())
+        } else if !self.search.is_empty() {
+            Line::from(t!("tui.filtered", search = self.search))
+        } else {
+            Line::from(t!("tui.help")).dim()
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+
+        if let Some(dialog) = &self.dialog {
+            draw_dialog(frame, dialog);
+        }
+    }
+}
+
+fn draw_dialog(frame: &mut Frame, dialog: &Dialog) {
+    let (title, mut lines) = match dialog {
+        Dialog::ConfirmDelete { description, .. } => (
+            t!("tui.delete"),
+            vec![
+                Line::from(t!("tui.confirm_delete")),
+                Line::from(description.as_str()),
+                Line::from(""),
+                Line::from(t!("tui.yes_no")).dim(),
+            ],
+        ),
+        Dialog::Form {
+            kind,
+            values,
+            focus,
+            error,
+        } => {
+            let mut lines: Vec<Line> = kind
+                .labels()
+                .iter()
+                .zip(values)
+                .enumerate()
+                .map(|(i, (label, value))| {
+                    if i == *focus {
+                        Line::from(format!("{}: {}█", label, value)).bold()
+                    } else {
+                        Line::from(format!("{}: {}", label, value))
+                    }
+                })
+                .collect();
+            lines.push(Line::from(""));
+            if let Some(error) = error {
+                lines.push(Line::from(error.as_str()).red());
+            }
+            lines.push(Line::from(t!("tui.form_help")).dim());
+            (kind.title(), lines)
+        }
+    };
+
+    let area = centered(frame.area(), 60, lines.len() as u16 + 2);
+    lines.truncate(area.height.saturating_sub(2) as usize);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(format!(" {} ", title))),
+        area,
+    );
+}
+
+/// Finds a rectangle of the given size centered in the given area
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
@@ -0,0 +1,269 @@
+use crate::args::command_types::user_subcommands::Role;
+use crate::args::command_types::video_subcommands;
+use crate::audit::{self, Action, AuditEntry};
+use crate::output::{self, Style};
+use crate::session;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::utilities;
+use clap::Args;
+use log::{error, info, warn};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Args)]
+pub struct Undo {
+    /// List the commands that can be undone, the latest first, instead of undoing one
+    #[arg(long, default_value_t = false)]
+    pub list: bool,
+}
+
+/// The changes a run of a command made
+///
+/// # Fields
+///
+/// * `run` - Identifies the run
+/// * `entries` - Every change the run made, in the order they were made
+pub struct Run<'a> {
+    pub run: u64,
+    pub entries: Vec<&'a AuditEntry>,
+}
+
+impl Run<'_> {
+    /// Describes the run, e.g. ``2023-06-25T12:00:00Z `video delete --id 5` ``
+    fn describe(&self) -> String {
+        let first = self.entries[0];
+        format!(
+            "{} `{}`",
+            output::paint_stdout(
+                &video_subcommands::format_timestamp(first.at),
+                Style::Header
+            ),
+            first.command
+        )
+    }
+}
+
+/// Picks out the runs whose changes can still be undone: those that weren't undone already and
+/// didn't undo another run themselves
+///
+/// # Arguments
+///
+/// * `entries` - Every entry of the audit log, oldest first
+///
+/// # Returns
+///
+/// The runs, the latest first
+pub fn undoable(entries: &[AuditEntry]) -> Vec<Run<'_>> {
+    let undone: HashSet<u64> = entries.iter().filter_map(|entry| entry.undoes).collect();
+    let mut runs: Vec<Run> = vec![];
+    for entry in entries {
+        match runs.iter_mut().find(|run| run.run == entry.run) {
+            Some(run) => run.entries.push(entry),
+            None => runs.push(Run {
+                run: entry.run,
+                entries: vec![entry],
+            }),
+        }
+    }
+    runs.retain(|run| {
+        !undone.contains(&run.run) && run.entries.iter().all(|entry| entry.undoes.is_none())
+    });
+    runs.reverse();
+    runs
+}
+
+/// Finds the position of a record in a store, by its ID or, for stores without one, as a whole
+fn position(records: &[Value], entry: &AuditEntry, record: &Value) -> Option<usize> {
+    match entry.id {
+        Some(id) => records
+            .iter()
+            .position(|other| other.get("id").and_then(Value::as_u64) == Some(id as u64)),
+        None => records.iter().position(|other| other == record),
+    }
+}
+
+/// Reverses the changes of a run in memory, the latest first: created records are deleted,
+/// deleted ones restored, and updated fields set back to what they were
+///
+/// Secrets were never written to the audit log, so they can't be set back. Restored records are
+/// given none, and secrets changed by the run are left as they are.
+///
+/// # Arguments
+///
+/// * `run` - The run to undo
+/// * `stores` - The records of every store the run changed
+///
+/// # Returns
+///
+/// Warnings about the secrets that couldn't be set back, or why the run can't be undone, if a
+/// record it changed was changed again since
+pub fn revert(run: &Run, stores: &mut HashMap<Store, Vec<Value>>) -> Result<Vec<String>, String> {
+    let mut warnings = vec![];
+    for entry in run.entries.iter().rev() {
+        let records = stores.entry(entry.entity.store()).or_default();
+        let name = entry.summary();
+        let gone = || t!("undo.gone", change = name);
+        match entry.action {
+            Action::Created => {
+                let new = entry.new.as_ref().ok_or_else(gone)?;
+                let index = position(records, entry, new).ok_or_else(gone)?;
+                records.remove(index);
+            }
+            Action::Deleted => {
+                let mut old = entry.old.clone().ok_or_else(gone)?;
+                if position(records, entry, &old).is_some() {
+                    return Err(t!("undo.exists_again", change = name));
+                }
+                if let Some(fields) = old.as_object_mut() {
+                    for (field, value) in fields.iter_mut() {
+                        if *value == audit::HIDDEN {
+                            *value = Value::Null;
+                            warnings.push(t!("undo.not_restored", field = field, record = name));
+                        }
+                    }
+                }
+                records.push(old);
+            }
+            Action::Updated => {
+                let (Some(old), Some(new)) = (&entry.old, &entry.new) else {
+                    return Err(gone());
+                };
+                let index = position(records, entry, new).ok_or_else(gone)?;
+                let current = &mut records[index];
+                for field in &entry.fields {
+                    if new[field] == audit::HIDDEN {
+                        warnings.push(t!("undo.not_set_back", field = field, record = name));
+                        continue;
+                    }
+                    if current[field] != new[field] {
+                        return Err(t!("undo.changed_again", change = name, field = field));
+                    }
+                    current[field] = old[field].clone();
+                }
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// Handles undoing the latest command that changed any record, or listing those that can be
+/// undone
+///
+/// # Arguments
+///
+/// * `undo` - The arguments for undoing
+pub fn handle_undo(undo: Undo) {
+    let entries = audit::read();
+    let runs = undoable(&entries);
+
+    if undo.list {
+        if runs.is_empty() {
+            info!("{}", t!("undo.nothing"));
+        }
+        for (number, run) in runs.iter().enumerate() {
+            println!("{}. {}", number + 1, run.describe());
+            for entry in &run.entries {
+                println!("    {}", entry.summary());
+            }
+        }
+        return;
+    }
+
+    if !session::require_role(Role::Admin, &t!("role.undo_changes")) {
+        return;
+    }
+    let Some(run) = runs.first() else {
+        error!("{}", t!("undo.nothing"));
+        return;
+    };
+    let changes: Vec<String> = run.entries.iter().map(|entry| entry.summary()).collect();
+    if !utilities::confirm(
+        &t!("undo.confirm", run = run.describe()),
+        Some(&t!("undo.changes", changes = changes.join(", "))),
+        Some(&t!("undo.cancelled")),
+        Some(true),
+        utilities::prompt_timeout(),
+    ) {
+        return;
+    }
+
+    let mut stores: HashMap<Store, Vec<Value>> = HashMap::new();
+    for entry in &run.entries {
+        let store = entry.entity.store();
+        stores.entry(store).or_insert_with(|| storage::load(store));
+    }
+    let warnings = match revert(run, &mut stores) {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            error!("{}", t!("undo.failed", error = e));
+            return;
+        }
+    };
+
+    audit::set_undoing(run.run);
+    for (store, records) in &stores {
+        storage::save(*store, records);
+    }
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+    info!("{}", t!("undo.undone", run = run.describe()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audit::Entity;
+    use serde_json::json;
+
+    fn entry(run: u64, action: Action, old: Option<Value>, new: Option<Value>) -> AuditEntry {
+        AuditEntry {
+            at: 0,
+            user: None,
+            command: String::new(),
+            run,
+            undoes: None,
+            entity: Entity::Video,
+            id: Some(1),
+            action,
+            fields: match action {
+                Action::Updated => vec!["name".to_string()],
+                _ => vec![],
+            },
+            old,
+            new,
+        }
+    }
+
+    #[test]
+    fn test_undo() {
+        let created = json!({"id": 1, "name": "a", "views": 0});
+        let renamed = json!({"id": 1, "name": "b", "views": 0});
+        let mut entries = vec![
+            entry(1, Action::Created, None, Some(created.clone())),
+            entry(
+                2,
+                Action::Updated,
+                Some(created.clone()),
+                Some(renamed.clone()),
+            ),
+        ];
+        let runs = undoable(&entries);
+        assert_eq!(runs.iter().map(|run| run.run).collect::<Vec<_>>(), [2, 1]);
+
+        let mut stores = HashMap::from([(Store::Videos, vec![renamed.clone()])]);
+        assert_eq!(revert(&runs[0], &mut stores), Ok(vec![]));
+        assert_eq!(stores[&Store::Videos][0], created);
+        assert!(revert(&runs[0], &mut stores).is_err());
+
+        entries.push(AuditEntry {
+            undoes: Some(2),
+            ..entry(3, Action::Updated, Some(renamed), Some(created))
+        });
+        let runs = undoable(&entries);
+        assert_eq!(runs.iter().map(|run| run.run).collect::<Vec<_>>(), [1]);
+        assert_eq!(revert(&runs[0], &mut stores), Ok(vec![]));
+        assert!(stores[&Store::Videos].is_empty());
+    }
+}
@@ -1,21 +1,328 @@
-use std::io;
+use crate::formatting;
+use crate::t;
+use clap::Args;
+use log::{error, info};
+use std::env;
+use std::fmt::Debug;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+/// Whether confirmation prompts should be answered with yes without asking
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether confirmation prompts should be answered with yes without asking, as with `--yes`
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+}
+
+/// The home directory of the user running rustflix, from `$HOME`, or the working directory if it
+/// isn't set
+pub fn home_dir() -> PathBuf {
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_default()
+}
+
+/// Determines if stdin is a terminal a person is typing into
+pub fn is_terminal() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Determines if the user can be asked for input, printing an explanation if not
+fn can_prompt() -> bool {
+    if io::stdin().is_terminal() {
+        return true;
+    }
+    error!("{}", t!("prompt.not_terminal"));
+    false
+}
+
+/// Asks the user a yes or no question
+///
+/// Nobody is there to answer if stdin is not a terminal, input ends, or no answer is given before
+/// the timeout, so the question is then answered with no, whatever the default. Only `--yes`
+/// confirms without asking.
+///
+/// # Arguments
+///
+/// * `prompt` - The question to ask
+/// * `post_prompt` - Extra details printed after the question
+/// * `cancel_message` - The message to print if the answer is no
+/// * `default` - The answer used when the user just presses enter
+/// * `timeout` - How long to wait for an answer. Waits forever if `None`
+///
+/// # Returns
+///
+/// `true` if the answer is yes
 pub fn confirm(
     prompt: &str,
     post_prompt: Option<&str>,
     cancel_message: Option<&str>,
     default: Option<bool>,
+    timeout: Option<Duration>,
 ) -> bool {
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        return true;
+    }
+
+    if !io::stdin().is_terminal() {
+        error!("{}", t!("prompt.not_terminal"));
+        error!("{}", t!("prompt.pass_yes"));
+        return false;
+    }
+
+    let (yes, no) = (t!("prompt.yes"), t!("prompt.no"));
+    let initial = |word: &str, upper: bool| {
+        let (first, rest) = word.split_at(word.chars().next().map_or(0, char::len_utf8));
+        match upper {
+            true => format!("[{}]{}", first.to_uppercase(), rest),
+            false => format!("[{}]{}", first, rest),
+        }
+    };
     println!(
-        "{} [{}]es/[{}]o",
+        "{} {}/{}",
         prompt,
-        if let Some(true) = default { "Y" } else { "y" },
-        if let Some(false) = default { "N" } else { "n" }
+        initial(&yes, default == Some(true)),
+        initial(&no, default == Some(false))
     );
 
-    if post_prompt.is_some() {
-        println!("{}", post_prompt.unwrap());
+    if let Some(post_prompt) = post_prompt {
+        println!("{}", post_prompt);
+    }
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let input = match read_answer(deadline) {
+            Answer::Line(input) => input,
+            Answer::EndOfInput => return answer_with(false, cancel_message),
+            Answer::TimedOut => {
+                info!("{}", t!("prompt.timed_out"));
+                return answer_with(false, cancel_message);
+            }
+        };
+
+        let input = input.to_lowercase();
+        let input = input.trim();
+        let is = |word: String| !input.is_empty() && (word == input || word.starts_with(input));
+        match input {
+            "y" | "yes" => return true,
+            "n" | "no" => return answer_with(false, cancel_message),
+            "" if default.is_some() => return answer_with(default.unwrap(), cancel_message),
+            _ if is(t!("prompt.yes")) => return true,
+            _ if is(t!("prompt.no")) => return answer_with(false, cancel_message),
+            _ => eprintln!("{}", t!("prompt.invalid_input")),
+        }
+    }
+}
+
+/// Gives an answer to a confirmation prompt, printing the cancel message if it is no
+fn answer_with(answer: bool, cancel_message: Option<&str>) -> bool {
+    if !answer {
+        if let Some(cancel_message) = cancel_message {
+            info!("{}", cancel_message);
+        }
     }
+    answer
+}
+
+/// The most records an operation may affect before it needs the count of records confirmed
+pub const BULK_THRESHOLD: usize = 10;
+
+#[derive(Debug, Args)]
+pub struct ConfirmCount {
+    /// Confirm an operation affecting many records without being asked, by giving the number of
+    /// records it affects
+    #[arg(long, default_value = None)]
+    pub confirm_count: Option<usize>,
+}
+
+/// Asks the user to confirm an operation that may affect many records
+///
+/// Operations affecting at most `BULK_THRESHOLD` records are confirmed as with `confirm`. Beyond
+/// that, `--yes` isn't enough: the user has to type the number of records affected, or give it
+/// beforehand with `--confirm-count`, so a query matching more than expected is caught.
+///
+/// # Arguments
+///
+/// * `prompt` - The question to ask
+/// * `post_prompt` - Extra details printed after the question
+/// * `cancel_message` - The message to print if the operation is not confirmed
+/// * `default` - The answer used for operations affecting few records when the user just presses
+///   enter
+/// * `affected` - The number of records the operation affects
+/// * `confirm_count` - The arguments that may confirm the count beforehand
+///
+/// # Returns
+///
+/// `true` if the operation is confirmed
+pub fn confirm_bulk(
+    prompt: &str,
+    post_prompt: Option<&str>,
+    cancel_message: Option<&str>,
+    default: Option<bool>,
+    affected: usize,
+    confirm_count: &ConfirmCount,
+) -> bool {
+    if confirm_count.confirm_count.is_none() && affected <= BULK_THRESHOLD {
+        return confirm(
+            prompt,
+            post_prompt,
+            cancel_message,
+            default,
+            prompt_timeout(),
+        );
+    }
+    confirm_typed_count(prompt, post_prompt, cancel_message, affected, confirm_count)
+}
+
+/// Asks the user to confirm an operation by typing the number of records it affects, however
+/// few, as `confirm_bulk` does for operations affecting many
+///
+/// # Arguments
+///
+/// * `prompt` - The question to ask
+/// * `post_prompt` - Extra details printed after the question
+/// * `cancel_message` - The message to print if the operation is not confirmed
+/// * `affected` - The number of records the operation affects
+/// * `confirm_count` - The arguments that may confirm the count beforehand
+///
+/// # Returns
+///
+/// `true` if the operation is confirmed
+pub fn confirm_typed_count(
+    prompt: &str,
+    post_prompt: Option<&str>,
+    cancel_message: Option<&str>,
+    affected: usize,
+    confirm_count: &ConfirmCount,
+) -> bool {
+    if let Some(count) = confirm_count.confirm_count {
+        if count != affected {
+            error!(
+                "{}",
+                t!("prompt.count_mismatch", count = count, affected = affected)
+            );
+        }
+        return count == affected;
+    }
+    if !io::stdin().is_terminal() {
+        error!("{}", t!("prompt.pass_count", affected = affected));
+        return false;
+    }
+
+    println!("{}", prompt);
+    if let Some(post_prompt) = post_prompt {
+        println!("{}", post_prompt);
+    }
+    println!("{}", t!("prompt.type_count", affected = affected));
+    let deadline = prompt_timeout().map(|timeout| Instant::now() + timeout);
+    match read_answer(deadline) {
+        Answer::Line(input) if input.trim() == affected.to_string() => true,
+        Answer::Line(_) => {
+            info!("{}", t!("prompt.count_differs"));
+            answer_with(false, cancel_message)
+        }
+        Answer::EndOfInput => answer_with(false, cancel_message),
+        Answer::TimedOut => {
+            info!("{}", t!("prompt.timed_out"));
+            answer_with(false, cancel_message)
+        }
+    }
+}
+
+/// The outcome of waiting for the user to answer a prompt
+///
+/// # Variants
+///
+/// * `Line` - The user entered a line
+/// * `EndOfInput` - Stdin was closed
+/// * `TimedOut` - The deadline passed before a line was entered
+enum Answer {
+    Line(String),
+    EndOfInput,
+    TimedOut,
+}
+
+/// Reads a line from stdin, giving up once the deadline passes
+///
+/// # Arguments
+///
+/// * `deadline` - When to stop waiting. Waits forever if `None`
+fn read_answer(deadline: Option<Instant>) -> Answer {
+    let read = || {
+        let mut input = "".to_string();
+        match io::stdin()
+            .read_line(&mut input)
+            .expect("Failed to read line")
+        {
+            0 => None,
+            _ => Some(input),
+        }
+    };
+
+    let received = match deadline {
+        None => Ok(read()),
+        Some(deadline) => {
+            // The reading thread is left blocked on stdin if the deadline passes
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || sender.send(read()));
+            receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        }
+    };
+
+    match received {
+        Ok(Some(input)) => Answer::Line(input),
+        Ok(None) => Answer::EndOfInput,
+        Err(_) => Answer::TimedOut,
+    }
+}
+
+/// The number of seconds to wait for an answer to a confirmation prompt. 0 waits forever
+static PROMPT_TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+/// Sets how long confirmation prompts wait for an answer, as with `--prompt-timeout`
+pub fn set_prompt_timeout(seconds: Option<u64>) {
+    PROMPT_TIMEOUT.store(seconds.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// How long confirmation prompts wait for an answer, or `None` to wait forever
+pub fn prompt_timeout() -> Option<Duration> {
+    match PROMPT_TIMEOUT.load(Ordering::Relaxed) {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    }
+}
+
+/// Asks the user to pick one of the given options by its number
+///
+/// # Arguments
+///
+/// * `prompt` - The prompt to print after the numbered options
+/// * `options` - The options to choose from
+/// * `cancel_message` - The message to print if the user picks no option
+///
+/// # Returns
+///
+/// The index of the chosen option, or `None` if the user cancelled
+pub fn choose<T: Debug>(
+    prompt: &str,
+    options: &[T],
+    cancel_message: Option<&str>,
+) -> Option<usize> {
+    if !can_prompt() {
+        error!("{}", t!("prompt.narrow_down"));
+        return None;
+    }
+
+    for (i, option) in options.iter().enumerate() {
+        println!("[{}] {:?}", i + 1, option);
+    }
+    println!(
+        "{}",
+        t!("prompt.choose", prompt = prompt, count = options.len())
+    );
 
     let mut input = "".to_string();
     loop {
@@ -23,31 +330,137 @@ pub fn confirm(
             .read_line(&mut input)
             .expect("Failed to read line");
 
-        match input.to_lowercase().trim() {
-            "y" | "yes" => return true,
-            "n" | "no" => {
-                if cancel_message.is_some() {
-                    println!("{}", cancel_message.unwrap());
+        match input.trim().parse::<usize>() {
+            Ok(choice) if (1..=options.len()).contains(&choice) => return Some(choice - 1),
+            _ if input.trim().is_empty() || input.trim().to_lowercase() == "c" => {
+                if let Some(cancel_message) = cancel_message {
+                    info!("{}", cancel_message);
                 }
-                return false;
+                return None;
             }
-            "" => match default {
-                Some(true) => return true,
-                Some(false) => {
-                    if cancel_message.is_some() {
-                        println!("{}", cancel_message.unwrap());
-                    }
-                    return false;
-                }
-                None => {
-                    eprintln!("Invalid input");
-                    input = "".to_string();
-                }
-            },
             _ => {
-                eprintln!("Invalid input");
+                eprintln!("{}", t!("prompt.invalid_input"));
                 input = "".to_string();
             }
         }
     }
 }
+
+/// Asks the user for a value until they give one passing validation
+///
+/// # Arguments
+///
+/// * `label` - The name of the value being asked for
+/// * `validate` - Checks the trimmed input, returning a message explaining why it is invalid
+///
+/// # Returns
+///
+/// The trimmed input, or `None` if no input could be read
+pub fn prompt(label: &str, validate: impl Fn(&str) -> Result<(), String>) -> Option<String> {
+    if !can_prompt() {
+        return None;
+    }
+
+    loop {
+        print!("{}: ", label);
+        io::stdout().flush().expect("Failed to flush stdout");
+
+        let mut input = "".to_string();
+        if io::stdin()
+            .read_line(&mut input)
+            .expect("Failed to read line")
+            == 0
+        {
+            println!();
+            return None;
+        }
+
+        let input = input.trim();
+        match validate(input) {
+            Ok(()) => return Some(input.to_string()),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+/// Asks the user for a password without echoing it
+///
+/// If stdin is not a terminal, the password is read from its first line instead, so it can be
+/// piped in.
+///
+/// # Arguments
+///
+/// * `label` - The name of the password being asked for
+///
+/// # Returns
+///
+/// The password, or `None` if none could be read
+pub fn prompt_password(label: &str) -> Option<String> {
+    if !io::stdin().is_terminal() {
+        let mut input = "".to_string();
+        if io::stdin().read_line(&mut input).ok()? == 0 {
+            error!("{}", t!("prompt.no_password"));
+            return None;
+        }
+        return Some(input.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    match rpassword::prompt_password(format!("{}: ", label)) {
+        Ok(password) => Some(password),
+        Err(e) => {
+            error!("{}", t!("prompt.password_unreadable", error = e));
+            None
+        }
+    }
+}
+
+/// Checks that a name is not empty
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err(t!("validation.name_empty"));
+    }
+    Ok(())
+}
+
+/// Checks that an email address has a local part of at most 64 characters made of letters, digits,
+/// dots and the symbols allowed unquoted, and a dotted domain whose labels are letters, digits,
+/// and inner hyphens
+pub fn validate_email(email: &str) -> Result<(), String> {
+    let invalid = || Err(t!("validation.invalid_email", email = email));
+
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return invalid();
+    };
+
+    if email.len() > 254
+        || local.is_empty()
+        || local.len() > 64
+        || local.starts_with('.')
+        || local.ends_with('.')
+        || local.contains("..")
+        || !local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+    {
+        return invalid();
+    }
+
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2
+        || labels.iter().any(|label| {
+            label.is_empty()
+                || label.len() > 63
+                || label.starts_with('-')
+                || label.ends_with('-')
+                || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+    {
+        return invalid();
+    }
+    Ok(())
+}
+
+/// Puts an email address in the form it is stored in, trimmed, normalized, and in lower case
+pub fn normalize_email(email: &str) -> String {
+    formatting::normalize(email.trim()).to_lowercase()
+}
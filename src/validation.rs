@@ -0,0 +1,160 @@
+use crate::t;
+use crate::utilities;
+use log::error;
+
+/// The most characters a name may have
+pub const MAX_NAME_LENGTH: usize = 200;
+
+/// The most characters a description or bio may have
+pub const MAX_TEXT_LENGTH: usize = 5000;
+
+/// The most views a video may be given. More is taken to be a typo
+pub const MAX_VIEWS: u32 = 1_000_000_000;
+
+/// Every problem found with the fields of a record about to be created or updated, so they can
+/// all be reported at once instead of one per attempt
+#[derive(Debug, Default)]
+pub struct Violations(Vec<String>);
+
+impl Violations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the error of a check, if it failed
+    pub fn check(&mut self, result: Result<(), String>) -> &mut Self {
+        if let Err(e) = result {
+            self.0.push(e);
+        }
+        self
+    }
+
+    /// Checks that a name is not blank or too long
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - What the name is, e.g. `Name`
+    /// * `name` - The name to check
+    pub fn name(&mut self, field: &str, name: &str) -> &mut Self {
+        self.check(check_length(field, name, MAX_NAME_LENGTH))
+    }
+
+    /// Checks that free text, like a description, is not blank or too long. A field meant to be
+    /// empty is left out instead
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - What the text is, e.g. `Description`
+    /// * `text` - The text to check
+    pub fn text(&mut self, field: &str, text: &str) -> &mut Self {
+        self.check(check_length(field, text, MAX_TEXT_LENGTH))
+    }
+
+    /// Checks that an email address is valid, once normalized
+    pub fn email(&mut self, email: &str) -> &mut Self {
+        self.check(utilities::validate_email(&utilities::normalize_email(
+            email,
+        )))
+    }
+
+    /// Checks that a view count is believable
+    pub fn views(&mut self, views: u32) -> &mut Self {
+        if views > MAX_VIEWS {
+            self.0.push(t!(
+                "validation.too_many_views",
+                views = views,
+                max = MAX_VIEWS
+            ));
+        }
+        self
+    }
+
+    /// Logs every problem found
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - What didn't happen because of the problems, e.g. `User not generated`
+    ///
+    /// # Returns
+    ///
+    /// Whether no problem was found
+    pub fn report(&self, action: &str) -> bool {
+        match self.0.as_slice() {
+            [] => true,
+            [violation] => {
+                error!("{}. {}", action, violation);
+                false
+            }
+            violations => {
+                error!(
+                    "{}",
+                    t!(
+                        "validation.problems",
+                        action = action,
+                        count = violations.len()
+                    )
+                );
+                for violation in violations {
+                    error!("  {}", violation);
+                }
+                false
+            }
+        }
+    }
+}
+
+/// Checks that a field is not blank and has at most the given number of characters
+fn check_length(field: &str, value: &str, max: usize) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(t!("validation.empty", field = field));
+    }
+    let length = value.chars().count();
+    if length > max {
+        return Err(t!(
+            "validation.too_long",
+            field = field,
+            max = max,
+            length = length
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_violations() {
+        let mut violations = Violations::new();
+        violations
+            .name("Name", "Alice")
+            .text("Bio", "Likes films")
+            .email("Alice@Example.com ")
+            .views(MAX_VIEWS);
+        assert!(violations.0.is_empty());
+
+        violations
+            .name("Name", "  \t")
+            .text("Bio", &"a".repeat(MAX_TEXT_LENGTH + 1))
+            .email("alice@")
+            .views(MAX_VIEWS + 1);
+        assert_eq!(
+            violations.0,
+            [
+                "Name must not be empty".to_string(),
+                format!(
+                    "Bio must be at most {} characters long, not {}",
+                    MAX_TEXT_LENGTH,
+                    MAX_TEXT_LENGTH + 1
+                ),
+                "alice@ is not a valid email address".to_string(),
+                format!(
+                    "{} views is more than the {} a video may have",
+                    MAX_VIEWS + 1,
+                    MAX_VIEWS
+                ),
+            ]
+        );
+    }
+}
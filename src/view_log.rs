@@ -0,0 +1,235 @@
+use crate::args::command_types::video_subcommands::Video;
+use crate::storage::{self, Store};
+use crate::t;
+use crate::{daemon, encryption, remote, s3};
+use log::{debug, error, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The size in bytes the log can grow to before it is compacted into the video store
+const COMPACTION_THRESHOLD: u64 = 16 * 1024;
+
+/// The name of the log file in a data directory
+pub const FILE_NAME: &str = "views.log";
+
+/// How many bytes from the start of the log were added to the videos loaded last, and so can be
+/// dropped from it once those videos are saved
+static APPLIED: AtomicU64 = AtomicU64::new(0);
+
+/// The path of the log of views added since the video store was last written. Each line holds
+/// the ID of a video and the number of views added to it, separated by a space
+pub fn path() -> PathBuf {
+    storage::data_dir().join(FILE_NAME)
+}
+
+/// Whether views can be added through the log, which is only kept next to store files in the
+/// data directory that nothing else is holding in memory. Encrypted stores don't use it, as the
+/// log is written in plain text, and neither do batches, which write the video store once anyway
+pub fn available() -> bool {
+    let available = !storage::in_batch()
+        && remote::url().is_none()
+        && s3::config().is_none()
+        && !encryption::enabled()
+        && daemon::connect().is_none();
+    if !available {
+        debug!("Not using the view log, as the stores are not kept in plain files");
+    }
+    available
+}
+
+/// Adds views to a video by appending them to the log, without rewriting the video store
+///
+/// The log is compacted into the video store once it grows past `COMPACTION_THRESHOLD`.
+///
+/// # Arguments
+///
+/// * `video` - The ID of the video
+/// * `views` - The number of views to add
+///
+/// # Returns
+///
+/// An error message if the log could not be written
+pub fn append(video: u32, views: u32) -> Result<(), String> {
+    let path = path();
+    fs::create_dir_all(storage::data_dir()).map_err(|e| e.to_string())?;
+    let size = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            file.write_all(format!("{} {}\n", video, views).as_bytes())?;
+            file.metadata()
+        })
+        .map_err(|e| t!("storage.could_not_write", path = path.display(), error = e))?
+        .len();
+
+    if size >= COMPACTION_THRESHOLD {
+        compact();
+    }
+    Ok(())
+}
+
+/// Writes every view in the log to the video store, emptying the log
+pub fn compact() {
+    debug!("Compacting {} into the video store", path().display());
+    let videos: Vec<Video> = storage::load(Store::Videos);
+    storage::save(Store::Videos, &videos);
+}
+
+/// Reads the views added through the log, remembering how much of it was read
+///
+/// # Returns
+///
+/// The number of views added to each video, by its ID
+pub fn read() -> HashMap<u32, u32> {
+    let Ok(log) = fs::read_to_string(path()) else {
+        APPLIED.store(0, Ordering::Relaxed);
+        return HashMap::new();
+    };
+
+    // A line still being written by another command is left for the next read
+    let complete = log.rfind('\n').map_or(0, |end| end + 1);
+    APPLIED.store(complete as u64, Ordering::Relaxed);
+    parse(&log[..complete])
+}
+
+/// Reads the views added through the log to the videos loaded last, without changing how much of
+/// it was read
+///
+/// # Returns
+///
+/// The number of views added to each video, by its ID
+pub fn applied() -> HashMap<u32, u32> {
+    let applied = APPLIED.load(Ordering::Relaxed) as usize;
+    match fs::read_to_string(path()) {
+        Ok(log) if applied > 0 => parse(log.get(..applied).unwrap_or_default()),
+        _ => HashMap::new(),
+    }
+}
+
+/// Totals the views in complete lines of the log by video
+pub fn parse(log: &str) -> HashMap<u32, u32> {
+    let mut views: HashMap<u32, u32> = HashMap::new();
+    for line in log.lines() {
+        let entry = line
+            .split_once(' ')
+            .and_then(|(video, added)| Some((video.parse().ok()?, added.parse().ok()?)));
+        match entry {
+            Some((video, added)) => {
+                let total: &mut u32 = views.entry(video).or_default();
+                *total = total.saturating_add(added);
+            }
+            None => warn!(
+                "{}",
+                t!("storage.invalid_view", line = format!("{:?}", line))
+            ),
+        }
+    }
+    views
+}
+
+/// Adds the views from the log to a video record
+///
+/// Records are taken as any serializable type, like the stores, so videos can be read as JSON
+/// values too.
+///
+/// # Arguments
+///
+/// * `record` - The video
+/// * `views` - The views read from the log
+pub fn apply_to<T: Serialize + DeserializeOwned>(record: T, views: &HashMap<u32, u32>) -> T {
+    let mut value = serde_json::to_value(&record).unwrap();
+    let Some(added) = value
+        .get("id")
+        .and_then(Value::as_u64)
+        .and_then(|id| views.get(&(id as u32)))
+    else {
+        return record;
+    };
+    let current = value.get("views").and_then(Value::as_u64).unwrap_or(0);
+    value["views"] = (current + *added as u64).min(u32::MAX as u64).into();
+    serde_json::from_value(value).unwrap()
+}
+
+/// Adds the views from the log to every video record
+pub fn apply<T: Serialize + DeserializeOwned>(records: Vec<T>) -> Vec<T> {
+    let views = read();
+    if views.is_empty() {
+        return records;
+    }
+    records
+        .into_iter()
+        .map(|record| apply_to(record, &views))
+        .collect()
+}
+
+/// Drops the views that were added to the videos loaded last from the log, now that those videos
+/// were written to the video store. Views appended since are kept
+pub fn forget_applied() {
+    let applied = APPLIED.swap(0, Ordering::Relaxed) as usize;
+    if applied == 0 {
+        return;
+    }
+
+    let path = path();
+    let Ok(log) = fs::read(&path) else {
+        return;
+    };
+    let rest = log.get(applied..).unwrap_or_default();
+    let result = match rest.is_empty() {
+        true => fs::remove_file(&path),
+        false => fs::write(&path, rest),
+    };
+    if let Err(e) = result {
+        error!(
+            "{}",
+            t!(
+                "storage.could_not_compact",
+                path = path.display(),
+                error = e
+            )
+        );
+    }
+}
+
+/// Throws away every view in the log, as when the stores are replaced by a backup
+pub fn clear() -> std::io::Result<()> {
+    APPLIED.store(0, Ordering::Relaxed);
+    match path().exists() {
+        true => fs::remove_file(path()),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_to() {
+        let video = Video {
+            id: 7,
+            name: "test".to_string(),
+            views: 3,
+            genres: vec!["Drama".to_string()],
+            ..Default::default()
+        };
+        let views = HashMap::from([(7, 2), (8, 5)]);
+
+        let applied = apply_to(video.clone(), &views);
+        assert_eq!(applied.views, 5);
+        assert_eq!(applied.genres, video.genres);
+
+        let value = apply_to(serde_json::to_value(&video).unwrap(), &views);
+        assert_eq!(value["views"], 5);
+
+        let other = Video { id: 9, ..video };
+        assert_eq!(apply_to(other, &views).views, 3);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::args::command_types::user_subcommands::User;
+use crate::args::command_types::video_subcommands;
+use crate::events::{self, Change, Event, EventSubscriber};
+use crate::t;
+use clap::{Args, Subcommand};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for a webhook to respond
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before retrying a webhook the first time. Doubles with every retry
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// A URL sent a POST request with a JSON payload for every event it is interested in, from a
+/// `[[webhooks]]` section of the config file
+///
+/// # Fields
+///
+/// * `url` - Where to send the events
+/// * `events` - The kinds of events to send, e.g. `["video_created", "views_crossed"]`. Every
+///   kind if empty
+/// * `thresholds` - The view counts to send a `views_crossed` event at, when a video reaches one
+/// * `retries` - How many times to try again when the webhook can't be reached or responds with a
+///   server error
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub thresholds: Vec<u32>,
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+impl Webhook {
+    /// Whether the webhook is interested in events of the given kind
+    fn wants(&self, kind: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|wanted| wanted == kind)
+    }
+
+    /// Picks out the events to send the webhook, adding a `views_crossed` event for each of its
+    /// thresholds a video reached by gaining views
+    ///
+    /// # Arguments
+    ///
+    /// * `changes` - Every change made
+    ///
+    /// # Returns
+    ///
+    /// The events to send, in the order they happened
+    pub fn events_for(&self, changes: &[Event]) -> Vec<Event> {
+        let mut sent = vec![];
+        for event in changes {
+            if self.wants(event.kind()) {
+                sent.push(event.clone());
+            }
+            if let Event::ViewsAdded { video, added } = event {
+                let before = video.views.saturating_sub(*added);
+                sent.extend(
+                    events::crossed(&self.thresholds, before, video.views)
+                        .into_iter()
+                        .map(|threshold| Event::ViewsCrossed {
+                            video: video.clone(),
+                            threshold,
+                        })
+                        .filter(|crossed| self.wants(crossed.kind())),
+                );
+            }
+        }
+        sent
+    }
+}
+
+/// The webhooks configured, if any
+static WEBHOOKS: OnceLock<Vec<Webhook>> = OnceLock::new();
+
+/// Sends every change made from now on to the given webhooks, warning about kinds of events they
+/// ask for that don't exist
+pub fn set_config(webhooks: Vec<Webhook>) {
+    for webhook in &webhooks {
+        for kind in &webhook.events {
+            if !events::KINDS.contains(&kind.as_str()) {
+                warn!(
+                    "{}",
+                    t!(
+                        "webhook.unknown_event",
+                        event = format!("{:?}", kind),
+                        url = webhook.url,
+                        events = events::KINDS.join(", ")
+                    )
+                );
+            }
+        }
+    }
+    WEBHOOKS.set(webhooks).expect("Webhooks set twice");
+}
+
+/// The webhooks configured, or none if none are
+pub fn configured() -> &'static [Webhook] {
+    WEBHOOKS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Builds the JSON body sent for an event, without the password and session hashes of users
+///
+/// # Arguments
+///
+/// * `event` - The event to send
+/// * `sent_at` - When the event is sent, in seconds since the Unix epoch
+pub fn payload(event: &Event, sent_at: u64) -> Value {
+    let hide = |user: &User| User {
+        password: None,
+        session: None,
+        ..user.clone()
+    };
+    let event = match event {
+        Event::UserCreated { user } => Event::UserCreated { user: hide(user) },
+        Event::UserUpdated { user } => Event::UserUpdated { user: hide(user) },
+        Event::UserDeleted { user } => Event::UserDeleted { user: hide(user) },
+        event => event.clone(),
+    };
+    let mut payload = serde_json::to_value(event).unwrap();
+    payload["sent_at"] = json!(sent_at);
+    payload
+}
+
+/// Posts a payload to a URL, trying again with a growing delay when the URL can't be reached, is
+/// rate limited, or responds with a server error
+///
+/// # Arguments
+///
+/// * `url` - Where to post the payload
+/// * `payload` - The JSON body to post
+/// * `retries` - How many times to try again
+///
+/// # Returns
+///
+/// The status the URL responded with, or why it couldn't be delivered
+pub fn deliver(url: &str, payload: &Value, retries: u32) -> Result<u16, String> {
+    let mut attempt = 0;
+    loop {
+        let error = match ureq::post(url).timeout(TIMEOUT).send_json(payload) {
+            Ok(response) => return Ok(response.status()),
+            Err(ureq::Error::Status(code, response)) => {
+                let e = t!(
+                    "remote.responded_with",
+                    url = url,
+                    code = code,
+                    body = response.into_string().unwrap_or_default()
+                );
+                if code != 429 && code < 500 {
+                    return Err(e);
+                }
+                e
+            }
+            Err(ureq::Error::Transport(e)) => t!("webhook.unreachable", error = e),
+        };
+        if attempt == retries {
+            return Err(error);
+        }
+        let delay = BACKOFF * 2u32.pow(attempt);
+        debug!("{}. Trying again in {:?}", error, delay);
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+/// Sends each webhook the changes it is interested in, all at once, warning about those that
+/// couldn't be delivered
+///
+/// # Arguments
+///
+/// * `changes` - The changes made
+pub fn fire(changes: &[Event]) {
+    let sent_at = video_subcommands::now();
+    thread::scope(|scope| {
+        for webhook in configured() {
+            let events = webhook.events_for(changes);
+            if events.is_empty() {
+                continue;
+            }
+            scope.spawn(move || {
+                for event in events {
+                    let payload = payload(&event, sent_at);
+                    match deliver(&webhook.url, &payload, webhook.retries) {
+                        Ok(status) => {
+                            debug!("Sent {} to {} ({})", event.kind(), webhook.url, status)
+                        }
+                        Err(e) => warn!(
+                            "{}",
+                            t!("webhook.could_not_send", event = event.kind(), error = e)
+                        ),
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Sends the webhooks the events every change to users and videos amounts to
+pub struct Webhooks;
+
+impl EventSubscriber for Webhooks {
+    fn handle(&self, change: &Change) {
+        if configured().is_empty() || change.events().is_empty() {
+            return;
+        }
+        fire(change.events());
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct WebhookCommand {
+    #[clap(subcommand)]
+    pub subcommand: WebhookSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WebhookSubcommand {
+    /// Send a test event to every webhook in the config file, or to the given URL
+    Test(TestWebhook),
+}
+
+#[derive(Debug, Args)]
+pub struct TestWebhook {
+    /// The URL to send the test event to instead of the configured webhooks
+    #[arg(long, default_value = None)]
+    pub url: Option<String>,
+}
+
+/// Handles sending a test event to webhooks, reporting whether each received it
+///
+/// # Arguments
+///
+/// * `test_webhook` - The arguments for the test
+pub fn handle_test_webhook(test_webhook: TestWebhook) {
+    let webhooks = match test_webhook.url {
+        Some(url) => vec![Webhook {
+            url,
+            events: vec![],
+            thresholds: vec![],
+            retries: 0,
+        }],
+        None => configured().to_vec(),
+    };
+    if webhooks.is_empty() {
+        error!("{}", t!("webhook.none"));
+        return;
+    }
+
+    let payload = json!({
+        "type": "test",
+        "message": "This is a test event from rustflix",
+        "sent_at": video_subcommands::now(),
+    });
+    for webhook in webhooks {
+        match deliver(&webhook.url, &payload, webhook.retries) {
+            Ok(status) => info!(
+                "{}",
+                t!("webhook.received", url = webhook.url, status = status)
+            ),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use video_subcommands::Video;
+
+    #[test]
+    fn test_webhook_events() {
+        let video = Video {
+            id: 1,
+            views: 1200,
+            ..Default::default()
+        };
+        let changes = [
+            Event::VideoCreated {
+                video: video.clone(),
+            },
+            Event::ViewsAdded {
+                video: video.clone(),
+                added: 300,
+            },
+        ];
+        let webhook = Webhook {
+            url: "http://localhost".to_string(),
+            events: vec!["views_crossed".to_string()],
+            thresholds: vec![100, 1000, 1200, 10000],
+            retries: 0,
+        };
+        assert_eq!(
+            webhook.events_for(&changes),
+            [1000, 1200].map(|threshold| Event::ViewsCrossed {
+                video: video.clone(),
+                threshold,
+            })
+        );
+
+        let webhook = Webhook {
+            events: vec![],
+            ..webhook
+        };
+        let kinds: Vec<&str> = webhook
+            .events_for(&changes)
+            .iter()
+            .map(Event::kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "video_created",
+                "views_added",
+                "views_crossed",
+                "views_crossed"
+            ]
+        );
+
+        let payload = payload(
+            &Event::UserCreated {
+                user: User {
+                    password: Some("hash".to_string()),
+                    ..Default::default()
+                },
+            },
+            5,
+        );
+        assert_eq!(payload["type"], "user_created");
+        assert_eq!(payload["user"]["password"], Value::Null);
+        assert_eq!(payload["sent_at"], 5);
+    }
+}